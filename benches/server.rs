@@ -0,0 +1,109 @@
+//! Load benchmarks for the server, using the load patterns of the server integration tests.
+//! Run with `cargo bench --bench server`. Each pattern prints the requests per second it reached.
+
+extern crate my_http;
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::thread::{available_parallelism, sleep, spawn};
+use std::time::{Duration, Instant};
+
+use my_http::common::request::Request;
+use my_http::common::response::Response;
+use my_http::common::status;
+use my_http::server;
+use my_http::server::{Config, ListenerResult, Router};
+use my_http::server::ListenerResult::SendResponse;
+
+/// Size of the bodies of the large responses.
+const LARGE_BODY_SIZE: usize = 1_000_000;
+
+fn main() {
+    // at least two event loops, so that handing connections between them is measured even on a single core
+    let event_loops = available_parallelism().map_or(1, |cores| cores.get()).max(2);
+    let servers = [
+        ("0.0.0.0:7100", 7100, 1, false),
+        ("0.0.0.0:7101", 7101, event_loops, false),
+        ("0.0.0.0:7102", 7102, 1, true),
+        ("0.0.0.0:7103", 7103, event_loops, true),
+    ];
+
+    for (addr, port, event_loops, inline) in servers {
+        println!("event_loops: {}, inline: {}", event_loops, inline);
+        start_server(addr, event_loops, inline);
+
+        run("many concurrent connections with many simple requests", port, 50, 2000, "/");
+        run("many connections with one simple request", port, 5000, 1, "/");
+        run("pipelined simple requests", port, 20, 5000, "/pipelined");
+        run("many concurrent connections with large responses", port, 10, 100, "/large");
+    }
+}
+
+/// Starts a server with the given event loops, and a router with a small response on "/" and "/pipelined", and a
+/// large response on "/large". The listeners are called inline on the event loop if `inline` is set, and on the
+/// thread pool otherwise.
+fn start_server(addr: &'static str, event_loops: usize, inline: bool) {
+    let mut router = Router::new();
+    let on: fn(&mut Router, &str, fn(&str, &Request) -> ListenerResult) = if inline { Router::on_inline } else { Router::on };
+    on(&mut router, "/", |_, _| SendResponse(Response::empty(status::OK)));
+    on(&mut router, "/pipelined", |_, _| SendResponse(Response::empty(status::OK)));
+    on(&mut router, "/large", |_, _| SendResponse(vec![b'a'; LARGE_BODY_SIZE].into()));
+
+    spawn(move || server::listen_http(Config { addr, event_loops, router, ..Default::default() }).unwrap());
+    sleep(Duration::from_millis(100));
+}
+
+/// Sends the given number of requests for the given URI over each of the given number of concurrent connections, and
+/// prints the requests per second. Requests to "/pipelined" are all written before any responses are read.
+fn run(name: &str, port: u16, connections: usize, requests_per_connection: usize, uri: &'static str) {
+    let start = Instant::now();
+
+    let clients: Vec<_> = (0..connections).map(|_| spawn(move || {
+        let mut client = TcpStream::connect(("localhost", port)).unwrap();
+        client.set_nodelay(true).unwrap();
+        let request = format!("GET {} HTTP/1.1\r\n\r\n", uri);
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+
+        if uri == "/pipelined" {
+            client.write_all(request.repeat(requests_per_connection).as_bytes()).unwrap();
+            for _ in 0..requests_per_connection {
+                read_response(&mut reader);
+            }
+        } else {
+            for _ in 0..requests_per_connection {
+                client.write_all(request.as_bytes()).unwrap();
+                read_response(&mut reader);
+            }
+        }
+    })).collect();
+
+    for client in clients {
+        client.join().unwrap();
+    }
+
+    let elapsed = start.elapsed();
+    let requests = connections * requests_per_connection;
+    println!("  {}: {} requests in {:?}, {:.0} requests/s", name, requests, elapsed, requests as f64 / elapsed.as_secs_f64());
+}
+
+/// Reads a response with a content-length header from the given reader, and checks that it is a 200 response.
+fn read_response(reader: &mut impl BufRead) {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "HTTP/1.1 200 OK\r\n");
+
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        if line == "\r\n" {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("content-length: ") {
+            content_length = value.trim_end().parse().unwrap();
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).unwrap();
+}
@@ -1,20 +1,30 @@
-use std::io::{BufReader, BufWriter, Error, Write};
+use std::io::{BufRead, BufReader, BufWriter, Error, Write};
+#[cfg(feature = "http2")]
+use std::io::Read;
 use std::net::TcpStream;
+#[cfg(feature = "http2")]
+use std::sync::Arc;
 use std::sync::Mutex;
 
 use rustls::ClientConfig;
 
 use crate::client::client::RequestError::{Reading, Writing};
 use crate::client::config::Config;
+#[cfg(feature = "http2")]
+use crate::client::http2::Http2Connection;
 use crate::client::RequestError::Connecting;
 use crate::client::stream_factory::{ClientTlsStream, StreamFactory, TcpStreamFactory, TlsStreamFactory};
+use crate::common::method::Method;
 use crate::common::request::Request;
 use crate::common::response::Response;
+use crate::common::status;
 use crate::common::version::HTTP_VERSION_1_1;
 use crate::parse::error::ParsingError;
 use crate::parse::parse::Parse;
 use crate::parse::parse::ParseStatus::{Done, IoErr};
 use crate::parse::response::ResponseParser;
+#[cfg(feature = "http2")]
+use crate::tls::default_alpn_protocols;
 use crate::util::stream;
 use crate::util::stream::{BufStream, StdBufStream, Stream};
 
@@ -29,6 +39,10 @@ pub enum RequestError {
     Reading(Error),
     /// Error sending the request to the server.
     Writing(Error),
+    /// The server reset the HTTP/2 stream of the request, closed the connection before the request was processed, or
+    /// violated the protocol.
+    #[cfg(feature = "http2")]
+    Http2(String),
 }
 
 impl From<ParsingError> for RequestError {
@@ -45,6 +59,24 @@ pub struct Client<S: Stream, F> {
     connections: Vec<Mutex<Connection<S>>>,
     /// Factory for spawning new streams to the server.
     stream_factory: F,
+    /// Whether the server supports HTTP/2, and the connection to it if it does.
+    #[cfg(feature = "http2")]
+    http2: Mutex<Http2State>,
+}
+
+/// An HTTP/2 connection over the halves of a split stream.
+#[cfg(feature = "http2")]
+type SplitHttp2Connection = Http2Connection<Box<dyn Read + Send>, Box<dyn Write + Send>>;
+
+/// What is known about HTTP/2 support of the server.
+#[cfg(feature = "http2")]
+enum Http2State {
+    /// No stream has been opened yet, so it is unknown whether the server supports HTTP/2.
+    Unknown,
+    /// The server does not support HTTP/2, so requests are sent over HTTP/1.1 connections.
+    Unsupported,
+    /// Requests are sent over a single HTTP/2 connection.
+    Connected(Arc<SplitHttp2Connection>),
 }
 
 impl Client<TcpStream, TcpStreamFactory> {
@@ -56,8 +88,17 @@ impl Client<TcpStream, TcpStreamFactory> {
 }
 
 impl Client<ClientTlsStream, TlsStreamFactory> {
-    /// Creates a new HTTP client.
-    pub fn new_https(config: Config, tls_config: ClientConfig) -> Client<ClientTlsStream, TlsStreamFactory> {
+    /// Creates a new HTTPS client. The TLS config is either a rustls ClientConfig or a ClientTlsConfig, which sets up
+    /// trusted CAs, client certificates, and pinning.
+    /// If the TLS config has no ALPN protocols, then HTTP/2 and HTTP/1.1 are offered when the http2 feature is enabled.
+    /// If the server picks HTTP/2, then all requests are multiplexed over a single connection.
+    pub fn new_https(config: Config, tls_config: impl Into<ClientConfig>) -> Client<ClientTlsStream, TlsStreamFactory> {
+        #[cfg_attr(not(feature = "http2"), allow(unused_mut))]
+        let mut tls_config = tls_config.into();
+        #[cfg(feature = "http2")]
+        if tls_config.alpn_protocols.is_empty() {
+            tls_config.set_protocols(&default_alpn_protocols());
+        }
         let factory = TlsStreamFactory::new(&config, tls_config);
         Self::new(config, factory)
     }
@@ -73,22 +114,87 @@ impl<S: Stream + 'static, F: StreamFactory<S>> Client<S, F> {
             connections.push(Mutex::new(Connection::new()))
         }
 
-        Client { connections, config, stream_factory }
+        Client {
+            connections,
+            config,
+            stream_factory,
+            #[cfg(feature = "http2")]
+            http2: Mutex::new(Http2State::Unknown),
+        }
     }
 }
 
 impl<S: Stream + 'static, F: StreamFactory<S>> Client<S, F> {
     /// Finds an unused connection to the server and makes a request. The connection will be locked until this method returns.
     /// If all connections are in use then this method will block until a connection is free.
-    /// Returns the returned response from the server or an error.
+    /// If the server picked HTTP/2, then the request is instead sent on a new stream of the single HTTP/2 connection.
+    /// Returns the returned response from the server or an error. Informational (1xx) responses from the server are skipped.
     pub fn send(&self, request: &Request) -> Result<Response, RequestError> {
+        self.send_with_informational(request, |_| {})
+    }
+
+    /// Like send, but informational (1xx) responses received before the final response, such as 103 early hints, are
+    /// passed to on_informational. A 101 switching protocols response is returned as the final response.
+    pub fn send_with_informational(&self, request: &Request, mut on_informational: impl FnMut(Response)) -> Result<Response, RequestError> {
+        #[cfg(feature = "http2")]
+        if self.stream_factory.offers_http2() {
+            if let Some(result) = self.send_http2(request, &mut on_informational) {
+                return result;
+            }
+        }
+
         loop {
             let mut free = self.connections.iter().filter_map(|conn| conn.try_lock().ok());
             if let Some(mut conn) = free.next() {
-                return conn.send(&self.stream_factory, request);
+                return conn.send(&self.stream_factory, request, &mut on_informational);
             }
         }
     }
+
+    /// Sends a request over the HTTP/2 connection to the server. If the connection fails before the response is
+    /// received, then a new connection is opened and the request is retried once, like over HTTP/1.1.
+    /// Returns None if the server does not support HTTP/2.
+    #[cfg(feature = "http2")]
+    fn send_http2(&self, request: &Request, on_informational: &mut dyn FnMut(Response)) -> Option<Result<Response, RequestError>> {
+        let mut retried = false;
+        loop {
+            let connection = match self.http2_connection() {
+                Ok(connection) => connection?,
+                Err(err) => return Some(Err(err)),
+            };
+            match connection.send(request, on_informational) {
+                Err(_) if !retried && !connection.is_open() => retried = true,
+                result => return Some(result),
+            }
+        }
+    }
+
+    /// Gets the HTTP/2 connection to the server, and opens a new one if there is none or it can no longer be used.
+    /// Returns None if the server did not pick HTTP/2, in which case the stream opened to find out is used by a
+    /// free HTTP/1.1 connection.
+    #[cfg(feature = "http2")]
+    fn http2_connection(&self) -> Result<Option<Arc<SplitHttp2Connection>>, RequestError> {
+        let mut http2 = self.http2.lock().unwrap();
+        match &*http2 {
+            Http2State::Unsupported => return Ok(None),
+            Http2State::Connected(connection) if connection.is_open() => return Ok(Some(connection.clone())),
+            _ => {}
+        }
+
+        let stream = self.stream_factory.create().map_err(Connecting)?;
+        if !self.stream_factory.negotiated_http2(&stream) {
+            *http2 = Http2State::Unsupported;
+            if let Some(mut conn) = self.connections.iter().find_map(|conn| conn.try_lock().ok()) {
+                conn.use_stream(stream);
+            }
+            return Ok(None);
+        }
+
+        let (reader, writer) = self.stream_factory.split(stream).map_err(Connecting)?;
+        let connection = Arc::new(Http2Connection::new(reader, writer, self.config.addr));
+        *http2 = Http2State::Connected(connection.clone());
+        Ok(Some(connection))
+    }
 }
 
 
@@ -107,15 +213,15 @@ impl<S: Stream + 'static> Connection<S> {
     /// If the connection is not yet open, then a new connection will be opened.
     /// If the request fails at any step (writing or reading), then a new connection is created and the entire request is retried.
     /// New connections are spawned using the given stream_factory argument.
-    fn send<F: StreamFactory<S>>(&mut self, stream_factory: &F, request: &Request) -> Result<Response, RequestError> {
+    fn send<F: StreamFactory<S>>(&mut self, stream_factory: &F, request: &Request, on_informational: &mut dyn FnMut(Response)) -> Result<Response, RequestError> {
         if self.stream.is_none() {
             self.connect(stream_factory)?;
         }
 
-        match send_request(self.stream.as_mut().unwrap(), request) {
+        match send_request(self.stream.as_mut().unwrap(), request, on_informational) {
             Err(_) => {
                 self.connect(stream_factory)?;
-                send_request(self.stream.as_mut().unwrap(), request)
+                send_request(self.stream.as_mut().unwrap(), request, on_informational)
             }
             x => x
         }
@@ -123,18 +229,34 @@ impl<S: Stream + 'static> Connection<S> {
 
     /// Opens a new connection to the server.
     fn connect<F: StreamFactory<S>>(&mut self, stream_factory: &F) -> Result<(), RequestError> {
-        let new_stream = stream_factory.create().map_err(|err| Connecting(err))?;
-        self.stream = Some(stream::with_buf_reader_and_writer(new_stream, BufReader::new, BufWriter::new));
+        let new_stream = stream_factory.create().map_err(Connecting)?;
+        self.use_stream(new_stream);
         Ok(())
     }
+
+    /// Replaces the stream to the server with the given stream.
+    fn use_stream(&mut self, stream: S) {
+        self.stream = Some(stream::with_buf_reader_and_writer(stream, BufReader::new, BufWriter::new));
+    }
 }
 
-/// Sends a request to the server and returns the response.
-fn send_request<T: BufStream>(stream: &mut T, request: &Request) -> Result<Response, RequestError> {
-    write_request(stream, request).map_err(|err| Writing(err))?;
+/// Sends a request to the server and returns the final response. Informational responses are passed to on_informational.
+fn send_request<T: BufStream>(stream: &mut T, request: &Request, on_informational: &mut dyn FnMut(Response)) -> Result<Response, RequestError> {
+    write_request(stream, request).map_err(Writing)?;
+
+    loop {
+        let response = read_response(stream, request.method == Method::HEAD)?;
+        if !response.status.is_informational() || response.status == status::SWITCHING_PROTOCOLS {
+            return Ok(response);
+        }
+        on_informational(response);
+    }
+}
 
-    let response_parser = ResponseParser::new();
-    match response_parser.parse(stream)? {
+/// Reads a single response from the given reader. head should be true if the response is to a HEAD request.
+fn read_response(reader: &mut impl BufRead, head: bool) -> Result<Response, RequestError> {
+    let response_parser = if head { ResponseParser::for_head_request() } else { ResponseParser::new() };
+    match response_parser.parse(reader)? {
         Done(response) => Ok(response),
         IoErr(_, err) => Err(Reading(err))
     }
@@ -156,19 +278,22 @@ pub fn write_request(writer: &mut impl Write, request: &Request) -> std::io::Res
 
 #[cfg(test)]
 mod tests {
-    use std::io::{Error, ErrorKind};
+    use std::io::{BufReader, Error, ErrorKind};
     use std::net::TcpStream;
     use std::sync::Arc;
     use std::thread::spawn;
     use std::time::Duration;
 
     use crate::client::{Client, Config, write_request};
+    use crate::client::client::send_request;
     use crate::client::stream_factory::StreamFactory;
-    use crate::common::header::CONTENT_TYPE;
+    use crate::common::header::{CONTENT_LENGTH, CONTENT_TYPE, LINK};
     use crate::common::method::Method;
     use crate::common::request::Request;
+    use crate::common::response::Response;
+    use crate::common::status;
     use crate::header_map;
-    use crate::util::mock::MockWriter;
+    use crate::util::mock::{MockReader, MockStream, MockWriter};
 
     struct MockFactory;
 
@@ -185,6 +310,7 @@ mod tests {
             addr: "localhost:7878",
             read_timeout: Duration::from_millis(10),
             num_connections: 0,
+            server_name: None,
         }, MockFactory);
     }
 
@@ -247,6 +373,7 @@ mod tests {
             addr: "0.0.0.0:9000",
             read_timeout: Duration::from_secs(1),
             num_connections: 5,
+            server_name: None,
         }, MockFactory);
 
         let client = Arc::new(client);
@@ -269,4 +396,34 @@ mod tests {
             handler.join().unwrap();
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn send_request_skips_informational_responses() {
+        let reader = MockReader::from_strs(vec![
+            "HTTP/1.1 102 Processing\r\n\r\n",
+            "HTTP/1.1 103 Early Hints\r\nlink: </style.css>; rel=preload\r\n\r\n",
+            "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nhi",
+        ]);
+        let mut stream = MockStream::new(BufReader::new(reader), MockWriter::new());
+
+        let mut informational = vec![];
+        let response = send_request(&mut stream, &Request::builder().build().unwrap(), &mut |response| informational.push(response)).unwrap();
+
+        assert_eq!(response, Response { status: status::OK, headers: header_map![(CONTENT_LENGTH, "2")], body: b"hi".to_vec() });
+        assert_eq!(informational, vec![
+            Response { status: status::PROCESSING, headers: header_map![], body: vec![] },
+            Response { status: status::EARLY_HINTS, headers: header_map![(LINK, "</style.css>; rel=preload")], body: vec![] },
+        ]);
+    }
+
+    #[test]
+    fn send_request_head() {
+        let reader = MockReader::from_strs(vec!["HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n"]);
+        let mut stream = MockStream::new(BufReader::new(reader), MockWriter::new());
+
+        let request = Request::builder().method(Method::HEAD).build().unwrap();
+        let response = send_request(&mut stream, &request, &mut |_| {}).unwrap();
+
+        assert_eq!(response, Response { status: status::OK, headers: header_map![(CONTENT_LENGTH, "2")], body: vec![] });
+    }
+}
@@ -8,4 +8,8 @@ pub struct Config {
     pub read_timeout: Duration,
     /// The number of connections to open to the server.
     pub num_connections: usize,
+    /// The hostname sent with SNI and used to verify the certificate of an HTTPS server. Defaults to the host of the
+    /// address if None. Must be set to connect to an HTTPS server by IP address, since rustls only supports
+    /// verifying DNS names.
+    pub server_name: Option<&'static str>,
 }
\ No newline at end of file
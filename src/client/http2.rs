@@ -0,0 +1,915 @@
+use std::collections::BTreeMap;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+use crate::client::RequestError;
+use crate::client::RequestError::{Reading, ResponseParsing, Writing};
+use crate::common::header::{Header, HeaderMap, HeaderMapOps, HOST};
+use crate::common::method::Method;
+use crate::common::request::Request;
+use crate::common::response::Response;
+use crate::common::status::Status;
+use crate::common::typed_header::{ContentLength, TypedHeader};
+use crate::http2::{CONNECTION_SPECIFIC_HEADERS, PREFACE, WINDOW_UPDATE_THRESHOLD};
+use crate::http2::error::{CANCEL, COMPRESSION_ERROR, ENHANCE_YOUR_CALM, ErrorCode, FLOW_CONTROL_ERROR, Http2Error, NO_ERROR, PROTOCOL_ERROR};
+use crate::http2::frame::{encode_data, encode_header_block, Frame};
+use crate::http2::hpack::{Decoder, Encoder};
+use crate::http2::settings::{DEFAULT_MAX_FRAME_SIZE, DEFAULT_WINDOW_SIZE, Settings};
+use crate::http2::window::Window;
+use crate::parse::body::MAX_BODY_SIZE;
+use crate::parse::error::ParsingError;
+use crate::parse::frame::FrameParser;
+use crate::parse::parse::Parse;
+use crate::parse::parse::ParseStatus::{Done, IoErr};
+
+/// The largest header list accepted in a response.
+const MAX_HEADER_LIST_SIZE: u32 = 65536;
+
+/// The largest stream ID. A connection whose stream IDs have run out can not send more requests.
+const MAX_STREAM_ID: u32 = 0x7fffffff;
+
+/// An HTTP/2 connection to a server, which is shared by all requests sent with the client.
+/// Each request is sent on its own stream. One of the threads waiting for a response reads frames for all streams of
+/// the connection, while the others wait to be notified of the frames it has handled. The state is not locked while
+/// reading, so requests can be sent while a response is being waited for.
+pub(crate) struct Http2Connection<R: Read, W: Write> {
+    state: Mutex<State<W>>,
+    reader: Mutex<FrameReader<R>>,
+    /// Notified when a frame has been handled, or when the thread reading frames has stopped.
+    progress: Condvar,
+}
+
+/// Reads frames from the server. Only one thread reads at a time.
+struct FrameReader<R: Read> {
+    reader: BufReader<R>,
+    parser: Option<FrameParser>,
+}
+
+/// The state of a connection, which is locked while frames are handled or written.
+struct State<W: Write> {
+    writer: BufWriter<W>,
+    /// Whether a thread is reading a frame.
+    reading: bool,
+    decoder: Decoder,
+    encoder: Encoder,
+    /// The settings sent by the server.
+    remote_settings: Settings,
+    /// The authority of requests without a host header.
+    authority: &'static str,
+    /// The streams whose results have not been taken by the threads that sent their requests.
+    streams: BTreeMap<u32, RequestStream>,
+    /// The ID of the next stream, which is always odd.
+    next_stream_id: u32,
+    /// The connection window for sending data.
+    send_window: Window,
+    /// The connection window for receiving data.
+    recv_window: Window,
+    /// The amount of data received on the connection that has not been acknowledged with a WINDOW_UPDATE frame.
+    recv_unacknowledged: usize,
+    /// The header block being received in CONTINUATION frames, if any.
+    header_block: Option<HeaderBlock>,
+    /// The largest stream ID the server will process, if it sent a GOAWAY frame.
+    going_away: Option<u32>,
+    /// Whether the connection has been closed because of an error.
+    closed: bool,
+    /// Encoded frames that have not been written to the connection yet.
+    out: Vec<u8>,
+}
+
+/// A header block that is split over multiple frames.
+struct HeaderBlock {
+    stream_id: u32,
+    /// Whether the HEADERS frame that started the block ended the stream.
+    end_stream: bool,
+    block: Vec<u8>,
+}
+
+/// A stream that carries one request and its response.
+struct RequestStream {
+    /// Whether the request is a HEAD request, so the response has no body.
+    head: bool,
+    send_window: Window,
+    /// Request body data waiting for the flow-control windows. Data before the sent offset has already been sent.
+    pending: Vec<u8>,
+    sent: usize,
+    /// Whether a frame that ends the stream has been sent.
+    sent_end: bool,
+    recv_window: Window,
+    /// The amount of data received on the stream that has not been acknowledged with a WINDOW_UPDATE frame.
+    recv_unacknowledged: usize,
+    /// Informational responses that have not been passed on yet.
+    informational: Vec<Response>,
+    /// The final response whose body is being received, once its head has been received.
+    response: Option<Response>,
+    /// The result of the request, once the response is complete or the stream has failed.
+    result: Option<Result<Response, RequestError>>,
+}
+
+impl<R: Read, W: Write> Http2Connection<R, W> {
+    /// Creates a connection over the given halves of a stream, on which HTTP/2 has been negotiated. The connection
+    /// preface is sent along with the first request. Requests without a host header are sent with the given authority.
+    pub fn new(reader: R, writer: W, authority: &'static str) -> Http2Connection<R, W> {
+        let local_settings = Settings {
+            enable_push: false,
+            max_header_list_size: Some(MAX_HEADER_LIST_SIZE),
+            ..Default::default()
+        };
+        let mut out = PREFACE.to_vec();
+        Frame::Settings { ack: false, params: local_settings.changes() }.encode(&mut out);
+
+        let state = State {
+            writer: BufWriter::new(writer),
+            reading: false,
+            decoder: Decoder::new(MAX_HEADER_LIST_SIZE as usize),
+            encoder: Encoder::new(),
+            remote_settings: Settings::default(),
+            authority,
+            streams: BTreeMap::new(),
+            next_stream_id: 1,
+            send_window: Window::new(DEFAULT_WINDOW_SIZE),
+            recv_window: Window::new(DEFAULT_WINDOW_SIZE),
+            recv_unacknowledged: 0,
+            header_block: None,
+            going_away: None,
+            closed: false,
+            out,
+        };
+        Http2Connection {
+            state: Mutex::new(state),
+            reader: Mutex::new(FrameReader { reader: BufReader::new(reader), parser: None }),
+            progress: Condvar::new(),
+        }
+    }
+
+    /// Checks if new requests can be sent on the connection. Connections that failed, that the server is closing, or
+    /// that ran out of stream IDs can not be used for new requests.
+    pub fn is_open(&self) -> bool {
+        self.state.lock().map(|state| state.is_open()).unwrap_or(false)
+    }
+
+    /// Sends a request on a new stream and returns the final response. Informational responses are passed to
+    /// on_informational. If the server already has as many streams open as its settings allow, then this method blocks
+    /// until one of them ends.
+    pub fn send(&self, request: &Request, on_informational: &mut dyn FnMut(Response)) -> Result<Response, RequestError> {
+        let stream_id = {
+            let state = self.state.lock().unwrap();
+            let mut state = self.wait(state, |state| !state.is_open() || state.open_streams() < state.max_concurrent_streams()).map_err(Reading)?;
+            if !state.is_open() {
+                return Err(Writing(Error::from(ErrorKind::NotConnected)));
+            }
+            state.open_stream(request)
+        };
+
+        loop {
+            let state = self.state.lock().unwrap();
+            let waited = self.wait(state, |state| {
+                let stream = &state.streams[&stream_id];
+                stream.result.is_some() || !stream.informational.is_empty()
+            });
+            let (mut state, result) = match waited {
+                Ok(mut state) => {
+                    let stream = state.streams.get_mut(&stream_id).unwrap();
+                    let result = stream.result.take();
+                    (state, result)
+                }
+                Err(error) => {
+                    let mut state = self.state.lock().unwrap();
+                    state.reset_stream(stream_id, CANCEL);
+                    (state, Some(Err(Reading(error))))
+                }
+            };
+            let informational = std::mem::take(&mut state.streams.get_mut(&stream_id).unwrap().informational);
+            if result.is_some() {
+                state.streams.remove(&stream_id);
+                state.write();
+            }
+            // the lock is not held while informational responses are passed on, so they can send requests
+            drop(state);
+
+            informational.into_iter().for_each(&mut *on_informational);
+            if let Some(result) = result {
+                return result;
+            }
+        }
+    }
+
+    /// Waits until the given condition holds for the state, and sends what can be sent in the meantime. If no other
+    /// thread is reading frames, then this thread reads them while waiting. Returns an error if reading timed out.
+    fn wait<'a>(&'a self, mut state: MutexGuard<'a, State<W>>, done: impl Fn(&State<W>) -> bool) -> std::io::Result<MutexGuard<'a, State<W>>> {
+        loop {
+            if done(&state) {
+                return Ok(state);
+            }
+            state.send_pending();
+            state.write();
+            if state.reading {
+                state = self.progress.wait(state).unwrap();
+                continue;
+            }
+
+            state.reading = true;
+            drop(state);
+            let frame = self.reader.lock().unwrap().read_frame();
+            state = self.state.lock().unwrap();
+            state.reading = false;
+            self.progress.notify_all();
+            state.handle_read(frame)?;
+        }
+    }
+
+    /// Reads and handles a single frame. Returns an error if reading timed out.
+    #[cfg(test)]
+    fn read_frame(&self) -> std::io::Result<()> {
+        let frame = self.reader.lock().unwrap().read_frame();
+        self.state.lock().unwrap().handle_read(frame)
+    }
+}
+
+impl<R: Read> FrameReader<R> {
+    /// Reads a single frame. A frame that is only partially read when reading times out is continued on the next call.
+    fn read_frame(&mut self) -> std::io::Result<Result<Frame, Http2Error>> {
+        let parser = self.parser.take().unwrap_or_else(|| FrameParser::new(DEFAULT_MAX_FRAME_SIZE));
+        match parser.parse(&mut self.reader) {
+            Ok(Done(frame)) => Ok(frame),
+            Ok(IoErr(parser, error)) => {
+                if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) {
+                    self.parser = Some(parser);
+                }
+                Err(error)
+            }
+            Err(_) => Ok(Err(Http2Error::Connection(PROTOCOL_ERROR, "invalid frame"))),
+        }
+    }
+}
+
+impl<W: Write> State<W> {
+    /// Checks if new streams can be opened on the connection.
+    fn is_open(&self) -> bool {
+        !self.closed && self.going_away.is_none() && self.next_stream_id <= MAX_STREAM_ID
+    }
+
+    /// Gets the number of streams the server allows to be open at once.
+    fn max_concurrent_streams(&self) -> usize {
+        self.remote_settings.max_concurrent_streams.unwrap_or(u32::MAX) as usize
+    }
+
+    /// Gets the number of streams whose responses have not been received yet.
+    fn open_streams(&self) -> usize {
+        self.streams.values().filter(|stream| stream.result.is_none()).count()
+    }
+
+    /// Opens a new stream for the given request, and queues the head of the request. The body is sent as the
+    /// flow-control windows allow. Returns the ID of the new stream.
+    fn open_stream(&mut self, request: &Request) -> u32 {
+        let stream_id = self.next_stream_id;
+        self.next_stream_id += 2;
+
+        let method = request.method.to_string();
+        let authority = request.headers.get_first_header_value(&HOST).map(String::as_str).unwrap_or(self.authority);
+        let mut fields = vec![(":method", method.as_str()), (":scheme", "https"), (":authority", authority), (":path", request.uri.as_str())];
+        fields.extend(request_fields(&request.headers));
+        let mut block = vec![];
+        self.encoder.encode(fields, &mut block);
+
+        let end_stream = request.body.is_empty();
+        encode_header_block(&mut self.out, stream_id, &block, end_stream, self.remote_settings.max_frame_size as usize);
+        self.streams.insert(stream_id, RequestStream {
+            head: request.method == Method::HEAD,
+            send_window: Window::new(self.remote_settings.initial_window_size),
+            pending: request.body.clone(),
+            sent: 0,
+            sent_end: end_stream,
+            recv_window: Window::new(DEFAULT_WINDOW_SIZE),
+            recv_unacknowledged: 0,
+            informational: vec![],
+            response: None,
+            result: None,
+        });
+        stream_id
+    }
+
+    /// Handles the result of reading a single frame. Returns an error if reading timed out. If the connection fails,
+    /// then it is closed and the requests of all open streams fail.
+    fn handle_read(&mut self, frame: std::io::Result<Result<Frame, Http2Error>>) -> std::io::Result<()> {
+        let result = match frame {
+            Ok(frame) => frame.and_then(|frame| self.handle_frame(frame)),
+            Err(error) if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => return Err(error),
+            Err(error) => {
+                self.close(|| Reading(Error::from(error.kind())));
+                return Ok(());
+            }
+        };
+
+        match result {
+            Ok(()) => {}
+            Err(Http2Error::Stream(stream_id, code)) => self.fail_stream(stream_id, code, RequestError::Http2(format!("response violated HTTP/2 with {}", code))),
+            Err(Http2Error::Connection(code, reason)) => {
+                Frame::GoAway { last_stream_id: 0, error: code, debug_data: reason.as_bytes().to_vec() }.encode(&mut self.out);
+                self.write();
+                self.close(|| RequestError::Http2(format!("server violated HTTP/2: {}", reason)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a frame received from the server.
+    fn handle_frame(&mut self, frame: Frame) -> Result<(), Http2Error> {
+        if self.header_block.is_some() && !matches!(frame, Frame::Continuation { .. }) {
+            return Err(Http2Error::Connection(PROTOCOL_ERROR, "header block interrupted by another frame"));
+        }
+
+        match frame {
+            Frame::Data { stream_id, data, end_stream, padding } => self.handle_data(stream_id, data, end_stream, padding),
+            Frame::Headers { stream_id, block, end_stream, end_headers, .. } => {
+                self.header_block = Some(HeaderBlock { stream_id, end_stream, block });
+                self.end_header_block_if(end_headers)
+            }
+            Frame::Continuation { stream_id, block, end_headers } => {
+                match &mut self.header_block {
+                    Some(header_block) if header_block.stream_id == stream_id => header_block.block.extend_from_slice(&block),
+                    _ => return Err(Http2Error::Connection(PROTOCOL_ERROR, "unexpected CONTINUATION frame")),
+                }
+                self.end_header_block_if(end_headers)
+            }
+            Frame::Priority { stream_id, priority } if priority.dependency == stream_id => Err(Http2Error::Stream(stream_id, PROTOCOL_ERROR)),
+            Frame::Priority { .. } => Ok(()),
+            Frame::RstStream { stream_id, .. } if self.is_idle(stream_id) => Err(Http2Error::Connection(PROTOCOL_ERROR, "RST_STREAM frame on idle stream")),
+            Frame::RstStream { stream_id, error } => {
+                if let Some(stream) = self.streams.get_mut(&stream_id) {
+                    stream.sent_end = true;
+                    if stream.result.is_none() {
+                        stream.result = Some(Err(RequestError::Http2(format!("stream reset by server with {}", error))));
+                    }
+                }
+                Ok(())
+            }
+            Frame::Settings { ack: true, .. } => Ok(()),
+            Frame::Settings { ack: false, params } => {
+                let old_window_size = self.remote_settings.initial_window_size;
+                self.remote_settings.apply(&params)?;
+                let delta = self.remote_settings.initial_window_size as i64 - old_window_size as i64;
+                for stream in self.streams.values_mut() {
+                    stream.send_window.increase(delta, 0)?;
+                }
+                self.encoder.set_max_table_size(self.remote_settings.header_table_size as usize);
+                Frame::Settings { ack: true, params: vec![] }.encode(&mut self.out);
+                Ok(())
+            }
+            Frame::PushPromise { .. } => Err(Http2Error::Connection(PROTOCOL_ERROR, "push is disabled")),
+            Frame::Ping { ack: true, .. } => Ok(()),
+            Frame::Ping { ack: false, data } => {
+                Frame::Ping { ack: true, data }.encode(&mut self.out);
+                Ok(())
+            }
+            Frame::GoAway { last_stream_id, error, .. } => {
+                self.going_away = Some(last_stream_id);
+                // the requests on streams the server will not process were not handled, so they can be sent again
+                for (_, stream) in self.streams.range_mut(last_stream_id + 1..) {
+                    if stream.result.is_none() {
+                        stream.sent_end = true;
+                        stream.result = Some(Err(RequestError::Http2(format!("connection closed by server with {}", error))));
+                    }
+                }
+                Ok(())
+            }
+            Frame::WindowUpdate { stream_id: 0, increment } => self.send_window.increase(increment as i64, 0),
+            Frame::WindowUpdate { stream_id, .. } if self.is_idle(stream_id) => Err(Http2Error::Connection(PROTOCOL_ERROR, "WINDOW_UPDATE frame on idle stream")),
+            Frame::WindowUpdate { stream_id, increment } => match self.streams.get_mut(&stream_id) {
+                Some(stream) => stream.send_window.increase(increment as i64, stream_id),
+                None => Ok(()),
+            },
+            Frame::Unknown { .. } => Ok(()),
+        }
+    }
+
+    /// Checks if the given stream has not been opened by the client.
+    fn is_idle(&self, stream_id: u32) -> bool {
+        stream_id % 2 == 0 || stream_id >= self.next_stream_id
+    }
+
+    /// Ends the header block being received if end_headers is true, or checks that it is not too large to decode
+    /// otherwise.
+    fn end_header_block_if(&mut self, end_headers: bool) -> Result<(), Http2Error> {
+        if end_headers {
+            return self.end_header_block();
+        }
+        match &self.header_block {
+            Some(header_block) if header_block.block.len() > MAX_HEADER_LIST_SIZE as usize =>
+                Err(Http2Error::Connection(ENHANCE_YOUR_CALM, "header block too large")),
+            _ => Ok(()),
+        }
+    }
+
+    /// Decodes the header block that has been fully received, which is either the head of a response or the trailers
+    /// of a response.
+    fn end_header_block(&mut self) -> Result<(), Http2Error> {
+        let HeaderBlock { stream_id, end_stream, block } = self.header_block.take().unwrap();
+        // the block is decoded even if its stream is closed, so the dynamic table stays in sync with the server
+        let fields = self.decoder.decode(&block).map_err(|_| Http2Error::Connection(COMPRESSION_ERROR, "invalid header block"))?;
+
+        if self.is_idle(stream_id) {
+            return Err(Http2Error::Connection(PROTOCOL_ERROR, "HEADERS frame on idle stream"));
+        }
+        let stream = match self.streams.get_mut(&stream_id) {
+            Some(stream) if stream.result.is_none() => stream,
+            // the stream was reset or its response was given up on, and the server may not have noticed yet
+            _ => return Ok(()),
+        };
+
+        if stream.response.is_some() {
+            // trailers, which are not added to the response
+            return if end_stream { self.end_response(stream_id) } else { Err(Http2Error::Stream(stream_id, PROTOCOL_ERROR)) };
+        }
+
+        let response = match build_response(fields) {
+            Ok(response) => response,
+            Err(error) => {
+                self.fail_stream(stream_id, PROTOCOL_ERROR, ResponseParsing(error));
+                return Ok(());
+            }
+        };
+        if response.status.is_informational() {
+            if end_stream {
+                return Err(Http2Error::Stream(stream_id, PROTOCOL_ERROR));
+            }
+            stream.informational.push(response);
+            return Ok(());
+        }
+
+        stream.response = Some(response);
+        if end_stream {
+            return self.end_response(stream_id);
+        }
+        Ok(())
+    }
+
+    /// Handles a DATA frame, which carries part of the body of a response.
+    fn handle_data(&mut self, stream_id: u32, data: Vec<u8>, end_stream: bool, padding: usize) -> Result<(), Http2Error> {
+        // the whole frame counts against flow control, even if its stream is closed
+        let length = data.len() + padding;
+        if !self.recv_window.consume(length) {
+            return Err(Http2Error::Connection(FLOW_CONTROL_ERROR, "connection window exceeded"));
+        }
+        self.recv_unacknowledged += length;
+        if self.recv_unacknowledged >= WINDOW_UPDATE_THRESHOLD {
+            Frame::WindowUpdate { stream_id: 0, increment: self.recv_unacknowledged as u32 }.encode(&mut self.out);
+            self.recv_window.increase(self.recv_unacknowledged as i64, 0)?;
+            self.recv_unacknowledged = 0;
+        }
+
+        if self.is_idle(stream_id) {
+            return Err(Http2Error::Connection(PROTOCOL_ERROR, "DATA frame on idle stream"));
+        }
+        let stream = match self.streams.get_mut(&stream_id) {
+            Some(stream) if stream.result.is_none() => stream,
+            _ => return Ok(()),
+        };
+        if !stream.recv_window.consume(length) {
+            return Err(Http2Error::Stream(stream_id, FLOW_CONTROL_ERROR));
+        }
+        let response = match &mut stream.response {
+            Some(response) => response,
+            None => return Err(Http2Error::Stream(stream_id, PROTOCOL_ERROR)),
+        };
+        if response.body.len() + data.len() > MAX_BODY_SIZE {
+            self.fail_stream(stream_id, CANCEL, ResponseParsing(ParsingError::ContentLengthTooLarge));
+            return Ok(());
+        }
+        response.body.extend_from_slice(&data);
+
+        if end_stream {
+            return self.end_response(stream_id);
+        }
+
+        stream.recv_unacknowledged += length;
+        if stream.recv_unacknowledged >= WINDOW_UPDATE_THRESHOLD {
+            Frame::WindowUpdate { stream_id, increment: stream.recv_unacknowledged as u32 }.encode(&mut self.out);
+            stream.recv_window.increase(stream.recv_unacknowledged as i64, stream_id)?;
+            stream.recv_unacknowledged = 0;
+        }
+        Ok(())
+    }
+
+    /// Completes the response of the given stream once the server has ended the stream. If the request body has not
+    /// been sent completely, then the rest of it is not sent.
+    fn end_response(&mut self, stream_id: u32) -> Result<(), Http2Error> {
+        let stream = self.streams.get_mut(&stream_id).unwrap();
+        let mut response = stream.response.take().unwrap();
+
+        if let Some(Ok(ContentLength(length))) = ContentLength::decode(&response.headers) {
+            if !stream.head && length != response.body.len() as u64 {
+                return Err(Http2Error::Stream(stream_id, PROTOCOL_ERROR));
+            }
+        }
+        if stream.head {
+            response.body.clear();
+        }
+
+        stream.result = Some(Ok(response));
+        if !stream.sent_end {
+            stream.sent_end = true;
+            Frame::RstStream { stream_id, error: NO_ERROR }.encode(&mut self.out);
+        }
+        Ok(())
+    }
+
+    /// Sends as much of the pending request bodies as the flow-control windows allow, in order of stream IDs.
+    fn send_pending(&mut self) {
+        let max_frame_size = self.remote_settings.max_frame_size as usize;
+        for (&stream_id, stream) in self.streams.iter_mut() {
+            while !stream.sent_end {
+                let remaining = &stream.pending[stream.sent..];
+                let amount = remaining.len().min(stream.send_window.available()).min(self.send_window.available()).min(max_frame_size);
+                let end_stream = amount == remaining.len();
+                if amount == 0 && !end_stream {
+                    break;
+                }
+
+                encode_data(&mut self.out, stream_id, &remaining[..amount], end_stream);
+                stream.send_window.consume(amount);
+                self.send_window.consume(amount);
+                stream.sent += amount;
+                stream.sent_end = end_stream;
+            }
+            if stream.sent_end {
+                stream.pending = vec![];
+            }
+        }
+    }
+
+    /// Writes the queued frames to the connection. If writing fails, then the connection is closed.
+    fn write(&mut self) {
+        if self.out.is_empty() || self.closed {
+            return;
+        }
+        let written = self.writer.write_all(&self.out).and_then(|_| self.writer.flush());
+        self.out.clear();
+        if let Err(error) = written {
+            self.close(|| Writing(Error::from(error.kind())));
+        }
+    }
+
+    /// Resets the given stream with the given error code, and fails its request with the given error.
+    fn fail_stream(&mut self, stream_id: u32, code: ErrorCode, error: RequestError) {
+        if let Some(stream) = self.streams.get_mut(&stream_id) {
+            if stream.result.is_none() {
+                stream.result = Some(Err(error));
+            }
+        }
+        self.reset_stream(stream_id, code);
+    }
+
+    /// Resets the given stream with the given error code.
+    fn reset_stream(&mut self, stream_id: u32, code: ErrorCode) {
+        Frame::RstStream { stream_id, error: code }.encode(&mut self.out);
+        if let Some(stream) = self.streams.get_mut(&stream_id) {
+            stream.sent_end = true;
+        }
+    }
+
+    /// Closes the connection, and fails the requests of all open streams with errors created by the given function.
+    fn close(&mut self, error: impl Fn() -> RequestError) {
+        self.closed = true;
+        self.out.clear();
+        for stream in self.streams.values_mut() {
+            if stream.result.is_none() {
+                stream.result = Some(Err(error()));
+            }
+        }
+    }
+}
+
+/// Gets the header fields of the given request headers that are allowed in HTTP/2 requests. The host header is sent as
+/// the :authority pseudo-header field instead.
+fn request_fields(headers: &HeaderMap) -> impl Iterator<Item=(&str, &str)> {
+    headers.iter()
+        .filter(|(header, _)| **header != HOST && !CONNECTION_SPECIFIC_HEADERS.contains(&header.as_str()))
+        .flat_map(|(header, values)| values.iter().map(move |value| (header.as_str(), value.as_str())))
+}
+
+/// Builds a response from the fields of a header block, which start with the :status pseudo-header field. The body
+/// of the response is received in DATA frames.
+fn build_response(fields: Vec<(String, String)>) -> Result<Response, ParsingError> {
+    let mut fields = fields.into_iter();
+    let status = match fields.next() {
+        Some((name, value)) if name == ":status" => value,
+        _ => return Err(ParsingError::BadSyntax),
+    };
+    let status = status.parse().ok().and_then(Status::from_code).ok_or(ParsingError::InvalidStatusCode)?;
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in fields {
+        if name.starts_with(':') || name.bytes().any(|b| b.is_ascii_uppercase()) || CONNECTION_SPECIFIC_HEADERS.contains(&name.as_str()) {
+            return Err(ParsingError::BadSyntax);
+        }
+        headers.add_header(Header::from(name), value);
+    }
+
+    Ok(Response { status, headers, body: vec![] })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::{BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::rc::Rc;
+    use std::sync::{Arc, mpsc};
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::client::RequestError;
+    use crate::client::http2::Http2Connection;
+    use crate::common::header::{CONTENT_LENGTH, LINK};
+    use crate::common::method::Method;
+    use crate::common::request::Request;
+    use crate::common::response::Response;
+    use crate::common::status;
+    use crate::header_map;
+    use crate::http2::error::{CANCEL, NO_ERROR, REFUSED_STREAM};
+    use crate::http2::frame::{encode_header_block, Frame};
+    use crate::http2::hpack::{Decoder, Encoder};
+    use crate::http2::PREFACE;
+    use crate::http2::settings::{ENABLE_PUSH, MAX_CONCURRENT_STREAMS, MAX_HEADER_LIST_SIZE};
+    use crate::parse::frame::FrameParser;
+    use crate::parse::parse::Parse;
+    use crate::parse::parse::ParseStatus::Done;
+    use crate::util::mock::{MockReader, MockWriter};
+
+    type MockConnection = Http2Connection<MockReader, MockWriter>;
+
+    /// Creates a connection whose server sends the given input, followed by WouldBlock errors. Returns the connection
+    /// and the data written to the server by each flush.
+    fn connection(input: Vec<u8>) -> (MockConnection, Rc<RefCell<Vec<Vec<u8>>>>) {
+        let chunks = if input.is_empty() { vec![] } else { vec![input.as_slice()] };
+        let mut reader = MockReader::from_bytes(chunks);
+        reader.return_would_block_when_empty = true;
+        let writer = MockWriter::new();
+        let flushed = writer.flushed.clone();
+        (Http2Connection::new(reader, writer, "localhost"), flushed)
+    }
+
+    /// Parses the given output of a connection, which starts with the connection preface.
+    fn frames(output: &[u8]) -> Vec<Frame> {
+        assert!(output.starts_with(PREFACE));
+        let mut output = &output[PREFACE.len()..];
+        let mut frames = vec![];
+        while let Ok(Done(frame)) = FrameParser::new((1 << 24) - 1).parse(&mut output) {
+            frames.push(frame.unwrap());
+        }
+        frames
+    }
+
+    fn encode(frames: &[Frame]) -> Vec<u8> {
+        let mut out = vec![];
+        for frame in frames {
+            frame.encode(&mut out);
+        }
+        out
+    }
+
+    fn headers(encoder: &mut Encoder, stream_id: u32, fields: &[(&str, &str)], end_stream: bool) -> Vec<u8> {
+        let mut block = vec![];
+        encoder.encode(fields.iter().copied(), &mut block);
+        let mut out = vec![];
+        encode_header_block(&mut out, stream_id, &block, end_stream, 16384);
+        out
+    }
+
+    fn data(stream_id: u32, data: &[u8], end_stream: bool) -> Frame {
+        Frame::Data { stream_id, data: data.to_vec(), end_stream, padding: 0 }
+    }
+
+    fn fields(fields: &[(&str, &str)]) -> Vec<(String, String)> {
+        fields.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn get_request() {
+        let mut encoder = Encoder::new();
+        let mut input = encode(&[Frame::Settings { ack: false, params: vec![] }]);
+        input.extend(headers(&mut encoder, 1, &[(":status", "200"), ("content-length", "2")], false));
+        input.extend(encode(&[data(1, b"hi", true)]));
+        let (connection, flushed) = connection(input);
+
+        let request = Request { uri: "/hello".to_string(), method: Method::GET, headers: header_map![("connection", "keep-alive"), ("custom", "value")], body: vec![] };
+        let response = connection.send(&request, &mut |_| panic!("no informational responses")).unwrap();
+
+        assert_eq!(response, Response { status: status::OK, headers: header_map![(CONTENT_LENGTH, "2")], body: b"hi".to_vec() });
+        let frames = frames(&flushed.borrow().concat());
+        assert_eq!(frames[0], Frame::Settings { ack: false, params: vec![(ENABLE_PUSH, 0), (MAX_HEADER_LIST_SIZE, 65536)] });
+        match &frames[1] {
+            Frame::Headers { stream_id: 1, block, end_stream: true, end_headers: true, .. } =>
+                assert_eq!(Decoder::new(65536).decode(block).unwrap(), fields(&[
+                    (":method", "GET"), (":scheme", "https"), (":authority", "localhost"), (":path", "/hello"), ("custom", "value")
+                ])),
+            frame => panic!("expected HEADERS frame, got {:?}", frame),
+        }
+        assert_eq!(frames[2], Frame::Settings { ack: true, params: vec![] });
+        assert!(connection.is_open());
+    }
+
+    #[test]
+    fn host_header_is_sent_as_authority() {
+        let mut encoder = Encoder::new();
+        let (connection, flushed) = connection(headers(&mut encoder, 1, &[(":status", "204")], true));
+
+        let request = Request { uri: "/".to_string(), method: Method::GET, headers: header_map![("host", "example.com")], body: vec![] };
+        connection.send(&request, &mut |_| {}).unwrap();
+
+        let frames = frames(&flushed.borrow().concat());
+        match &frames[1] {
+            Frame::Headers { block, .. } => assert_eq!(Decoder::new(65536).decode(block).unwrap(), fields(&[
+                (":method", "GET"), (":scheme", "https"), (":authority", "example.com"), (":path", "/")
+            ])),
+            frame => panic!("expected HEADERS frame, got {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn informational_responses() {
+        let mut encoder = Encoder::new();
+        let mut input = headers(&mut encoder, 1, &[(":status", "103"), ("link", "</style.css>; rel=preload")], false);
+        input.extend(headers(&mut encoder, 1, &[(":status", "200")], true));
+        let (connection, _) = connection(input);
+
+        let mut informational = vec![];
+        let response = connection.send(&Request::builder().build().unwrap(), &mut |response| informational.push(response)).unwrap();
+
+        assert_eq!(response, Response { status: status::OK, headers: header_map![], body: vec![] });
+        assert_eq!(informational, vec![
+            Response { status: status::EARLY_HINTS, headers: header_map![(LINK, "</style.css>; rel=preload")], body: vec![] }
+        ]);
+    }
+
+    #[test]
+    fn request_body_waits_for_flow_control() {
+        let mut encoder = Encoder::new();
+        let mut input = encode(&[
+            Frame::WindowUpdate { stream_id: 0, increment: 10000 },
+            Frame::WindowUpdate { stream_id: 1, increment: 10000 },
+        ]);
+        input.extend(headers(&mut encoder, 1, &[(":status", "200")], true));
+        let (connection, flushed) = connection(input);
+
+        let request = Request { uri: "/".to_string(), method: Method::POST, headers: header_map![], body: vec![1; 70000] };
+        connection.send(&request, &mut |_| {}).unwrap();
+
+        let sent_data = |frames: &[Frame]| frames.iter().map(|frame| match frame {
+            Frame::Data { data, .. } => data.len(),
+            _ => 0,
+        }).sum::<usize>();
+        let flushed = flushed.borrow();
+        assert_eq!(sent_data(&frames(&flushed[0])), 65535);
+        let frames = frames(&flushed.concat());
+        assert_eq!(sent_data(&frames), 70000);
+        assert!(matches!(frames.last().unwrap(), Frame::Data { stream_id: 1, end_stream: true, .. }));
+    }
+
+    #[test]
+    fn response_before_request_body_is_sent() {
+        let mut encoder = Encoder::new();
+        let (connection, flushed) = connection(headers(&mut encoder, 1, &[(":status", "413")], true));
+
+        // larger than the initial window, so the stream can not be ended before the response is received
+        let request = Request { uri: "/".to_string(), method: Method::POST, headers: header_map![], body: vec![1; 70000] };
+        let response = connection.send(&request, &mut |_| {}).unwrap();
+
+        assert_eq!(response.status, status::PAYLOAD_TOO_LARGE);
+        let frames = frames(&flushed.borrow().concat());
+        assert_eq!(frames.last().unwrap(), &Frame::RstStream { stream_id: 1, error: NO_ERROR });
+    }
+
+    #[test]
+    fn multiplexed_responses() {
+        let mut encoder = Encoder::new();
+        let mut input = headers(&mut encoder, 3, &[(":status", "200")], false);
+        input.extend(headers(&mut encoder, 1, &[(":status", "404")], false));
+        input.extend(encode(&[data(3, b"second", true), data(1, b"first", true)]));
+        let (connection, _) = connection(input);
+
+        let mut state = connection.state.lock().unwrap();
+        let first = state.open_stream(&Request::builder().uri("/first").build().unwrap());
+        let second = state.open_stream(&Request::builder().uri("/second").build().unwrap());
+        assert_eq!(state.open_streams(), 2);
+        drop(state);
+        for _ in 0..4 {
+            connection.read_frame().unwrap();
+        }
+
+        let mut state = connection.state.lock().unwrap();
+        assert_eq!(state.open_streams(), 0);
+        let mut result = |stream_id| state.streams.get_mut(&stream_id).unwrap().result.take().unwrap().unwrap();
+        assert_eq!(result(first), Response { status: status::NOT_FOUND, headers: header_map![], body: b"first".to_vec() });
+        assert_eq!(result(second), Response { status: status::OK, headers: header_map![], body: b"second".to_vec() });
+    }
+
+    #[test]
+    fn concurrent_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (second_received, second_received_rx) = mpsc::channel();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(socket.try_clone().unwrap());
+            reader.read_exact(&mut [0; PREFACE.len()]).unwrap();
+            let mut requests = 0;
+            while requests < 2 {
+                if let Ok(Done(Ok(Frame::Headers { .. }))) = FrameParser::new(16384).parse(&mut reader) {
+                    requests += 1;
+                }
+            }
+
+            // the response to the first request is delayed until the response to the second one has been received
+            let mut encoder = Encoder::new();
+            socket.write_all(&headers(&mut encoder, 3, &[(":status", "200")], true)).unwrap();
+            second_received_rx.recv().unwrap();
+            socket.write_all(&headers(&mut encoder, 1, &[(":status", "404")], true)).unwrap();
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let connection = Arc::new(Http2Connection::new(stream.try_clone().unwrap(), stream, "localhost"));
+        let first = {
+            let connection = connection.clone();
+            thread::spawn(move || connection.send(&Request::builder().uri("/first").build().unwrap(), &mut |_| {}))
+        };
+        while !connection.state.lock().unwrap().reading {
+            thread::yield_now();
+        }
+
+        let second = connection.send(&Request::builder().uri("/second").build().unwrap(), &mut |_| {}).unwrap();
+        assert_eq!(second.status, status::OK);
+        second_received.send(()).unwrap();
+        assert_eq!(first.join().unwrap().unwrap().status, status::NOT_FOUND);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn waits_for_max_concurrent_streams() {
+        let input = encode(&[Frame::Settings { ack: false, params: vec![(MAX_CONCURRENT_STREAMS, 1)] }]);
+        let (connection, _) = connection(input);
+
+        let mut state = connection.state.lock().unwrap();
+        state.open_stream(&Request::builder().build().unwrap());
+        drop(state);
+
+        // the first request never gets a response, so the second one can not be sent
+        let result = connection.send(&Request::builder().build().unwrap(), &mut |_| {});
+        assert!(matches!(result, Err(RequestError::Reading(_))));
+        assert_eq!(connection.state.lock().unwrap().streams.len(), 1);
+    }
+
+    #[test]
+    fn reset_stream() {
+        let (connection, _) = connection(encode(&[Frame::RstStream { stream_id: 1, error: REFUSED_STREAM }]));
+
+        let result = connection.send(&Request::builder().build().unwrap(), &mut |_| {});
+
+        assert!(matches!(result, Err(RequestError::Http2(_))));
+        assert!(connection.is_open());
+    }
+
+    #[test]
+    fn go_away() {
+        let (connection, _) = connection(encode(&[Frame::GoAway { last_stream_id: 0, error: NO_ERROR, debug_data: vec![] }]));
+
+        let result = connection.send(&Request::builder().build().unwrap(), &mut |_| {});
+
+        assert!(matches!(result, Err(RequestError::Http2(_))));
+        assert!(!connection.is_open());
+    }
+
+    #[test]
+    fn invalid_status() {
+        let mut encoder = Encoder::new();
+        let (connection, _) = connection(headers(&mut encoder, 1, &[(":status", "99x")], true));
+
+        let result = connection.send(&Request::builder().build().unwrap(), &mut |_| {});
+
+        assert!(matches!(result, Err(RequestError::ResponseParsing(_))));
+        assert!(connection.is_open());
+    }
+
+    #[test]
+    fn connection_error_closes_connection() {
+        let (connection, flushed) = connection(encode(&[Frame::PushPromise { stream_id: 1, promised_stream_id: 2, block: vec![], end_headers: true }]));
+
+        let result = connection.send(&Request::builder().build().unwrap(), &mut |_| {});
+
+        assert!(matches!(result, Err(RequestError::Http2(_))));
+        assert!(!connection.is_open());
+        assert!(matches!(frames(&flushed.borrow().concat()).last().unwrap(), Frame::GoAway { .. }));
+    }
+
+    #[test]
+    fn read_timeout() {
+        let (connection, flushed) = connection(vec![]);
+
+        let result = connection.send(&Request::builder().build().unwrap(), &mut |_| {});
+
+        assert!(matches!(result, Err(RequestError::Reading(_))));
+        assert!(connection.is_open());
+        assert_eq!(frames(&flushed.borrow().concat()).last().unwrap(), &Frame::RstStream { stream_id: 1, error: CANCEL });
+    }
+}
@@ -6,5 +6,8 @@ pub use stream_factory::*;
 mod client;
 /// Config for client.
 mod config;
+/// HTTP/2 connections.
+#[cfg(feature = "http2")]
+mod http2;
 /// Stream factory for spawning new streams to a server.
 mod stream_factory;
\ No newline at end of file
@@ -1,8 +1,10 @@
-use std::net::TcpStream;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{IpAddr, TcpStream};
 use std::sync::Arc;
 use std::time::Duration;
 
 use rustls::{ClientConfig, ClientSession};
+use webpki::{DNSName, DNSNameRef};
 
 use crate::client::Config;
 use crate::util::tls_stream::TlsStream;
@@ -13,6 +15,22 @@ pub type ClientTlsStream = TlsStream<ClientSession, TcpStream>;
 /// A factory that produces new streams to a server.
 pub trait StreamFactory<T>: Send + Sync {
     fn create(&self) -> std::io::Result<T>;
+
+    /// Checks if the streams produced by this factory may use HTTP/2, which is negotiated when a stream is created.
+    fn offers_http2(&self) -> bool {
+        false
+    }
+
+    /// Checks if HTTP/2 was negotiated for the given stream, which was produced by this factory.
+    fn negotiated_http2(&self, _stream: &T) -> bool {
+        false
+    }
+
+    /// Splits a stream on which HTTP/2 was negotiated into a reader and a writer, so requests can be written while
+    /// another thread is waiting for frames to read.
+    fn split(&self, _stream: T) -> std::io::Result<(Box<dyn Read + Send>, Box<dyn Write + Send>)> {
+        Err(ErrorKind::Unsupported.into())
+    }
 }
 
 /// A stream factory for producing plain TCP streams.
@@ -41,31 +59,112 @@ impl StreamFactory<TcpStream> for TcpStreamFactory {
     }
 }
 
+/// Gets the host of the given address, without the port or the brackets around an IPv6 address.
+fn host(addr: &str) -> &str {
+    let host = match addr.rsplit_once(':') {
+        // the colons of an IPv6 address without a port are not followed by a port
+        Some((host, port)) if port.parse::<u16>().is_ok() && (!host.contains(':') || host.ends_with(']')) => host,
+        _ => addr,
+    };
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
 /// A stream factory for producing TLS encrypted streams to a server.
 pub struct TlsStreamFactory {
     tcp_stream_factory: TcpStreamFactory,
     tls_config: Arc<ClientConfig>,
-    dns_name: webpki::DNSName,
+    /// The name used for SNI and to verify the server certificate, or the reason the configured name can not be used.
+    server_name: Result<DNSName, String>,
 }
 
 impl TlsStreamFactory {
-    /// Creates a new TLS stream factory with the given configs.
-    pub fn new(config: &Config, tls_config: ClientConfig) -> TlsStreamFactory {
-        let dns_name = config.addr.split(":").next().expect("Invalid address.");
-        let dns_name = webpki::DNSNameRef::try_from_ascii_str(dns_name).expect("Failed to look up address.").into();
+    /// Creates a new TLS stream factory with the given configs. The TLS config is either a rustls ClientConfig or a
+    /// ClientTlsConfig.
+    pub fn new(config: &Config, tls_config: impl Into<ClientConfig>) -> TlsStreamFactory {
+        let name = config.server_name.unwrap_or_else(|| host(config.addr));
+        let server_name = DNSNameRef::try_from_ascii_str(name).map(Into::into).map_err(|_| {
+            if name.parse::<IpAddr>().is_ok() {
+                format!("{} is an IP address, so a server name must be set in the config to verify the server certificate", name)
+            } else {
+                format!("invalid server name {:?}", name)
+            }
+        });
 
         TlsStreamFactory {
             tcp_stream_factory: TcpStreamFactory::new(config),
-            tls_config: Arc::new(tls_config),
-            dns_name,
+            tls_config: Arc::new(tls_config.into()),
+            server_name,
         }
     }
 }
 
 impl StreamFactory<ClientTlsStream> for TlsStreamFactory {
     fn create(&self) -> std::io::Result<ClientTlsStream> {
+        let server_name = self.server_name.as_ref().map_err(|message| std::io::Error::new(ErrorKind::InvalidInput, message.as_str()))?;
         let stream = self.tcp_stream_factory.create()?;
-        let session = ClientSession::new(&self.tls_config, self.dns_name.as_ref());
-        Ok(ClientTlsStream::new(session, stream))
+        let session = ClientSession::new(&self.tls_config, server_name.as_ref());
+        let mut stream = ClientTlsStream::new(session, stream);
+        if !self.tls_config.alpn_protocols.is_empty() {
+            // the negotiated protocol is only known once the handshake is complete
+            stream.complete_handshake()?;
+        }
+        Ok(stream)
+    }
+
+    fn offers_http2(&self) -> bool {
+        self.tls_config.alpn_protocols.iter().any(|protocol| protocol == b"h2")
+    }
+
+    fn negotiated_http2(&self, stream: &ClientTlsStream) -> bool {
+        stream.alpn_protocol() == Some(b"h2")
+    }
+
+    fn split(&self, stream: ClientTlsStream) -> std::io::Result<(Box<dyn Read + Send>, Box<dyn Write + Send>)> {
+        let (reader, writer) = stream.split()?;
+        Ok((Box::new(reader), Box::new(writer)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+    use std::time::Duration;
+
+    use rustls::ClientConfig;
+
+    use crate::client::{Config, StreamFactory, TlsStreamFactory};
+    use crate::client::stream_factory::host;
+
+    fn factory(addr: &'static str, server_name: Option<&'static str>) -> TlsStreamFactory {
+        TlsStreamFactory::new(&Config { addr, read_timeout: Duration::from_secs(1), num_connections: 1, server_name }, ClientConfig::new())
+    }
+
+    #[test]
+    fn hosts() {
+        assert_eq!(host("localhost:443"), "localhost");
+        assert_eq!(host("example.com"), "example.com");
+        assert_eq!(host("127.0.0.1:8443"), "127.0.0.1");
+        assert_eq!(host("[::1]:8443"), "::1");
+        assert_eq!(host("::1"), "::1");
+    }
+
+    #[test]
+    fn server_name_from_addr() {
+        assert!(factory("localhost:443", None).server_name.is_ok());
+    }
+
+    #[test]
+    fn ip_address_needs_server_name() {
+        let error = factory("127.0.0.1:8443", None).create().err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(error.to_string().contains("IP address"));
+
+        assert!(factory("127.0.0.1:8443", Some("localhost")).server_name.is_ok());
+    }
+
+    #[test]
+    fn invalid_server_name() {
+        let error = factory("localhost:443", Some("not a name")).create().err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
     }
 }
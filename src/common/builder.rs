@@ -0,0 +1,344 @@
+use std::fmt::{Display, Formatter};
+
+use crate::common::header::{Header, HeaderMap, HeaderMapOps};
+use crate::common::method::Method;
+use crate::common::request::Request;
+use crate::common::response::Response;
+use crate::common::status;
+use crate::common::status::Status;
+use crate::common::typed_header::TypedHeader;
+
+/// Error for when a message can not be built.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum BuildError {
+    /// A header name is empty or contains characters that are not allowed in a header name. Contains the header name.
+    InvalidHeaderName(String),
+    /// A header value contains control characters, such as CR or LF. Contains the header name.
+    InvalidHeaderValue(Header),
+    /// The URI is empty or contains whitespace or control characters.
+    InvalidUri(String),
+    /// A redirect response was given a status that is not 3xx. Contains the status code.
+    NotRedirectStatus(u16),
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildError::InvalidHeaderName(name) => write!(f, "invalid header name {:?}", name),
+            BuildError::InvalidHeaderValue(header) => write!(f, "invalid value for header {}", header),
+            BuildError::InvalidUri(uri) => write!(f, "invalid uri {:?}", uri),
+            BuildError::NotRedirectStatus(code) => write!(f, "{} is not a redirect status", code),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Builder for a response. Headers are validated as they are added, and the first error is returned from build.
+/// ```
+/// use my_http::common::response::Response;
+/// use my_http::common::status;
+/// use my_http::common::typed_header::{ContentType, MediaType};
+///
+/// let response = Response::builder()
+///     .status(status::CREATED)
+///     .header("x-request-id", "1234")
+///     .typed_header(&ContentType(MediaType::new("text", "plain")))
+///     .body("hello")
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(response.status, status::CREATED);
+/// assert_eq!(response.body, b"hello");
+///
+/// assert!(Response::builder().header("x-bad", "hello\r\nworld").build().is_err());
+/// ```
+#[derive(Debug)]
+pub struct ResponseBuilder {
+    status: Status,
+    headers: HeaderMap,
+    body: Vec<u8>,
+    error: Option<BuildError>,
+}
+
+impl ResponseBuilder {
+    /// Creates a new builder for an empty response with a 200 status.
+    pub fn new() -> ResponseBuilder {
+        ResponseBuilder { status: status::OK, headers: HeaderMap::new(), body: vec![], error: None }
+    }
+
+    /// Sets the status.
+    pub fn status(mut self, status: Status) -> ResponseBuilder {
+        self.status = status;
+        self
+    }
+
+    /// Adds a header value. Existing values for the header are kept.
+    pub fn header(mut self, header: impl Into<Header>, value: impl Into<String>) -> ResponseBuilder {
+        add_header(&mut self.headers, &mut self.error, header.into(), value.into());
+        self
+    }
+
+    /// Sets a typed header, replacing any existing values for the header.
+    pub fn typed_header<T: TypedHeader>(mut self, header: &T) -> ResponseBuilder {
+        set_typed_header(&mut self.headers, &mut self.error, header);
+        self
+    }
+
+    /// Sets the body.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> ResponseBuilder {
+        self.body = body.into();
+        self
+    }
+
+    /// Builds the response, or returns the first error encountered while building.
+    pub fn build(self) -> Result<Response, BuildError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        Ok(Response { status: self.status, headers: self.headers, body: self.body })
+    }
+}
+
+impl Default for ResponseBuilder {
+    fn default() -> Self {
+        ResponseBuilder::new()
+    }
+}
+
+/// Builder for a request. Headers are validated as they are added, and the first error is returned from build.
+/// ```
+/// use my_http::common::method::Method;
+/// use my_http::common::request::Request;
+///
+/// let request = Request::builder()
+///     .method(Method::POST)
+///     .uri("/submit")
+///     .header("content-type", "text/plain")
+///     .body("hello")
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(request.method, Method::POST);
+/// assert_eq!(request.uri, "/submit");
+///
+/// assert!(Request::builder().uri("/hello world").build().is_err());
+/// ```
+#[derive(Debug)]
+pub struct RequestBuilder {
+    method: Method,
+    uri: String,
+    headers: HeaderMap,
+    body: Vec<u8>,
+    error: Option<BuildError>,
+}
+
+impl RequestBuilder {
+    /// Creates a new builder for an empty GET request to "/".
+    pub fn new() -> RequestBuilder {
+        RequestBuilder { method: Method::GET, uri: String::from("/"), headers: HeaderMap::new(), body: vec![], error: None }
+    }
+
+    /// Sets the method.
+    pub fn method(mut self, method: Method) -> RequestBuilder {
+        self.method = method;
+        self
+    }
+
+    /// Sets the URI.
+    pub fn uri(mut self, uri: impl Into<String>) -> RequestBuilder {
+        let uri = uri.into();
+        if self.error.is_none() && !is_valid_uri(&uri) {
+            self.error = Some(BuildError::InvalidUri(uri.clone()));
+        }
+        self.uri = uri;
+        self
+    }
+
+    /// Adds a header value. Existing values for the header are kept.
+    pub fn header(mut self, header: impl Into<Header>, value: impl Into<String>) -> RequestBuilder {
+        add_header(&mut self.headers, &mut self.error, header.into(), value.into());
+        self
+    }
+
+    /// Sets a typed header, replacing any existing values for the header.
+    pub fn typed_header<T: TypedHeader>(mut self, header: &T) -> RequestBuilder {
+        set_typed_header(&mut self.headers, &mut self.error, header);
+        self
+    }
+
+    /// Sets the body.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> RequestBuilder {
+        self.body = body.into();
+        self
+    }
+
+    /// Builds the request, or returns the first error encountered while building.
+    pub fn build(self) -> Result<Request, BuildError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        Ok(Request { method: self.method, uri: self.uri, headers: self.headers, body: self.body })
+    }
+}
+
+impl Default for RequestBuilder {
+    fn default() -> Self {
+        RequestBuilder::new()
+    }
+}
+
+/// Validates and adds the given header to the header map. If the header is invalid, then the error is stored unless there already is one.
+fn add_header(headers: &mut HeaderMap, error: &mut Option<BuildError>, header: Header, value: String) {
+    if !is_valid_header_name(header.as_str()) {
+        error.get_or_insert(BuildError::InvalidHeaderName(header.as_str().to_string()));
+        return;
+    }
+    if !is_valid_header_value(&value) {
+        error.get_or_insert(BuildError::InvalidHeaderValue(header));
+        return;
+    }
+    headers.add_header(header, value);
+}
+
+/// Validates and sets the given typed header in the header map. If the header is invalid, then the error is stored unless there already is one.
+fn set_typed_header<T: TypedHeader>(headers: &mut HeaderMap, error: &mut Option<BuildError>, header: &T) {
+    let value = header.encode_value();
+    if !is_valid_header_value(&value) {
+        error.get_or_insert(BuildError::InvalidHeaderValue(T::HEADER));
+        return;
+    }
+    headers.insert(T::HEADER, vec![value]);
+}
+
+/// Checks if the given header name is a non empty token.
+fn is_valid_header_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// Checks if the given header value has no control characters other than horizontal tab.
+fn is_valid_header_value(value: &str) -> bool {
+    value.bytes().all(|b| b == b'\t' || !(b.is_ascii_control()))
+}
+
+/// Checks if the given URI is non empty and has no whitespace or control characters.
+fn is_valid_uri(uri: &str) -> bool {
+    !uri.is_empty() && uri.bytes().all(|b| b.is_ascii_graphic() || b >= 0x80)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::builder::BuildError;
+    use crate::common::header::{CONTENT_TYPE, Header, LOCATION};
+    use crate::common::method::Method;
+    use crate::common::request::Request;
+    use crate::common::response::Response;
+    use crate::common::status;
+    use crate::common::typed_header::{ContentLength, Location};
+    use crate::header_map;
+
+    #[test]
+    fn empty_response() {
+        assert_eq!(Response::builder().build().unwrap(), Response { status: status::OK, headers: header_map![], body: vec![] });
+    }
+
+    #[test]
+    fn response_with_everything() {
+        let response = Response::builder()
+            .status(status::NOT_FOUND)
+            .header(CONTENT_TYPE, "text/plain")
+            .header("x-custom", "a")
+            .header("x-custom", "b")
+            .typed_header(&ContentLength(5))
+            .body("hello")
+            .build()
+            .unwrap();
+
+        assert_eq!(response, Response {
+            status: status::NOT_FOUND,
+            headers: header_map![
+                (CONTENT_TYPE, "text/plain"),
+                ("x-custom", "a"),
+                ("x-custom", "b"),
+                ("content-length", "5")
+            ],
+            body: b"hello".to_vec(),
+        });
+    }
+
+    #[test]
+    fn typed_header_replaces_values() {
+        let response = Response::builder()
+            .header("location", "/a")
+            .header("location", "/b")
+            .typed_header(&Location("/c".to_string()))
+            .build()
+            .unwrap();
+
+        assert_eq!(response.headers, header_map![(LOCATION, "/c")]);
+    }
+
+    #[test]
+    fn invalid_header_name() {
+        assert_eq!(Response::builder().header("", "value").build(), Err(BuildError::InvalidHeaderName("".to_string())));
+        assert_eq!(Response::builder().header("bad name", "value").build(), Err(BuildError::InvalidHeaderName("bad name".to_string())));
+        assert_eq!(Response::builder().header("bad:", "value").build(), Err(BuildError::InvalidHeaderName("bad:".to_string())));
+    }
+
+    #[test]
+    fn invalid_header_value() {
+        assert_eq!(Response::builder().header("X-Custom", "a\r\nb").build(),
+                   Err(BuildError::InvalidHeaderValue(Header::Custom("x-custom".to_string()))));
+        assert_eq!(Response::builder().header("x-custom", "a\0").build(),
+                   Err(BuildError::InvalidHeaderValue(Header::Custom("x-custom".to_string()))));
+        assert_eq!(Response::builder().typed_header(&Location("/a\nb".to_string())).build(),
+                   Err(BuildError::InvalidHeaderValue(LOCATION)));
+        assert!(Response::builder().header("x-custom", "tab\tand ünicode").build().is_ok());
+    }
+
+    #[test]
+    fn first_error_returned() {
+        let result = Response::builder()
+            .header("x-first", "\n")
+            .header("bad name", "value")
+            .build();
+
+        assert_eq!(result, Err(BuildError::InvalidHeaderValue(Header::Custom("x-first".to_string()))));
+    }
+
+    #[test]
+    fn request() {
+        let request = Request::builder()
+            .method(Method::PUT)
+            .uri("/hello?a=b")
+            .header("x-custom", "value")
+            .body(vec![1, 2, 3])
+            .build()
+            .unwrap();
+
+        assert_eq!(request, Request {
+            method: Method::PUT,
+            uri: "/hello?a=b".to_string(),
+            headers: header_map![("x-custom", "value")],
+            body: vec![1, 2, 3],
+        });
+    }
+
+    #[test]
+    fn default_request() {
+        assert_eq!(Request::builder().build().unwrap(), Request {
+            method: Method::GET,
+            uri: "/".to_string(),
+            headers: header_map![],
+            body: vec![],
+        });
+    }
+
+    #[test]
+    fn invalid_request() {
+        assert_eq!(Request::builder().uri("").build(), Err(BuildError::InvalidUri("".to_string())));
+        assert_eq!(Request::builder().uri("/a b").build(), Err(BuildError::InvalidUri("/a b".to_string())));
+        assert_eq!(Request::builder().uri("/a\r\n").build(), Err(BuildError::InvalidUri("/a\r\n".to_string())));
+        assert_eq!(Request::builder().header("x", "\r").build(), Err(BuildError::InvalidHeaderValue(Header::Custom("x".to_string()))));
+    }
+}
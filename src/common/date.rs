@@ -0,0 +1,302 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Abbreviated day names, starting at Sunday.
+const DAY_NAMES: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+/// Full day names used by the obsolete RFC 850 format, starting at Sunday.
+const FULL_DAY_NAMES: [&str; 7] = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+
+/// Abbreviated month names, starting at January.
+const MONTH_NAMES: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Number of seconds in a day.
+const SECONDS_PER_DAY: i64 = 86400;
+
+/// Formats the given time as an IMF-fixdate, like "Sun, 06 Nov 1994 08:49:37 GMT". Sub-second precision is discarded.
+/// Since the year has four digits, times before the year 0000 or after the year 9999 are clamped to the first or last
+/// second of that range.
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+/// use my_http::common::date::format_http_date;
+///
+/// assert_eq!(format_http_date(UNIX_EPOCH + Duration::from_secs(784111777)), "Sun, 06 Nov 1994 08:49:37 GMT");
+/// ```
+pub fn format_http_date(time: SystemTime) -> String {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs_f64().ceil() as i64)
+    };
+    let seconds = seconds.clamp(days_from_civil(0, 1, 1) * SECONDS_PER_DAY, days_from_civil(10000, 1, 1) * SECONDS_PER_DAY - 1);
+
+    let days = seconds.div_euclid(SECONDS_PER_DAY);
+    let seconds_of_day = seconds.rem_euclid(SECONDS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+
+    format!("{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+            DAY_NAMES[weekday(days)],
+            day,
+            MONTH_NAMES[month as usize - 1],
+            year,
+            seconds_of_day / 3600,
+            seconds_of_day / 60 % 60,
+            seconds_of_day % 60)
+}
+
+/// Parses an HTTP-date. Accepts the preferred IMF-fixdate format as well as the obsolete RFC 850 and asctime formats.
+/// Returns None if the date is not in any of the formats or is not a valid date.
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+/// use my_http::common::date::parse_http_date;
+///
+/// let expected = Some(UNIX_EPOCH + Duration::from_secs(784111777));
+/// assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), expected);
+/// assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), expected);
+/// assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), expected);
+/// ```
+pub fn parse_http_date(raw: &str) -> Option<SystemTime> {
+    parse_http_date_relative_to(raw, current_year())
+}
+
+/// Parses an HTTP-date, using the given current year to resolve two digit years in the RFC 850 format.
+fn parse_http_date_relative_to(raw: &str, current_year: i64) -> Option<SystemTime> {
+    let parts: Vec<&str> = raw.split_whitespace().collect();
+
+    let (year, month, day, time) = match parts.as_slice() {
+        // IMF-fixdate: Sun, 06 Nov 1994 08:49:37 GMT
+        [day_name, day, month, year, time, "GMT"] if day_name.strip_suffix(',').is_some_and(|name| DAY_NAMES.contains(&name)) =>
+            (parse_number(year, 4)?, parse_month(month)?, parse_number(day, 2)?, *time),
+        // RFC 850: Sunday, 06-Nov-94 08:49:37 GMT
+        [day_name, date, time, "GMT"] if day_name.strip_suffix(',').is_some_and(|name| FULL_DAY_NAMES.contains(&name)) => {
+            let mut date = date.split('-');
+            let day = parse_number(date.next()?, 2)?;
+            let month = parse_month(date.next()?)?;
+            let year = resolve_two_digit_year(parse_number(date.next()?, 2)?, current_year);
+            if date.next().is_some() {
+                return None;
+            }
+            (year, month, day, *time)
+        }
+        // asctime: Sun Nov  6 08:49:37 1994
+        [day_name, month, day, time, year] if DAY_NAMES.contains(day_name) =>
+            (parse_number(year, 4)?, parse_month(month)?, parse_day_of_asctime(day)?, *time),
+        _ => return None
+    };
+
+    if day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let (hour, minute, second) = parse_time(time)?;
+
+    let seconds = days_from_civil(year, month, day) * SECONDS_PER_DAY + hour * 3600 + minute * 60 + second;
+
+    Some(if seconds >= 0 {
+        UNIX_EPOCH + Duration::from_secs(seconds as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs())
+    })
+}
+
+/// Parses a number that must have exactly the given number of digits.
+fn parse_number(raw: &str, digits: usize) -> Option<i64> {
+    if raw.len() != digits || !raw.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    raw.parse().ok()
+}
+
+/// Parses the day of an asctime date, which is either one or two digits.
+fn parse_day_of_asctime(raw: &str) -> Option<i64> {
+    parse_number(raw, 1).or_else(|| parse_number(raw, 2))
+}
+
+/// Parses a case sensitive abbreviated month name into a month number from 1 to 12.
+fn parse_month(raw: &str) -> Option<i64> {
+    MONTH_NAMES.iter().position(|name| *name == raw).map(|index| index as i64 + 1)
+}
+
+/// Parses an "hh:mm:ss" time of day. A second of 60 is allowed for leap seconds.
+fn parse_time(raw: &str) -> Option<(i64, i64, i64)> {
+    let mut split = raw.split(':');
+    let hour = parse_number(split.next()?, 2)?;
+    let minute = parse_number(split.next()?, 2)?;
+    let second = parse_number(split.next()?, 2)?;
+
+    if split.next().is_some() || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    Some((hour, minute, second))
+}
+
+/// Resolves a two digit RFC 850 year. Per RFC 9110, a year that appears to be more than 50 years
+/// in the future is interpreted as the most recent past year with the same last two digits.
+fn resolve_two_digit_year(year: i64, current_year: i64) -> i64 {
+    let century = current_year - current_year.rem_euclid(100);
+    let year = century + year;
+    if year > current_year + 50 { year - 100 } else { year }
+}
+
+/// Gets the current year in UTC.
+fn current_year() -> i64 {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+    civil_from_days(seconds.div_euclid(SECONDS_PER_DAY)).0
+}
+
+/// Checks if the given year is a leap year.
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+/// Gets the number of days in the given month of the given year.
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31
+    }
+}
+
+/// Gets the day of the week, with 0 being Sunday, for the given number of days since the unix epoch.
+fn weekday(days: i64) -> usize {
+    // the unix epoch was a Thursday
+    (days + 4).rem_euclid(7) as usize
+}
+
+/// Gets the number of days since the unix epoch for the given proleptic Gregorian calendar date.
+/// This is Howard Hinnant's days_from_civil algorithm.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_from_march = (month + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Gets the (year, month, day) proleptic Gregorian calendar date for the given number of days since the unix epoch.
+/// This is Howard Hinnant's civil_from_days algorithm.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use crate::common::date::{civil_from_days, days_from_civil, format_http_date, parse_http_date, parse_http_date_relative_to};
+
+    fn time(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn format_epoch() {
+        assert_eq!(format_http_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+
+    #[test]
+    fn format_rfc_example() {
+        assert_eq!(format_http_date(time(784111777)), "Sun, 06 Nov 1994 08:49:37 GMT");
+    }
+
+    #[test]
+    fn format_leap_day() {
+        assert_eq!(format_http_date(time(951782400)), "Tue, 29 Feb 2000 00:00:00 GMT");
+        assert_eq!(format_http_date(time(1709251199)), "Thu, 29 Feb 2024 23:59:59 GMT");
+    }
+
+    #[test]
+    fn format_discards_sub_seconds() {
+        assert_eq!(format_http_date(time(784111777) + Duration::from_millis(999)), "Sun, 06 Nov 1994 08:49:37 GMT");
+    }
+
+    #[test]
+    fn format_before_epoch() {
+        assert_eq!(format_http_date(UNIX_EPOCH - Duration::from_secs(1)), "Wed, 31 Dec 1969 23:59:59 GMT");
+    }
+
+    #[test]
+    fn format_clamps_years_to_four_digits() {
+        let last = "Fri, 31 Dec 9999 23:59:59 GMT";
+        assert_eq!(format_http_date(time(253402300799)), last);
+        assert_eq!(format_http_date(time(253402300800)), last);
+        assert_eq!(format_http_date(time(1 << 40)), last);
+        assert_eq!(format_http_date(UNIX_EPOCH - Duration::from_secs(62167219200)), "Sat, 01 Jan 0000 00:00:00 GMT");
+        assert_eq!(format_http_date(UNIX_EPOCH - Duration::from_secs(62167219201)), "Sat, 01 Jan 0000 00:00:00 GMT");
+        assert_eq!(parse_http_date(&format_http_date(time(1 << 40))), Some(time(253402300799)));
+    }
+
+    #[test]
+    fn parse_imf_fixdate() {
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(time(784111777)));
+        assert_eq!(parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(UNIX_EPOCH));
+        assert_eq!(parse_http_date("Wed, 31 Dec 1969 23:59:59 GMT"), Some(UNIX_EPOCH - Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn parse_rfc_850() {
+        assert_eq!(parse_http_date_relative_to("Sunday, 06-Nov-94 08:49:37 GMT", 2026), Some(time(784111777)));
+        assert_eq!(parse_http_date_relative_to("Thursday, 01-Jan-70 00:00:00 GMT", 1990), Some(UNIX_EPOCH));
+        assert_eq!(parse_http_date_relative_to("Saturday, 01-Jan-00 00:00:00 GMT", 2026), Some(time(946684800)));
+        // 2076 is 50 years in the future, so it is not moved back to 1976
+        assert_eq!(parse_http_date_relative_to("Wednesday, 01-Jan-76 00:00:00 GMT", 2026), parse_http_date("Wed, 01 Jan 2076 00:00:00 GMT"));
+        assert_eq!(parse_http_date_relative_to("Thursday, 01-Jan-77 00:00:00 GMT", 2026), parse_http_date("Sat, 01 Jan 1977 00:00:00 GMT"));
+    }
+
+    #[test]
+    fn parse_asctime() {
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), Some(time(784111777)));
+        assert_eq!(parse_http_date("Sun Nov 16 08:49:37 1994"), Some(time(784111777 + 10 * 86400)));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for raw in &[
+            "",
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+            "Sun, 6 Nov 1994 08:49:37 GMT",
+            "Sun, 06 nov 1994 08:49:37 GMT",
+            "Sun, 06 Nov 94 08:49:37 GMT",
+            "Sun, 31 Nov 1994 08:49:37 GMT",
+            "Sun, 29 Feb 1900 08:49:37 GMT",
+            "Sun, 06 Nov 1994 24:00:00 GMT",
+            "Sun, 06 Nov 1994 08:60:00 GMT",
+            "Sun, 06 Nov 1994 08:49 GMT",
+            "Sunday, 06 Nov 1994 08:49:37 GMT",
+            "Sun, 06-Nov-94 08:49:37 GMT",
+            "Sunday, 06-Nov-1994 08:49:37 GMT",
+            "Sun Nov 006 08:49:37 1994",
+            "Sun Nov 6 08:49:37 94",
+            "hello",
+        ] {
+            assert_eq!(parse_http_date(raw), None, "{}", raw);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for seconds in &[0, 1, 59, 86399, 86400, 784111777, 951782400, 1709251199, 4102444800, 253402300799] {
+            assert_eq!(parse_http_date(&format_http_date(time(*seconds))), Some(time(*seconds)));
+        }
+    }
+
+    #[test]
+    fn civil_days_round_trip() {
+        for days in -800000..800000 {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+}
@@ -108,6 +108,7 @@ standard_headers! {
     (IF_NONE_MATCH, "if-none-match");
     (IF_RANGE, "if-range");
     (IF_UNMODIFIED_SINCE, "if-unmodified-since");
+    (LAST_EVENT_ID, "last-event-id");
     (LAST_MODIFIED, "last-modified");
     (LINK, "link");
     (LOCATION, "location");
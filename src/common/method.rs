@@ -11,6 +11,8 @@ pub enum Method {
     DELETE,
     /// PUT method
     PUT,
+    /// HEAD method.
+    HEAD,
 }
 
 impl Display for Method {
@@ -28,6 +30,7 @@ impl Method {
             "POST" => Some(Method::POST),
             "DELETE" => Some(Method::DELETE),
             "PUT" => Some(Method::PUT),
+            "HEAD" => Some(Method::HEAD),
             _ => None
         }
     }
@@ -39,6 +42,7 @@ impl Method {
             b"POST" => Some(Method::POST),
             b"DELETE" => Some(Method::DELETE),
             b"PUT" => Some(Method::PUT),
+            b"HEAD" => Some(Method::HEAD),
             _ => None
         }
     }
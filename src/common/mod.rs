@@ -1,3 +1,7 @@
+/// Builders for HTTP requests and responses.
+pub mod builder;
+/// HTTP-date formatting and parsing.
+pub mod date;
 /// HTTP header data types and functions.
 pub mod header;
 /// HTTP method data type and functions.
@@ -8,5 +12,7 @@ pub mod request;
 pub mod response;
 /// HTTP status data type and functions.
 pub mod status;
+/// Typed HTTP header data types and functions.
+pub mod typed_header;
 /// HTTP version constants and functions.
 pub mod version;
\ No newline at end of file
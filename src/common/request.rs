@@ -1,3 +1,4 @@
+use crate::common::builder::RequestBuilder;
 use crate::common::header::HeaderMap;
 use crate::common::method::Method;
 
@@ -12,4 +13,11 @@ pub struct Request {
     pub headers: HeaderMap,
     /// The body.
     pub body: Vec<u8>,
-}
\ No newline at end of file
+}
+
+impl Request {
+    /// Creates a builder for a request.
+    pub fn builder() -> RequestBuilder {
+        RequestBuilder::new()
+    }
+}
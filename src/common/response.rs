@@ -1,6 +1,8 @@
+use crate::common::builder::{BuildError, ResponseBuilder};
 use crate::common::header::{CONTENT_LENGTH, HeaderMap};
 use crate::common::status;
 use crate::common::status::Status;
+use crate::common::typed_header::{ContentType, Location, MediaType, TypedHeader};
 use crate::header_map;
 
 /// An HTTP response.
@@ -14,6 +16,57 @@ pub struct Response {
     pub body: Vec<u8>,
 }
 
+impl Response {
+    /// Creates a builder for a response.
+    pub fn builder() -> ResponseBuilder {
+        ResponseBuilder::new()
+    }
+
+    /// Creates an empty response with the given status.
+    pub fn empty(status: Status) -> Response {
+        Response { status, headers: HeaderMap::new(), body: vec![] }
+    }
+
+    /// Creates a redirect response with the given 3xx status to the given location.
+    /// Returns an error if the status is not 3xx or the location can not be used as a header value.
+    /// ```
+    /// use my_http::common::header::{HeaderMapOps, LOCATION};
+    /// use my_http::common::response::Response;
+    /// use my_http::common::status;
+    ///
+    /// let response = Response::redirect(status::SEE_OTHER, "/login").unwrap();
+    ///
+    /// assert_eq!(response.status, status::SEE_OTHER);
+    /// assert!(response.headers.contains_header_value(&LOCATION, "/login"));
+    /// ```
+    pub fn redirect(status: Status, location: &str) -> Result<Response, BuildError> {
+        if !(300..400).contains(&status.code) {
+            return Err(BuildError::NotRedirectStatus(status.code));
+        }
+        Response::builder()
+            .status(status)
+            .typed_header(&Location(location.to_string()))
+            .build()
+    }
+
+    /// Creates a response with the given text as its body and a "text/plain; charset=utf-8" content type.
+    pub fn text(body: impl Into<String>) -> Response {
+        Response::bytes_with_type(body.into().into_bytes(), MediaType::new("text", "plain").with_param("charset", "utf-8"))
+    }
+
+    /// Creates a response with the given HTML as its body and a "text/html; charset=utf-8" content type.
+    pub fn html(body: impl Into<String>) -> Response {
+        Response::bytes_with_type(body.into().into_bytes(), MediaType::new("text", "html").with_param("charset", "utf-8"))
+    }
+
+    /// Creates a response with the given bytes as its body and the given content type.
+    pub fn bytes_with_type(body: impl Into<Vec<u8>>, content_type: MediaType) -> Response {
+        let mut headers = HeaderMap::new();
+        ContentType(content_type).encode(&mut headers);
+        Response { status: status::OK, headers, body: body.into() }
+    }
+}
+
 impl From<Status> for Response {
     /// Creates an empty response with the given status.
     fn from(status: Status) -> Self {
@@ -48,4 +101,57 @@ impl From<Vec<u8>> for Response {
             body,
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::builder::BuildError;
+    use crate::common::header::{CONTENT_TYPE, LOCATION};
+    use crate::common::response::Response;
+    use crate::common::status;
+    use crate::common::typed_header::MediaType;
+    use crate::header_map;
+
+    #[test]
+    fn empty() {
+        assert_eq!(Response::empty(status::NO_CONTENT), Response { status: status::NO_CONTENT, headers: header_map![], body: vec![] });
+    }
+
+    #[test]
+    fn redirect() {
+        assert_eq!(Response::redirect(status::SEE_OTHER, "/somewhere").unwrap(), Response {
+            status: status::SEE_OTHER,
+            headers: header_map![(LOCATION, "/somewhere")],
+            body: vec![],
+        });
+        assert!(Response::redirect(status::FOUND, "/a\r\nset-cookie: a=b").is_err());
+    }
+
+    #[test]
+    fn redirect_non_3xx() {
+        assert_eq!(Response::redirect(status::OK, "/"), Err(BuildError::NotRedirectStatus(200)));
+    }
+
+    #[test]
+    fn text_and_html() {
+        assert_eq!(Response::text("hello"), Response {
+            status: status::OK,
+            headers: header_map![(CONTENT_TYPE, "text/plain; charset=utf-8")],
+            body: b"hello".to_vec(),
+        });
+        assert_eq!(Response::html(String::from("<p>hello</p>")), Response {
+            status: status::OK,
+            headers: header_map![(CONTENT_TYPE, "text/html; charset=utf-8")],
+            body: b"<p>hello</p>".to_vec(),
+        });
+    }
+
+    #[test]
+    fn bytes_with_type() {
+        assert_eq!(Response::bytes_with_type(vec![1, 2, 3], MediaType::new("image", "png")), Response {
+            status: status::OK,
+            headers: header_map![(CONTENT_TYPE, "image/png")],
+            body: vec![1, 2, 3],
+        });
+    }
+}
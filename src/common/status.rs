@@ -19,8 +19,8 @@ macro_rules! status_codes {
             pub const $name: Status = Status { code: $num, reason: $phrase };
         )+
 
-        /// Gets the status from the given status code.
         impl Status {
+            /// Gets the status from the given status code.
             pub fn from_code(code: u16) -> Option<Status> {
                 match code {
                     $(
@@ -33,7 +33,18 @@ macro_rules! status_codes {
     }
 }
 
+impl Status {
+    /// Checks if this is an informational (1xx) status.
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.code)
+    }
+}
+
 status_codes! {
+    (CONTINUE, 100, "CONTINUE");
+    (SWITCHING_PROTOCOLS, 101, "SWITCHING PROTOCOLS");
+    (PROCESSING, 102, "PROCESSING");
+    (EARLY_HINTS, 103, "EARLY HINTS");
     (OK, 200, "OK");
     (CREATED, 201, "CREATED");
     (ACCEPTED, 202, "ACCEPTED");
@@ -0,0 +1,77 @@
+use std::fmt::{Display, Formatter};
+
+/// An error code, sent in RST_STREAM and GOAWAY frames to give the reason a stream or connection ended.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ErrorCode(pub u32);
+
+/// Ended without an error.
+pub const NO_ERROR: ErrorCode = ErrorCode(0x0);
+/// The peer violated the protocol.
+pub const PROTOCOL_ERROR: ErrorCode = ErrorCode(0x1);
+/// An unexpected internal error.
+pub const INTERNAL_ERROR: ErrorCode = ErrorCode(0x2);
+/// The peer violated flow control.
+pub const FLOW_CONTROL_ERROR: ErrorCode = ErrorCode(0x3);
+/// Settings were not acknowledged in time.
+pub const SETTINGS_TIMEOUT: ErrorCode = ErrorCode(0x4);
+/// A frame was received on a stream that was already closed.
+pub const STREAM_CLOSED: ErrorCode = ErrorCode(0x5);
+/// A frame has an invalid size.
+pub const FRAME_SIZE_ERROR: ErrorCode = ErrorCode(0x6);
+/// The stream was refused before any processing.
+pub const REFUSED_STREAM: ErrorCode = ErrorCode(0x7);
+/// The stream is no longer needed.
+pub const CANCEL: ErrorCode = ErrorCode(0x8);
+/// The header compression state can not be kept.
+pub const COMPRESSION_ERROR: ErrorCode = ErrorCode(0x9);
+/// The connection of a CONNECT request failed.
+pub const CONNECT_ERROR: ErrorCode = ErrorCode(0xa);
+/// The peer is generating too much load.
+pub const ENHANCE_YOUR_CALM: ErrorCode = ErrorCode(0xb);
+/// The transport does not meet security requirements.
+pub const INADEQUATE_SECURITY: ErrorCode = ErrorCode(0xc);
+/// HTTP/1.1 is required instead.
+pub const HTTP_1_1_REQUIRED: ErrorCode = ErrorCode(0xd);
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match *self {
+            NO_ERROR => "NO_ERROR",
+            PROTOCOL_ERROR => "PROTOCOL_ERROR",
+            INTERNAL_ERROR => "INTERNAL_ERROR",
+            FLOW_CONTROL_ERROR => "FLOW_CONTROL_ERROR",
+            SETTINGS_TIMEOUT => "SETTINGS_TIMEOUT",
+            STREAM_CLOSED => "STREAM_CLOSED",
+            FRAME_SIZE_ERROR => "FRAME_SIZE_ERROR",
+            REFUSED_STREAM => "REFUSED_STREAM",
+            CANCEL => "CANCEL",
+            COMPRESSION_ERROR => "COMPRESSION_ERROR",
+            CONNECT_ERROR => "CONNECT_ERROR",
+            ENHANCE_YOUR_CALM => "ENHANCE_YOUR_CALM",
+            INADEQUATE_SECURITY => "INADEQUATE_SECURITY",
+            HTTP_1_1_REQUIRED => "HTTP_1_1_REQUIRED",
+            ErrorCode(code) => return write!(f, "unknown error code {:#x}", code),
+        };
+        f.write_str(name)
+    }
+}
+
+/// An error that ends either a single stream or the whole connection.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Http2Error {
+    /// Ends the connection with a GOAWAY frame with the given code, for the given reason.
+    Connection(ErrorCode, &'static str),
+    /// Ends the stream with the given ID with a RST_STREAM frame with the given code.
+    Stream(u32, ErrorCode),
+}
+
+impl Display for Http2Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Http2Error::Connection(code, reason) => write!(f, "connection error {}: {}", code, reason),
+            Http2Error::Stream(stream_id, code) => write!(f, "stream error {} on stream {}", code, stream_id),
+        }
+    }
+}
+
+impl std::error::Error for Http2Error {}
@@ -0,0 +1,383 @@
+use std::convert::TryInto;
+
+use crate::http2::error::{ErrorCode, FRAME_SIZE_ERROR, Http2Error, PROTOCOL_ERROR};
+
+/// Type of frames that carry request and response bodies.
+pub const DATA: u8 = 0x0;
+/// Type of frames that open a stream and carry a header block.
+pub const HEADERS: u8 = 0x1;
+/// Type of frames that set the priority of a stream.
+pub const PRIORITY: u8 = 0x2;
+/// Type of frames that end a stream immediately.
+pub const RST_STREAM: u8 = 0x3;
+/// Type of frames that change the settings of the connection or acknowledge a change.
+pub const SETTINGS: u8 = 0x4;
+/// Type of frames that reserve a stream for a pushed response.
+pub const PUSH_PROMISE: u8 = 0x5;
+/// Type of frames used to measure round trips and check the connection is alive.
+pub const PING: u8 = 0x6;
+/// Type of frames that start shutting down the connection.
+pub const GOAWAY: u8 = 0x7;
+/// Type of frames that increase a flow-control window.
+pub const WINDOW_UPDATE: u8 = 0x8;
+/// Type of frames that continue the header block of a HEADERS or PUSH_PROMISE frame.
+pub const CONTINUATION: u8 = 0x9;
+
+/// Flag for the last frame the sender sends on a stream.
+const END_STREAM: u8 = 0x1;
+/// Flag for SETTINGS and PING frames that acknowledge a frame from the peer.
+const ACK: u8 = 0x1;
+/// Flag for the last frame of a header block.
+const END_HEADERS: u8 = 0x4;
+/// Flag for frames with padding.
+const PADDED: u8 = 0x8;
+/// Flag for HEADERS frames that set the priority of the stream.
+const PRIORITY_FLAG: u8 = 0x20;
+
+/// Length of the header of every frame.
+pub const FRAME_HEADER_LEN: usize = 9;
+
+/// Mask for stream IDs, which exclude the reserved most significant bit.
+const STREAM_ID_MASK: u32 = 0x7fffffff;
+
+/// The fields of a frame header.
+#[derive(Debug, Copy, Clone)]
+pub struct FrameHeader {
+    /// The length of the payload.
+    pub length: usize,
+    /// The type of the frame.
+    pub kind: u8,
+    pub flags: u8,
+    /// The stream the frame is on, or 0 for the connection.
+    pub stream_id: u32,
+}
+
+impl FrameHeader {
+    /// Reads a frame header from its encoded bytes.
+    pub fn from_bytes(header: [u8; FRAME_HEADER_LEN]) -> FrameHeader {
+        FrameHeader {
+            length: u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize,
+            kind: header[3],
+            flags: header[4],
+            stream_id: read_u32(&header[5..]) & STREAM_ID_MASK,
+        }
+    }
+}
+
+/// The priority of a stream, which is only a suggestion for the order in which streams are sent.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Priority {
+    /// The stream this stream depends on.
+    pub dependency: u32,
+    /// Whether this stream becomes the only dependency of the stream it depends on.
+    pub exclusive: bool,
+    /// The weight of the stream minus 1.
+    pub weight: u8,
+}
+
+/// A frame of an HTTP/2 connection.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Frame {
+    Data {
+        stream_id: u32,
+        data: Vec<u8>,
+        end_stream: bool,
+        /// The length of the padding, including the pad length field, which counts against flow control.
+        padding: usize,
+    },
+    Headers {
+        stream_id: u32,
+        /// The first fragment of the header block.
+        block: Vec<u8>,
+        end_stream: bool,
+        end_headers: bool,
+        priority: Option<Priority>,
+    },
+    Priority {
+        stream_id: u32,
+        priority: Priority,
+    },
+    RstStream {
+        stream_id: u32,
+        error: ErrorCode,
+    },
+    Settings {
+        ack: bool,
+        params: Vec<(u16, u32)>,
+    },
+    PushPromise {
+        stream_id: u32,
+        promised_stream_id: u32,
+        block: Vec<u8>,
+        end_headers: bool,
+    },
+    Ping {
+        ack: bool,
+        data: [u8; 8],
+    },
+    GoAway {
+        last_stream_id: u32,
+        error: ErrorCode,
+        debug_data: Vec<u8>,
+    },
+    WindowUpdate {
+        /// 0 for the window of the connection.
+        stream_id: u32,
+        increment: u32,
+    },
+    Continuation {
+        stream_id: u32,
+        block: Vec<u8>,
+        end_headers: bool,
+    },
+    /// A frame of an unknown type, which is ignored.
+    Unknown {
+        kind: u8,
+        stream_id: u32,
+    },
+}
+
+impl Frame {
+    /// Appends the encoded frame to out.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Frame::Data { stream_id, data, end_stream, padding } => {
+                let flags = if *end_stream { END_STREAM } else { 0 } | if *padding > 0 { PADDED } else { 0 };
+                write_frame_header(out, data.len() + padding, DATA, flags, *stream_id);
+                if *padding > 0 {
+                    out.push((padding - 1) as u8);
+                }
+                out.extend_from_slice(data);
+                out.resize(out.len() + padding.saturating_sub(1), 0);
+            }
+            Frame::Headers { stream_id, block, end_stream, end_headers, priority } => {
+                let flags = if *end_stream { END_STREAM } else { 0 }
+                    | if *end_headers { END_HEADERS } else { 0 }
+                    | if priority.is_some() { PRIORITY_FLAG } else { 0 };
+                write_frame_header(out, block.len() + if priority.is_some() { 5 } else { 0 }, HEADERS, flags, *stream_id);
+                if let Some(priority) = priority {
+                    write_priority(out, priority);
+                }
+                out.extend_from_slice(block);
+            }
+            Frame::Priority { stream_id, priority } => {
+                write_frame_header(out, 5, PRIORITY, 0, *stream_id);
+                write_priority(out, priority);
+            }
+            Frame::RstStream { stream_id, error } => {
+                write_frame_header(out, 4, RST_STREAM, 0, *stream_id);
+                out.extend_from_slice(&error.0.to_be_bytes());
+            }
+            Frame::Settings { ack, params } => {
+                write_frame_header(out, params.len() * 6, SETTINGS, if *ack { ACK } else { 0 }, 0);
+                for (id, value) in params {
+                    out.extend_from_slice(&id.to_be_bytes());
+                    out.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+            Frame::PushPromise { stream_id, promised_stream_id, block, end_headers } => {
+                write_frame_header(out, block.len() + 4, PUSH_PROMISE, if *end_headers { END_HEADERS } else { 0 }, *stream_id);
+                out.extend_from_slice(&promised_stream_id.to_be_bytes());
+                out.extend_from_slice(block);
+            }
+            Frame::Ping { ack, data } => {
+                write_frame_header(out, 8, PING, if *ack { ACK } else { 0 }, 0);
+                out.extend_from_slice(data);
+            }
+            Frame::GoAway { last_stream_id, error, debug_data } => {
+                write_frame_header(out, debug_data.len() + 8, GOAWAY, 0, 0);
+                out.extend_from_slice(&last_stream_id.to_be_bytes());
+                out.extend_from_slice(&error.0.to_be_bytes());
+                out.extend_from_slice(debug_data);
+            }
+            Frame::WindowUpdate { stream_id, increment } => {
+                write_frame_header(out, 4, WINDOW_UPDATE, 0, *stream_id);
+                out.extend_from_slice(&increment.to_be_bytes());
+            }
+            Frame::Continuation { stream_id, block, end_headers } => {
+                write_frame_header(out, block.len(), CONTINUATION, if *end_headers { END_HEADERS } else { 0 }, *stream_id);
+                out.extend_from_slice(block);
+            }
+            Frame::Unknown { kind, stream_id } => write_frame_header(out, 0, *kind, 0, *stream_id),
+        }
+    }
+}
+
+/// Appends a DATA frame with the given data to out, without copying the data into a frame first.
+pub fn encode_data(out: &mut Vec<u8>, stream_id: u32, data: &[u8], end_stream: bool) {
+    write_frame_header(out, data.len(), DATA, if end_stream { END_STREAM } else { 0 }, stream_id);
+    out.extend_from_slice(data);
+}
+
+/// Appends a HEADERS frame with the given header block to out, followed by CONTINUATION frames if the block is larger
+/// than the given maximum frame size.
+pub fn encode_header_block(out: &mut Vec<u8>, stream_id: u32, block: &[u8], end_stream: bool, max_frame_size: usize) {
+    let mut fragments = block.chunks(max_frame_size).peekable();
+    let mut kind = HEADERS;
+    let mut flags = if end_stream { END_STREAM } else { 0 };
+
+    // an empty block still needs a HEADERS frame
+    let first: &[u8] = fragments.next().unwrap_or(&[]);
+    let mut fragment = first;
+    loop {
+        let last = fragments.peek().is_none();
+        if last {
+            flags |= END_HEADERS;
+        }
+        write_frame_header(out, fragment.len(), kind, flags, stream_id);
+        out.extend_from_slice(fragment);
+
+        match fragments.next() {
+            Some(next) => fragment = next,
+            None => return,
+        }
+        kind = CONTINUATION;
+        flags = 0;
+    }
+}
+
+/// Writes a frame header to out.
+fn write_frame_header(out: &mut Vec<u8>, length: usize, kind: u8, flags: u8, stream_id: u32) {
+    out.extend_from_slice(&(length as u32).to_be_bytes()[1..]);
+    out.push(kind);
+    out.push(flags);
+    out.extend_from_slice(&(stream_id & STREAM_ID_MASK).to_be_bytes());
+}
+
+/// Writes the priority fields of a HEADERS or PRIORITY frame to out.
+fn write_priority(out: &mut Vec<u8>, priority: &Priority) {
+    let dependency = priority.dependency & STREAM_ID_MASK | if priority.exclusive { 1 << 31 } else { 0 };
+    out.extend_from_slice(&dependency.to_be_bytes());
+    out.push(priority.weight);
+}
+
+/// Decodes the payload of a frame with the given header. Frames that violate the protocol are decoded as errors, which
+/// end either the stream they are on or the whole connection.
+pub fn decode(header: FrameHeader, payload: Vec<u8>) -> Result<Frame, Http2Error> {
+    let FrameHeader { kind, flags, stream_id, .. } = header;
+    let on_stream = || if stream_id == 0 { Err(Http2Error::Connection(PROTOCOL_ERROR, "frame requires a stream")) } else { Ok(()) };
+    let on_connection = || if stream_id != 0 { Err(Http2Error::Connection(PROTOCOL_ERROR, "frame can not be on a stream")) } else { Ok(()) };
+    let frame_size_error = |reason| Err(Http2Error::Connection(FRAME_SIZE_ERROR, reason));
+
+    match kind {
+        DATA => {
+            on_stream()?;
+            let length = payload.len();
+            let data = strip_padding(payload, flags)?;
+            Ok(Frame::Data { stream_id, end_stream: flags & END_STREAM != 0, padding: length - data.len(), data })
+        }
+        HEADERS => {
+            on_stream()?;
+            let mut block = strip_padding(payload, flags)?;
+            let priority = if flags & PRIORITY_FLAG != 0 {
+                if block.len() < 5 {
+                    return frame_size_error("HEADERS frame too small for priority");
+                }
+                let priority = read_priority(&block);
+                block.drain(..5);
+                Some(priority)
+            } else {
+                None
+            };
+            Ok(Frame::Headers { stream_id, block, end_stream: flags & END_STREAM != 0, end_headers: flags & END_HEADERS != 0, priority })
+        }
+        PRIORITY => {
+            on_stream()?;
+            if payload.len() != 5 {
+                return Err(Http2Error::Stream(stream_id, FRAME_SIZE_ERROR));
+            }
+            Ok(Frame::Priority { stream_id, priority: read_priority(&payload) })
+        }
+        RST_STREAM => {
+            on_stream()?;
+            if payload.len() != 4 {
+                return frame_size_error("RST_STREAM frame must have 4 bytes");
+            }
+            Ok(Frame::RstStream { stream_id, error: ErrorCode(read_u32(&payload)) })
+        }
+        SETTINGS => {
+            on_connection()?;
+            let ack = flags & ACK != 0;
+            if ack && !payload.is_empty() {
+                return frame_size_error("SETTINGS acknowledgement must be empty");
+            }
+            if payload.len() % 6 != 0 {
+                return frame_size_error("SETTINGS frame length must be a multiple of 6");
+            }
+            let params = payload.chunks(6)
+                .map(|param| (u16::from_be_bytes([param[0], param[1]]), read_u32(&param[2..])))
+                .collect();
+            Ok(Frame::Settings { ack, params })
+        }
+        PUSH_PROMISE => {
+            on_stream()?;
+            let mut block = strip_padding(payload, flags)?;
+            if block.len() < 4 {
+                return frame_size_error("PUSH_PROMISE frame too small");
+            }
+            let promised_stream_id = read_u32(&block) & STREAM_ID_MASK;
+            block.drain(..4);
+            Ok(Frame::PushPromise { stream_id, promised_stream_id, block, end_headers: flags & END_HEADERS != 0 })
+        }
+        PING => {
+            on_connection()?;
+            match payload.as_slice().try_into() {
+                Ok(data) => Ok(Frame::Ping { ack: flags & ACK != 0, data }),
+                Err(_) => frame_size_error("PING frame must have 8 bytes"),
+            }
+        }
+        GOAWAY => {
+            on_connection()?;
+            if payload.len() < 8 {
+                return frame_size_error("GOAWAY frame too small");
+            }
+            Ok(Frame::GoAway {
+                last_stream_id: read_u32(&payload) & STREAM_ID_MASK,
+                error: ErrorCode(read_u32(&payload[4..])),
+                debug_data: payload[8..].to_vec(),
+            })
+        }
+        WINDOW_UPDATE => {
+            if payload.len() != 4 {
+                return frame_size_error("WINDOW_UPDATE frame must have 4 bytes");
+            }
+            let increment = read_u32(&payload) & STREAM_ID_MASK;
+            match (increment, stream_id) {
+                (0, 0) => Err(Http2Error::Connection(PROTOCOL_ERROR, "window increment of 0")),
+                (0, stream_id) => Err(Http2Error::Stream(stream_id, PROTOCOL_ERROR)),
+                (increment, stream_id) => Ok(Frame::WindowUpdate { stream_id, increment }),
+            }
+        }
+        CONTINUATION => {
+            on_stream()?;
+            Ok(Frame::Continuation { stream_id, block: payload, end_headers: flags & END_HEADERS != 0 })
+        }
+        kind => Ok(Frame::Unknown { kind, stream_id }),
+    }
+}
+
+/// Removes the pad length field and the padding from the payload of a frame with the given flags, if it is padded.
+fn strip_padding(mut payload: Vec<u8>, flags: u8) -> Result<Vec<u8>, Http2Error> {
+    if flags & PADDED == 0 {
+        return Ok(payload);
+    }
+
+    let pad_length = match payload.first() {
+        Some(pad_length) if (*pad_length as usize) < payload.len() => *pad_length as usize,
+        _ => return Err(Http2Error::Connection(PROTOCOL_ERROR, "padding exceeds frame payload")),
+    };
+    payload.truncate(payload.len() - pad_length);
+    payload.remove(0);
+    Ok(payload)
+}
+
+/// Reads the priority fields at the start of the given data.
+fn read_priority(data: &[u8]) -> Priority {
+    let dependency = read_u32(data);
+    Priority { dependency: dependency & STREAM_ID_MASK, exclusive: dependency & !STREAM_ID_MASK != 0, weight: data[4] }
+}
+
+/// Reads a big-endian integer at the start of the given data.
+fn read_u32(data: &[u8]) -> u32 {
+    u32::from_be_bytes([data[0], data[1], data[2], data[3]])
+}
@@ -0,0 +1,214 @@
+use crate::http2::hpack::{DEFAULT_TABLE_SIZE, HpackError, huffman};
+use crate::http2::hpack::table::Table;
+
+/// The overhead counted for each header field in the size of a header list, in addition to the length of its name and value.
+const FIELD_OVERHEAD: usize = 32;
+
+/// Decoder for header blocks. Keeps the dynamic table shared by the blocks of a connection.
+pub struct Decoder {
+    table: Table,
+    /// The largest dynamic table size the encoder may switch to, which is the header table size setting sent to it.
+    max_table_size: usize,
+    /// The largest size of a decoded header list.
+    max_header_list_size: usize,
+}
+
+impl Decoder {
+    /// Creates a decoder that rejects header lists larger than the given size.
+    pub fn new(max_header_list_size: usize) -> Decoder {
+        Decoder { table: Table::new(DEFAULT_TABLE_SIZE), max_table_size: DEFAULT_TABLE_SIZE, max_header_list_size }
+    }
+
+    /// Decodes the given header block into a list of header fields.
+    pub fn decode(&mut self, block: &[u8]) -> Result<Vec<(String, String)>, HpackError> {
+        let mut reader = BlockReader { block, pos: 0 };
+        let mut fields = vec![];
+        let mut list_size = 0;
+        let mut at_start = true;
+
+        while let Some(byte) = reader.peek() {
+            let field = if byte & 0x80 != 0 {
+                // indexed field
+                let index = reader.read_integer(7)?;
+                let (name, value) = self.table.get(index).ok_or(HpackError::InvalidIndex)?;
+                (name.to_string(), value.to_string())
+            } else if byte & 0x40 != 0 {
+                // literal with incremental indexing
+                let (name, value) = self.read_literal(&mut reader, 6)?;
+                self.table.insert(name.clone(), value.clone());
+                (name, value)
+            } else if byte & 0x20 != 0 {
+                // dynamic table size update, which only appears at the start of a block
+                let size = reader.read_integer(5)?;
+                if !at_start || size > self.max_table_size {
+                    return Err(HpackError::InvalidTableSizeUpdate);
+                }
+                self.table.set_max_size(size);
+                continue;
+            } else {
+                // literal without indexing, or never indexed
+                self.read_literal(&mut reader, 4)?
+            };
+
+            at_start = false;
+            list_size += field.0.len() + field.1.len() + FIELD_OVERHEAD;
+            // the rest of the block is still decoded to keep the dynamic table in sync with the encoder
+            if list_size <= self.max_header_list_size {
+                fields.push(field);
+            }
+        }
+
+        if list_size > self.max_header_list_size {
+            return Err(HpackError::HeaderListTooLarge);
+        }
+
+        Ok(fields)
+    }
+
+    /// Reads a literal field whose name index has the given prefix length, where 0 means the name is a literal.
+    fn read_literal(&self, reader: &mut BlockReader, prefix: u8) -> Result<(String, String), HpackError> {
+        let name = match reader.read_integer(prefix)? {
+            0 => reader.read_string()?,
+            index => self.table.get(index).ok_or(HpackError::InvalidIndex)?.0.to_string(),
+        };
+        let value = reader.read_string()?;
+        Ok((name, value))
+    }
+}
+
+/// Reads the primitive representations of a header block.
+struct BlockReader<'a> {
+    block: &'a [u8],
+    pos: usize,
+}
+
+impl BlockReader<'_> {
+    /// Gets the next byte without consuming it.
+    fn peek(&self) -> Option<u8> {
+        self.block.get(self.pos).copied()
+    }
+
+    /// Reads the next byte.
+    fn read_byte(&mut self) -> Result<u8, HpackError> {
+        let byte = self.peek().ok_or(HpackError::Truncated)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    /// Reads an integer that starts in the low bits of the next byte, whose number is given by prefix.
+    fn read_integer(&mut self, prefix: u8) -> Result<usize, HpackError> {
+        let max_prefix = (1 << prefix) - 1;
+        let mut value = (self.read_byte()? & max_prefix) as usize;
+        if value < max_prefix as usize {
+            return Ok(value);
+        }
+
+        let mut shift = 0;
+        loop {
+            let byte = self.read_byte()?;
+            // limiting the integer to 28 bits leaves room for any valid size or index
+            if shift > 21 {
+                return Err(HpackError::IntegerOverflow);
+            }
+            value += ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// Reads a string literal, which may be Huffman encoded.
+    fn read_string(&mut self) -> Result<String, HpackError> {
+        let huffman_encoded = self.peek().ok_or(HpackError::Truncated)? & 0x80 != 0;
+        let length = self.read_integer(7)?;
+        let end = self.pos.checked_add(length).filter(|end| *end <= self.block.len()).ok_or(HpackError::Truncated)?;
+        let data = &self.block[self.pos..end];
+        self.pos = end;
+
+        let data = if huffman_encoded { huffman::decode(data)? } else { data.to_vec() };
+        String::from_utf8(data).map_err(|_| HpackError::InvalidUtf8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http2::hpack::{Decoder, HpackError};
+
+    fn fields(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn rfc_requests_without_huffman() {
+        // from RFC 7541 appendix C.3
+        let mut decoder = Decoder::new(16384);
+        assert_eq!(
+            decoder.decode(b"\x82\x86\x84\x41\x0fwww.example.com").unwrap(),
+            fields(&[(":method", "GET"), (":scheme", "http"), (":path", "/"), (":authority", "www.example.com")])
+        );
+        assert_eq!(
+            decoder.decode(b"\x82\x86\x84\xbe\x58\x08no-cache").unwrap(),
+            fields(&[(":method", "GET"), (":scheme", "http"), (":path", "/"), (":authority", "www.example.com"), ("cache-control", "no-cache")])
+        );
+        assert_eq!(
+            decoder.decode(b"\x82\x87\x85\xbf\x40\x0acustom-key\x0ccustom-value").unwrap(),
+            fields(&[(":method", "GET"), (":scheme", "https"), (":path", "/index.html"), (":authority", "www.example.com"), ("custom-key", "custom-value")])
+        );
+    }
+
+    #[test]
+    fn rfc_requests_with_huffman() {
+        // from RFC 7541 appendix C.4
+        let mut decoder = Decoder::new(16384);
+        assert_eq!(
+            decoder.decode(&[0x82, 0x86, 0x84, 0x41, 0x8c, 0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab, 0x90, 0xf4, 0xff]).unwrap(),
+            fields(&[(":method", "GET"), (":scheme", "http"), (":path", "/"), (":authority", "www.example.com")])
+        );
+        assert_eq!(
+            decoder.decode(&[0x82, 0x86, 0x84, 0xbe, 0x58, 0x86, 0xa8, 0xeb, 0x10, 0x64, 0x9c, 0xbf]).unwrap(),
+            fields(&[(":method", "GET"), (":scheme", "http"), (":path", "/"), (":authority", "www.example.com"), ("cache-control", "no-cache")])
+        );
+    }
+
+    #[test]
+    fn never_indexed_literal() {
+        // from RFC 7541 appendix C.2.3
+        let mut decoder = Decoder::new(16384);
+        assert_eq!(decoder.decode(b"\x10\x08password\x06secret").unwrap(), fields(&[("password", "secret")]));
+        assert_eq!(decoder.decode(b"\xbe"), Err(HpackError::InvalidIndex));
+    }
+
+    #[test]
+    fn table_size_update() {
+        let mut decoder = Decoder::new(16384);
+        assert_eq!(decoder.decode(b"\x40\x01a\x01b").unwrap(), fields(&[("a", "b")]));
+        assert_eq!(decoder.decode(b"\x20\xbe"), Err(HpackError::InvalidIndex));
+        // too large
+        assert_eq!(decoder.decode(b"\x3f\xe2\x1f"), Err(HpackError::InvalidTableSizeUpdate));
+        // not at the start of the block
+        assert_eq!(decoder.decode(b"\x82\x20"), Err(HpackError::InvalidTableSizeUpdate));
+    }
+
+    #[test]
+    fn truncated() {
+        let mut decoder = Decoder::new(16384);
+        assert_eq!(decoder.decode(b"\x41\x0fwww.example"), Err(HpackError::Truncated));
+        assert_eq!(decoder.decode(b"\x7f"), Err(HpackError::Truncated));
+        assert_eq!(decoder.decode(b"\x7f\xff\xff\xff\xff\xff\x01"), Err(HpackError::IntegerOverflow));
+    }
+
+    #[test]
+    fn header_list_too_large() {
+        let mut decoder = Decoder::new(40);
+        assert_eq!(decoder.decode(b"\x40\x01a\x01b\x40\x01c\x01d"), Err(HpackError::HeaderListTooLarge));
+        // both fields were still added to the table
+        assert_eq!(decoder.decode(b"\xbf").unwrap(), fields(&[("a", "b")]));
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let mut decoder = Decoder::new(16384);
+        assert_eq!(decoder.decode(b"\x00\x01a\x01\xff"), Err(HpackError::InvalidUtf8));
+    }
+}
@@ -0,0 +1,173 @@
+use crate::http2::hpack::{DEFAULT_TABLE_SIZE, huffman};
+use crate::http2::hpack::table::{Match, Table};
+
+/// Headers whose values are never added to the dynamic table, so they can not be recovered by compression attacks.
+const SENSITIVE_HEADERS: [&str; 4] = ["authorization", "proxy-authorization", "cookie", "set-cookie"];
+
+/// Encoder for header blocks. Keeps the dynamic table shared by the blocks of a connection.
+pub struct Encoder {
+    table: Table,
+    /// The smallest table size since the last block, if the size has changed, so the decoder evicts the same entries.
+    size_update: Option<usize>,
+}
+
+impl Encoder {
+    /// Creates an encoder with the default dynamic table size.
+    pub fn new() -> Encoder {
+        Encoder { table: Table::new(DEFAULT_TABLE_SIZE), size_update: None }
+    }
+
+    /// Sets the maximum size of the dynamic table, which is the header table size setting of the decoder.
+    /// The table never grows beyond the default size.
+    pub fn set_max_table_size(&mut self, size: usize) {
+        let size = size.min(DEFAULT_TABLE_SIZE);
+        if size != self.table.max_size() {
+            self.size_update = Some(self.size_update.map_or(size, |update| update.min(size)));
+            self.table.set_max_size(size);
+        }
+    }
+
+    /// Encodes the given header fields as a header block and appends it to out.
+    /// Names must be lowercase.
+    pub fn encode<'a>(&mut self, fields: impl IntoIterator<Item=(&'a str, &'a str)>, out: &mut Vec<u8>) {
+        if let Some(size) = self.size_update.take() {
+            encode_integer(size, 5, 0x20, out);
+            if size != self.table.max_size() {
+                encode_integer(self.table.max_size(), 5, 0x20, out);
+            }
+        }
+
+        for (name, value) in fields {
+            let sensitive = SENSITIVE_HEADERS.contains(&name);
+            let name_index = match self.table.find(name, value) {
+                Some(Match::Field(index)) if !sensitive => {
+                    encode_integer(index, 7, 0x80, out);
+                    continue;
+                }
+                Some(Match::Field(index)) | Some(Match::Name(index)) => index,
+                None => 0,
+            };
+
+            // entries that do not fit in the table would only empty it
+            let fits = name.len() + value.len() + 32 <= self.table.max_size();
+            if sensitive {
+                encode_integer(name_index, 4, 0x10, out);
+            } else if fits {
+                encode_integer(name_index, 6, 0x40, out);
+                self.table.insert(name.to_string(), value.to_string());
+            } else {
+                encode_integer(name_index, 4, 0x00, out);
+            }
+            if name_index == 0 {
+                encode_string(name, out);
+            }
+            encode_string(value, out);
+        }
+    }
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Encoder::new()
+    }
+}
+
+/// Encodes an integer in the low bits of a byte, whose number is given by prefix, with the given high bits set.
+fn encode_integer(value: usize, prefix: u8, flags: u8, out: &mut Vec<u8>) {
+    let max_prefix = (1usize << prefix) - 1;
+    if value < max_prefix {
+        out.push(flags | value as u8);
+        return;
+    }
+
+    out.push(flags | max_prefix as u8);
+    let mut value = value - max_prefix;
+    while value >= 0x80 {
+        out.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Encodes a string literal, using the Huffman code if it is shorter.
+fn encode_string(value: &str, out: &mut Vec<u8>) {
+    let huffman_len = huffman::encoded_len(value.as_bytes());
+    if huffman_len < value.len() {
+        encode_integer(huffman_len, 7, 0x80, out);
+        huffman::encode(value.as_bytes(), out);
+    } else {
+        encode_integer(value.len(), 7, 0x00, out);
+        out.extend_from_slice(value.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http2::hpack::{Decoder, Encoder};
+    use crate::http2::hpack::encoder::encode_integer;
+
+    fn round_trip(encoder: &mut Encoder, decoder: &mut Decoder, fields: &[(&str, &str)]) -> Vec<u8> {
+        let mut block = vec![];
+        encoder.encode(fields.iter().copied(), &mut block);
+        let decoded = decoder.decode(&block).unwrap();
+        let expected: Vec<(String, String)> = fields.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        assert_eq!(decoded, expected);
+        block
+    }
+
+    #[test]
+    fn integers() {
+        // from RFC 7541 appendix C.1
+        let mut out = vec![];
+        encode_integer(10, 5, 0, &mut out);
+        encode_integer(1337, 5, 0, &mut out);
+        encode_integer(42, 8, 0, &mut out);
+        assert_eq!(out, vec![0x0a, 0x1f, 0x9a, 0x0a, 0x2a]);
+    }
+
+    #[test]
+    fn rfc_responses() {
+        // from RFC 7541 appendix C.6, except the first block is smaller since cache-control is not in the table yet
+        let mut encoder = Encoder::new();
+        let mut decoder = Decoder::new(16384);
+        let first = [(":status", "302"), ("cache-control", "private"), ("date", "Mon, 21 Oct 2013 20:13:21 GMT"), ("location", "https://www.example.com")];
+        round_trip(&mut encoder, &mut decoder, &first);
+
+        // every field is indexed now
+        assert_eq!(round_trip(&mut encoder, &mut decoder, &first), vec![0xc1, 0xc0, 0xbf, 0xbe]);
+        assert_eq!(round_trip(&mut encoder, &mut decoder, &[(":status", "200"), ("cache-control", "private")]), vec![0x88, 0xc0]);
+    }
+
+    #[test]
+    fn sensitive_fields_not_indexed() {
+        let mut encoder = Encoder::new();
+        let mut decoder = Decoder::new(16384);
+        let fields = [("authorization", "secret"), ("cookie", "a=b")];
+        let first = round_trip(&mut encoder, &mut decoder, &fields);
+        assert_eq!(first[0] & 0xf0, 0x10);
+        assert_eq!(round_trip(&mut encoder, &mut decoder, &fields), first);
+    }
+
+    #[test]
+    fn large_fields_not_indexed() {
+        let mut encoder = Encoder::new();
+        let mut decoder = Decoder::new(16384);
+        let value = "a".repeat(5000);
+        round_trip(&mut encoder, &mut decoder, &[("small", "value"), ("large", &value)]);
+        assert_eq!(round_trip(&mut encoder, &mut decoder, &[("small", "value")]), vec![0xbe]);
+    }
+
+    #[test]
+    fn table_size_changes() {
+        let mut encoder = Encoder::new();
+        let mut decoder = Decoder::new(16384);
+        round_trip(&mut encoder, &mut decoder, &[("a", "b")]);
+
+        encoder.set_max_table_size(0);
+        encoder.set_max_table_size(100);
+        let block = round_trip(&mut encoder, &mut decoder, &[("a", "b")]);
+        // the size drops to 0, which empties the table, and then rises to 100
+        assert_eq!(&block[..3], &[0x20, 0x3f, 0x45]);
+        assert_eq!(round_trip(&mut encoder, &mut decoder, &[("a", "b")]), vec![0xbe]);
+    }
+}
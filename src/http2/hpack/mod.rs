@@ -0,0 +1,34 @@
+pub use decoder::*;
+pub use encoder::*;
+
+/// Decoding header blocks.
+mod decoder;
+/// Encoding header blocks.
+mod encoder;
+/// The static and dynamic tables of header fields.
+mod table;
+/// The Huffman code for string literals.
+mod huffman;
+
+/// The default maximum size of the dynamic table.
+pub const DEFAULT_TABLE_SIZE: usize = 4096;
+
+/// Error decoding a header block. Any error other than HeaderListTooLarge leaves the dynamic table in an unknown
+/// state, so the connection can not continue.
+#[derive(Debug, Eq, PartialEq)]
+pub enum HpackError {
+    /// The block ended in the middle of a representation.
+    Truncated,
+    /// An integer does not fit in the range accepted by the decoder.
+    IntegerOverflow,
+    /// An index does not refer to an entry of the table.
+    InvalidIndex,
+    /// A string literal has an invalid Huffman code or padding.
+    InvalidHuffmanCode,
+    /// A string literal is not valid UTF-8.
+    InvalidUtf8,
+    /// A dynamic table size update exceeds the maximum size, or does not appear at the start of a block.
+    InvalidTableSizeUpdate,
+    /// The decoded header list exceeds the maximum size. The block was still decoded, so the dynamic table is intact.
+    HeaderListTooLarge,
+}
@@ -0,0 +1,210 @@
+use std::collections::VecDeque;
+
+/// The static table from RFC 7541 appendix A. Index 1 is the first entry.
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// The overhead counted for each entry in addition to the length of its name and value.
+const ENTRY_OVERHEAD: usize = 32;
+
+/// A match for a header field in the table.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Match {
+    /// An entry has the same name and value.
+    Field(usize),
+    /// An entry has the same name, but a different value.
+    Name(usize),
+}
+
+/// The static table followed by a dynamic table, which evicts its oldest entries to stay within its maximum size.
+pub struct Table {
+    /// The dynamic entries, newest first.
+    entries: VecDeque<(String, String)>,
+    size: usize,
+    max_size: usize,
+}
+
+impl Table {
+    /// Creates a table whose dynamic part has the given maximum size.
+    pub fn new(max_size: usize) -> Table {
+        Table { entries: VecDeque::new(), size: 0, max_size }
+    }
+
+    /// Gets the maximum size of the dynamic part of the table.
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Sets the maximum size of the dynamic part of the table, evicting entries until it fits.
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+        self.evict(0);
+    }
+
+    /// Gets the entry at the given index, where 1 is the first entry of the static table.
+    pub fn get(&self, index: usize) -> Option<(&str, &str)> {
+        match index {
+            0 => None,
+            index if index <= STATIC_TABLE.len() => Some(STATIC_TABLE[index - 1]),
+            index => self.entries.get(index - STATIC_TABLE.len() - 1).map(|(name, value)| (name.as_str(), value.as_str())),
+        }
+    }
+
+    /// Adds an entry to the dynamic table. An entry larger than the maximum size empties the table.
+    pub fn insert(&mut self, name: String, value: String) {
+        let size = entry_size(&name, &value);
+        self.evict(size);
+        if size <= self.max_size {
+            self.size += size;
+            self.entries.push_front((name, value));
+        }
+    }
+
+    /// Finds the entry that best matches the given header field.
+    pub fn find(&self, name: &str, value: &str) -> Option<Match> {
+        let dynamic_entries = self.entries.iter().map(|(name, value)| (name.as_str(), value.as_str()));
+        let mut name_match = None;
+
+        for (index, (entry_name, entry_value)) in STATIC_TABLE.iter().copied().chain(dynamic_entries).enumerate() {
+            if entry_name == name {
+                if entry_value == value {
+                    return Some(Match::Field(index + 1));
+                }
+                name_match.get_or_insert(index + 1);
+            }
+        }
+
+        name_match.map(Match::Name)
+    }
+
+    /// Evicts the oldest entries until an entry of the given size fits.
+    fn evict(&mut self, size: usize) {
+        while self.size + size > self.max_size {
+            match self.entries.pop_back() {
+                Some((name, value)) => self.size -= entry_size(&name, &value),
+                None => break,
+            }
+        }
+    }
+}
+
+/// Gets the size an entry counts for in the table.
+fn entry_size(name: &str, value: &str) -> usize {
+    name.len() + value.len() + ENTRY_OVERHEAD
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http2::hpack::table::{Match, Table};
+
+    #[test]
+    fn static_entries() {
+        let table = Table::new(4096);
+        assert_eq!(table.get(0), None);
+        assert_eq!(table.get(1), Some((":authority", "")));
+        assert_eq!(table.get(8), Some((":status", "200")));
+        assert_eq!(table.get(61), Some(("www-authenticate", "")));
+        assert_eq!(table.get(62), None);
+    }
+
+    #[test]
+    fn dynamic_entries_newest_first() {
+        let mut table = Table::new(4096);
+        table.insert("a".to_string(), "1".to_string());
+        table.insert("b".to_string(), "2".to_string());
+        assert_eq!(table.get(62), Some(("b", "2")));
+        assert_eq!(table.get(63), Some(("a", "1")));
+        assert_eq!(table.get(64), None);
+    }
+
+    #[test]
+    fn eviction() {
+        // room for two entries of size 34
+        let mut table = Table::new(70);
+        table.insert("a".to_string(), "1".to_string());
+        table.insert("b".to_string(), "2".to_string());
+        table.insert("c".to_string(), "3".to_string());
+        assert_eq!(table.get(62), Some(("c", "3")));
+        assert_eq!(table.get(63), Some(("b", "2")));
+        assert_eq!(table.get(64), None);
+
+        table.set_max_size(40);
+        assert_eq!(table.get(62), Some(("c", "3")));
+        assert_eq!(table.get(63), None);
+
+        table.insert("too large".to_string(), "value".to_string());
+        assert_eq!(table.get(62), None);
+    }
+
+    #[test]
+    fn find() {
+        let mut table = Table::new(4096);
+        table.insert("custom".to_string(), "value".to_string());
+        assert_eq!(table.find(":method", "GET"), Some(Match::Field(2)));
+        assert_eq!(table.find(":method", "PUT"), Some(Match::Name(2)));
+        assert_eq!(table.find("custom", "value"), Some(Match::Field(62)));
+        assert_eq!(table.find("custom", "other"), Some(Match::Name(62)));
+        assert_eq!(table.find("unknown", ""), None);
+    }
+}
@@ -0,0 +1,19 @@
+/// Frames and their encoding.
+pub mod frame;
+/// HPACK header compression.
+pub mod hpack;
+/// Settings of a connection.
+pub mod settings;
+/// Error codes and the errors that end streams and connections.
+pub mod error;
+/// Flow-control windows.
+pub mod window;
+
+/// The connection preface, which a client sends before its first frame.
+pub const PREFACE: &[u8; 24] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Headers that only apply to HTTP/1.1 connections, so they are not allowed in HTTP/2 messages.
+pub const CONNECTION_SPECIFIC_HEADERS: [&str; 5] = ["connection", "keep-alive", "proxy-connection", "transfer-encoding", "upgrade"];
+
+/// The amount of received data after which a WINDOW_UPDATE frame is sent, which is half of the initial window.
+pub const WINDOW_UPDATE_THRESHOLD: usize = settings::DEFAULT_WINDOW_SIZE as usize / 2;
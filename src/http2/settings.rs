@@ -0,0 +1,142 @@
+use crate::http2::error::{FLOW_CONTROL_ERROR, Http2Error, PROTOCOL_ERROR};
+use crate::http2::window::MAX_WINDOW_SIZE;
+
+/// Identifier of the setting for the maximum size of the dynamic table used to decode header blocks.
+pub const HEADER_TABLE_SIZE: u16 = 0x1;
+/// Identifier of the setting for whether server push is allowed.
+pub const ENABLE_PUSH: u16 = 0x2;
+/// Identifier of the setting for the maximum number of streams the sender allows the peer to open at once.
+pub const MAX_CONCURRENT_STREAMS: u16 = 0x3;
+/// Identifier of the setting for the initial flow-control window size of streams.
+pub const INITIAL_WINDOW_SIZE: u16 = 0x4;
+/// Identifier of the setting for the largest frame payload the sender accepts.
+pub const MAX_FRAME_SIZE: u16 = 0x5;
+/// Identifier of the setting for the largest header list the sender accepts.
+pub const MAX_HEADER_LIST_SIZE: u16 = 0x6;
+
+/// The initial flow-control window size of streams and connections.
+pub const DEFAULT_WINDOW_SIZE: u32 = 65535;
+
+/// The smallest value of the maximum frame size setting, which is also its initial value.
+pub const DEFAULT_MAX_FRAME_SIZE: u32 = 16384;
+
+/// The largest value of the maximum frame size setting.
+const MAX_MAX_FRAME_SIZE: u32 = (1 << 24) - 1;
+
+/// The settings of one side of a connection.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Settings {
+    /// The maximum size of the dynamic table used to decode header blocks.
+    pub header_table_size: u32,
+    /// Whether server push is allowed. Only sent by clients.
+    pub enable_push: bool,
+    /// The maximum number of streams the peer may open at once, or None for no limit.
+    pub max_concurrent_streams: Option<u32>,
+    /// The initial flow-control window size of streams.
+    pub initial_window_size: u32,
+    /// The largest frame payload accepted.
+    pub max_frame_size: u32,
+    /// The largest header list accepted, or None for no limit.
+    pub max_header_list_size: Option<u32>,
+}
+
+impl Settings {
+    /// Applies the parameters of a SETTINGS frame. Returns an error without applying any parameters if one of them
+    /// has an invalid value. Unknown parameters are ignored.
+    pub fn apply(&mut self, params: &[(u16, u32)]) -> Result<(), Http2Error> {
+        let mut settings = *self;
+        for (id, value) in params {
+            let value = *value;
+            match *id {
+                HEADER_TABLE_SIZE => settings.header_table_size = value,
+                ENABLE_PUSH if value > 1 => return Err(Http2Error::Connection(PROTOCOL_ERROR, "invalid enable push setting")),
+                ENABLE_PUSH => settings.enable_push = value == 1,
+                MAX_CONCURRENT_STREAMS => settings.max_concurrent_streams = Some(value),
+                INITIAL_WINDOW_SIZE if value as i64 > MAX_WINDOW_SIZE =>
+                    return Err(Http2Error::Connection(FLOW_CONTROL_ERROR, "initial window size setting too large")),
+                INITIAL_WINDOW_SIZE => settings.initial_window_size = value,
+                MAX_FRAME_SIZE if !(DEFAULT_MAX_FRAME_SIZE..=MAX_MAX_FRAME_SIZE).contains(&value) =>
+                    return Err(Http2Error::Connection(PROTOCOL_ERROR, "invalid max frame size setting")),
+                MAX_FRAME_SIZE => settings.max_frame_size = value,
+                MAX_HEADER_LIST_SIZE => settings.max_header_list_size = Some(value),
+                _ => {}
+            }
+        }
+        *self = settings;
+        Ok(())
+    }
+
+    /// Gets the parameters of a SETTINGS frame for each setting that differs from its initial value.
+    pub fn changes(&self) -> Vec<(u16, u32)> {
+        let initial = Settings::default();
+        let mut params = vec![];
+        if self.header_table_size != initial.header_table_size {
+            params.push((HEADER_TABLE_SIZE, self.header_table_size));
+        }
+        if self.enable_push != initial.enable_push {
+            params.push((ENABLE_PUSH, self.enable_push as u32));
+        }
+        if let Some(max_concurrent_streams) = self.max_concurrent_streams {
+            params.push((MAX_CONCURRENT_STREAMS, max_concurrent_streams));
+        }
+        if self.initial_window_size != initial.initial_window_size {
+            params.push((INITIAL_WINDOW_SIZE, self.initial_window_size));
+        }
+        if self.max_frame_size != initial.max_frame_size {
+            params.push((MAX_FRAME_SIZE, self.max_frame_size));
+        }
+        if let Some(max_header_list_size) = self.max_header_list_size {
+            params.push((MAX_HEADER_LIST_SIZE, max_header_list_size));
+        }
+        params
+    }
+}
+
+impl Default for Settings {
+    /// Gets the initial settings of a connection, which apply until the first SETTINGS frame is received.
+    fn default() -> Self {
+        Settings {
+            header_table_size: 4096,
+            enable_push: true,
+            max_concurrent_streams: None,
+            initial_window_size: DEFAULT_WINDOW_SIZE,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            max_header_list_size: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http2::error::{FLOW_CONTROL_ERROR, Http2Error, PROTOCOL_ERROR};
+    use crate::http2::settings::{ENABLE_PUSH, INITIAL_WINDOW_SIZE, MAX_CONCURRENT_STREAMS, MAX_FRAME_SIZE, Settings};
+
+    #[test]
+    fn apply() {
+        let mut settings = Settings::default();
+        settings.apply(&[(ENABLE_PUSH, 0), (MAX_CONCURRENT_STREAMS, 10), (0xff, 1), (MAX_FRAME_SIZE, 20000)]).unwrap();
+        assert_eq!(settings, Settings {
+            enable_push: false,
+            max_concurrent_streams: Some(10),
+            max_frame_size: 20000,
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn invalid_values() {
+        let mut settings = Settings::default();
+        assert!(matches!(settings.apply(&[(MAX_CONCURRENT_STREAMS, 10), (ENABLE_PUSH, 2)]), Err(Http2Error::Connection(PROTOCOL_ERROR, _))));
+        assert!(matches!(settings.apply(&[(INITIAL_WINDOW_SIZE, 1 << 31)]), Err(Http2Error::Connection(FLOW_CONTROL_ERROR, _))));
+        assert!(matches!(settings.apply(&[(MAX_FRAME_SIZE, 100)]), Err(Http2Error::Connection(PROTOCOL_ERROR, _))));
+        assert!(matches!(settings.apply(&[(MAX_FRAME_SIZE, 1 << 24)]), Err(Http2Error::Connection(PROTOCOL_ERROR, _))));
+        assert_eq!(settings, Settings::default());
+    }
+
+    #[test]
+    fn changes() {
+        assert_eq!(Settings::default().changes(), vec![]);
+        let settings = Settings { enable_push: false, max_concurrent_streams: Some(100), ..Default::default() };
+        assert_eq!(settings.changes(), vec![(ENABLE_PUSH, 0), (MAX_CONCURRENT_STREAMS, 100)]);
+    }
+}
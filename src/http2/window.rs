@@ -0,0 +1,85 @@
+use crate::http2::error::{FLOW_CONTROL_ERROR, Http2Error};
+
+/// The largest size of a flow-control window.
+pub const MAX_WINDOW_SIZE: i64 = (1 << 31) - 1;
+
+/// A flow-control window, which is the amount of data that may be sent before the receiver allows more.
+/// The window of a stream can become negative when the initial window size setting is reduced.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Window(i64);
+
+impl Window {
+    /// Creates a window of the given size.
+    pub fn new(size: u32) -> Window {
+        Window(size as i64)
+    }
+
+    /// Gets the amount of data that may be sent right now.
+    pub fn available(&self) -> usize {
+        self.0.max(0) as usize
+    }
+
+    /// Takes the given amount of data out of the window. Returns false, leaving the window unchanged, if the data
+    /// does not fit.
+    pub fn consume(&mut self, amount: usize) -> bool {
+        if amount > self.available() {
+            return false;
+        }
+        self.0 -= amount as i64;
+        true
+    }
+
+    /// Grows the window by the given amount, which may be negative when the initial window size setting is reduced.
+    /// Returns a FLOW_CONTROL_ERROR for the given stream, or the connection if the ID is 0, if the window grows too large.
+    pub fn increase(&mut self, amount: i64, stream_id: u32) -> Result<(), Http2Error> {
+        if self.0 + amount > MAX_WINDOW_SIZE {
+            return Err(flow_control_error(stream_id));
+        }
+        self.0 += amount;
+        Ok(())
+    }
+}
+
+/// Gets a FLOW_CONTROL_ERROR for the given stream, or for the connection if the ID is 0.
+fn flow_control_error(stream_id: u32) -> Http2Error {
+    match stream_id {
+        0 => Http2Error::Connection(FLOW_CONTROL_ERROR, "flow-control window too large"),
+        stream_id => Http2Error::Stream(stream_id, FLOW_CONTROL_ERROR),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http2::error::{FLOW_CONTROL_ERROR, Http2Error};
+    use crate::http2::window::{MAX_WINDOW_SIZE, Window};
+
+    #[test]
+    fn consume_and_increase() {
+        let mut window = Window::new(10);
+        assert!(window.consume(4));
+        assert_eq!(window.available(), 6);
+        assert!(!window.consume(7));
+        assert_eq!(window.available(), 6);
+        window.increase(4, 1).unwrap();
+        assert_eq!(window.available(), 10);
+    }
+
+    #[test]
+    fn negative_window() {
+        let mut window = Window::new(10);
+        window.increase(-15, 1).unwrap();
+        assert_eq!(window.available(), 0);
+        assert!(!window.consume(1));
+        assert!(window.consume(0));
+        window.increase(10, 1).unwrap();
+        assert_eq!(window.available(), 5);
+    }
+
+    #[test]
+    fn too_large() {
+        let mut window = Window::new(10);
+        assert_eq!(window.increase(MAX_WINDOW_SIZE, 3), Err(Http2Error::Stream(3, FLOW_CONTROL_ERROR)));
+        assert!(matches!(window.increase(MAX_WINDOW_SIZE, 0), Err(Http2Error::Connection(FLOW_CONTROL_ERROR, _))));
+        assert_eq!(window.available(), 10);
+    }
+}
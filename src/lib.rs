@@ -4,9 +4,19 @@ pub mod common;
 pub mod server;
 /// Components for communicating with an HTTP server.
 pub mod client;
+/// WebSocket connections for servers and clients.
+pub mod websocket;
+/// Server-sent events (text/event-stream) for servers and clients.
+pub mod sse;
+/// Certificates, keys, and TLS settings for HTTPS servers and clients.
+pub mod tls;
 
 /// Utility components.
 pub(crate) mod util;
 
 /// Components for parsing HTTP requests and responses.
-pub(crate) mod parse;
\ No newline at end of file
+pub(crate) mod parse;
+
+/// HTTP/2 framing, header compression, and flow control.
+#[cfg(feature = "http2")]
+pub(crate) mod http2;
\ No newline at end of file
@@ -12,14 +12,7 @@ use my_http::server::ListenerResult::{SendResponse, SendResponseArc};
 fn main() -> Result<(), Error> {
     let mut router = Router::new();
 
-    router.on("/secret/message/path", |_, _| {
-        let message = b"You found the secret message!";
-        SendResponse(Response {
-            status: status::OK,
-            headers: header_map![(header::CONTENT_LENGTH, "29")],
-            body: message.to_vec(),
-        })
-    });
+    router.on("/secret/message/path", |_, _| SendResponse(Response::text("You found the secret message!")));
 
     router.route("/my/middleton/website/", file_router("/Users/Ben/Code/middletonSite/"));
     router.route("/", file_router("/Users/Ben/Code/ReactTetris/tetris-app/build/"));
@@ -28,6 +21,7 @@ fn main() -> Result<(), Error> {
         addr: "0.0.0.0:80",
         connection_handler_threads: 5,
         router,
+        ..Default::default()
     })
 }
 
@@ -60,10 +54,7 @@ fn file_router(directory: &'static str) -> Router {
 
 fn file_response(file_path: &str) -> Response {
     if let Ok(contents) = fs::read(file_path) {
-        let headers = header_map![
-            (header::CONTENT_LENGTH, contents.len().to_string()),
-            (header::CONTENT_TYPE, get_content_type(file_path))
-        ];
+        let headers = header_map![(header::CONTENT_TYPE, get_content_type(file_path))];
 
         return Response { status: status::OK, headers, body: contents };
     }
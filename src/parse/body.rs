@@ -12,7 +12,7 @@ use crate::parse::parse::{Parse, ParseResult};
 use crate::parse::parse::ParseStatus::Done;
 
 /// The maximum size of a body.
-const MAX_BODY_SIZE: usize = 3 * 1024 * 1024; // 3 megabytes
+pub(crate) const MAX_BODY_SIZE: usize = 3 * 1024 * 1024; // 3 megabytes
 
 /// Parser for a message body.
 pub enum BodyParser {
@@ -42,7 +42,7 @@ impl BodyParser {
     }
 
     /// Gets the size of the body read so far.
-    fn read_so_far(&self) -> usize {
+    pub fn read_so_far(&self) -> usize {
         match self {
             WithSize(parser) => parser.read_so_far(),
             UntilEof(parser) => parser.read_so_far(),
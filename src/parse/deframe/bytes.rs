@@ -33,7 +33,7 @@ impl Deframe<Vec<u8>> for BytesDeframer {
     }
 
     fn read_so_far(&self) -> usize {
-        self.data.len()
+        self.pos
     }
 }
 
@@ -17,12 +17,12 @@ impl LineDeframer {
 impl Deframe<String> for LineDeframer {
     fn read(mut self, reader: &mut impl BufRead) -> DeframerResult<String, Self> {
         match reader.read_line(&mut self.line) {
-            Ok(_) =>
-                if let Some('\n') = self.line.pop() {
-                    Ok(self.line)
-                } else {
-                    Err((self, Error::from(ErrorKind::UnexpectedEof)))
-                },
+            Ok(_) if self.line.ends_with('\n') => {
+                self.line.pop();
+                Ok(self.line)
+            }
+            // the partial line is kept, so parsing can resume if more data becomes available
+            Ok(_) => Err((self, Error::from(ErrorKind::UnexpectedEof))),
             Err(err) => Err((self, err))
         }
     }
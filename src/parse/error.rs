@@ -17,4 +17,6 @@ pub enum ParsingError {
     InvalidStatusCode,
     /// Data is not valid UTF8.
     InvalidUtf8,
+    /// Line or data of a server-sent event exceeds maximum size.
+    EventTooLarge,
 }
\ No newline at end of file
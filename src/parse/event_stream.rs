@@ -0,0 +1,240 @@
+use std::io::{BufRead, ErrorKind};
+use std::time::Duration;
+
+use crate::parse::error::ParsingError;
+use crate::parse::parse::{Parse, ParseResult};
+use crate::parse::parse::ParseStatus::{Done, IoErr};
+use crate::sse::Event;
+
+/// The maximum size of a line or of the data of an event.
+const MAX_EVENT_SIZE: usize = 1024 * 1024; // 1 megabyte
+
+/// Parser for the next event of a text/event-stream body.
+/// Blocks of fields without any data are not events, but their ID and retry fields still take effect.
+pub struct EventParser {
+    /// The line being read.
+    line: Vec<u8>,
+    /// Whether the last line ended with "\r", so a "\n" right after it is part of the same line break.
+    skip_lf: bool,
+    event: Option<String>,
+    data: Option<String>,
+    last_event_id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl EventParser {
+    /// Creates a new parser. The given ID is used as the ID of events until the stream sets a different one.
+    pub fn new(last_event_id: Option<String>) -> EventParser {
+        EventParser { line: vec![], skip_lf: false, event: None, data: None, last_event_id, retry: None }
+    }
+
+    /// Gets the ID of the last event, which a reconnecting client sends in its last-event-id header.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    /// Gets the reconnection time set by the stream since this parser was created, if any.
+    pub fn retry(&self) -> Option<Duration> {
+        self.retry
+    }
+
+    /// Reads the next line into the line buffer. Returns true if a whole line has been read, or false at EOF.
+    fn read_line(&mut self, reader: &mut impl BufRead) -> std::io::Result<bool> {
+        loop {
+            let buf = reader.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(false);
+            }
+
+            if self.skip_lf {
+                self.skip_lf = false;
+                if buf[0] == b'\n' {
+                    reader.consume(1);
+                    continue;
+                }
+            }
+
+            match buf.iter().position(|b| *b == b'\r' || *b == b'\n') {
+                Some(end) => {
+                    self.line.extend_from_slice(&buf[..end]);
+                    self.skip_lf = buf[end] == b'\r';
+                    reader.consume(end + 1);
+                    return Ok(true);
+                }
+                None => {
+                    let amount = buf.len();
+                    self.line.extend_from_slice(buf);
+                    reader.consume(amount);
+                }
+            }
+
+            if self.line.len() > MAX_EVENT_SIZE {
+                return Err(std::io::Error::new(ErrorKind::InvalidData, "line too long"));
+            }
+        }
+    }
+
+    /// Processes the line in the line buffer. Returns an event if the line ends one.
+    fn process_line(&mut self) -> Result<Option<Event>, ParsingError> {
+        let line = String::from_utf8_lossy(&self.line).into_owned();
+        self.line.clear();
+
+        if line.is_empty() {
+            return Ok(self.dispatch());
+        }
+        if line.starts_with(':') {
+            return Ok(None);
+        }
+
+        let (field, value) = match line.find(':') {
+            Some(colon) => {
+                let value = &line[colon + 1..];
+                (&line[..colon], value.strip_prefix(' ').unwrap_or(value))
+            }
+            None => (line.as_str(), ""),
+        };
+
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => {
+                let data = self.data.get_or_insert_with(String::new);
+                if data.len() + value.len() > MAX_EVENT_SIZE {
+                    return Err(ParsingError::EventTooLarge);
+                }
+                data.push_str(value);
+                data.push('\n');
+            }
+            "id" if !value.contains('\0') => {
+                self.last_event_id = if value.is_empty() { None } else { Some(value.to_string()) };
+            }
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                if let Ok(millis) = value.parse() {
+                    self.retry = Some(Duration::from_millis(millis));
+                }
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    /// Ends the current block of fields. Returns an event if the block had any data.
+    fn dispatch(&mut self) -> Option<Event> {
+        let event = self.event.take().filter(|event| !event.is_empty());
+        let mut data = self.data.take()?;
+        data.pop();
+        Some(Event { event, data, id: self.last_event_id.clone(), retry: self.retry })
+    }
+}
+
+impl Parse<Event> for EventParser {
+    fn parse(mut self, reader: &mut impl BufRead) -> ParseResult<Event, Self> {
+        loop {
+            match self.read_line(reader) {
+                Ok(true) => {}
+                Ok(false) => return Ok(IoErr(self, ErrorKind::UnexpectedEof.into())),
+                Err(error) if error.kind() == ErrorKind::InvalidData => return Err(ParsingError::EventTooLarge),
+                Err(error) => return Ok(IoErr(self, error)),
+            }
+
+            if let Some(event) = self.process_line()? {
+                return Ok(Done(event));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+    use std::time::Duration;
+
+    use crate::parse::error::ParsingError::EventTooLarge;
+    use crate::parse::event_stream::EventParser;
+    use crate::parse::test_util;
+    use crate::parse::test_util::TestParseResult::{ParseErr, Value};
+    use crate::sse::Event;
+
+    fn event(data: &str, event: Option<&str>, id: Option<&str>, retry: Option<u64>) -> Event {
+        Event {
+            event: event.map(String::from),
+            data: data.to_string(),
+            id: id.map(String::from),
+            retry: retry.map(Duration::from_millis),
+        }
+    }
+
+    #[test]
+    fn single_event() {
+        test_util::test_with_eof(EventParser::new(None), vec!["data: hello\n\n"], Value(Event::new("hello")));
+    }
+
+    #[test]
+    fn all_fields() {
+        test_util::test_with_eof(
+            EventParser::new(None),
+            vec!["event: greeting\nid: 5\nretry: 3000\ndata: hello\n\n"],
+            Value(event("hello", Some("greeting"), Some("5"), Some(3000))),
+        );
+    }
+
+    #[test]
+    fn multiline_data_and_line_breaks() {
+        test_util::test_with_eof(
+            EventParser::new(None),
+            vec!["data: a\r\ndata:b\rdata:  c\n", "data\r\n\r\n"],
+            Value(Event::new("a\nb\n c\n")),
+        );
+    }
+
+    #[test]
+    fn comments_and_unknown_fields_ignored() {
+        test_util::test_with_eof(
+            EventParser::new(None),
+            vec![": keep-alive\n\nfoo: bar\nretry: soon\ndata: hello\n\n"],
+            Value(Event::new("hello")),
+        );
+    }
+
+    #[test]
+    fn blocks_without_data_update_state() {
+        test_util::test_with_eof(
+            EventParser::new(Some("1".to_string())),
+            vec!["id: 2\nretry: 10\nevent: ignored\n\ndata: hello\n\n"],
+            Value(event("hello", None, Some("2"), Some(10))),
+        );
+    }
+
+    #[test]
+    fn id_persists_and_can_be_reset() {
+        test_util::test_with_eof(EventParser::new(Some("1".to_string())), vec!["data: a\n\n"], Value(event("a", None, Some("1"), None)));
+        test_util::test_with_eof(EventParser::new(Some("1".to_string())), vec!["id\ndata: a\n\n"], Value(Event::new("a")));
+        test_util::test_with_eof(EventParser::new(Some("1".to_string())), vec!["id: a\0b\ndata: a\n\n"], Value(event("a", None, Some("1"), None)));
+    }
+
+    #[test]
+    fn empty_data_is_an_event() {
+        test_util::test_with_eof(EventParser::new(None), vec!["data\n\n"], Value(Event::new("")));
+    }
+
+    #[test]
+    fn partial_event() {
+        test_util::test_blocking(EventParser::new(None), vec![
+            (vec![b"da"], ErrorKind::WouldBlock.into()),
+            (vec![b"ta: hel", b"lo\r"], ErrorKind::WouldBlock.into()),
+            // the "\n" after "\r" is part of the same line break
+            (vec![b"\n", b"\r"], Value(Event::new("hello"))),
+        ]);
+    }
+
+    #[test]
+    fn eof_before_event_ends() {
+        test_util::test_with_eof(EventParser::new(None), vec!["data: hello\n"], ErrorKind::UnexpectedEof.into());
+    }
+
+    #[test]
+    fn event_too_large() {
+        let line = format!("data: {}\n", "a".repeat(600 * 1024));
+        test_util::test_with_eof(EventParser::new(None), vec![&line, &line], ParseErr(EventTooLarge));
+        test_util::test_endless_strs(EventParser::new(None), vec!["data: "], "a", ParseErr(EventTooLarge));
+    }
+}
@@ -0,0 +1,172 @@
+use std::convert::TryInto;
+use std::io::BufRead;
+
+use crate::http2::error::{FRAME_SIZE_ERROR, Http2Error};
+use crate::http2::frame::{decode, Frame, FRAME_HEADER_LEN, FrameHeader};
+use crate::parse::deframe::bytes::BytesDeframer;
+use crate::parse::deframe::deframe::Deframe;
+use crate::parse::parse::{Parse, ParseResult};
+use crate::parse::parse::ParseStatus::{Done, IoErr};
+
+/// Parser for HTTP/2 frames. Frames that violate the protocol are parsed as errors, which end either the stream they
+/// are on or the whole connection.
+pub struct FrameParser {
+    /// The largest payload accepted, which is the max frame size setting sent to the peer.
+    max_frame_size: usize,
+    state: ParserState,
+}
+
+enum ParserState {
+    Header(BytesDeframer),
+    Payload(FrameHeader, BytesDeframer),
+}
+
+impl FrameParser {
+    /// Creates a parser for frames whose payloads are no larger than the given size.
+    pub fn new(max_frame_size: u32) -> FrameParser {
+        FrameParser { max_frame_size: max_frame_size as usize, state: ParserState::Header(BytesDeframer::new(FRAME_HEADER_LEN)) }
+    }
+}
+
+impl Parse<Result<Frame, Http2Error>> for FrameParser {
+    fn parse(self, reader: &mut impl BufRead) -> ParseResult<Result<Frame, Http2Error>, Self> {
+        let max_frame_size = self.max_frame_size;
+        let mut state = self.state;
+
+        loop {
+            state = match state {
+                ParserState::Header(deframer) => match deframer.read(reader) {
+                    Ok(header) => {
+                        let header = FrameHeader::from_bytes(header.as_slice().try_into().unwrap());
+                        if header.length > max_frame_size {
+                            return Ok(Done(Err(Http2Error::Connection(FRAME_SIZE_ERROR, "frame too large"))));
+                        }
+                        ParserState::Payload(header, BytesDeframer::new(header.length))
+                    }
+                    Err((deframer, error)) => return Ok(IoErr(FrameParser { max_frame_size, state: ParserState::Header(deframer) }, error)),
+                },
+                ParserState::Payload(header, deframer) => match deframer.read(reader) {
+                    Ok(payload) => return Ok(Done(decode(header, payload))),
+                    Err((deframer, error)) => return Ok(IoErr(FrameParser { max_frame_size, state: ParserState::Payload(header, deframer) }, error)),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http2::error::{CANCEL, FRAME_SIZE_ERROR, Http2Error, PROTOCOL_ERROR};
+    use crate::http2::frame::{encode_data, encode_header_block, Frame, Priority};
+    use crate::parse::frame::FrameParser;
+    use crate::parse::parse::Parse;
+    use crate::parse::parse::ParseStatus::Done;
+    use crate::parse::test_util;
+    use crate::parse::test_util::TestParseResult::Value;
+
+    fn encode(frame: &Frame) -> Vec<u8> {
+        let mut out = vec![];
+        frame.encode(&mut out);
+        out
+    }
+
+    fn test_round_trip(frame: Frame) {
+        let encoded = encode(&frame);
+        test_util::test_blocking(FrameParser::new(16384), vec![(vec![&encoded], Value(Ok(frame)))]);
+    }
+
+    fn parse_bytes(bytes: &[u8]) -> Result<Frame, Http2Error> {
+        match FrameParser::new(16384).parse(&mut &bytes[..]) {
+            Ok(Done(result)) => result,
+            _ => panic!("frame not parsed"),
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        test_round_trip(Frame::Data { stream_id: 1, data: b"hello".to_vec(), end_stream: true, padding: 0 });
+        test_round_trip(Frame::Data { stream_id: 3, data: b"hello".to_vec(), end_stream: false, padding: 4 });
+        test_round_trip(Frame::Headers { stream_id: 1, block: b"block".to_vec(), end_stream: false, end_headers: true, priority: None });
+        test_round_trip(Frame::Headers {
+            stream_id: 5,
+            block: b"block".to_vec(),
+            end_stream: true,
+            end_headers: false,
+            priority: Some(Priority { dependency: 3, exclusive: true, weight: 15 }),
+        });
+        test_round_trip(Frame::Priority { stream_id: 7, priority: Priority { dependency: 1, exclusive: false, weight: 255 } });
+        test_round_trip(Frame::RstStream { stream_id: 1, error: CANCEL });
+        test_round_trip(Frame::Settings { ack: false, params: vec![(1, 0), (4, 1 << 20)] });
+        test_round_trip(Frame::Settings { ack: true, params: vec![] });
+        test_round_trip(Frame::PushPromise { stream_id: 1, promised_stream_id: 2, block: b"block".to_vec(), end_headers: true });
+        test_round_trip(Frame::Ping { ack: true, data: *b"12345678" });
+        test_round_trip(Frame::GoAway { last_stream_id: 9, error: PROTOCOL_ERROR, debug_data: b"bad".to_vec() });
+        test_round_trip(Frame::WindowUpdate { stream_id: 0, increment: 1000 });
+        test_round_trip(Frame::Continuation { stream_id: 1, block: b"more".to_vec(), end_headers: true });
+        test_round_trip(Frame::Unknown { kind: 0xbe, stream_id: 1 });
+    }
+
+    #[test]
+    fn fragmented() {
+        let encoded = encode(&Frame::Data { stream_id: 1, data: b"hello".to_vec(), end_stream: true, padding: 0 });
+        let (first, second) = encoded.split_at(4);
+        let (second, third) = second.split_at(7);
+        test_util::test_blocking(FrameParser::new(16384), vec![
+            (vec![first], std::io::ErrorKind::WouldBlock.into()),
+            (vec![second], std::io::ErrorKind::WouldBlock.into()),
+            (vec![third], Value(Ok(Frame::Data { stream_id: 1, data: b"hello".to_vec(), end_stream: true, padding: 0 }))),
+        ]);
+    }
+
+    #[test]
+    fn too_large() {
+        let mut out = vec![];
+        encode_data(&mut out, 1, &[0; 100], false);
+        match FrameParser::new(50).parse(&mut &out[..]) {
+            Ok(Done(Err(Http2Error::Connection(FRAME_SIZE_ERROR, _)))) => {}
+            _ => panic!("frame should be too large"),
+        }
+    }
+
+    #[test]
+    fn invalid_frames() {
+        // DATA on stream 0
+        assert!(matches!(parse_bytes(&[0, 0, 0, 0, 0, 0, 0, 0, 0]), Err(Http2Error::Connection(PROTOCOL_ERROR, _))));
+        // padding longer than the payload
+        assert!(matches!(parse_bytes(&[0, 0, 2, 0, 0x8, 0, 0, 0, 1, 5, 0]), Err(Http2Error::Connection(PROTOCOL_ERROR, _))));
+        // SETTINGS on a stream
+        assert!(matches!(parse_bytes(&[0, 0, 0, 4, 0, 0, 0, 0, 1]), Err(Http2Error::Connection(PROTOCOL_ERROR, _))));
+        // SETTINGS with a partial parameter
+        assert!(matches!(parse_bytes(&[0, 0, 3, 4, 0, 0, 0, 0, 0, 0, 1, 0]), Err(Http2Error::Connection(FRAME_SIZE_ERROR, _))));
+        // PING with the wrong length
+        assert!(matches!(parse_bytes(&[0, 0, 1, 6, 0, 0, 0, 0, 0, 0]), Err(Http2Error::Connection(FRAME_SIZE_ERROR, _))));
+        // PRIORITY with the wrong length only ends the stream
+        assert_eq!(parse_bytes(&[0, 0, 1, 2, 0, 0, 0, 0, 3, 0]), Err(Http2Error::Stream(3, FRAME_SIZE_ERROR)));
+        // WINDOW_UPDATE with an increment of 0
+        assert_eq!(parse_bytes(&[0, 0, 4, 8, 0, 0, 0, 0, 3, 0, 0, 0, 0]), Err(Http2Error::Stream(3, PROTOCOL_ERROR)));
+        assert!(matches!(parse_bytes(&[0, 0, 4, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0]), Err(Http2Error::Connection(PROTOCOL_ERROR, _))));
+    }
+
+    #[test]
+    fn header_block_fragments() {
+        let mut out = vec![];
+        encode_header_block(&mut out, 1, b"abcdefgh", true, 3);
+        let mut reader = &out[..];
+        let mut frames = vec![];
+        while !reader.is_empty() {
+            match FrameParser::new(16384).parse(&mut reader) {
+                Ok(Done(frame)) => frames.push(frame.unwrap()),
+                _ => panic!("frame not parsed"),
+            }
+        }
+        assert_eq!(frames, vec![
+            Frame::Headers { stream_id: 1, block: b"abc".to_vec(), end_stream: true, end_headers: false, priority: None },
+            Frame::Continuation { stream_id: 1, block: b"def".to_vec(), end_headers: false },
+            Frame::Continuation { stream_id: 1, block: b"gh".to_vec(), end_headers: true },
+        ]);
+
+        let mut out = vec![];
+        encode_header_block(&mut out, 1, b"", false, 3);
+        assert_eq!(parse_bytes(&out), Ok(Frame::Headers { stream_id: 1, block: vec![], end_stream: false, end_headers: true, priority: None }));
+    }
+}
@@ -10,6 +10,7 @@ use crate::parse::parse::ParseStatus::{Done, IoErr};
 /// Generic HTTP message parser, used by both response and request parsing.
 pub struct MessageParser<R, T> {
     read_body_if_no_content_length: bool,
+    has_body: fn(&T) -> bool,
     state: State<R, T>,
 }
 
@@ -17,10 +18,20 @@ impl<R, T> MessageParser<R, T> {
     /// Creates a new message parser with the given parser to parse the first line.
     /// If read_body_if_no_content_length is true and no content length is provided, then the message
     /// body will consist of all data up to EOF. Otherwise the body will be empty.
-    pub fn new(first_line_parser: R, read_body_if_no_content_length: bool) -> MessageParser<R, T> {
+    /// If has_body returns false for the parsed first line, then the body will be empty regardless of the headers.
+    pub fn new(first_line_parser: R, read_body_if_no_content_length: bool, has_body: fn(&T) -> bool) -> MessageParser<R, T> {
         MessageParser {
             state: FirstLine(first_line_parser),
             read_body_if_no_content_length,
+            has_body,
+        }
+    }
+
+    /// Gets the first line, headers, and body parser if this message parser is parsing the body.
+    pub fn body_state(&self) -> Option<(&T, &HeaderMap, &BodyParser)> {
+        match &self.state {
+            Body(first_line, headers, parser) => Some((first_line, headers, parser)),
+            _ => None
         }
     }
 
@@ -48,19 +59,19 @@ enum State<R, T> {
 
 impl<T, R: Parse<T>> Parse<(T, HeaderMap, Vec<u8>)> for MessageParser<R, T> {
     fn parse(self, reader: &mut impl BufRead) -> ParseResult<(T, HeaderMap, Vec<u8>), Self> {
-        let Self { mut state, read_body_if_no_content_length } = self;
+        let Self { mut state, read_body_if_no_content_length, has_body } = self;
 
         loop {
             let result = match state {
                 FirstLine(parser) => first_line_state(reader, parser)?,
-                Headers(first_line, parser) => headers_state(reader, first_line, parser, read_body_if_no_content_length)?,
+                Headers(first_line, parser) => headers_state(reader, first_line, parser, read_body_if_no_content_length, has_body)?,
                 Body(first_line, headers, parser) => body_state(reader, first_line, headers, parser)?,
                 Finished(first_line, headers, body) => return Ok(Done((first_line, headers, body)))
             };
 
             state = match result {
                 Done(state) => state,
-                IoErr(state, err) => return Ok(IoErr(Self { state, read_body_if_no_content_length }, err))
+                IoErr(state, err) => return Ok(IoErr(Self { state, read_body_if_no_content_length, has_body }, err))
             }
         }
     }
@@ -75,10 +86,14 @@ fn first_line_state<T, R: Parse<T>>(reader: &mut impl BufRead, parser: R) -> Par
 }
 
 /// Parses the headers and returns the next state if possible.
-fn headers_state<T, R>(reader: &mut impl BufRead, first_line: T, parser: HeadersParser, read_body_if_no_content_length: bool) -> ParseResult<State<R, T>, State<R, T>> {
+fn headers_state<T, R>(reader: &mut impl BufRead, first_line: T, parser: HeadersParser, read_body_if_no_content_length: bool, has_body: fn(&T) -> bool) -> ParseResult<State<R, T>, State<R, T>> {
     Ok(match parser.parse(reader)? {
         Done(headers) => {
-            let body_parser = BodyParser::new(&headers, read_body_if_no_content_length)?;
+            let body_parser = if has_body(&first_line) {
+                BodyParser::new(&headers, read_body_if_no_content_length)?
+            } else {
+                BodyParser::Empty
+            };
             Done(Body(first_line, headers, body_parser))
         }
         IoErr(parser, err) => IoErr(Headers(first_line, parser), err)
@@ -111,7 +126,7 @@ mod tests {
     type Parser = MessageParser<CrlfLineParser, String>;
 
     fn get_message_deframer(read_if_no_content_length: bool) -> Parser {
-        MessageParser::new(CrlfLineParser::new(), read_if_no_content_length)
+        MessageParser::new(CrlfLineParser::new(), read_if_no_content_length, |_| true)
     }
 
     fn test_with_eof(input: Vec<&str>, read_if_no_content_length: bool, expected: TestParseResult<Message>) {
@@ -6,13 +6,18 @@ pub mod parse;
 pub mod request;
 /// Response parsing components.
 pub mod response;
+/// Server-sent event stream parsing components.
+pub mod event_stream;
+/// HTTP/2 frame parsing components.
+#[cfg(feature = "http2")]
+pub mod frame;
 
 /// Parser for CRLF lines.
 mod crlf_line;
 /// Parser for headers.
 mod headers;
 /// Parser for message bodies.
-mod body;
+pub(crate) mod body;
 /// Deframing components (or, in other words, stateful IO reading).
 mod deframe;
 /// error_take method utility.
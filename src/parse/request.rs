@@ -1,5 +1,6 @@
 use std::io::BufRead;
 
+use crate::common::header::{EXPECT, HeaderMap, HeaderMapOps};
 use crate::common::method::Method;
 use crate::common::request::Request;
 use crate::common::version;
@@ -10,24 +11,45 @@ use crate::parse::parse::{Parse, ParseResult};
 use crate::parse::parse::ParseStatus::{Done, IoErr};
 
 /// Parser for requests.
-pub struct RequestParser(MessageParser<FirstLineParser, (Method, String)>);
+pub struct RequestParser(MessageParser<FirstLineParser, (Method, String, &'static str)>);
 
 impl RequestParser {
     /// Creates a new request parser.
     pub fn new() -> RequestParser {
-        RequestParser(MessageParser::new(FirstLineParser::new(), false))
+        RequestParser(MessageParser::new(FirstLineParser::new(), false, |_| true))
     }
 
     /// Returns true if this parser has read any data so far.
     pub fn has_data(&self) -> bool {
         self.0.first_line_parser().map(|p| { p.0.read_so_far() > 0 }).unwrap_or(true)
     }
+
+    /// Gets the amount of the body that has been read, or None if the parser has not gotten to the body yet.
+    pub fn body_read(&self) -> Option<usize> {
+        self.0.body_state().map(|(_, _, body_parser)| body_parser.read_so_far())
+    }
+
+    /// Gets the request without its body, along with its HTTP version, if this parser is waiting for a body that the
+    /// client will only send after receiving a 100 continue response. Returns None otherwise, or if some of the body
+    /// has already been read.
+    pub fn expecting_continue(&self) -> Option<(Request, &'static str)> {
+        let ((method, uri, version), headers, body_parser) = self.0.body_state()?;
+        if body_parser.read_so_far() > 0 || !expects_continue(headers) {
+            return None;
+        }
+        Some((Request { method: *method, uri: uri.clone(), headers: headers.clone(), body: vec![] }, *version))
+    }
+}
+
+/// Checks if the given headers have an "expect: 100-continue" header.
+fn expects_continue(headers: &HeaderMap) -> bool {
+    headers.get_first_header_value(&EXPECT).map(|value| value.eq_ignore_ascii_case("100-continue")).unwrap_or(false)
 }
 
 impl Parse<Request> for RequestParser {
     fn parse(self, reader: &mut impl BufRead) -> ParseResult<Request, Self> {
         Ok(match self.0.parse(reader)? {
-            Done(((method, uri), headers, body)) => Done(Request { method, uri, headers, body }),
+            Done(((method, uri, _), headers, body)) => Done(Request { method, uri, headers, body }),
             IoErr(parser, err) => IoErr(Self(parser), err)
         })
     }
@@ -43,8 +65,8 @@ impl FirstLineParser {
     }
 }
 
-impl Parse<(Method, String)> for FirstLineParser {
-    fn parse(self, reader: &mut impl BufRead) -> ParseResult<(Method, String), Self> {
+impl Parse<(Method, String, &'static str)> for FirstLineParser {
+    fn parse(self, reader: &mut impl BufRead) -> ParseResult<(Method, String, &'static str), Self> {
         Ok(match self.0.parse(reader)? {
             Done(line) => Done(parse_first_line(line)?),
             IoErr(parser, err) => IoErr(Self(parser), err)
@@ -52,19 +74,22 @@ impl Parse<(Method, String)> for FirstLineParser {
     }
 }
 
-/// Parses the given string as the first line of a request. Verifies the HTTP version and returns the method and URI.
-fn parse_first_line(line: String) -> Result<(Method, String), ParsingError> {
+/// Parses the given string as the first line of a request. Verifies the HTTP version and returns the method, URI, and
+/// version.
+fn parse_first_line(line: String) -> Result<(Method, String, &'static str), ParsingError> {
     let mut split = line.split(" ");
 
     let method_raw = split.next().ok_or(ParsingError::BadSyntax)?;
     let uri = split.next().ok_or(ParsingError::BadSyntax)?;
     let http_version = split.next().ok_or(ParsingError::BadSyntax)?;
 
-    if !version::is_supported(http_version) {
-        return Err(ParsingError::InvalidHttpVersion.into());
-    }
+    let http_version = match http_version {
+        version::HTTP_VERSION_1_0 => version::HTTP_VERSION_1_0,
+        version::HTTP_VERSION_1_1 => version::HTTP_VERSION_1_1,
+        _ => return Err(ParsingError::InvalidHttpVersion.into()),
+    };
 
-    Ok((parse_method(method_raw)?, uri.to_string()))
+    Ok((parse_method(method_raw)?, uri.to_string(), http_version))
 }
 
 /// Parses the given string into a method. If the method is not recognized, will return an error.
@@ -76,9 +101,10 @@ fn parse_method(raw: &str) -> Result<Method, ParsingError> {
 mod tests {
     use std::io::{BufReader, ErrorKind};
 
-    use crate::common::header::{CONNECTION, CONTENT_LENGTH, HeaderMap};
+    use crate::common::header::{CONNECTION, CONTENT_LENGTH, EXPECT, HeaderMap};
     use crate::common::method::Method;
     use crate::common::request::Request;
+    use crate::common::version::{HTTP_VERSION_1_0, HTTP_VERSION_1_1};
     use crate::header_map;
     use crate::parse::error::ParsingError::{BadSyntax, InvalidHeaderValue, InvalidHttpVersion, UnrecognizedMethod};
     use crate::parse::parse::{Parse, ParseStatus};
@@ -538,4 +564,63 @@ mod tests {
             _ => panic!("parse gave unexpected result")
         }
     }
+
+    fn parse_until_blocked(data: Vec<&str>) -> RequestParser {
+        let mut reader = MockReader::from_strs(data);
+        reader.return_would_block_when_empty = true;
+        let mut reader = BufReader::new(reader);
+
+        match RequestParser::new().parse(&mut reader) {
+            Ok(ParseStatus::IoErr(parser, err)) if err.kind() == ErrorKind::WouldBlock => parser,
+            _ => panic!("parser did not block")
+        }
+    }
+
+    #[test]
+    fn expecting_continue() {
+        let parser = parse_until_blocked(vec!["POST /upload HTTP/1.1\r\ncontent-length: 10\r\nexpect: 100-Continue\r\n\r\n"]);
+
+        assert_eq!(parser.expecting_continue(), Some((Request {
+            uri: String::from("/upload"),
+            method: Method::POST,
+            headers: header_map![(CONTENT_LENGTH, "10"), (EXPECT, "100-Continue")],
+            body: vec![],
+        }, HTTP_VERSION_1_1)));
+
+        let parser = parse_until_blocked(vec!["POST /upload HTTP/1.0\r\ncontent-length: 10\r\nexpect: 100-continue\r\n\r\n"]);
+        assert_eq!(parser.expecting_continue().map(|(_, version)| version), Some(HTTP_VERSION_1_0));
+    }
+
+    #[test]
+    fn not_expecting_continue() {
+        let without_header = parse_until_blocked(vec!["POST / HTTP/1.1\r\ncontent-length: 10\r\n\r\n"]);
+        assert_eq!(without_header.expecting_continue(), None);
+
+        let reading_headers = parse_until_blocked(vec!["POST / HTTP/1.1\r\ncontent-length: 10\r\nexpect: 100-continue\r\n"]);
+        assert_eq!(reading_headers.expecting_continue(), None);
+
+        let reading_body = parse_until_blocked(vec!["POST / HTTP/1.1\r\ncontent-length: 10\r\nexpect: 100-continue\r\n\r\n01234"]);
+        assert_eq!(reading_body.expecting_continue(), None);
+    }
+
+    #[test]
+    fn body_read() {
+        assert_eq!(parse_until_blocked(vec!["POST / HTTP/1.1\r\ncontent-length: 10\r\n"]).body_read(), None);
+        assert_eq!(parse_until_blocked(vec!["POST / HTTP/1.1\r\ncontent-length: 10\r\n\r\n"]).body_read(), Some(0));
+        assert_eq!(parse_until_blocked(vec!["POST / HTTP/1.1\r\ncontent-length: 10\r\n\r\n0123", "45"]).body_read(), Some(6));
+    }
+
+    #[test]
+    fn resumes_after_eof_in_first_line() {
+        let parser = match RequestParser::new().parse(&mut &b"P"[..]) {
+            Ok(ParseStatus::IoErr(parser, err)) if err.kind() == ErrorKind::UnexpectedEof => parser,
+            _ => panic!("parser did not reach EOF")
+        };
+        assert!(parser.has_data());
+
+        match parser.parse(&mut &b"UT / HTTP/1.1\r\n\r\n"[..]) {
+            Ok(ParseStatus::Done(request)) => assert_eq!(request.method, Method::PUT),
+            _ => panic!("request was not parsed")
+        }
+    }
 }
@@ -15,7 +15,12 @@ pub struct ResponseParser(MessageParser<FirstLineParser, Status>);
 impl ResponseParser {
     /// Returns a new response parser.
     pub fn new() -> ResponseParser {
-        ResponseParser(MessageParser::new(FirstLineParser::new(), true))
+        ResponseParser(MessageParser::new(FirstLineParser::new(), true, status_has_body))
+    }
+
+    /// Returns a new parser for a response to a HEAD request, which never has a body.
+    pub fn for_head_request() -> ResponseParser {
+        ResponseParser(MessageParser::new(FirstLineParser::new(), true, |_| false))
     }
 }
 
@@ -28,6 +33,11 @@ impl Parse<Response> for ResponseParser {
     }
 }
 
+/// Checks if a response with the given status can have a body. 1xx, 204, and 304 responses never have a body.
+fn status_has_body(status: &Status) -> bool {
+    !(status.is_informational() || status.code == 204 || status.code == 304)
+}
+
 /// Parser for the first line of a response.
 struct FirstLineParser(CrlfLineParser);
 
@@ -71,7 +81,7 @@ fn parse_status(code: &str) -> Result<Status, ParsingError> {
 mod tests {
     use std::io::ErrorKind;
 
-    use crate::common::header::{CONTENT_LENGTH, Header, HeaderMap, HeaderMapOps};
+    use crate::common::header::{CONTENT_LENGTH, Header, HeaderMap, HeaderMapOps, LINK};
     use crate::common::response::Response;
     use crate::common::status;
     use crate::parse::error::ParsingError::{BadSyntax, InvalidHeaderValue, InvalidHttpVersion, InvalidStatusCode};
@@ -371,4 +381,37 @@ mod tests {
             }),
         );
     }
+
+    #[test]
+    fn informational_response_has_no_body() {
+        test_with_eof(
+            vec!["HTTP/1.1 103 Early Hints\r\nlink: </style.css>; rel=preload\r\n\r\nHTTP/1.1 200 OK\r\n\r\nbody"],
+            Value(Response {
+                status: status::EARLY_HINTS,
+                headers: HeaderMap::from_pairs(vec![(LINK, "</style.css>; rel=preload".to_string())]),
+                body: vec![],
+            }),
+        );
+    }
+
+    #[test]
+    fn no_content_and_not_modified_have_no_body() {
+        test_with_eof(
+            vec!["HTTP/1.1 204 No Content\r\n\r\nnot the body"],
+            Value(Response { status: status::NO_CONTENT, headers: Default::default(), body: vec![] }),
+        );
+        test_with_eof(
+            vec!["HTTP/1.1 304 Not Modified\r\ncontent-length: 5\r\n\r\n"],
+            Value(Response { status: status::NOT_MODIFIED, headers: HeaderMap::from_pairs(vec![(CONTENT_LENGTH, "5".to_string())]), body: vec![] }),
+        );
+    }
+
+    #[test]
+    fn head_response_has_no_body() {
+        test_util::test_with_eof(
+            ResponseParser::for_head_request(),
+            vec!["HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\n"],
+            Value(Response { status: status::OK, headers: HeaderMap::from_pairs(vec![(CONTENT_LENGTH, "5".to_string())]), body: vec![] }),
+        );
+    }
 }
@@ -1,12 +1,174 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::common::request::Request;
+use crate::common::response::Response;
 use crate::server::Router;
 
+/// The result of a continue listener.
+pub enum ContinueResult {
+    /// Sends a 100 continue response so the client sends the body. The request is then routed once it is complete.
+    Continue,
+    /// Sends the given final response, such as a 417 or 413 response, instead of reading the body.
+    /// The connection is closed after the response is sent.
+    Reject(Response),
+}
+
+/// A function that is called on requests with an "expect: 100-continue" header before their body is read.
+/// The request passed to the function has an empty body.
+pub type ContinueListener = Box<dyn Fn(&Request) -> ContinueResult + 'static + Send + Sync>;
+
 /// The config for an HTTP server.
 pub struct Config {
     /// The address to bind the server listener to.
     pub addr: &'static str,
-    /// The number of threads to spawn for handling connections. Each thread is used for one
-    /// connection at a time.
+    /// The number of threads to spawn for handling connections whose requests are for listeners that may block. Each
+    /// thread is used for one connection at a time.
     pub connection_handler_threads: usize,
+    /// The number of event loops, which each poll for IO on their own thread. Every event loop accepts new connections
+    /// from the shared listener, reads their requests, and calls the inline listeners of the router, so more than one
+    /// spreads the work across cores. Must be at least 1.
+    pub event_loops: usize,
     /// The router used for handling requests.
     pub router: Router,
-}
\ No newline at end of file
+    /// The value of the server header added to every response, or None to not send a server header.
+    /// Responses that already have a server header are left unchanged.
+    pub server_header: Option<String>,
+    /// Called on requests with an "expect: 100-continue" header when the server has to wait for their body.
+    /// The header is ignored on HTTP/1.0 requests, since those clients do not know 100 continue responses.
+    /// It is called on the event loop of the connection, so it should return right away.
+    /// If None, then a 100 continue response is always sent.
+    pub continue_listener: Option<ContinueListener>,
+    /// The limits on how long the server waits for clients.
+    pub timeouts: Timeouts,
+    /// The maximum number of requests served on an HTTP/1.1 connection, or None for no limit. The response to the last
+    /// request has a "connection: close" header, and the connection is closed once the response has been sent, so
+    /// clients open new connections, which may be balanced to other servers.
+    pub max_requests_per_connection: Option<u64>,
+    /// The maximum number of connections open at once, or None for no limit.
+    pub max_connections: Option<usize>,
+    /// What happens to new connections while the maximum number of connections are open.
+    pub at_max_connections: ConnectionLimitAction,
+    /// Counts the connections that are open. Keep a clone of it to read the count while the server runs.
+    pub connection_counter: ConnectionCounter,
+    /// The maximum number of upgraded connections open at once. Each upgraded connection is handled on its own thread,
+    /// so upgrades past the limit are answered with a 503 service unavailable response instead.
+    pub max_upgraded_connections: usize,
+    /// Counts the upgraded connections that are open, which are not counted by connection_counter.
+    pub upgraded_connection_counter: ConnectionCounter,
+}
+
+impl Default for Config {
+    /// Creates a config listening on "0.0.0.0:80" with one event loop and 5 connection handler threads, an empty
+    /// router, no server header, no continue listener, the default timeouts, no request or connection limits, and a
+    /// limit of 1000 upgraded connections.
+    fn default() -> Self {
+        Config {
+            addr: "0.0.0.0:80",
+            connection_handler_threads: 5,
+            event_loops: 1,
+            router: Router::new(),
+            server_header: None,
+            continue_listener: None,
+            timeouts: Timeouts::default(),
+            max_requests_per_connection: None,
+            max_connections: None,
+            at_max_connections: ConnectionLimitAction::default(),
+            connection_counter: ConnectionCounter::new(),
+            max_upgraded_connections: 1000,
+            upgraded_connection_counter: ConnectionCounter::new(),
+        }
+    }
+}
+
+/// The limits on how long the server waits for clients, which keep slow or idle clients from holding connections open.
+/// A connection that exceeds a limit is closed. If the client was in the middle of sending a request, then it is sent a
+/// 408 request timeout response first. A limit of None is never exceeded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timeouts {
+    /// The longest time a connection may stay open without sending a request, both before its first request and
+    /// after its last response has been sent.
+    pub keep_alive: Option<Duration>,
+    /// The longest time a client may take to send the head of a request, counted from when the first of it is received.
+    pub header_read: Option<Duration>,
+    /// The slowest a client may send the body of a request.
+    pub min_body_rate: Option<MinDataRate>,
+    /// The longest time the server waits for a client to receive more of a response that could not be sent yet.
+    pub write: Option<Duration>,
+}
+
+impl Default for Timeouts {
+    /// Creates timeouts with a 60 second keep-alive timeout, a 30 second header read timeout, a minimum body rate of
+    /// 240 bytes per second after 5 seconds, and a 60 second write timeout.
+    fn default() -> Self {
+        Timeouts {
+            keep_alive: Some(Duration::from_secs(60)),
+            header_read: Some(Duration::from_secs(30)),
+            min_body_rate: Some(MinDataRate { bytes_per_second: 240, grace_period: Duration::from_secs(5) }),
+            write: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+/// A minimum average rate at which data has to be received, counted from when the first of it is received.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MinDataRate {
+    /// The number of bytes that have to be received per second on average. A rate of 0 is never too slow.
+    pub bytes_per_second: u64,
+    /// The time before the rate is enforced, which gives the data time to start arriving.
+    pub grace_period: Duration,
+}
+
+/// What a server does with new connections while the maximum number of connections are open.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ConnectionLimitAction {
+    /// Stops accepting connections until one closes. New connections wait in the backlog of the listener, which the
+    /// OS may refuse connections from once it is full.
+    #[default]
+    Wait,
+    /// Accepts new connections, sends them a 503 service unavailable response with a retry-after header of the given
+    /// number of seconds, and closes them. HTTPS servers close new connections without a response, since the response
+    /// could only be sent after a TLS handshake, so retry_after has no effect on them.
+    Reject { retry_after: u64 },
+}
+
+/// Counts the connections open to a server. Clones share the same count.
+/// ```
+/// use my_http::server::{Config, ConnectionCounter};
+///
+/// let counter = ConnectionCounter::new();
+/// let config = Config { max_connections: Some(1000), connection_counter: counter.clone(), ..Default::default() };
+/// // once the server is started with the config
+/// println!("{} connections", counter.get());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionCounter(Arc<AtomicUsize>);
+
+impl ConnectionCounter {
+    /// Creates a counter with a count of 0.
+    pub fn new() -> ConnectionCounter {
+        ConnectionCounter::default()
+    }
+
+    /// Gets the number of connections that are open.
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Adds a connection that was opened to the count.
+    pub(crate) fn increment(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Adds a connection to the count, unless the given maximum number of connections are open. Returns true if it was
+    /// added.
+    pub(crate) fn try_increment(&self, max: usize) -> bool {
+        self.0.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| (count < max).then_some(count + 1)).is_ok()
+    }
+
+    /// Removes a connection that was closed from the count.
+    pub(crate) fn decrement(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
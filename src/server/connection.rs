@@ -1,12 +1,22 @@
 use std::io::{ErrorKind, Write};
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crate::common::request::Request;
+#[cfg(feature = "http2")]
+use crate::http2::PREFACE;
 use crate::parse::error::ParsingError;
 use crate::parse::parse::{Parse, ParseStatus};
 use crate::parse::request::RequestParser;
 use crate::server::connection::ReadRequestError::{IoErr, ParseErr};
-use crate::server::connection::ReadRequestResult::{Closed, Error, NotReady, Ready};
+use crate::server::config::Timeouts;
+use crate::server::connection::ReadRequestResult::{Closed, Error, ExpectsContinue, NotReady, Ready};
+use crate::server::connection::Waiting::{Body, Head, Nothing, Request as NextRequest, Write as WriteData};
+use crate::server::connection_info::{ConnectionInfo, DescribeConnection};
+use crate::server::event_stream::EventStream;
+#[cfg(feature = "http2")]
+use crate::server::http2::Http2Connection;
 use crate::util::stream::BufStream;
 
 /// The result of attempting to read a request.
@@ -15,6 +25,9 @@ pub enum ReadRequestResult {
     NotReady,
     /// A new request has been parsed.
     Ready(Request),
+    /// A request has been parsed up to its body, which the client will only send after a 100 continue response.
+    /// Contains the request without its body and its HTTP version. Only returned once per request.
+    ExpectsContinue(Request, &'static str),
     /// An error occurred while trying to read a request.
     Error(ReadRequestError),
     /// The connection was closed.
@@ -30,12 +43,84 @@ pub enum ReadRequestError {
     IoErr(std::io::Error),
 }
 
+/// The protocol used by a client, as found by reading the HTTP/2 connection preface.
+#[cfg(feature = "http2")]
+pub enum Preface {
+    /// Not enough data has been received yet to tell which protocol the client uses.
+    NotReady,
+    /// The client sent the preface, so the connection uses HTTP/2.
+    Http2,
+    /// The client did not send the preface, so the connection uses HTTP/1.1.
+    Http1,
+    /// The data that started like the preface could not be parsed as an HTTP/1.1 request.
+    Error(ReadRequestError),
+    /// The connection was closed.
+    Closed,
+}
+
+/// What a connection is waiting for, which decides the timeout that applies to it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waiting {
+    /// Nothing from the client, such as while events are being sent. No timeout applies.
+    Nothing,
+    /// The next request, since the given time.
+    Request(Instant),
+    /// The rest of the head of a request, which started arriving at the given time.
+    Head(Instant),
+    /// The rest of the body of a request, which started arriving at the given time. Contains the amount read so far.
+    Body(Instant, usize),
+    /// The client to receive written data, since the given time. Contains the amount of data that has not been sent.
+    Write(Instant, usize),
+}
+
+impl Waiting {
+    /// Gets the time the connection times out at with the given timeouts, or None if it never times out.
+    pub fn deadline(&self, timeouts: &Timeouts) -> Option<Instant> {
+        match *self {
+            Nothing => None,
+            NextRequest(since) => Some(since + timeouts.keep_alive?),
+            Head(since) => Some(since + timeouts.header_read?),
+            Body(since, read) => {
+                let rate = timeouts.min_body_rate.filter(|rate| rate.bytes_per_second > 0)?;
+                let allowed = Duration::from_nanos((read as u64).saturating_mul(1_000_000_000) / rate.bytes_per_second);
+                Some(since + rate.grace_period + allowed)
+            }
+            WriteData(since, _) => Some(since + timeouts.write?),
+        }
+    }
+
+    /// Checks if the client is in the middle of sending a request, so it should get a 408 response if it times out.
+    pub fn is_receiving_request(&self) -> bool {
+        matches!(self, Head(_) | Body(_, _))
+    }
+}
+
 /// A connection to a client. The main purpose of this is to store the state of asynchronous IO.
 pub struct Connection<S: BufStream> {
     /// The address of the client.
     pub addr: SocketAddr,
     stream: S,
     parser: Option<RequestParser>,
+    /// Whether ExpectsContinue has been returned for the request currently being parsed.
+    continue_handled: bool,
+    /// The event stream being sent in response to the last request, if any. No more requests are read until it ends.
+    pub event_stream: Option<EventStream>,
+    /// Whether the connection is closed once the data written to it has been sent. No more requests are read from it.
+    pub closing: bool,
+    /// The request read on the event loop that is left for the thread pool, since its listener may block.
+    pub offloaded: Option<Request>,
+    /// The information about the connection, once it has been gathered.
+    info: Option<Arc<ConnectionInfo>>,
+    /// The number of requests read from the connection.
+    requests: u64,
+    /// What the connection was waiting for when it was last checked, along with the number of requests read by then.
+    waiting: (Waiting, u64),
+    /// The number of bytes of the HTTP/2 preface read so far, or None once the protocol of the connection is known.
+    #[cfg(feature = "http2")]
+    preface_read: Option<usize>,
+    /// The state of the connection if it uses HTTP/2.
+    #[cfg(feature = "http2")]
+    http2: Option<Box<Http2Connection>>,
 }
 
 impl<S: BufStream> Connection<S> {
@@ -45,24 +130,205 @@ impl<S: BufStream> Connection<S> {
             addr,
             stream,
             parser: Some(RequestParser::new()),
+            continue_handled: false,
+            event_stream: None,
+            closing: false,
+            offloaded: None,
+            info: None,
+            requests: 0,
+            waiting: (NextRequest(Instant::now()), 0),
+            #[cfg(feature = "http2")]
+            preface_read: Some(0),
+            #[cfg(feature = "http2")]
+            http2: None,
         }
     }
 
+    /// Gets a reference to the stream of the connection.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Consumes the connection and returns its stream, including any data that has been buffered but not parsed.
+    pub fn into_stream(self) -> S {
+        self.stream
+    }
+
+    /// Gets the HTTP/2 state of the connection along with its stream, if the connection uses HTTP/2.
+    #[cfg(feature = "http2")]
+    pub fn http2_mut(&mut self) -> Option<(&mut Http2Connection, &mut S)> {
+        match &mut self.http2 {
+            Some(http2) => Some((http2, &mut self.stream)),
+            None => None,
+        }
+    }
+
+    /// Gets the number of HTTP/1.1 requests read from the connection.
+    pub fn requests(&self) -> u64 {
+        self.requests
+    }
+
     /// Attempts to read a request and parse it from the underlying stream.
     pub fn read_request(&mut self) -> ReadRequestResult {
-        let parser = self.parser.take().unwrap_or_else(|| RequestParser::new());
+        // a connection that has been read as HTTP/1.1 can not switch to HTTP/2
+        #[cfg(feature = "http2")]
+        {
+            self.preface_read = None;
+        }
+        let parser = self.parser.take().unwrap_or_else(RequestParser::new);
 
         match parser.parse(&mut self.stream) {
-            Ok(ParseStatus::Done(request)) => Ready(request),
+            Ok(ParseStatus::Done(request)) => {
+                self.continue_handled = false;
+                self.requests += 1;
+                Ready(request)
+            }
             Ok(ParseStatus::IoErr(parser, err)) if err.kind() == ErrorKind::WouldBlock => {
+                let expecting_continue = if self.continue_handled { None } else { parser.expecting_continue() };
                 self.parser = Some(parser);
-                NotReady
+                match expecting_continue {
+                    Some((request, version)) => {
+                        self.continue_handled = true;
+                        ExpectsContinue(request, version)
+                    }
+                    None => NotReady
+                }
             }
             Ok(ParseStatus::IoErr(parser, err)) if is_closed(&parser, &err) => Closed,
             Ok(ParseStatus::IoErr(_, err)) => Error(IoErr(err)),
             Err(res) => Error(ParseErr(res))
         }
     }
+
+    /// Updates what the connection is waiting for and returns it. Unsent is the amount of written data that has not
+    /// been sent to the client, or None if all of it has been sent. Waiting for the client to receive data takes
+    /// precedence over waiting for it to send data. The time the connection started waiting is kept as long as it
+    /// waits for the same thing, or until more of the unsent data has been sent.
+    pub fn update_waiting(&mut self, unsent: Option<usize>, now: Instant) -> Waiting {
+        let requests = self.requests_read();
+        let previous = if self.waiting.1 == requests { self.waiting.0 } else { Nothing };
+        let current = match unsent {
+            Some(unsent) => WriteData(now, unsent),
+            None => self.waiting_to_read(now),
+        };
+        let waiting = match (previous, current) {
+            (WriteData(since, previous), WriteData(_, unsent)) if unsent >= previous => WriteData(since, unsent),
+            (NextRequest(since), NextRequest(_)) => NextRequest(since),
+            (Head(since), Head(_)) => Head(since),
+            (Body(since, _), Body(_, read)) => Body(since, read),
+            (_, current) => current,
+        };
+        self.waiting = (waiting, requests);
+        waiting
+    }
+
+    /// Gets what the connection is waiting to read from the client, as if it started waiting at the given time.
+    fn waiting_to_read(&self, now: Instant) -> Waiting {
+        if self.event_stream.is_some() {
+            return Nothing;
+        }
+        #[cfg(feature = "http2")]
+        {
+            if let Some(http2) = &self.http2 {
+                return if http2.has_open_streams() { Nothing } else { NextRequest(now) };
+            }
+            if matches!(self.preface_read, Some(read) if read > 0) {
+                return Head(now);
+            }
+        }
+        match &self.parser {
+            Some(parser) => match parser.body_read() {
+                Some(read) => Body(now, read),
+                None if parser.has_data() => Head(now),
+                None => NextRequest(now),
+            },
+            None => NextRequest(now),
+        }
+    }
+
+    /// Gets the number of requests read from the connection, or the last stream ID for HTTP/2 connections, which only
+    /// grows as requests are read.
+    fn requests_read(&self) -> u64 {
+        #[cfg(feature = "http2")]
+        if let Some(http2) = &self.http2 {
+            return http2.last_stream_id() as u64;
+        }
+        self.requests
+    }
+
+    /// Reads and discards any data the client has sent. Returns false if the client has closed the connection.
+    pub fn discard_input(&mut self) -> bool {
+        loop {
+            match self.stream.fill_buf() {
+                Ok([]) => return false,
+                Ok(buf) => {
+                    let amount = buf.len();
+                    self.stream.consume(amount);
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return true,
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(_) => return false,
+            }
+        }
+    }
+}
+
+impl<S: BufStream + DescribeConnection> Connection<S> {
+    /// Gets the information about the connection. It is gathered the first time this is called, which must not be before
+    /// the TLS handshake is complete.
+    pub fn info(&mut self) -> Arc<ConnectionInfo> {
+        let (stream, addr) = (&self.stream, self.addr);
+        self.info.get_or_insert_with(|| Arc::new(stream.connection_info(addr))).clone()
+    }
+
+    /// Reads the HTTP/2 connection preface, if the client sends it, to find the protocol of the connection.
+    /// The preface is only consumed once it is known to be the whole preface. Data that turns out to be an HTTP/1.1
+    /// request is passed to the request parser instead.
+    #[cfg(feature = "http2")]
+    pub fn read_preface(&mut self) -> Preface {
+        let mut read = match self.preface_read {
+            Some(read) => read,
+            None if self.http2.is_some() => return Preface::Http2,
+            None => return Preface::Http1,
+        };
+
+        loop {
+            let buf = match self.stream.fill_buf() {
+                Ok([]) => return Preface::Closed,
+                Ok(buf) => buf,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Preface::NotReady,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => return Preface::Closed,
+            };
+
+            let expected = &PREFACE[read..];
+            let amount = buf.len().min(expected.len());
+            if buf[..amount] != expected[..amount] {
+                self.preface_read = None;
+                if read == 0 {
+                    return Preface::Http1;
+                }
+                // the start of the preface was already consumed, so it is parsed as the start of a request
+                return match RequestParser::new().parse(&mut &PREFACE[..read]) {
+                    Ok(ParseStatus::IoErr(parser, _)) => {
+                        self.parser = Some(parser);
+                        Preface::Http1
+                    }
+                    Ok(ParseStatus::Done(_)) => Preface::Http1,
+                    Err(error) => Preface::Error(ParseErr(error)),
+                };
+            }
+
+            self.stream.consume(amount);
+            read += amount;
+            self.preface_read = Some(read);
+            if read == PREFACE.len() {
+                self.preface_read = None;
+                self.http2 = Some(Box::new(Http2Connection::new(self.info())));
+                return Preface::Http2;
+            }
+        }
+    }
 }
 
 impl<S: BufStream> Write for Connection<S> {
@@ -0,0 +1,112 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use mio::net::TcpStream;
+use rustls::{Certificate, CipherSuite, ProtocolVersion, ServerSession, Session};
+
+use crate::server::upgrade::ServerStream;
+use crate::tls::CertificateIdentity;
+use crate::util::stream::Stream;
+use crate::util::tls_stream::TlsStream;
+
+/// Information about the connection a request was received on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionInfo {
+    /// The address of the client.
+    pub peer_addr: SocketAddr,
+    /// The address of the server that the client connected to.
+    pub local_addr: SocketAddr,
+    /// The TLS session of the connection, or None if the connection does not use TLS.
+    pub tls: Option<TlsInfo>,
+}
+
+/// Information about the TLS session of a connection, as negotiated in its handshake.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsInfo {
+    /// The TLS version of the session.
+    pub protocol_version: Option<ProtocolVersion>,
+    /// The cipher suite of the session.
+    pub cipher_suite: Option<CipherSuite>,
+    /// The hostname the client requested with SNI, if any.
+    pub sni_hostname: Option<String>,
+    /// The protocol negotiated with ALPN, if any.
+    pub alpn_protocol: Option<Vec<u8>>,
+    /// The certificate chain the client sent, starting with the client's own certificate. The chain has been verified
+    /// by the client certificate verifier of the TLS config. None if the client did not send a certificate.
+    pub client_certs: Option<Vec<Certificate>>,
+    /// The identity in the certificate the client sent, if any.
+    pub client_identity: Option<CertificateIdentity>,
+}
+
+impl TlsInfo {
+    /// Gets the information about the given session.
+    fn new(session: &ServerSession) -> TlsInfo {
+        let client_certs = session.get_peer_certificates();
+        // the certificate has already been parsed by the verifier, so it is valid
+        let client_identity = client_certs.as_ref()
+            .and_then(|certs| certs.first())
+            .and_then(|cert| CertificateIdentity::from_certificate(cert).ok());
+        TlsInfo {
+            protocol_version: session.get_protocol_version(),
+            cipher_suite: session.get_negotiated_ciphersuite().map(|suite| suite.suite),
+            sni_hostname: session.get_sni_hostname().map(String::from),
+            alpn_protocol: session.get_alpn_protocol().map(Vec::from),
+            client_certs,
+            client_identity,
+        }
+    }
+}
+
+/// A stream that can describe the connection it is on.
+pub(crate) trait DescribeConnection {
+    /// Gets the local address of the connection.
+    fn local_addr(&self) -> std::io::Result<SocketAddr>;
+
+    /// Gets the TLS session of the connection, or None if the connection does not use TLS.
+    fn tls_info(&self) -> Option<TlsInfo> {
+        None
+    }
+
+    /// Gets the information about the connection to the client at the given address.
+    /// Must not be called before the TLS handshake is complete, since the session is not known until then.
+    fn connection_info(&self, peer_addr: SocketAddr) -> ConnectionInfo {
+        ConnectionInfo {
+            peer_addr,
+            // the address is only missing if the socket has already been closed, in which case it does not matter
+            local_addr: self.local_addr().unwrap_or_else(|_| (Ipv4Addr::UNSPECIFIED, 0).into()),
+            tls: self.tls_info(),
+        }
+    }
+}
+
+impl DescribeConnection for TcpStream {
+    fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        TcpStream::local_addr(self)
+    }
+}
+
+impl DescribeConnection for TlsStream<ServerSession, TcpStream> {
+    fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.get_ref().local_addr()
+    }
+
+    fn tls_info(&self) -> Option<TlsInfo> {
+        Some(TlsInfo::new(self.session()))
+    }
+}
+
+impl<T: Stream + DescribeConnection> DescribeConnection for ServerStream<T> {
+    fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.get_ref().get_ref().get_ref().get_ref().local_addr()
+    }
+
+    fn tls_info(&self) -> Option<TlsInfo> {
+        self.get_ref().get_ref().get_ref().get_ref().tls_info()
+    }
+}
+
+#[cfg(test)]
+impl<R, W> DescribeConnection for crate::util::mock::MockStream<R, W> {
+    fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        Ok((Ipv4Addr::LOCALHOST, 80).into())
+    }
+}
@@ -0,0 +1,56 @@
+use std::sync::{PoisonError, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::common::date::format_http_date;
+
+/// Length of a formatted HTTP-date, like "Sun, 06 Nov 1994 08:49:37 GMT".
+const DATE_LENGTH: usize = 29;
+
+/// The second the cached date was formatted for.
+static CACHED_SECOND: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// The formatted date, shared by every thread that writes responses.
+static CACHED_DATE: RwLock<[u8; DATE_LENGTH]> = RwLock::new([0; DATE_LENGTH]);
+
+/// Calls the given function with the current time formatted as an HTTP-date.
+/// The formatted date is cached for all threads and only recomputed when the second changes. The cache is only locked
+/// while the date is copied, so the given function is not called with the lock held.
+pub fn with_current_date<R>(f: impl FnOnce(&str) -> R) -> R {
+    let now = SystemTime::now();
+    let second = now.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+
+    let mut date = [0; DATE_LENGTH];
+    if CACHED_SECOND.load(Ordering::Acquire) == second {
+        date = *CACHED_DATE.read().unwrap_or_else(PoisonError::into_inner);
+    } else {
+        // dates are clamped to four digit years, so they always have the same length
+        date.copy_from_slice(format_http_date(now).as_bytes());
+        *CACHED_DATE.write().unwrap_or_else(PoisonError::into_inner) = date;
+        CACHED_SECOND.store(second, Ordering::Release);
+    }
+
+    f(std::str::from_utf8(&date).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::common::date::parse_http_date;
+    use crate::server::date_cache::with_current_date;
+
+    #[test]
+    fn current_date_is_valid_http_date() {
+        let date = with_current_date(|date| date.to_string());
+        assert!(parse_http_date(&date).is_some());
+    }
+
+    #[test]
+    fn current_date_from_many_threads() {
+        let threads: Vec<_> = (0..8).map(|_| thread::spawn(|| with_current_date(|date| date.to_string()))).collect();
+        for thread in threads {
+            assert!(parse_http_date(&thread.join().unwrap()).is_some());
+        }
+    }
+}
@@ -0,0 +1,441 @@
+use std::fmt::{Debug, Formatter};
+use std::io::{Error as IoError, ErrorKind, Result};
+use std::sync::{Arc, Condvar, Mutex, OnceLock, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::common::header::{CACHE_CONTROL, CONTENT_TYPE, HeaderMap, HeaderMapOps, LAST_EVENT_ID, TRANSFER_ENCODING};
+use crate::common::request::Request;
+use crate::common::response::Response;
+use crate::common::status;
+use crate::header_map;
+use crate::sse::{Event, write_comment, write_event};
+
+/// The default time between keep-alive comments.
+const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// The text of keep-alive comments.
+const KEEP_ALIVE_COMMENT: &str = "keep-alive";
+
+/// The default max number of bytes of events that can be waiting to be written to the connection.
+const DEFAULT_MAX_PENDING: usize = 1024 * 1024;
+
+/// A function that schedules the connection of an event stream to be handled, so that sent events are written to it.
+pub(crate) type Waker = Arc<dyn Fn() + Send + Sync>;
+
+/// A long-lived text/event-stream response. Returned from a listener to start streaming the events pushed into its
+/// EventSender to the client. The stream ends once every sender has been dropped.
+/// The stream is written as the connection becomes writable, so no thread is blocked while it is open. Events are only
+/// taken from the stream once the previous ones have been sent, so the events of a client that stops reading are held
+/// by the stream until its max pending bytes are reached.
+/// Data sent by the client while the stream is open is discarded.
+pub struct EventStream {
+    /// Extra headers to send with the response, such as CORS headers.
+    pub headers: HeaderMap,
+    shared: Arc<Shared>,
+    /// Whether the connection should be closed once the stream ends.
+    pub(crate) close_after: bool,
+}
+
+/// Sends events to the client of an event stream. Can be cloned and used from any thread.
+pub struct EventSender {
+    shared: Arc<Shared>,
+    last_event_id: Option<String>,
+}
+
+/// The state of an event stream shared by its senders, its connection, and the keep-alive timer.
+struct Shared {
+    state: Mutex<State>,
+}
+
+struct State {
+    /// Encoded events that have not been written to the connection yet.
+    pending: Vec<u8>,
+    /// The max length of pending.
+    max_pending: usize,
+    /// The number of senders that have not been dropped.
+    senders: usize,
+    /// Whether the connection is gone, so events can no longer be sent.
+    closed: bool,
+    /// Schedules the connection to be handled. None until the response has been sent.
+    waker: Option<Waker>,
+    /// Whether the connection has been scheduled and has not taken the pending events yet.
+    woken: bool,
+    /// The time after the last write that a keep-alive comment is sent.
+    keep_alive: Option<Duration>,
+    /// When data was last added to the stream.
+    last_write: Instant,
+}
+
+impl EventStream {
+    /// Creates a new event stream in response to the given request, along with the sender for its events.
+    /// The last-event-id header of the request, sent by reconnecting clients, is available from the sender.
+    /// Keep-alive comments are sent after 15 seconds without any events.
+    /// ```
+    /// use std::thread;
+    /// use my_http::server::{EventStream, ListenerResult, Router};
+    /// use my_http::sse::Event;
+    ///
+    /// let mut router = Router::new();
+    /// router.on("/events", |_, request| {
+    ///     let (stream, sender) = EventStream::new(request);
+    ///     thread::spawn(move || {
+    ///         let start = sender.last_event_id().and_then(|id| id.parse().ok()).unwrap_or(0);
+    ///         for i in start + 1.. {
+    ///             if sender.send(&Event::new("tick").with_id(&i.to_string())).is_err() {
+    ///                 break;
+    ///             }
+    ///             thread::sleep(std::time::Duration::from_secs(1));
+    ///         }
+    ///     });
+    ///     ListenerResult::EventStream(stream)
+    /// });
+    /// ```
+    pub fn new(request: &Request) -> (EventStream, EventSender) {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                pending: vec![],
+                max_pending: DEFAULT_MAX_PENDING,
+                senders: 1,
+                closed: false,
+                waker: None,
+                woken: false,
+                keep_alive: Some(DEFAULT_KEEP_ALIVE),
+                last_write: Instant::now(),
+            })
+        });
+        let last_event_id = request.headers.get_first_header_value(&LAST_EVENT_ID).cloned();
+
+        let stream = EventStream { headers: HeaderMap::new(), shared: shared.clone(), close_after: false };
+        let sender = EventSender { shared, last_event_id };
+        (stream, sender)
+    }
+
+    /// Sets the time without any events after which a keep-alive comment is sent, or None to never send them.
+    pub fn with_keep_alive(self, keep_alive: Option<Duration>) -> EventStream {
+        self.shared.state.lock().unwrap().keep_alive = keep_alive;
+        self
+    }
+
+    /// Sets the max number of bytes of events that can be waiting to be written to the connection, which is 1 MiB by
+    /// default. Sending an event that does not fit returns a WouldBlock error.
+    pub fn with_max_pending(self, max_pending: usize) -> EventStream {
+        self.shared.state.lock().unwrap().max_pending = max_pending;
+        self
+    }
+
+    /// Gets the head of the response that starts the stream.
+    pub(crate) fn response(&self) -> Response {
+        let mut headers = header_map![
+            (CONTENT_TYPE, "text/event-stream"),
+            (CACHE_CONTROL, "no-cache"),
+            (TRANSFER_ENCODING, "chunked")
+        ];
+        headers.extend(self.headers.clone());
+        Response { status: status::OK, headers, body: vec![] }
+    }
+
+    /// Starts sending events and keep-alive comments once the response has been sent.
+    /// The waker is called whenever there is new data for the connection.
+    pub(crate) fn attach(&self, waker: Waker) {
+        self.shared.state.lock().unwrap().waker = Some(waker);
+        keep_alive_timer().add(&self.shared);
+        self.shared.wake();
+    }
+
+    /// Takes the encoded events that have not been written to the connection yet.
+    /// Also returns true if the stream has ended, in which case there will be no more events.
+    pub(crate) fn take_events(&self) -> (Vec<u8>, bool) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.woken = false;
+        (std::mem::take(&mut state.pending), state.senders == 0)
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().closed = true;
+    }
+}
+
+impl Debug for EventStream {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventStream").field("headers", &self.headers).finish_non_exhaustive()
+    }
+}
+
+impl EventSender {
+    /// Gets the value of the last-event-id header sent by the client, which is the ID of the last event it received
+    /// before reconnecting.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    /// Sends the given event. Returns a BrokenPipe error if the connection has closed, an InvalidInput error if the
+    /// type or ID of the event contains a line break, or a WouldBlock error if the client has not received enough of the
+    /// previous events to make room for it. Nothing is sent when an error is returned.
+    pub fn send(&self, event: &Event) -> Result<()> {
+        self.shared.push(|pending| write_event(pending, event).map_err(|error| IoError::new(ErrorKind::InvalidInput, error)))
+    }
+
+    /// Sends a comment, which clients ignore. Returns a BrokenPipe error if the connection has closed, or a WouldBlock
+    /// error if the client has not received enough of the previous events to make room for it.
+    pub fn send_comment(&self, text: &str) -> Result<()> {
+        self.shared.push(|pending| {
+            write_comment(pending, text);
+            Ok(())
+        })
+    }
+
+    /// Checks if the connection has closed, meaning any more events can not be sent.
+    pub fn is_closed(&self) -> bool {
+        self.shared.state.lock().unwrap().closed
+    }
+}
+
+impl Clone for EventSender {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().unwrap().senders += 1;
+        EventSender { shared: self.shared.clone(), last_event_id: self.last_event_id.clone() }
+    }
+}
+
+impl Drop for EventSender {
+    /// Ends the stream if this is the last sender.
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.shared.wake();
+        }
+    }
+}
+
+impl Shared {
+    /// Adds data to the stream with the given function and wakes the connection. The data is removed again if it makes
+    /// the pending data longer than its max.
+    fn push(&self, write: impl FnOnce(&mut Vec<u8>) -> Result<()>) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(IoError::new(ErrorKind::BrokenPipe, "event stream closed"));
+        }
+        let previous_len = state.pending.len();
+        write(&mut state.pending)?;
+        if state.pending.len() > state.max_pending {
+            state.pending.truncate(previous_len);
+            return Err(IoError::new(ErrorKind::WouldBlock, "too many events waiting to be sent"));
+        }
+        state.last_write = Instant::now();
+        drop(state);
+        self.wake();
+        Ok(())
+    }
+
+    /// Schedules the connection to be handled, unless it is already scheduled or the response has not been sent yet.
+    fn wake(&self) {
+        let waker = {
+            let mut state = self.state.lock().unwrap();
+            if state.woken || state.waker.is_none() {
+                return;
+            }
+            state.woken = true;
+            state.waker.clone()
+        };
+        // the connection may be handled right away, which takes the lock
+        if let Some(waker) = waker {
+            waker();
+        }
+    }
+
+    /// Sends a keep-alive comment if nothing has been written for the keep-alive time.
+    /// Returns when the next keep-alive comment is due, or None if the stream no longer needs keep-alive comments.
+    fn keep_alive(&self, now: Instant) -> Option<Instant> {
+        let mut state = self.state.lock().unwrap();
+        let keep_alive = state.keep_alive.filter(|_| !state.closed && state.senders > 0)?;
+        if now < state.last_write + keep_alive {
+            return Some(state.last_write + keep_alive);
+        }
+
+        // a client that is not receiving the pending events has no use for a keep-alive comment
+        let previous_len = state.pending.len();
+        write_comment(&mut state.pending, KEEP_ALIVE_COMMENT);
+        if state.pending.len() > state.max_pending {
+            state.pending.truncate(previous_len);
+        }
+        state.last_write = now;
+        drop(state);
+        self.wake();
+        Some(now + keep_alive)
+    }
+}
+
+/// Sends keep-alive comments for all open event streams from a single thread.
+#[derive(Default)]
+struct KeepAliveTimer {
+    streams: Mutex<Vec<Weak<Shared>>>,
+    added: Condvar,
+}
+
+/// Gets the keep-alive timer, starting its thread the first time it is used.
+fn keep_alive_timer() -> &'static KeepAliveTimer {
+    static TIMER: OnceLock<KeepAliveTimer> = OnceLock::new();
+
+    let mut started = false;
+    let timer = TIMER.get_or_init(|| {
+        started = true;
+        KeepAliveTimer::default()
+    });
+    if started {
+        thread::spawn(move || timer.run());
+    }
+    timer
+}
+
+impl KeepAliveTimer {
+    /// Starts sending keep-alive comments for the given stream.
+    fn add(&self, stream: &Arc<Shared>) {
+        self.streams.lock().unwrap().push(Arc::downgrade(stream));
+        self.added.notify_one();
+    }
+
+    /// Sends keep-alive comments whenever they are due. Loops indefinitely.
+    fn run(&self) {
+        let mut streams = self.streams.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            let mut next: Option<Instant> = None;
+            streams.retain(|stream| {
+                let deadline = stream.upgrade().and_then(|stream| stream.keep_alive(now));
+                if let Some(deadline) = deadline {
+                    next = Some(next.map_or(deadline, |next| next.min(deadline)));
+                }
+                deadline.is_some()
+            });
+
+            streams = match next {
+                Some(next) => self.added.wait_timeout(streams, next.saturating_duration_since(now)).unwrap().0,
+                None => self.added.wait(streams).unwrap(),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    use crate::common::header::{CONTENT_TYPE, HeaderMapOps, TRANSFER_ENCODING};
+    use crate::common::request::Request;
+    use crate::server::event_stream::{EventSender, EventStream};
+    use crate::sse::Event;
+
+    fn new_stream() -> (EventStream, EventSender) {
+        EventStream::new(&Request::builder().uri("/").build().unwrap())
+    }
+
+    #[test]
+    fn response_head() {
+        let (stream, _) = new_stream();
+        let response = stream.response();
+        assert!(response.headers.contains_header_value(&CONTENT_TYPE, "text/event-stream"));
+        assert!(response.headers.contains_header_value(&TRANSFER_ENCODING, "chunked"));
+    }
+
+    #[test]
+    fn last_event_id() {
+        let request = Request::builder().uri("/").header("last-event-id", "42").build().unwrap();
+        let (_, sender) = EventStream::new(&request);
+        assert_eq!(sender.last_event_id(), Some("42"));
+        assert_eq!(new_stream().1.last_event_id(), None);
+    }
+
+    #[test]
+    fn events_are_buffered_until_taken() {
+        let (stream, sender) = new_stream();
+        sender.send(&Event::new("a")).unwrap();
+        sender.send_comment("b").unwrap();
+
+        assert_eq!(stream.take_events(), (b"data: a\n\n: b\n\n".to_vec(), false));
+        assert_eq!(stream.take_events(), (vec![], false));
+    }
+
+    #[test]
+    fn ends_when_all_senders_dropped() {
+        let (stream, sender) = new_stream();
+        let other = sender.clone();
+        drop(sender);
+        assert!(!stream.take_events().1);
+
+        other.send(&Event::new("last")).unwrap();
+        drop(other);
+        assert_eq!(stream.take_events(), (b"data: last\n\n".to_vec(), true));
+    }
+
+    #[test]
+    fn send_after_connection_closed() {
+        let (stream, sender) = new_stream();
+        drop(stream);
+        assert!(sender.is_closed());
+        assert_eq!(sender.send(&Event::new("a")).unwrap_err().kind(), ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn invalid_event() {
+        let (stream, sender) = new_stream();
+        assert_eq!(sender.send(&Event::new("a").with_id("1\n2")).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(stream.take_events(), (vec![], false));
+    }
+
+    #[test]
+    fn max_pending_without_reader() {
+        let (stream, sender) = new_stream();
+        let stream = stream.with_max_pending(30);
+
+        // nothing takes the events, so they fill up the stream
+        sender.send(&Event::new("0123456789")).unwrap();
+        assert_eq!(sender.send(&Event::new("0123456789")).unwrap_err().kind(), ErrorKind::WouldBlock);
+        sender.send_comment("0123").unwrap();
+        assert_eq!(sender.send_comment("a").unwrap_err().kind(), ErrorKind::WouldBlock);
+        assert!(!sender.is_closed());
+
+        assert_eq!(stream.take_events(), (b"data: 0123456789\n\n: 0123\n\n".to_vec(), false));
+        sender.send(&Event::new("0123456789")).unwrap();
+    }
+
+    #[test]
+    fn wakes_once_until_events_taken() {
+        let (stream, sender) = new_stream();
+        sender.send(&Event::new("before attach")).unwrap();
+
+        let wakes = Arc::new(AtomicUsize::new(0));
+        let counter = wakes.clone();
+        stream.attach(Arc::new(move || { counter.fetch_add(1, Ordering::SeqCst); }));
+        // pending events are written as soon as the stream is attached
+        assert_eq!(wakes.load(Ordering::SeqCst), 1);
+
+        sender.send(&Event::new("a")).unwrap();
+        assert_eq!(wakes.load(Ordering::SeqCst), 1);
+
+        stream.take_events();
+        sender.send(&Event::new("b")).unwrap();
+        assert_eq!(wakes.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn keep_alive_comments() {
+        let (stream, _sender) = new_stream();
+        let stream = stream.with_keep_alive(Some(Duration::from_millis(50)));
+        stream.attach(Arc::new(|| {}));
+
+        sleep(Duration::from_millis(180));
+
+        let (data, _) = stream.take_events();
+        let data = String::from_utf8(data).unwrap();
+        assert!(data.starts_with(": keep-alive\n\n: keep-alive\n\n"), "{}", data);
+    }
+}
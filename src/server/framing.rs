@@ -0,0 +1,186 @@
+use std::fmt::{Display, Formatter};
+
+use crate::common::response::Response;
+use crate::common::typed_header::{ContentLength, TransferEncoding, TypedHeader};
+
+/// How the body of a response is written.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum BodyFraming {
+    /// The body is not written.
+    Omitted,
+    /// The body is written as is, delimited by the content-length header.
+    Length,
+    /// The body is written using chunked transfer coding.
+    Chunked,
+}
+
+/// The framing of a response, which determines the framing headers and how the body is written.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct Framing {
+    /// A content-length to add to the response, since the response does not have one.
+    pub add_content_length: Option<usize>,
+    /// Whether the content-length and transfer-encoding headers of the response should be written.
+    pub write_framing_headers: bool,
+    /// How the body is written.
+    pub body: BodyFraming,
+}
+
+/// An error caused by framing headers of a response that conflict with each other or with the body.
+#[derive(Debug, Eq, PartialEq)]
+pub enum FramingError {
+    /// The content-length header is not a valid length, or has multiple different values.
+    InvalidContentLength,
+    /// The transfer-encoding header is invalid or its final coding is not chunked.
+    InvalidTransferEncoding,
+    /// The content-length header does not equal the length of the body.
+    ContentLengthMismatch { content_length: u64, body_length: usize },
+    /// The response has a content-length header and uses chunked transfer coding.
+    ContentLengthWithChunked,
+}
+
+impl Display for FramingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FramingError::InvalidContentLength => write!(f, "invalid content-length header"),
+            FramingError::InvalidTransferEncoding => write!(f, "invalid transfer-encoding header"),
+            FramingError::ContentLengthMismatch { content_length, body_length } =>
+                write!(f, "content-length header is {} but body has length {}", content_length, body_length),
+            FramingError::ContentLengthWithChunked => write!(f, "content-length header used with chunked transfer coding"),
+        }
+    }
+}
+
+impl std::error::Error for FramingError {}
+
+/// Gets the framing of the given response. head should be true if the response is to a HEAD request.
+/// Responses with 1xx and 204 statuses never have a body or framing headers. Responses with a 304 status or to HEAD
+/// requests never have a body, but keep the framing headers they would have had for a GET request.
+pub fn response_framing(response: &Response, head: bool) -> Result<Framing, FramingError> {
+    let code = response.status.code;
+    if (100..200).contains(&code) || code == 204 {
+        return Ok(Framing { add_content_length: None, write_framing_headers: false, body: BodyFraming::Omitted });
+    }
+
+    let content_length = ContentLength::decode(&response.headers)
+        .transpose()
+        .map_err(|_| FramingError::InvalidContentLength)?;
+    let transfer_encoding = TransferEncoding::decode(&response.headers)
+        .transpose()
+        .map_err(|_| FramingError::InvalidTransferEncoding)?;
+
+    // without a body there is nothing to check the framing headers against
+    let omit_body = head || code == 304;
+    let body_length = response.body.len();
+
+    let framing = match (content_length, transfer_encoding) {
+        (Some(_), Some(_)) => return Err(FramingError::ContentLengthWithChunked),
+        (None, Some(transfer_encoding)) if transfer_encoding.is_chunked() =>
+            Framing { add_content_length: None, write_framing_headers: true, body: BodyFraming::Chunked },
+        (None, Some(_)) => return Err(FramingError::InvalidTransferEncoding),
+        (Some(ContentLength(content_length)), None) => {
+            // an empty body in a response that omits the body may just mean the handler did not produce one
+            let omitted_and_empty = omit_body && body_length == 0;
+            if content_length != body_length as u64 && !omitted_and_empty {
+                return Err(FramingError::ContentLengthMismatch { content_length, body_length });
+            }
+            Framing { add_content_length: None, write_framing_headers: true, body: BodyFraming::Length }
+        }
+        (None, None) if code == 304 =>
+            Framing { add_content_length: None, write_framing_headers: true, body: BodyFraming::Length },
+        (None, None) =>
+            Framing { add_content_length: Some(body_length), write_framing_headers: true, body: BodyFraming::Length },
+    };
+
+    if omit_body {
+        return Ok(Framing { body: BodyFraming::Omitted, ..framing });
+    }
+
+    Ok(framing)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::header::{CONTENT_LENGTH, HeaderMap, TRANSFER_ENCODING};
+    use crate::common::response::Response;
+    use crate::common::status;
+    use crate::common::status::Status;
+    use crate::header_map;
+    use crate::server::framing::{BodyFraming, Framing, FramingError, response_framing};
+
+    fn response(status: Status, headers: HeaderMap, body: &[u8]) -> Response {
+        Response { status, headers, body: body.to_vec() }
+    }
+
+    fn framing(add_content_length: Option<usize>, write_framing_headers: bool, body: BodyFraming) -> Result<Framing, FramingError> {
+        Ok(Framing { add_content_length, write_framing_headers, body })
+    }
+
+    #[test]
+    fn missing_content_length_added() {
+        assert_eq!(response_framing(&response(status::OK, header_map![], b"hello"), false),
+                   framing(Some(5), true, BodyFraming::Length));
+        assert_eq!(response_framing(&response(status::OK, header_map![], b""), false),
+                   framing(Some(0), true, BodyFraming::Length));
+    }
+
+    #[test]
+    fn matching_content_length() {
+        assert_eq!(response_framing(&response(status::OK, header_map![(CONTENT_LENGTH, "5")], b"hello"), false),
+                   framing(None, true, BodyFraming::Length));
+        assert_eq!(response_framing(&response(status::OK, header_map![(CONTENT_LENGTH, "5"), (CONTENT_LENGTH, "5")], b"hello"), false),
+                   framing(None, true, BodyFraming::Length));
+    }
+
+    #[test]
+    fn conflicting_content_length() {
+        assert_eq!(response_framing(&response(status::OK, header_map![(CONTENT_LENGTH, "4")], b"hello"), false),
+                   Err(FramingError::ContentLengthMismatch { content_length: 4, body_length: 5 }));
+        assert_eq!(response_framing(&response(status::OK, header_map![(CONTENT_LENGTH, "5"), (CONTENT_LENGTH, "6")], b"hello"), false),
+                   Err(FramingError::InvalidContentLength));
+        assert_eq!(response_framing(&response(status::OK, header_map![(CONTENT_LENGTH, "five")], b"hello"), false),
+                   Err(FramingError::InvalidContentLength));
+    }
+
+    #[test]
+    fn chunked() {
+        assert_eq!(response_framing(&response(status::OK, header_map![(TRANSFER_ENCODING, "chunked")], b"hello"), false),
+                   framing(None, true, BodyFraming::Chunked));
+        assert_eq!(response_framing(&response(status::OK, header_map![(TRANSFER_ENCODING, "gzip, chunked")], b"hello"), false),
+                   framing(None, true, BodyFraming::Chunked));
+    }
+
+    #[test]
+    fn chunked_conflicts() {
+        assert_eq!(response_framing(&response(status::OK, header_map![(TRANSFER_ENCODING, "chunked"), (CONTENT_LENGTH, "5")], b"hello"), false),
+                   Err(FramingError::ContentLengthWithChunked));
+        assert_eq!(response_framing(&response(status::OK, header_map![(TRANSFER_ENCODING, "gzip")], b"hello"), false),
+                   Err(FramingError::InvalidTransferEncoding));
+    }
+
+    #[test]
+    fn no_body_statuses() {
+        let headers = header_map![(CONTENT_LENGTH, "5")];
+        assert_eq!(response_framing(&response(status::NO_CONTENT, headers.clone(), b"hello"), false),
+                   framing(None, false, BodyFraming::Omitted));
+        assert_eq!(response_framing(&response(status::CONTINUE, headers, b"hello"), false),
+                   framing(None, false, BodyFraming::Omitted));
+    }
+
+    #[test]
+    fn not_modified() {
+        assert_eq!(response_framing(&response(status::NOT_MODIFIED, header_map![], b""), false),
+                   framing(None, true, BodyFraming::Omitted));
+        assert_eq!(response_framing(&response(status::NOT_MODIFIED, header_map![(CONTENT_LENGTH, "100")], b""), false),
+                   framing(None, true, BodyFraming::Omitted));
+    }
+
+    #[test]
+    fn head() {
+        assert_eq!(response_framing(&response(status::OK, header_map![], b"hello"), true),
+                   framing(Some(5), true, BodyFraming::Omitted));
+        assert_eq!(response_framing(&response(status::OK, header_map![(CONTENT_LENGTH, "100")], b""), true),
+                   framing(None, true, BodyFraming::Omitted));
+        assert_eq!(response_framing(&response(status::OK, header_map![(CONTENT_LENGTH, "100")], b"hello"), true),
+                   Err(FramingError::ContentLengthMismatch { content_length: 100, body_length: 5 }));
+    }
+}
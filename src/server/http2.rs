@@ -0,0 +1,1218 @@
+use std::collections::BTreeMap;
+use std::io::{ErrorKind, Write};
+use std::sync::Arc;
+
+use crate::common::header::{CONTENT_LENGTH, COOKIE, DATE, EXPECT, Header, HeaderMap, HeaderMapOps, HOST, SERVER, TRANSFER_ENCODING};
+use crate::common::method::Method;
+use crate::common::request::Request;
+use crate::common::response::Response;
+use crate::common::status::{BAD_REQUEST, CONTINUE, INTERNAL_SERVER_ERROR, NOT_FOUND, PAYLOAD_TOO_LARGE, REQUEST_HEADER_FIELDS_TOO_LARGE, SWITCHING_PROTOCOLS};
+use crate::common::typed_header::{ContentLength, TypedHeader};
+use crate::http2::error::{COMPRESSION_ERROR, ENHANCE_YOUR_CALM, ErrorCode, FLOW_CONTROL_ERROR, Http2Error, NO_ERROR, PROTOCOL_ERROR, REFUSED_STREAM, STREAM_CLOSED};
+use crate::http2::frame::{encode_data, encode_header_block, Frame};
+use crate::http2::hpack::{Decoder, Encoder, HpackError};
+use crate::http2::settings::{DEFAULT_MAX_FRAME_SIZE, DEFAULT_WINDOW_SIZE, Settings};
+use crate::http2::window::Window;
+use crate::http2::{CONNECTION_SPECIFIC_HEADERS, WINDOW_UPDATE_THRESHOLD};
+use crate::parse::body::MAX_BODY_SIZE;
+use crate::parse::frame::FrameParser;
+use crate::parse::parse::Parse;
+use crate::parse::parse::ParseStatus::{Done, IoErr};
+use crate::server::config::{Config, ContinueResult};
+use crate::server::connection_info::ConnectionInfo;
+use crate::server::date_cache::with_current_date;
+use crate::server::event_stream::{EventStream, Waker};
+use crate::server::framing::{BodyFraming, Framing, response_framing};
+use crate::server::router::ListenerResult::{EventStream as SendEventStream, Next, SendResponse, SendResponseArc, Upgrade};
+use crate::server::router::{RequestContext, ResponseSink};
+use crate::util::panic::catch_panic;
+use crate::util::stream::BufStream;
+
+/// The maximum number of streams a client may open at once.
+const MAX_CONCURRENT_STREAMS: u32 = 100;
+
+/// The largest header list accepted. Larger header blocks are rejected before they are decoded.
+const MAX_HEADER_LIST_SIZE: u32 = 16384;
+
+/// Framing for a response head that is sent on its own, such as an informational response or the head of an event
+/// stream response, whose body is sent in DATA frames as events are sent.
+const HEAD_ONLY_FRAMING: Framing = Framing { add_content_length: None, write_framing_headers: false, body: BodyFraming::Omitted };
+
+/// The state of an HTTP/2 connection to a client, after the client has sent the connection preface.
+/// Responses are queued on their streams and sent as the flow-control windows of the client allow.
+pub(crate) struct Http2Connection {
+    parser: Option<FrameParser>,
+    decoder: Decoder,
+    encoder: Encoder,
+    /// The settings sent by the client.
+    remote_settings: Settings,
+    /// The open streams, which are removed once the response has been sent or the stream has been reset.
+    streams: BTreeMap<u32, Stream>,
+    /// The largest stream ID opened by the client. Streams with smaller IDs that are not open are closed.
+    last_stream_id: u32,
+    /// The connection window for sending data.
+    send_window: Window,
+    /// The connection window for receiving data.
+    recv_window: Window,
+    /// The amount of data received on the connection that has not been acknowledged with a WINDOW_UPDATE frame.
+    recv_unacknowledged: usize,
+    /// The header block being received in CONTINUATION frames, if any.
+    header_block: Option<HeaderBlock>,
+    /// Whether the client sent a GOAWAY frame, so no more streams are accepted.
+    going_away: bool,
+    /// Encoded frames that have not been written to the connection yet.
+    out: Vec<u8>,
+    /// The waker shared by the event streams of the connection.
+    waker: Option<Waker>,
+    /// The complete requests whose listeners may block, which are routed on the thread pool rather than the event loop.
+    offloaded: Vec<(u32, Request)>,
+    /// The information about the connection, which is given to the router with every request.
+    info: Arc<ConnectionInfo>,
+}
+
+/// A header block that is split over multiple frames.
+struct HeaderBlock {
+    stream_id: u32,
+    /// Whether the HEADERS frame that started the block ended the stream.
+    end_stream: bool,
+    block: Vec<u8>,
+}
+
+/// A stream that carries one request and its response.
+struct Stream {
+    /// The request whose body is being received, or None once it has been routed or rejected.
+    request: Option<Request>,
+    /// Whether the client may still send frames on the stream.
+    receiving: bool,
+    recv_window: Window,
+    /// The amount of data received on the stream that has not been acknowledged with a WINDOW_UPDATE frame.
+    recv_unacknowledged: usize,
+    send_window: Window,
+    /// Body data waiting for the flow-control windows. Data before the sent offset has already been sent.
+    pending: Vec<u8>,
+    sent: usize,
+    /// Whether the stream ends once the pending data has been sent.
+    end_after_pending: bool,
+    /// Whether a frame that ends the stream has been sent.
+    sent_end: bool,
+    /// The event stream being sent as the body of the response, if any.
+    event_stream: Option<EventStream>,
+}
+
+/// A request that can not be routed.
+enum InvalidRequest {
+    /// The request violates the protocol, so its stream is reset.
+    Malformed,
+    /// The request has a method that is not supported, so it is answered with a 400 response.
+    UnsupportedMethod,
+}
+
+impl Http2Connection {
+    /// Creates the state of a new connection with the given information, and queues the SETTINGS frame that starts the
+    /// server side of the connection.
+    pub fn new(info: Arc<ConnectionInfo>) -> Http2Connection {
+        let local_settings = Settings {
+            max_concurrent_streams: Some(MAX_CONCURRENT_STREAMS),
+            max_header_list_size: Some(MAX_HEADER_LIST_SIZE),
+            ..Default::default()
+        };
+        let mut out = vec![];
+        Frame::Settings { ack: false, params: local_settings.changes() }.encode(&mut out);
+
+        Http2Connection {
+            parser: Some(FrameParser::new(DEFAULT_MAX_FRAME_SIZE)),
+            decoder: Decoder::new(MAX_HEADER_LIST_SIZE as usize),
+            encoder: Encoder::new(),
+            remote_settings: Settings::default(),
+            streams: BTreeMap::new(),
+            last_stream_id: 0,
+            send_window: Window::new(DEFAULT_WINDOW_SIZE),
+            recv_window: Window::new(DEFAULT_WINDOW_SIZE),
+            recv_unacknowledged: 0,
+            header_block: None,
+            going_away: false,
+            out,
+            waker: None,
+            offloaded: vec![],
+            info,
+        }
+    }
+
+    /// Checks if the connection has streams that are still open.
+    pub fn has_open_streams(&self) -> bool {
+        !self.streams.is_empty()
+    }
+
+    /// Checks if there are requests whose listeners may block, so the connection has to be served on the thread pool.
+    pub fn has_offloaded(&self) -> bool {
+        !self.offloaded.is_empty()
+    }
+
+    /// Gets the largest stream ID opened by the client, which grows with every request.
+    pub fn last_stream_id(&self) -> u32 {
+        self.last_stream_id
+    }
+
+    /// Reads the frames the client has sent, routes the requests that are complete, and sends as much of the pending
+    /// responses and events as flow control allows. The given function creates the waker that schedules the connection
+    /// to be served again when an event stream has new events.
+    /// On the event loop, requests whose listeners may block are left for the thread pool.
+    /// Returns false if the connection should be closed.
+    pub fn serve(&mut self, stream: &mut impl BufStream, config: &Config, on_event_loop: bool, new_waker: &dyn Fn() -> Waker) -> bool {
+        let result = self.read_frames(stream, config, new_waker).and_then(|open| {
+            if !on_event_loop {
+                self.route_offloaded(stream, config, new_waker)?;
+            }
+            Ok(open)
+        });
+        let mut open = match result {
+            Ok(open) => open,
+            Err(error) => {
+                println!("Error: {}", error);
+                if let Http2Error::Connection(code, reason) = error {
+                    Frame::GoAway { last_stream_id: self.last_stream_id, error: code, debug_data: reason.as_bytes().to_vec() }.encode(&mut self.out);
+                }
+                false
+            }
+        };
+
+        if open {
+            self.take_events();
+            self.send_pending();
+            open = !self.going_away || !self.streams.is_empty();
+        }
+
+        let written = stream.write_all(&self.out).and_then(|_| stream.flush());
+        self.out.clear();
+        open && written.is_ok()
+    }
+
+    /// Reads and handles frames until reading would block. Returns false if the client closed the connection, or an
+    /// error if the client violated the protocol in a way that ends the connection.
+    fn read_frames(&mut self, stream: &mut impl BufStream, config: &Config, new_waker: &dyn Fn() -> Waker) -> Result<bool, Http2Error> {
+        loop {
+            let parser = self.parser.take().unwrap_or_else(|| FrameParser::new(DEFAULT_MAX_FRAME_SIZE));
+            match parser.parse(stream) {
+                Ok(Done(frame)) => match frame.and_then(|frame| self.handle_frame(frame, stream, config, new_waker)) {
+                    Ok(()) => {}
+                    Err(Http2Error::Stream(stream_id, code)) => self.reset_stream(stream_id, code),
+                    Err(error) => return Err(error),
+                },
+                Ok(IoErr(parser, error)) if error.kind() == ErrorKind::WouldBlock => {
+                    self.parser = Some(parser);
+                    return Ok(true);
+                }
+                Ok(IoErr(_, _)) | Err(_) => return Ok(false),
+            }
+        }
+    }
+
+    /// Routes the requests whose listeners may block, unless their streams have been reset since.
+    fn route_offloaded(&mut self, writer: &mut dyn Write, config: &Config, new_waker: &dyn Fn() -> Waker) -> Result<(), Http2Error> {
+        for (stream_id, request) in std::mem::take(&mut self.offloaded) {
+            if !self.streams.contains_key(&stream_id) {
+                continue;
+            }
+            match self.route(stream_id, &request, writer, config, new_waker) {
+                Err(Http2Error::Stream(stream_id, code)) => self.reset_stream(stream_id, code),
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a frame received from the client.
+    fn handle_frame(&mut self, frame: Frame, writer: &mut dyn Write, config: &Config, new_waker: &dyn Fn() -> Waker) -> Result<(), Http2Error> {
+        if self.header_block.is_some() && !matches!(frame, Frame::Continuation { .. }) {
+            return Err(Http2Error::Connection(PROTOCOL_ERROR, "header block interrupted by another frame"));
+        }
+
+        match frame {
+            Frame::Data { stream_id, data, end_stream, padding } => self.handle_data(stream_id, data, end_stream, padding, writer, config, new_waker),
+            Frame::Headers { stream_id, block, end_stream, end_headers, .. } => {
+                self.header_block = Some(HeaderBlock { stream_id, end_stream, block });
+                self.check_header_block_size()?;
+                if end_headers {
+                    self.end_header_block(writer, config, new_waker)?;
+                }
+                Ok(())
+            }
+            Frame::Continuation { stream_id, block, end_headers } => {
+                match &mut self.header_block {
+                    Some(header_block) if header_block.stream_id == stream_id => header_block.block.extend_from_slice(&block),
+                    _ => return Err(Http2Error::Connection(PROTOCOL_ERROR, "unexpected CONTINUATION frame")),
+                }
+                self.check_header_block_size()?;
+                if end_headers {
+                    self.end_header_block(writer, config, new_waker)?;
+                }
+                Ok(())
+            }
+            Frame::Priority { stream_id, priority } if priority.dependency == stream_id => Err(Http2Error::Stream(stream_id, PROTOCOL_ERROR)),
+            // streams are sent in order of their IDs, so priorities are ignored
+            Frame::Priority { .. } => Ok(()),
+            Frame::RstStream { stream_id, .. } if stream_id > self.last_stream_id => Err(Http2Error::Connection(PROTOCOL_ERROR, "RST_STREAM frame on idle stream")),
+            Frame::RstStream { stream_id, .. } => {
+                self.streams.remove(&stream_id);
+                Ok(())
+            }
+            Frame::Settings { ack: true, .. } => Ok(()),
+            Frame::Settings { ack: false, params } => {
+                let old_window_size = self.remote_settings.initial_window_size;
+                self.remote_settings.apply(&params)?;
+                let delta = self.remote_settings.initial_window_size as i64 - old_window_size as i64;
+                for stream in self.streams.values_mut() {
+                    stream.send_window.increase(delta, 0)?;
+                }
+                self.encoder.set_max_table_size(self.remote_settings.header_table_size as usize);
+                Frame::Settings { ack: true, params: vec![] }.encode(&mut self.out);
+                Ok(())
+            }
+            Frame::PushPromise { .. } => Err(Http2Error::Connection(PROTOCOL_ERROR, "clients can not push streams")),
+            Frame::Ping { ack: true, .. } => Ok(()),
+            Frame::Ping { ack: false, data } => {
+                Frame::Ping { ack: true, data }.encode(&mut self.out);
+                Ok(())
+            }
+            Frame::GoAway { .. } => {
+                self.going_away = true;
+                Ok(())
+            }
+            Frame::WindowUpdate { stream_id: 0, increment } => self.send_window.increase(increment as i64, 0),
+            Frame::WindowUpdate { stream_id, .. } if stream_id > self.last_stream_id => Err(Http2Error::Connection(PROTOCOL_ERROR, "WINDOW_UPDATE frame on idle stream")),
+            Frame::WindowUpdate { stream_id, increment } => match self.streams.get_mut(&stream_id) {
+                Some(stream) => stream.send_window.increase(increment as i64, stream_id),
+                None => Ok(()),
+            },
+            Frame::Unknown { .. } => Ok(()),
+        }
+    }
+
+    /// Checks that the header block being received is not too large to decode.
+    fn check_header_block_size(&self) -> Result<(), Http2Error> {
+        match &self.header_block {
+            Some(header_block) if header_block.block.len() > MAX_HEADER_LIST_SIZE as usize =>
+                Err(Http2Error::Connection(ENHANCE_YOUR_CALM, "header block too large")),
+            _ => Ok(()),
+        }
+    }
+
+    /// Decodes the header block that has been fully received, and either opens a new stream with it or treats it as the
+    /// trailers of an open stream.
+    fn end_header_block(&mut self, writer: &mut dyn Write, config: &Config, new_waker: &dyn Fn() -> Waker) -> Result<(), Http2Error> {
+        let HeaderBlock { stream_id, end_stream, block } = self.header_block.take().unwrap();
+        // the block is decoded even if its stream is rejected, so the dynamic table stays in sync with the client
+        let fields = match self.decoder.decode(&block) {
+            Ok(fields) => Some(fields),
+            Err(HpackError::HeaderListTooLarge) => None,
+            Err(_) => return Err(Http2Error::Connection(COMPRESSION_ERROR, "invalid header block")),
+        };
+
+        if let Some(stream) = self.streams.get_mut(&stream_id) {
+            // trailers, which are not passed on to the router
+            if !stream.receiving {
+                return Err(Http2Error::Stream(stream_id, STREAM_CLOSED));
+            }
+            if !end_stream {
+                return Err(Http2Error::Stream(stream_id, PROTOCOL_ERROR));
+            }
+            return self.end_request(stream_id, writer, config, new_waker);
+        }
+
+        if stream_id % 2 == 0 {
+            return Err(Http2Error::Connection(PROTOCOL_ERROR, "clients must use odd stream IDs"));
+        }
+        if stream_id <= self.last_stream_id {
+            return Err(Http2Error::Connection(STREAM_CLOSED, "HEADERS frame on closed stream"));
+        }
+        self.last_stream_id = stream_id;
+        if self.going_away || self.streams.len() >= MAX_CONCURRENT_STREAMS as usize {
+            return Err(Http2Error::Stream(stream_id, REFUSED_STREAM));
+        }
+
+        self.streams.insert(stream_id, Stream {
+            request: None,
+            receiving: !end_stream,
+            recv_window: Window::new(DEFAULT_WINDOW_SIZE),
+            recv_unacknowledged: 0,
+            send_window: Window::new(self.remote_settings.initial_window_size),
+            pending: vec![],
+            sent: 0,
+            end_after_pending: false,
+            sent_end: false,
+            event_stream: None,
+        });
+
+        let request = match fields.map(build_request) {
+            None => return self.send_response(stream_id, config, false, &Response::empty(REQUEST_HEADER_FIELDS_TOO_LARGE)),
+            Some(Err(InvalidRequest::Malformed)) => return Err(Http2Error::Stream(stream_id, PROTOCOL_ERROR)),
+            Some(Err(InvalidRequest::UnsupportedMethod)) => return self.send_response(stream_id, config, false, &Response::empty(BAD_REQUEST)),
+            Some(Ok(request)) => request,
+        };
+
+        match ContentLength::decode(&request.headers) {
+            Some(Err(_)) => return Err(Http2Error::Stream(stream_id, PROTOCOL_ERROR)),
+            Some(Ok(ContentLength(length))) if length > MAX_BODY_SIZE as u64 =>
+                return self.send_response(stream_id, config, request.method == Method::HEAD, &Response::empty(PAYLOAD_TOO_LARGE)),
+            _ => {}
+        }
+
+        let expects_continue = !end_stream && request.headers.get_first_header_value(&EXPECT)
+            .map(|value| value.eq_ignore_ascii_case("100-continue"))
+            .unwrap_or(false);
+        let result = match &config.continue_listener {
+            Some(listener) if expects_continue => catch_panic(|| listener(&request)).unwrap_or_else(|message| {
+                println!("Error: continue listener panicked on {} {}: {}", request.method, request.uri, message);
+                ContinueResult::Reject(Response::empty(INTERNAL_SERVER_ERROR))
+            }),
+            _ => ContinueResult::Continue,
+        };
+        match result {
+            ContinueResult::Continue if expects_continue => self.send_head(stream_id, config, &Response::empty(CONTINUE), HEAD_ONLY_FRAMING, false),
+            ContinueResult::Continue => {}
+            ContinueResult::Reject(response) => return self.send_response(stream_id, config, request.method == Method::HEAD, &response),
+        }
+
+        self.streams.get_mut(&stream_id).unwrap().request = Some(request);
+        if end_stream {
+            return self.end_request(stream_id, writer, config, new_waker);
+        }
+        Ok(())
+    }
+
+    /// Handles a DATA frame, which carries part of the body of a request.
+    #[allow(clippy::too_many_arguments)]
+    fn handle_data(&mut self, stream_id: u32, data: Vec<u8>, end_stream: bool, padding: usize,
+                   writer: &mut dyn Write, config: &Config, new_waker: &dyn Fn() -> Waker) -> Result<(), Http2Error> {
+        // the whole frame counts against flow control, even if its stream is closed
+        let length = data.len() + padding;
+        if !self.recv_window.consume(length) {
+            return Err(Http2Error::Connection(FLOW_CONTROL_ERROR, "connection window exceeded"));
+        }
+        self.recv_unacknowledged += length;
+        if self.recv_unacknowledged >= WINDOW_UPDATE_THRESHOLD {
+            Frame::WindowUpdate { stream_id: 0, increment: self.recv_unacknowledged as u32 }.encode(&mut self.out);
+            self.recv_window.increase(self.recv_unacknowledged as i64, 0)?;
+            self.recv_unacknowledged = 0;
+        }
+
+        let stream = match self.streams.get_mut(&stream_id) {
+            Some(stream) => stream,
+            None if stream_id > self.last_stream_id => return Err(Http2Error::Connection(PROTOCOL_ERROR, "DATA frame on idle stream")),
+            // the stream was reset or its response has been sent, and the client may not have noticed yet
+            None => return Ok(()),
+        };
+        if !stream.receiving {
+            return Err(Http2Error::Stream(stream_id, STREAM_CLOSED));
+        }
+        if !stream.recv_window.consume(length) {
+            return Err(Http2Error::Stream(stream_id, FLOW_CONTROL_ERROR));
+        }
+
+        if let Some(request) = &mut stream.request {
+            if request.body.len() + data.len() > MAX_BODY_SIZE {
+                let head = request.method == Method::HEAD;
+                stream.request = None;
+                return self.send_response(stream_id, config, head, &Response::empty(PAYLOAD_TOO_LARGE));
+            }
+            request.body.extend_from_slice(&data);
+        }
+
+        if end_stream {
+            return self.end_request(stream_id, writer, config, new_waker);
+        }
+
+        stream.recv_unacknowledged += length;
+        if stream.recv_unacknowledged >= WINDOW_UPDATE_THRESHOLD {
+            Frame::WindowUpdate { stream_id, increment: stream.recv_unacknowledged as u32 }.encode(&mut self.out);
+            stream.recv_window.increase(stream.recv_unacknowledged as i64, stream_id)?;
+            stream.recv_unacknowledged = 0;
+        }
+        Ok(())
+    }
+
+    /// Ends the request of the given stream once the client has ended the stream, and routes the request if it has
+    /// not been rejected.
+    fn end_request(&mut self, stream_id: u32, writer: &mut dyn Write, config: &Config, new_waker: &dyn Fn() -> Waker) -> Result<(), Http2Error> {
+        let stream = self.streams.get_mut(&stream_id).unwrap();
+        stream.receiving = false;
+        let request = match stream.request.take() {
+            Some(request) => request,
+            None => return Ok(()),
+        };
+
+        if let Some(Ok(ContentLength(length))) = ContentLength::decode(&request.headers) {
+            if length != request.body.len() as u64 {
+                return Err(Http2Error::Stream(stream_id, PROTOCOL_ERROR));
+            }
+        }
+
+        if config.router.is_blocking(&request) {
+            self.offloaded.push((stream_id, request));
+            return Ok(());
+        }
+        self.route(stream_id, &request, writer, config, new_waker)
+    }
+
+    /// Gets a response to the given request from the router and queues it on the given stream.
+    /// If a listener panics, then a 500 response is queued instead.
+    fn route(&mut self, stream_id: u32, request: &Request, writer: &mut dyn Write, config: &Config, new_waker: &dyn Fn() -> Waker) -> Result<(), Http2Error> {
+        let head = request.method == Method::HEAD;
+        let max_frame_size = self.remote_settings.max_frame_size as usize;
+        let mut sink = InformationalSink { encoder: &mut self.encoder, out: &mut self.out, writer, stream_id, max_frame_size };
+        let mut context = RequestContext::new(Some(&self.info), &mut sink);
+
+        let result = match catch_panic(|| config.router.result_with_context(request, &mut context)) {
+            Ok(result) => result,
+            Err(message) => {
+                println!("Error: listener panicked on {} {}: {}", request.method, request.uri, message);
+                return self.send_response(stream_id, config, head, &Response::empty(INTERNAL_SERVER_ERROR));
+            }
+        };
+        match result {
+            SendResponse(response) => self.send_response(stream_id, config, head, &response),
+            SendResponseArc(response) => self.send_response(stream_id, config, head, &response),
+            Upgrade(_) => {
+                println!("Error: connection upgrades are not supported over HTTP/2");
+                self.send_response(stream_id, config, head, &Response::empty(INTERNAL_SERVER_ERROR))
+            }
+            // a response to a HEAD request has no body, so the stream ends right away
+            SendEventStream(event_stream) if head => {
+                self.send_head(stream_id, config, &event_stream.response(), HEAD_ONLY_FRAMING, true);
+                Ok(())
+            }
+            SendEventStream(event_stream) => {
+                self.send_head(stream_id, config, &event_stream.response(), HEAD_ONLY_FRAMING, false);
+                event_stream.attach(self.waker.get_or_insert_with(new_waker).clone());
+                self.streams.get_mut(&stream_id).unwrap().event_stream = Some(event_stream);
+                Ok(())
+            }
+            Next => self.send_response(stream_id, config, head, &Response::empty(NOT_FOUND)),
+        }
+    }
+
+    /// Queues the given response on the given stream. If the response has invalid framing, then a 500 response is
+    /// queued instead.
+    fn send_response(&mut self, stream_id: u32, config: &Config, head: bool, response: &Response) -> Result<(), Http2Error> {
+        let framing = match response_framing(response, head) {
+            Ok(framing) => framing,
+            Err(error) => {
+                println!("Error: {}", error);
+                return self.send_response(stream_id, config, head, &Response::empty(INTERNAL_SERVER_ERROR));
+            }
+        };
+
+        let body = match framing.body {
+            BodyFraming::Omitted => &[][..],
+            BodyFraming::Length | BodyFraming::Chunked => &response.body[..],
+        };
+        self.send_head(stream_id, config, response, framing, body.is_empty());
+        if !body.is_empty() {
+            let stream = self.streams.get_mut(&stream_id).unwrap();
+            stream.pending.extend_from_slice(body);
+            stream.end_after_pending = true;
+        }
+        Ok(())
+    }
+
+    /// Queues a HEADERS frame with the head of the given response, along with a date header and the configured server
+    /// header if the response does not have them.
+    fn send_head(&mut self, stream_id: u32, config: &Config, response: &Response, framing: Framing, end_stream: bool) {
+        let mut block = vec![];
+        with_current_date(|date| {
+            let status = response.status.code.to_string();
+            let content_length = framing.add_content_length.map(|length| length.to_string());
+            let mut fields = vec![(":status", status.as_str())];
+            if !response.status.is_informational() && !response.headers.contains_key(&DATE) {
+                fields.push((DATE.as_str(), date));
+            }
+            if let Some(server) = &config.server_header {
+                if !response.status.is_informational() && !response.headers.contains_key(&SERVER) {
+                    fields.push((SERVER.as_str(), server));
+                }
+            }
+            if let Some(content_length) = &content_length {
+                fields.push((CONTENT_LENGTH.as_str(), content_length));
+            }
+            fields.extend(response_fields(&response.headers, framing.write_framing_headers));
+            self.encoder.encode(fields, &mut block);
+        });
+
+        encode_header_block(&mut self.out, stream_id, &block, end_stream, self.remote_settings.max_frame_size as usize);
+        if end_stream {
+            if let Some(stream) = self.streams.get_mut(&stream_id) {
+                stream.sent_end = true;
+            }
+        }
+    }
+
+    /// Moves the events sent to the event streams of the connection into the pending data of their streams. Events are
+    /// left in the event streams of streams that have not sent their pending data yet.
+    fn take_events(&mut self) {
+        for stream in self.streams.values_mut() {
+            if let Some(event_stream) = stream.event_stream.as_ref().filter(|_| stream.pending.is_empty()) {
+                let (events, finished) = event_stream.take_events();
+                stream.pending.extend_from_slice(&events);
+                if finished {
+                    stream.event_stream = None;
+                    stream.end_after_pending = true;
+                }
+            }
+        }
+    }
+
+    /// Sends as much pending data as the flow-control windows allow, in order of stream IDs, and removes the streams
+    /// whose responses have been sent.
+    fn send_pending(&mut self) {
+        let max_frame_size = self.remote_settings.max_frame_size as usize;
+        let out = &mut self.out;
+        let send_window = &mut self.send_window;
+
+        self.streams.retain(|&stream_id, stream| {
+            while !stream.sent_end {
+                let remaining = &stream.pending[stream.sent..];
+                let amount = remaining.len().min(stream.send_window.available()).min(send_window.available()).min(max_frame_size);
+                let end_stream = stream.end_after_pending && amount == remaining.len();
+                if amount == 0 && !end_stream {
+                    break;
+                }
+
+                encode_data(out, stream_id, &remaining[..amount], end_stream);
+                stream.send_window.consume(amount);
+                send_window.consume(amount);
+                stream.sent += amount;
+                stream.sent_end = end_stream;
+            }
+            if stream.sent == stream.pending.len() {
+                stream.pending.clear();
+                stream.sent = 0;
+            }
+
+            if stream.sent_end && stream.receiving {
+                // the rest of the request is not needed, since the response is complete
+                Frame::RstStream { stream_id, error: NO_ERROR }.encode(out);
+            }
+            !stream.sent_end
+        });
+    }
+
+    /// Resets the given stream with the given error code.
+    fn reset_stream(&mut self, stream_id: u32, error: ErrorCode) {
+        Frame::RstStream { stream_id, error }.encode(&mut self.out);
+        self.streams.remove(&stream_id);
+    }
+}
+
+/// Response sink that sends informational responses on a stream, along with any frames queued before them.
+struct InformationalSink<'a> {
+    encoder: &'a mut Encoder,
+    out: &'a mut Vec<u8>,
+    writer: &'a mut dyn Write,
+    stream_id: u32,
+    max_frame_size: usize,
+}
+
+impl ResponseSink for InformationalSink<'_> {
+    fn send_informational(&mut self, response: &Response) -> std::io::Result<()> {
+        let code = response.status.code;
+        if !response.status.is_informational() || response.status == SWITCHING_PROTOCOLS {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput, format!("{} is not an informational status", code)));
+        }
+
+        let status = code.to_string();
+        let mut block = vec![];
+        let fields = std::iter::once((":status", status.as_str())).chain(response_fields(&response.headers, true));
+        self.encoder.encode(fields, &mut block);
+        encode_header_block(self.out, self.stream_id, &block, false, self.max_frame_size);
+
+        self.writer.write_all(self.out)?;
+        self.out.clear();
+        self.writer.flush()
+    }
+}
+
+/// Gets the header fields of the given headers that are allowed in HTTP/2 responses. The content-length header is
+/// excluded if write_framing_headers is false.
+fn response_fields(headers: &HeaderMap, write_framing_headers: bool) -> impl Iterator<Item=(&str, &str)> {
+    headers.iter()
+        .filter(move |(header, _)| write_framing_headers || (**header != CONTENT_LENGTH && **header != TRANSFER_ENCODING))
+        .filter(|(header, _)| !CONNECTION_SPECIFIC_HEADERS.contains(&header.as_str()))
+        .flat_map(|(header, values)| values.iter().map(move |value| (header.as_str(), value.as_str())))
+}
+
+/// Builds a request from the fields of a header block, which start with the request pseudo-header fields.
+fn build_request(fields: Vec<(String, String)>) -> Result<Request, InvalidRequest> {
+    let mut method = None;
+    let mut scheme = None;
+    let mut authority = None;
+    let mut path = None;
+    let mut headers = HeaderMap::new();
+    let mut cookies = vec![];
+
+    for (name, value) in fields {
+        if name.starts_with(':') {
+            if !headers.is_empty() || !cookies.is_empty() {
+                return Err(InvalidRequest::Malformed);
+            }
+            let field = match name.as_str() {
+                ":method" => &mut method,
+                ":scheme" => &mut scheme,
+                ":authority" => &mut authority,
+                ":path" => &mut path,
+                _ => return Err(InvalidRequest::Malformed),
+            };
+            if field.replace(value).is_some() {
+                return Err(InvalidRequest::Malformed);
+            }
+            continue;
+        }
+
+        if name.bytes().any(|b| b.is_ascii_uppercase()) || CONNECTION_SPECIFIC_HEADERS.contains(&name.as_str()) {
+            return Err(InvalidRequest::Malformed);
+        }
+        if name == "te" && value != "trailers" {
+            return Err(InvalidRequest::Malformed);
+        }
+        // cookies may be split into separate fields for better compression
+        if name == "cookie" {
+            cookies.push(value);
+        } else {
+            headers.add_header(Header::from(name), value);
+        }
+    }
+
+    let (method, path) = match (method, scheme, path) {
+        (Some(method), Some(_), Some(path)) if !path.is_empty() => (method, path),
+        _ => return Err(InvalidRequest::Malformed),
+    };
+    if !cookies.is_empty() {
+        headers.add_header(COOKIE, cookies.join("; "));
+    }
+    if let Some(authority) = authority {
+        if !headers.contains_key(&HOST) {
+            headers.add_header(HOST, authority);
+        }
+    }
+
+    let method = Method::try_from_str(&method).ok_or(InvalidRequest::UnsupportedMethod)?;
+    Ok(Request { uri: path, method, headers, body: vec![] })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+    use std::sync::{Arc, Mutex};
+
+    use crate::common::header::{CONNECTION, HeaderMap, LINK, TRANSFER_ENCODING};
+    use crate::common::response::Response;
+    use crate::common::status;
+    use crate::header_map;
+    use crate::http2::error::{NO_ERROR, PROTOCOL_ERROR, REFUSED_STREAM};
+    use crate::http2::frame::{encode_header_block, Frame};
+    use crate::http2::hpack::{Decoder, Encoder};
+    use crate::http2::settings::{INITIAL_WINDOW_SIZE, MAX_CONCURRENT_STREAMS, MAX_HEADER_LIST_SIZE};
+    use crate::parse::frame::FrameParser;
+    use crate::parse::parse::Parse;
+    use crate::parse::parse::ParseStatus::Done;
+    use crate::server::config::{Config, ContinueResult};
+    use crate::server::connection_info::ConnectionInfo;
+    use crate::server::event_stream::Waker;
+    use crate::server::http2::Http2Connection;
+    use crate::server::router::ListenerResult::SendResponse;
+    use crate::server::router::Router;
+    use crate::sse::Event;
+    use crate::util::mock::{MockReader, MockStream, MockWriter};
+
+    /// The client side of a test connection.
+    struct Client {
+        encoder: Encoder,
+        decoder: Decoder,
+        connection: Http2Connection,
+        config: Config,
+        /// Whether the connection is served as if on its event loop.
+        on_event_loop: bool,
+        /// Whether the last call to serve returned true.
+        open: bool,
+    }
+
+    impl Client {
+        fn new(config: Config) -> Client {
+            let info = ConnectionInfo { peer_addr: "127.0.0.1:5000".parse().unwrap(), local_addr: "127.0.0.1:443".parse().unwrap(), tls: None };
+            Client { encoder: Encoder::new(), decoder: Decoder::new(65536), connection: Http2Connection::new(Arc::new(info)), config, on_event_loop: false, open: true }
+        }
+
+        /// Serves the given input, which is followed by a WouldBlock error, and returns the frames written by the server.
+        fn serve(&mut self, input: Vec<u8>) -> Vec<Frame> {
+            let chunks = if input.is_empty() { vec![] } else { vec![input.as_slice()] };
+            let mut reader = MockReader::from_bytes(chunks);
+            reader.return_would_block_when_empty = true;
+            let writer = MockWriter::new();
+            let flushed = writer.flushed.clone();
+            let mut stream = MockStream::new(BufReader::new(reader), writer);
+
+            self.open = self.connection.serve(&mut stream, &self.config, self.on_event_loop, &no_waker);
+
+            let output = flushed.borrow().concat();
+            let mut output = output.as_slice();
+            let mut frames = vec![];
+            while let Ok(Done(frame)) = FrameParser::new((1 << 24) - 1).parse(&mut output) {
+                frames.push(frame.unwrap());
+            }
+            frames
+        }
+
+        /// Encodes a HEADERS frame for a request with the given method and path.
+        fn request(&mut self, stream_id: u32, method: &str, path: &str, headers: &[(&str, &str)], end_stream: bool) -> Vec<u8> {
+            let fields = [(":method", method), (":scheme", "https"), (":path", path), (":authority", "localhost")];
+            let mut block = vec![];
+            self.encoder.encode(fields.iter().chain(headers.iter()).copied(), &mut block);
+            let mut out = vec![];
+            encode_header_block(&mut out, stream_id, &block, end_stream, 16384);
+            out
+        }
+
+        /// Decodes the header block of the given HEADERS frame.
+        fn fields(&mut self, frame: &Frame) -> Vec<(String, String)> {
+            match frame {
+                Frame::Headers { block, end_headers: true, .. } => self.decoder.decode(block).unwrap(),
+                frame => panic!("expected HEADERS frame, got {:?}", frame),
+            }
+        }
+
+        /// Decodes the header block of the given HEADERS frame, without the date header.
+        fn fields_without_date(&mut self, frame: &Frame) -> Vec<(String, String)> {
+            self.fields(frame).into_iter().filter(|(name, _)| name != "date").collect()
+        }
+    }
+
+    fn no_waker() -> Waker {
+        Arc::new(|| {})
+    }
+
+    fn encode(frames: &[Frame]) -> Vec<u8> {
+        let mut out = vec![];
+        for frame in frames {
+            frame.encode(&mut out);
+        }
+        out
+    }
+
+    fn fields(fields: &[(&str, &str)]) -> Vec<(String, String)> {
+        fields.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    fn data(stream_id: u32, data: &[u8], end_stream: bool) -> Frame {
+        Frame::Data { stream_id, data: data.to_vec(), end_stream, padding: 0 }
+    }
+
+    /// Creates a config whose router responds with the body of each request, and records the headers of each request.
+    fn echo_config() -> (Config, Arc<Mutex<Vec<HeaderMap>>>) {
+        let requests = Arc::new(Mutex::new(vec![]));
+        let new_requests = requests.clone();
+        let mut router = Router::new();
+        router.on_prefix("/echo", move |_, request| {
+            new_requests.lock().unwrap().push(request.headers.clone());
+            SendResponse(Response { status: status::OK, headers: HeaderMap::new(), body: request.body.clone() })
+        });
+        (Config { router, ..Default::default() }, requests)
+    }
+
+    /// Creates a client that has exchanged settings with the server.
+    fn started_client(config: Config, settings: Vec<(u16, u32)>) -> Client {
+        let mut client = Client::new(config);
+        let frames = client.serve(encode(&[Frame::Settings { ack: false, params: settings }]));
+        assert_eq!(frames, vec![
+            Frame::Settings { ack: false, params: vec![(MAX_CONCURRENT_STREAMS, 100), (MAX_HEADER_LIST_SIZE, 16384)] },
+            Frame::Settings { ack: true, params: vec![] },
+        ]);
+        client
+    }
+
+    #[test]
+    fn get_request() {
+        let (config, requests) = echo_config();
+        let mut client = started_client(config, vec![]);
+
+        let input = client.request(1, "GET", "/echo", &[("cookie", "a=1"), ("cookie", "b=2")], true);
+        let frames = client.serve(input);
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(client.fields_without_date(&frames[0]), fields(&[(":status", "200"), ("content-length", "0")]));
+        assert!(matches!(frames[0], Frame::Headers { stream_id: 1, end_stream: true, .. }));
+
+        let headers = requests.lock().unwrap().pop().unwrap();
+        assert_eq!(headers.get(&"host".into()).unwrap(), &vec!["localhost".to_string()]);
+        assert_eq!(headers.get(&"cookie".into()).unwrap(), &vec!["a=1; b=2".to_string()]);
+        assert!(client.open);
+    }
+
+    #[test]
+    fn request_with_body() {
+        let (config, _) = echo_config();
+        let mut client = started_client(config, vec![]);
+
+        let mut input = client.request(1, "POST", "/echo", &[("content-length", "11")], false);
+        input.extend(encode(&[data(1, b"hello ", false), data(1, b"world", true)]));
+        let frames = client.serve(input);
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(client.fields_without_date(&frames[0]), fields(&[(":status", "200"), ("content-length", "11")]));
+        assert!(matches!(frames[0], Frame::Headers { end_stream: false, .. }));
+        assert_eq!(frames[1], data(1, b"hello world", true));
+    }
+
+    #[test]
+    fn header_block_in_continuation_frames() {
+        let (config, _) = echo_config();
+        let mut client = started_client(config, vec![]);
+
+        let mut block = vec![];
+        client.encoder.encode(vec![(":method", "GET"), (":scheme", "http"), (":path", "/echo")], &mut block);
+        let mut input = vec![];
+        encode_header_block(&mut input, 1, &block, true, 2);
+        let frames = client.serve(input);
+
+        assert_eq!(client.fields_without_date(&frames[0]), fields(&[(":status", "200"), ("content-length", "0")]));
+    }
+
+    #[test]
+    fn multiple_streams() {
+        let (config, _) = echo_config();
+        let mut client = started_client(config, vec![]);
+
+        let mut input = client.request(1, "POST", "/echo", &[], false);
+        input.extend(client.request(3, "POST", "/echo", &[], false));
+        input.extend(encode(&[data(3, b"three", true), data(1, b"one", true)]));
+        let frames = client.serve(input);
+
+        assert!(matches!(frames[0], Frame::Headers { stream_id: 3, .. }));
+        assert!(matches!(frames[1], Frame::Headers { stream_id: 1, .. }));
+        // pending data is sent in order of stream IDs
+        assert_eq!(&frames[2..], &[data(1, b"one", true), data(3, b"three", true)]);
+    }
+
+    #[test]
+    fn flow_control() {
+        let mut router = Router::new();
+        router.on("/", |_, _| SendResponse(Response { status: status::OK, headers: HeaderMap::new(), body: vec![b'a'; 25] }));
+        let mut client = started_client(Config { router, ..Default::default() }, vec![(INITIAL_WINDOW_SIZE, 10)]);
+
+        let input = client.request(1, "GET", "/", &[], true);
+        let frames = client.serve(input);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1], data(1, &[b'a'; 10], false));
+
+        assert_eq!(client.serve(encode(&[Frame::WindowUpdate { stream_id: 1, increment: 5 }])), vec![data(1, &[b'a'; 5], false)]);
+
+        // raising the initial window size raises the window of open streams
+        let frames = client.serve(encode(&[Frame::Settings { ack: false, params: vec![(INITIAL_WINDOW_SIZE, 100)] }]));
+        assert_eq!(frames, vec![Frame::Settings { ack: true, params: vec![] }, data(1, &[b'a'; 10], true)]);
+    }
+
+    #[test]
+    fn connection_window_limits_all_streams() {
+        let mut router = Router::new();
+        router.on("/", |_, _| SendResponse(Response { status: status::OK, headers: HeaderMap::new(), body: vec![b'a'; 40000] }));
+        let mut client = started_client(Config { router, ..Default::default() }, vec![]);
+
+        let mut input = client.request(1, "GET", "/", &[], true);
+        input.extend(client.request(3, "GET", "/", &[], true));
+        let frames = client.serve(input);
+
+        let sent: Vec<(u32, usize)> = frames.iter().filter_map(|frame| match frame {
+            Frame::Data { stream_id, data, .. } => Some((*stream_id, data.len())),
+            _ => None,
+        }).collect();
+        assert_eq!(sent, vec![(1, 16384), (1, 16384), (1, 7232), (3, 16384), (3, 9151)]);
+
+        let frames = client.serve(encode(&[Frame::WindowUpdate { stream_id: 0, increment: 100000 }]));
+        assert_eq!(frames, vec![data(3, &[b'a'; 14465], true)]);
+    }
+
+    #[test]
+    fn window_updates_for_received_data() {
+        let (config, _) = echo_config();
+        let mut client = started_client(config, vec![]);
+
+        let mut input = client.request(1, "POST", "/echo", &[], false);
+        input.extend(encode(&[data(1, &[0; 16384], false), data(1, &[0; 16384], false)]));
+        let frames = client.serve(input);
+
+        assert_eq!(frames, vec![Frame::WindowUpdate { stream_id: 0, increment: 32768 }, Frame::WindowUpdate { stream_id: 1, increment: 32768 }]);
+    }
+
+    #[test]
+    fn ping() {
+        let mut client = started_client(Config::default(), vec![]);
+        let frames = client.serve(encode(&[Frame::Ping { ack: false, data: [1, 2, 3, 4, 5, 6, 7, 8] }]));
+        assert_eq!(frames, vec![Frame::Ping { ack: true, data: [1, 2, 3, 4, 5, 6, 7, 8] }]);
+    }
+
+    #[test]
+    fn malformed_requests() {
+        let (config, requests) = echo_config();
+        let mut client = started_client(config, vec![]);
+
+        let mut input = client.request(1, "GET", "/echo", &[("connection", "close")], true);
+        input.extend(client.request(3, "GET", "", &[], true));
+        input.extend(client.request(5, "POST", "/echo", &[("content-length", "3")], false));
+        input.extend(encode(&[data(5, b"hello", true)]));
+        input.extend(client.request(7, "GET", "/echo", &[("te", "gzip")], true));
+        let frames = client.serve(input);
+
+        assert_eq!(frames, vec![
+            Frame::RstStream { stream_id: 1, error: PROTOCOL_ERROR },
+            Frame::RstStream { stream_id: 3, error: PROTOCOL_ERROR },
+            Frame::RstStream { stream_id: 5, error: PROTOCOL_ERROR },
+            Frame::RstStream { stream_id: 7, error: PROTOCOL_ERROR },
+        ]);
+        assert!(requests.lock().unwrap().is_empty());
+        assert!(client.open);
+    }
+
+    #[test]
+    fn unsupported_method() {
+        let (config, _) = echo_config();
+        let mut client = started_client(config, vec![]);
+
+        let input = client.request(1, "OPTIONS", "/echo", &[], true);
+        let frames = client.serve(input);
+
+        assert_eq!(client.fields_without_date(&frames[0]), fields(&[(":status", "400"), ("content-length", "0")]));
+    }
+
+    #[test]
+    fn not_found() {
+        let mut client = started_client(Config::default(), vec![]);
+
+        let input = client.request(1, "GET", "/", &[], true);
+        let frames = client.serve(input);
+
+        assert_eq!(client.fields_without_date(&frames[0]), fields(&[(":status", "404"), ("content-length", "0")]));
+    }
+
+    #[test]
+    fn response_headers() {
+        let mut router = Router::new();
+        router.on("/", |_, _| SendResponse(Response {
+            status: status::OK,
+            headers: header_map![(CONNECTION, "keep-alive"), (TRANSFER_ENCODING, "chunked"), ("x-custom", "value")],
+            body: b"body".to_vec(),
+        }));
+        let config = Config { router, server_header: Some("test".to_string()), ..Default::default() };
+        let mut client = started_client(config, vec![]);
+
+        let input = client.request(1, "GET", "/", &[], true);
+        let frames = client.serve(input);
+
+        assert_eq!(client.fields_without_date(&frames[0]), fields(&[(":status", "200"), ("server", "test"), ("x-custom", "value")]));
+        assert_eq!(frames[1], data(1, b"body", true));
+    }
+
+    #[test]
+    fn head_request() {
+        let (config, _) = echo_config();
+        let mut client = started_client(config, vec![]);
+
+        let input = client.request(1, "HEAD", "/echo", &[], true);
+        let frames = client.serve(input);
+
+        assert_eq!(frames.len(), 1);
+        assert!(matches!(frames[0], Frame::Headers { end_stream: true, .. }));
+    }
+
+    #[test]
+    fn response_before_request_ends() {
+        let mut client = started_client(Config::default(), vec![]);
+
+        let mut input = client.request(1, "POST", "/", &[("content-length", "100000000")], false);
+        input.extend(encode(&[data(1, b"ignored", false)]));
+        let frames = client.serve(input);
+
+        assert_eq!(client.fields_without_date(&frames[0]), fields(&[(":status", "413"), ("content-length", "0")]));
+        // the client is told to stop sending the request
+        assert_eq!(frames[1], Frame::RstStream { stream_id: 1, error: NO_ERROR });
+        assert!(client.serve(encode(&[data(1, b"ignored", true)])).is_empty());
+    }
+
+    #[test]
+    fn expect_continue() {
+        let (mut config, _) = echo_config();
+        config.continue_listener = Some(Box::new(|request| match request.uri.as_str() {
+            "/echo/reject" => ContinueResult::Reject(Response::empty(status::EXPECTATION_FAILED)),
+            _ => ContinueResult::Continue,
+        }));
+        let mut client = started_client(config, vec![]);
+
+        let input = client.request(1, "POST", "/echo", &[("expect", "100-continue")], false);
+        let frames = client.serve(input);
+        assert_eq!(client.fields(&frames[0]), fields(&[(":status", "100")]));
+
+        let input = client.request(3, "POST", "/echo/reject", &[("expect", "100-continue")], false);
+        let frames = client.serve(input);
+        assert_eq!(client.fields_without_date(&frames[0]), fields(&[(":status", "417"), ("content-length", "0")]));
+    }
+
+    #[test]
+    fn panicking_listener() {
+        let mut router = Router::new();
+        router.on("/panic", |_, _| panic!("oh no"));
+        router.on("/", |_, _| SendResponse(Response::empty(status::NO_CONTENT)));
+        let mut client = started_client(Config { router, ..Default::default() }, vec![]);
+
+        let input = client.request(1, "GET", "/panic", &[], true);
+        let frames = client.serve(input);
+        assert_eq!(client.fields_without_date(&frames[0]), fields(&[(":status", "500"), ("content-length", "0")]));
+
+        // only the stream of the request is affected
+        let input = client.request(3, "GET", "/", &[], true);
+        let frames = client.serve(input);
+        assert_eq!(client.fields_without_date(&frames[0]), fields(&[(":status", "204")]));
+        assert!(client.open);
+    }
+
+    #[test]
+    fn panicking_continue_listener() {
+        let (mut config, _) = echo_config();
+        config.continue_listener = Some(Box::new(|_| panic!("oh no")));
+        let mut client = started_client(config, vec![]);
+
+        let input = client.request(1, "POST", "/echo", &[("expect", "100-continue")], false);
+        let frames = client.serve(input);
+        assert_eq!(client.fields_without_date(&frames[0]), fields(&[(":status", "500"), ("content-length", "0")]));
+    }
+
+    #[test]
+    fn informational_responses() {
+        let mut router = Router::new();
+        router.on_with_sink("/", |_, _, sink| {
+            sink.send_informational(&Response { status: status::EARLY_HINTS, headers: header_map![(LINK, "</style.css>")], body: vec![] }).unwrap();
+            assert!(sink.send_informational(&Response::empty(status::OK)).is_err());
+            SendResponse(Response::empty(status::NO_CONTENT))
+        });
+        let mut client = started_client(Config { router, ..Default::default() }, vec![]);
+
+        let input = client.request(1, "GET", "/", &[], true);
+        let frames = client.serve(input);
+
+        assert_eq!(client.fields(&frames[0]), fields(&[(":status", "103"), ("link", "</style.css>")]));
+        assert_eq!(client.fields_without_date(&frames[1]), fields(&[(":status", "204")]));
+    }
+
+    #[test]
+    fn connection_info() {
+        let mut router = Router::new();
+        router.on_with_context("/", |_, _, context| {
+            let info = context.connection().unwrap();
+            SendResponse(Response::text(format!("{} {}", info.peer_addr, info.local_addr)))
+        });
+        let mut client = started_client(Config { router, ..Default::default() }, vec![]);
+
+        let input = client.request(1, "GET", "/", &[], true);
+        let frames = client.serve(input);
+
+        assert!(matches!(&frames[1], Frame::Data { data, .. } if data == b"127.0.0.1:5000 127.0.0.1:443"));
+    }
+
+    #[test]
+    fn event_stream() {
+        let senders = Arc::new(Mutex::new(vec![]));
+        let new_senders = senders.clone();
+        let mut router = Router::new();
+        router.event_stream("/events", move |_, sender| new_senders.lock().unwrap().push(sender));
+        let mut client = started_client(Config { router, ..Default::default() }, vec![]);
+
+        let input = client.request(1, "GET", "/events", &[], true);
+        let frames = client.serve(input);
+        let head = client.fields_without_date(&frames[0]);
+        assert_eq!(head[0], (":status".to_string(), "200".to_string()));
+        assert!(head.contains(&("content-type".to_string(), "text/event-stream".to_string())));
+        assert!(!head.iter().any(|(name, _)| name == "transfer-encoding"));
+        assert!(matches!(frames[0], Frame::Headers { end_stream: false, .. }));
+
+        let sender = senders.lock().unwrap().pop().unwrap();
+        sender.send(&Event::new("hello")).unwrap();
+        assert_eq!(client.serve(vec![]), vec![data(1, b"data: hello\n\n", false)]);
+
+        drop(sender);
+        assert_eq!(client.serve(vec![]), vec![data(1, b"", true)]);
+    }
+
+    #[test]
+    fn goaway_refuses_new_streams() {
+        let (config, _) = echo_config();
+        let mut client = started_client(config, vec![]);
+
+        let mut input = client.request(1, "POST", "/echo", &[], false);
+        input.extend(encode(&[Frame::GoAway { last_stream_id: 0, error: NO_ERROR, debug_data: vec![] }]));
+        input.extend(client.request(3, "GET", "/echo", &[], true));
+        let frames = client.serve(input);
+        assert_eq!(frames, vec![Frame::RstStream { stream_id: 3, error: REFUSED_STREAM }]);
+        assert!(client.open);
+
+        // the connection closes once the open stream is done
+        let frames = client.serve(encode(&[data(1, b"", true)]));
+        assert_eq!(frames.len(), 1);
+        assert!(!client.open);
+    }
+
+    #[test]
+    fn connection_errors() {
+        let inputs = vec![
+            encode(&[data(2, b"", true)]),
+            encode(&[Frame::Continuation { stream_id: 1, block: vec![], end_headers: true }]),
+            encode(&[Frame::Headers { stream_id: 1, block: vec![], end_stream: true, end_headers: false, priority: None }, Frame::Ping { ack: false, data: [0; 8] }]),
+            encode(&[Frame::Headers { stream_id: 1, block: vec![0xff, 0xff, 0xff, 0xff], end_stream: true, end_headers: true, priority: None }]),
+            encode(&[Frame::WindowUpdate { stream_id: 0, increment: 0x7fffffff }]),
+            encode(&[Frame::RstStream { stream_id: 1, error: NO_ERROR }]),
+        ];
+
+        for input in inputs {
+            let mut client = started_client(Config::default(), vec![]);
+            let frames = client.serve(input.clone());
+            assert!(matches!(frames.last(), Some(Frame::GoAway { last_stream_id: 0, .. })), "{:?} {:?}", input, frames);
+            assert!(!client.open);
+        }
+    }
+
+    #[test]
+    fn closed_stream_ids_can_not_be_reused() {
+        let (config, _) = echo_config();
+        let mut client = started_client(config, vec![]);
+
+        let input = client.request(3, "GET", "/echo", &[], true);
+        client.serve(input);
+        let input = client.request(1, "GET", "/echo", &[], true);
+        let frames = client.serve(input);
+
+        assert!(matches!(frames.last(), Some(Frame::GoAway { last_stream_id: 3, .. })));
+        assert!(!client.open);
+    }
+
+    #[test]
+    fn blocking_listeners_are_left_for_thread_pool() {
+        let mut router = Router::new();
+        router.on("/slow", |_, _| SendResponse(Response::text("slow")));
+        router.on_inline("/fast", |_, _| SendResponse(Response::text("fast")));
+        let mut client = started_client(Config { router, ..Default::default() }, vec![]);
+
+        client.on_event_loop = true;
+        let mut input = client.request(1, "GET", "/slow", &[], true);
+        input.extend(client.request(3, "GET", "/fast", &[], true));
+        let frames = client.serve(input);
+        assert!(matches!(frames.as_slice(), [Frame::Headers { stream_id: 3, .. }, Frame::Data { stream_id: 3, .. }]), "{:?}", frames);
+        assert!(client.connection.has_offloaded());
+
+        client.on_event_loop = false;
+        let frames = client.serve(vec![]);
+        assert!(matches!(frames.as_slice(), [Frame::Headers { stream_id: 1, .. }, Frame::Data { stream_id: 1, .. }]), "{:?}", frames);
+        assert!(!client.connection.has_offloaded());
+    }
+
+    #[test]
+    fn closed_by_client() {
+        let mut client = Client::new(Config::default());
+        let mut stream = MockStream::new(BufReader::new(MockReader::from_bytes(vec![])), MockWriter::new());
+        assert!(!client.connection.serve(&mut stream, &client.config, false, &no_waker));
+    }
+}
@@ -1,6 +1,9 @@
 pub use config::*;
+pub use connection_info::*;
+pub use event_stream::*;
 pub use router::*;
 pub use server::*;
+pub use upgrade::*;
 
 /// Entry point for starting a server.
 mod server;
@@ -10,9 +13,26 @@ mod config;
 mod router;
 /// Connection for storing state about a connection to a client.
 mod connection;
+/// Information about connections that is given to listeners.
+mod connection_info;
 /// Utility functions for polling IO and enabling async listening.
 mod poll;
 /// A buffered writer that handles WouldBlock errors.
 mod nonblocking_buf_writer;
+/// Cached HTTP-date strings for the date header.
+mod date_cache;
+/// Framing of response bodies.
+mod framing;
+/// Upgrading connections to other protocols.
+mod upgrade;
+/// Server-sent event stream responses.
+mod event_stream;
+/// HTTP/2 connections.
+#[cfg(feature = "http2")]
+mod http2;
 /// A slab data structure implementation for storing connections.
 mod slab;
+/// A timer wheel for the deadlines of connections.
+mod timer_wheel;
+/// Scheduling state that keeps connections from being queued on the thread pool more than once.
+mod schedule;
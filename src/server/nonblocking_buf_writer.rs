@@ -17,6 +17,16 @@ impl<T: Write> NonBlockingBufWriter<T> {
         NonBlockingBufWriter { pos: 0, buf: Vec::with_capacity(capacity), inner }
     }
 
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Gets the amount of data in the buffer that has not been written to the underlying writer.
+    pub fn buffered(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
     /// Writes the contents of the buffer to the underlying writer.
     /// May only partially flush if the underlying writer blocks.
     fn flush_buf(&mut self) -> Result<()> {
@@ -86,7 +96,7 @@ impl<W> InnerMut for NonBlockingBufWriter<W> {
 
 #[cfg(test)]
 mod tests {
-    use std::io::Write;
+    use std::io::{ErrorKind, Write};
     use std::ops::Deref;
 
     use crate::server::nonblocking_buf_writer::NonBlockingBufWriter;
@@ -117,4 +127,44 @@ mod tests {
 
         assert_eq!(flushed.borrow().deref(), &vec![b"hello 1hello 2".to_vec(), b"hello 3".to_vec(), b"this is big and wont fit in the buffer".to_vec()]);
     }
-}
\ No newline at end of file
+
+    /// Writer that accepts a limited number of bytes before blocking.
+    struct BlockingWriter {
+        data: Vec<u8>,
+        remaining: usize,
+    }
+
+    impl Write for BlockingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.remaining == 0 {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            let amount = buf.len().min(self.remaining);
+            self.data.extend_from_slice(&buf[..amount]);
+            self.remaining -= amount;
+            Ok(amount)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn keeps_order_of_flushed_writes_when_blocked() {
+        let mut writer = NonBlockingBufWriter::with_capacity(16, BlockingWriter { data: vec![], remaining: 10 });
+
+        // an informational response that only partially gets written before blocking
+        writer.write_all(b"HTTP/1.1 103 Early Hints\r\n\r\n").unwrap();
+        writer.flush().unwrap();
+        writer.write_all(b"HTTP/1.1 200 OK\r\n\r\n").unwrap();
+        writer.flush().unwrap();
+
+        assert_eq!(writer.inner.data, b"HTTP/1.1 1");
+
+        writer.inner.remaining = usize::MAX;
+        writer.flush().unwrap();
+
+        assert_eq!(writer.inner.data, b"HTTP/1.1 103 Early Hints\r\n\r\nHTTP/1.1 200 OK\r\n\r\n");
+    }
+}
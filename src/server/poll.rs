@@ -1,11 +1,17 @@
 use std::io::ErrorKind;
-use std::net::SocketAddr;
+use std::mem;
+use std::net::{self, SocketAddr};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use mio::{Events, Interest, Poll, Token};
-use mio::event::Event;
+use mio::{Events, Interest, Poll, Registry, Token, Waker};
 use mio::net::{TcpListener, TcpStream};
 
+use crate::server::config::ConnectionCounter;
 use crate::server::slab::Slab;
+use crate::server::timer_wheel::TimerWheel;
 
 /// The number of IO events processed at a time.
 const POLL_EVENT_CAPACITY: usize = 128;
@@ -16,61 +22,400 @@ const INITIAL_CONNECTION_CAPACITY: usize = 128;
 /// Token used for the listener.
 const LISTENER_TOKEN: Token = Token(usize::MAX);
 
-/// Listens asynchronously on the given address. Calls make_connection for each new stream, and calls
-/// on_io_ready for each stream that is IO ready.
-/// The result of on_new_connection will be passed to on_io_ready when the corresponding stream is ready for reading or writing.
+/// Token used for the waker that wakes the poll when timers are updated.
+const NOTIFIER_TOKEN: Token = Token(usize::MAX - 1);
+
+/// The length of a tick of the timer wheel, which is how precisely deadlines are kept.
+const TIMER_TICK: Duration = Duration::from_millis(100);
+
+/// The number of slots in the timer wheel. Together with the tick, covers deadlines up to about 100 seconds away
+/// without them being visited more than once.
+const TIMER_SLOTS: usize = 1024;
+
+/// Listens asynchronously on the given address with the given number of event loops, which each run on their own
+/// thread with their own poll. Every event loop polls the listener, and accepts new connections into its own poll.
+/// Calls on_new_connection for each new stream, and calls on_io_ready for each stream that is IO ready, both on the
+/// thread of the event loop that accepted the stream.
+/// The result of on_new_connection will be passed to on_io_ready when the corresponding stream is ready for reading or writing,
+/// along with the registry of the poll, which can be used to take the stream out of the poll.
+/// Each connection is given a timer, which also passes the connection to on_io_ready once its deadline passes or once
+/// it is woken, and which removes the connection from the poll once it is closed.
+/// While the given limit of connections are open, new connections are passed to on_rejected if it is given, or are
+/// left in the backlog of the listener until a connection closes otherwise. The number of open connections is kept in
+/// the given counter.
+/// Returns once every event loop has stopped, which only happens if polling fails.
 pub fn listen<T>(addr: SocketAddr,
-                 on_new_connection: impl Fn(TcpStream, SocketAddr) -> T,
-                 on_io_ready: impl Fn(&T)) -> std::io::Result<()> {
-    let mut listener = TcpListener::bind(addr)?;
+                 event_loops: usize,
+                 max_connections: Option<usize>,
+                 counter: &ConnectionCounter,
+                 on_new_connection: impl Fn(TcpStream, SocketAddr, Timer) -> T + Sync,
+                 on_io_ready: impl Fn(&T, &Arc<Registry>) + Sync,
+                 on_rejected: Option<&(dyn Fn(TcpStream) + Sync)>) -> std::io::Result<()> {
+    assert!(event_loops > 0);
+    let listener = net::TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
 
-    let poll = Poll::new()?;
-    poll.registry().register(&mut listener, LISTENER_TOKEN, Interest::READABLE)?;
+    // each event loop registers its own handle of the listener, so connections are accepted on every event loop
+    let mut event_loops = (0..event_loops)
+        .map(|_| {
+            let poll = Poll::new()?;
+            let mut listener = TcpListener::from_std(listener.try_clone()?);
+            poll.registry().register(&mut listener, LISTENER_TOKEN, Interest::READABLE)?;
+            let notifier = Arc::new(Notifier { updated: Mutex::new(vec![]), waker: Waker::new(poll.registry(), NOTIFIER_TOKEN)? });
+            Ok((poll, listener, notifier))
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    drop(listener);
+
+    let open = OpenConnections {
+        max: max_connections.unwrap_or(usize::MAX),
+        count: AtomicUsize::new(0),
+        backlogged: AtomicBool::new(false),
+        counter,
+    };
+    let (open, on_new_connection, on_io_ready) = (&open, &on_new_connection, &on_io_ready);
+
+    thread::scope(|scope| {
+        let (poll, listener, notifier) = event_loops.remove(0);
+        let others: Vec<_> = event_loops.into_iter()
+            .map(|(poll, listener, notifier)| scope.spawn(move || run_event_loop(poll, listener, notifier, open, on_new_connection, on_io_ready, on_rejected)))
+            .collect();
+        let result = run_event_loop(poll, listener, notifier, open, on_new_connection, on_io_ready, on_rejected);
+        others.into_iter().fold(result, |result, other| result.and(other.join().unwrap()))
+    })
+}
 
-    let mut connections = Slab::with_capacity(INITIAL_CONNECTION_CAPACITY);
+/// Runs an event loop with the given poll, which accepts connections from the given listener and polls them until
+/// polling fails.
+fn run_event_loop<T>(poll: Poll,
+                     listener: TcpListener,
+                     notifier: Arc<Notifier>,
+                     open: &OpenConnections,
+                     on_new_connection: &impl Fn(TcpStream, SocketAddr, Timer) -> T,
+                     on_io_ready: &impl Fn(&T, &Arc<Registry>),
+                     on_rejected: Option<&(dyn Fn(TcpStream) + Sync)>) -> std::io::Result<()> {
+    let registry = Arc::new(poll.registry().try_clone()?);
 
-    poll_events(
+    let mut connections: Slab<(T, Timer)> = Slab::with_capacity(INITIAL_CONNECTION_CAPACITY);
+    let mut timers = TimerWheel::new(TIMER_TICK, TIMER_SLOTS, Instant::now());
+
+    let result = poll_events(
         poll,
-        |poll, event|
-            match event.token() {
-                LISTENER_TOKEN => {
-                    listen_until_blocked(&listener, |(mut stream, addr)| {
-                        let token = connections.next_key();
-                        poll.registry().register(&mut stream, Token(token), Interest::READABLE | Interest::WRITABLE)?;
-                        connections.insert(on_new_connection(stream, addr));
-                        Ok(())
-                    });
+        |poll, events| {
+            let add = |connections: &mut Slab<(T, Timer)>, mut stream: TcpStream, addr| {
+                let token = connections.next_key();
+                if let Err(error) = poll.registry().register(&mut stream, Token(token), Interest::READABLE | Interest::WRITABLE) {
+                    open.remove();
+                    return Err(error);
+                }
+                let timer = Timer::new(token, notifier.clone());
+                connections.insert((on_new_connection(stream, addr, timer.clone()), timer));
+                Ok(())
+            };
+
+            // returns false if connections were left in the backlog because of the limit
+            let accept = |connections: &mut Slab<(T, Timer)>| {
+                let room = if on_rejected.is_some() { usize::MAX } else { open.room() };
+                listen_until_blocked(&listener, room, |(stream, addr)| {
+                    match on_rejected {
+                        Some(on_rejected) if !open.try_add() => {
+                            on_rejected(stream);
+                            return Ok(());
+                        }
+                        Some(_) => {}
+                        None => open.add(),
+                    }
+                    add(connections, stream, addr)
+                })
+            };
+
+            for event in events {
+                match event.token() {
+                    LISTENER_TOKEN => open.set_backlogged(!accept(&mut connections)),
+                    // the updated timers are taken below, whether or not the waker was woken
+                    NOTIFIER_TOKEN => {}
+                    token if event.is_write_closed() => {
+                        if let Some((_, timer)) = connections.remove(token.0) {
+                            timer.state().closed = true;
+                            open.remove();
+                        }
+                    }
+                    token => {
+                        if let Some((connection, _)) = connections.get(token.0) {
+                            on_io_ready(connection, &registry);
+                        }
+                    }
+                }
+            }
+
+            for timer in notifier.take_updated() {
+                // the key may have been reused if the connection was already removed
+                let current = matches!(connections.get(timer.key()), Some((_, current)) if current.is(&timer));
+                let mut state = timer.state();
+                if state.closed {
+                    drop(state);
+                    if current {
+                        connections.remove(timer.key());
+                        open.remove();
+                    }
+                } else if mem::take(&mut state.woken) {
+                    drop(state);
+                    if let (true, Some((connection, _))) = (current, connections.get(timer.key())) {
+                        on_io_ready(connection, &registry);
+                    }
+                } else if let Some(deadline) = state.scheduled {
+                    drop(state);
+                    timers.insert(deadline, timer);
+                }
+            }
+
+            let now = Instant::now();
+            let mut rescheduled = vec![];
+            timers.expire(now, |deadline, timer| match timer.fire(deadline, now) {
+                Fired::Stale => {}
+                Fired::Rescheduled(deadline) => rescheduled.push((deadline, timer)),
+                Fired::Expired => {
+                    if let Some((connection, _)) = connections.get(timer.key()) {
+                        on_io_ready(connection, &registry);
+                    }
                 }
-                token if event.is_write_closed() => { connections.remove(token.0); }
-                token => { connections.get(token.0).map(&on_io_ready); }
-            },
-    )
+            });
+            for (deadline, timer) in rescheduled {
+                timers.insert(deadline, timer);
+            }
+
+            if open.is_backlogged() && open.room() > 0 {
+                open.set_backlogged(!accept(&mut connections));
+            }
+
+            timers.next_timeout(now)
+        },
+    );
+
+    if let Err(error) = &result {
+        println!("Error: event loop stopped: {:?}", error);
+    }
+    result
 }
 
-/// Pulls events out of the given poll and passes them to on_event. Loops indefinitely.
-fn poll_events(mut poll: Poll, mut on_event: impl FnMut(&mut Poll, &Event)) -> std::io::Result<()> {
-    let mut events = Events::with_capacity(POLL_EVENT_CAPACITY);
+/// The connections that are open across the event loops of a server.
+struct OpenConnections<'a> {
+    /// The maximum number of connections that may be open.
+    max: usize,
+    /// The number of connections that are open.
+    count: AtomicUsize,
+    /// Whether connections were left in the backlog of the listener because of the limit, so they have to be accepted
+    /// once there is room. Connections are only removed by event loops, which accept backlogged connections after.
+    backlogged: AtomicBool,
+    counter: &'a ConnectionCounter,
+}
 
-    loop {
-        poll.poll(&mut events, None)?;
+impl OpenConnections<'_> {
+    /// Gets the number of connections that can be opened before reaching the limit.
+    fn room(&self) -> usize {
+        self.max.saturating_sub(self.count.load(Ordering::Acquire))
+    }
+
+    /// Adds a connection that was opened.
+    fn add(&self) {
+        self.count.fetch_add(1, Ordering::AcqRel);
+        self.counter.increment();
+    }
 
-        for event in &events {
-            on_event(&mut poll, event);
+    /// Adds a connection that was opened, unless the limit has been reached. Returns true if it was added.
+    fn try_add(&self) -> bool {
+        let added = self.count.fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| (count < self.max).then_some(count + 1)).is_ok();
+        if added {
+            self.counter.increment();
         }
+        added
+    }
+
+    /// Removes a connection that was closed.
+    fn remove(&self) {
+        self.count.fetch_sub(1, Ordering::AcqRel);
+        self.counter.decrement();
+    }
+
+    /// Checks if connections were left in the backlog because of the limit.
+    fn is_backlogged(&self) -> bool {
+        self.backlogged.load(Ordering::Acquire)
+    }
+
+    /// Sets whether connections were left in the backlog because of the limit.
+    fn set_backlogged(&self, backlogged: bool) {
+        self.backlogged.store(backlogged, Ordering::Release)
     }
 }
 
-/// Accepts new connections to the given listener until blocked. Calls on_connection for each connection stream.
-fn listen_until_blocked(listener: &TcpListener, mut on_connection: impl FnMut((TcpStream, SocketAddr)) -> std::io::Result<()>) {
+/// Pulls events out of the given poll and passes them to on_events, which returns the longest time to wait for the
+/// next events. on_events is also called with no events once that time has passed. Loops indefinitely.
+fn poll_events(mut poll: Poll, mut on_events: impl FnMut(&mut Poll, &Events) -> Option<Duration>) -> std::io::Result<()> {
+    let mut events = Events::with_capacity(POLL_EVENT_CAPACITY);
+    let mut timeout = None;
+
     loop {
+        poll.poll(&mut events, timeout)?;
+
+        timeout = on_events(&mut poll, &events);
+    }
+}
+
+/// Accepts new connections to the given listener until blocked, or until the given number of connections have been
+/// accepted. Calls on_connection for each connection stream. Returns false if it stopped before being blocked.
+fn listen_until_blocked(listener: &TcpListener, max: usize, mut on_connection: impl FnMut((TcpStream, SocketAddr)) -> std::io::Result<()>) -> bool {
+    for _ in 0..max {
         match listener.accept() {
             Ok(conn) => {
                 if let Some(err) = on_connection(conn).err() {
                     println!("Error initializing connection: {:?}", err)
                 }
             }
-            Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => return true,
             Err(err) => println!("Error unwrapping connection: {:?}", err)
         }
     }
-}
\ No newline at end of file
+    false
+}
+
+/// The timer of a connection, which is used from any thread to tell the poll when the connection should be handled
+/// again and when it has been closed.
+#[derive(Clone)]
+pub struct Timer {
+    inner: Arc<TimerInner>,
+}
+
+struct TimerInner {
+    /// The key of the connection in the slab of connections.
+    key: usize,
+    state: Mutex<TimerState>,
+    notifier: Arc<Notifier>,
+}
+
+/// The state of a timer.
+#[derive(Default)]
+struct TimerState {
+    /// The time the connection should be handled again at.
+    deadline: Option<Instant>,
+    /// The deadline of the entry in the timer wheel that is still valid. Other entries for the timer are ignored.
+    scheduled: Option<Instant>,
+    /// Whether the connection has been closed, so the timer is no longer used.
+    closed: bool,
+    /// Whether the connection has been woken, so the poll should handle it as if it were IO ready.
+    woken: bool,
+}
+
+/// The result of the deadline of an entry in the timer wheel passing.
+enum Fired {
+    /// The entry was replaced by another entry, or the timer no longer has a deadline.
+    Stale,
+    /// The deadline of the timer was moved to the given time, so the timer has to be put back in the wheel.
+    Rescheduled(Instant),
+    /// The deadline of the timer has passed.
+    Expired,
+}
+
+impl Timer {
+    /// Creates a timer with no deadline for the connection with the given key.
+    fn new(key: usize, notifier: Arc<Notifier>) -> Timer {
+        Timer { inner: Arc::new(TimerInner { key, state: Mutex::new(TimerState::default()), notifier }) }
+    }
+
+    /// Sets the time the connection should be handled again at, or None if the connection should only be handled when
+    /// it is IO ready.
+    pub fn set(&self, deadline: Option<Instant>) {
+        let mut state = self.state();
+        if state.closed {
+            return;
+        }
+        state.deadline = deadline;
+        // later deadlines are picked up when the scheduled entry fires, so the poll is only woken for earlier ones
+        if let Some(deadline) = deadline {
+            if state.scheduled.map_or(true, |scheduled| deadline < scheduled) {
+                state.scheduled = Some(deadline);
+                drop(state);
+                self.inner.notifier.notify(self.clone());
+            }
+        }
+    }
+
+    /// Tells the poll that the connection has been closed, so it is removed from the poll.
+    pub fn close(&self) {
+        let mut state = self.state();
+        if !state.closed {
+            state.closed = true;
+            drop(state);
+            self.inner.notifier.notify(self.clone());
+        }
+    }
+
+    /// Tells the poll to handle the connection as if it were IO ready, such as when there is data for it to send.
+    pub fn wake(&self) {
+        let mut state = self.state();
+        if !state.closed && !state.woken {
+            state.woken = true;
+            drop(state);
+            self.inner.notifier.notify(self.clone());
+        }
+    }
+
+    /// Updates the timer for its entry with the given deadline having fired at the given time.
+    fn fire(&self, entry: Instant, now: Instant) -> Fired {
+        let mut state = self.state();
+        if state.closed || state.scheduled != Some(entry) {
+            return Fired::Stale;
+        }
+        state.scheduled = None;
+        match state.deadline {
+            None => Fired::Stale,
+            Some(deadline) if deadline > now => {
+                state.scheduled = Some(deadline);
+                Fired::Rescheduled(deadline)
+            }
+            Some(_) => Fired::Expired,
+        }
+    }
+
+    /// Gets the key of the connection.
+    fn key(&self) -> usize {
+        self.inner.key
+    }
+
+    /// Checks if this is the same timer as the given timer.
+    fn is(&self, other: &Timer) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    /// Locks the state of the timer.
+    fn state(&self) -> MutexGuard<'_, TimerState> {
+        self.inner.state.lock().unwrap()
+    }
+}
+
+/// Collects the timers of an event loop that have been updated, and wakes its poll so it can take them.
+struct Notifier {
+    updated: Mutex<Vec<Timer>>,
+    waker: Waker,
+}
+
+impl Notifier {
+    /// Adds the given timer to the updated timers, and wakes the poll if it is the first one.
+    fn notify(&self, timer: Timer) {
+        let mut updated = self.updated.lock().unwrap();
+        updated.push(timer);
+        if updated.len() == 1 {
+            self.wake();
+        }
+    }
+
+    /// Wakes the poll.
+    fn wake(&self) {
+        self.waker.wake().unwrap_or_else(|error| println!("Error waking poll: {:?}", error));
+    }
+
+    /// Takes the timers that have been updated since this was last called.
+    fn take_updated(&self) -> Vec<Timer> {
+        mem::take(&mut *self.updated.lock().unwrap())
+    }
+
+}
@@ -2,10 +2,16 @@ use std::sync::Arc;
 
 use crate::common::request::Request;
 use crate::common::response::Response;
-use crate::server::router::ListenerResult::Next;
+use crate::server::connection_info::ConnectionInfo;
+use crate::server::event_stream::{EventSender, EventStream};
+use crate::server::router::ListenerResult::{Next, SendResponse};
+use crate::server::upgrade::{Upgrade, UpgradedStream};
+use crate::websocket;
+use crate::websocket::{Role, WebSocket};
+use crate::websocket::handshake;
 
 /// The result of a request listener.
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub enum ListenerResult {
     /// Continues to the next listener to be called on the request, if any.
     Next,
@@ -13,11 +19,80 @@ pub enum ListenerResult {
     SendResponse(Response),
     /// Sends a shared response.
     SendResponseArc(Arc<Response>),
+    /// Sends the 101 switching protocols response of the upgrade, and then hands the connection to its handler.
+    /// No more requests are read from the connection.
+    Upgrade(Upgrade),
+    /// Sends the event stream as a long-lived response. More requests are only read from the connection once it ends.
+    EventStream(EventStream),
 }
 
+impl PartialEq for ListenerResult {
+    /// Compares the results by their responses. Upgrades and event streams are never equal, since their handlers and
+    /// senders can not be compared.
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ListenerResult::Next, ListenerResult::Next) => true,
+            (ListenerResult::SendResponse(a), ListenerResult::SendResponse(b)) => a == b,
+            (ListenerResult::SendResponseArc(a), ListenerResult::SendResponseArc(b)) => a == b,
+            _ => false
+        }
+    }
+}
+
+/// A sink for informational (1xx) responses, such as 103 early hints, that are sent before the final response to a request.
+pub trait ResponseSink {
+    /// Sends the given informational response immediately. Returns an error if the response does not have a 1xx status,
+    /// or if it could not be sent.
+    fn send_informational(&mut self, response: &Response) -> std::io::Result<()>;
+}
+
+/// The context a request is handled in, which is given to listeners along with the request.
+pub struct RequestContext<'a> {
+    connection: Option<&'a ConnectionInfo>,
+    sink: &'a mut dyn ResponseSink,
+}
+
+impl<'a> RequestContext<'a> {
+    /// Creates a context for a request received on the given connection, which sends informational responses to the
+    /// given sink.
+    pub fn new(connection: Option<&'a ConnectionInfo>, sink: &'a mut dyn ResponseSink) -> RequestContext<'a> {
+        RequestContext { connection, sink }
+    }
+
+    /// Gets the information about the connection the request was received on, such as the address of the client and
+    /// the TLS session. Returns None if the router was not called for a connection, as with Router::result.
+    pub fn connection(&self) -> Option<&ConnectionInfo> {
+        self.connection
+    }
+}
+
+impl ResponseSink for RequestContext<'_> {
+    fn send_informational(&mut self, response: &Response) -> std::io::Result<()> {
+        self.sink.send_informational(response)
+    }
+}
+
+/// A response sink that discards all informational responses.
+struct DiscardSink;
+
+impl ResponseSink for DiscardSink {
+    fn send_informational(&mut self, _: &Response) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A function that is called on requests to a router.
+type Listener = Box<dyn Fn(&str, &Request, &mut RequestContext) -> ListenerResult + 'static + Send + Sync>;
+
+/// A function that checks if a listener may block on a request, given the URI local to the router.
+type Blocking = Box<dyn Fn(&str, &Request) -> bool + 'static + Send + Sync>;
+
 /// A router that calls functions when requests with certain URI's are received.
+/// Listeners are called on the thread pool of the server, so they may block without holding up other connections.
+/// Listeners added with on_inline or on_prefix_inline are instead called on the event loop that read the request,
+/// which saves handing the connection to another thread, so they should respond right away.
 pub struct Router {
-    listeners: Vec<(String, Box<dyn Fn(&str, &Request) -> ListenerResult + 'static + Send + Sync>)>
+    listeners: Vec<(String, Listener, Blocking)>
 }
 
 impl Router {
@@ -30,19 +105,146 @@ impl Router {
     /// If uri is empty, then the function will be called on all requests directed to this router.
     /// The first argument to the listener function is the URI local to this router.
     pub fn on_prefix(&mut self, uri: &str, listener: impl Fn(&str, &Request) -> ListenerResult + 'static + Send + Sync) {
-        self.listeners.push((uri.into(), Box::new(listener)))
+        self.on_prefix_with_context(uri, move |uri, request, _| listener(uri, request))
+    }
+
+    /// Like on_prefix, but the listener is also given the context of the request, which has the information about the
+    /// connection, such as the client address and TLS session, and can send informational responses.
+    /// ```
+    /// use my_http::common::response::Response;
+    /// use my_http::server::Router;
+    /// use my_http::server::ListenerResult::SendResponse;
+    ///
+    /// let mut router = Router::new();
+    /// router.on_prefix_with_context("/whoami", |_, _, context| {
+    ///     let client = context.connection().map(|info| info.peer_addr.to_string());
+    ///     SendResponse(Response::text(client.unwrap_or_default()))
+    /// });
+    /// ```
+    pub fn on_prefix_with_context(&mut self, uri: &str, listener: impl Fn(&str, &Request, &mut RequestContext) -> ListenerResult + 'static + Send + Sync) {
+        self.add(uri, listener, |_, _| true)
+    }
+
+    /// Like on_prefix, but the listener is called on the event loop of the connection instead of the thread pool, so it
+    /// must not block. This is for listeners that respond right away, such as from memory.
+    /// ```
+    /// use my_http::common::response::Response;
+    /// use my_http::server::Router;
+    /// use my_http::server::ListenerResult::SendResponse;
+    ///
+    /// let mut router = Router::new();
+    /// router.on_prefix_inline("/health", |_, _| SendResponse(Response::text("ok")));
+    /// ```
+    pub fn on_prefix_inline(&mut self, uri: &str, listener: impl Fn(&str, &Request) -> ListenerResult + 'static + Send + Sync) {
+        self.add(uri, move |uri, request, _| listener(uri, request), |_, _| false)
+    }
+
+    /// Like on_prefix, but the listener is also given a response sink for sending informational responses before the final response.
+    /// ```
+    /// use my_http::common::response::Response;
+    /// use my_http::common::status;
+    /// use my_http::server::Router;
+    /// use my_http::server::ListenerResult::SendResponse;
+    ///
+    /// let mut router = Router::new();
+    /// router.on_prefix_with_sink("/page", |_, _, sink| {
+    ///     let hints = Response::builder()
+    ///         .status(status::EARLY_HINTS)
+    ///         .header("link", "</style.css>; rel=preload; as=style")
+    ///         .build()
+    ///         .unwrap();
+    ///     sink.send_informational(&hints).unwrap_or_default();
+    ///     SendResponse(Response::html("<link rel=stylesheet href=/style.css>"))
+    /// });
+    /// ```
+    pub fn on_prefix_with_sink(&mut self, uri: &str, listener: impl Fn(&str, &Request, &mut dyn ResponseSink) -> ListenerResult + 'static + Send + Sync) {
+        self.on_prefix_with_context(uri, move |uri, request, context| listener(uri, request, context))
     }
 
     /// Calls the given function on only requests with URIs that equal the given URI.
     pub fn on(&mut self, uri: &str, listener: impl Fn(&str, &Request) -> ListenerResult + 'static + Send + Sync) {
-        let uri_string = uri.to_string();
-        let listener = move |router_uri: &str, request: &Request| {
-            if uri_string.eq(router_uri) {
-                return listener(router_uri, request);
-            }
-            Next
-        };
-        self.on_prefix("", listener);
+        self.on_with_context(uri, move |uri, request, _| listener(uri, request))
+    }
+
+    /// Like on, but the listener is also given the context of the request, which has the information about the
+    /// connection and can send informational responses.
+    pub fn on_with_context(&mut self, uri: &str, listener: impl Fn(&str, &Request, &mut RequestContext) -> ListenerResult + 'static + Send + Sync) {
+        self.add_exact(uri, listener, true)
+    }
+
+    /// Like on, but the listener is called on the event loop of the connection instead of the thread pool, so it must
+    /// not block.
+    pub fn on_inline(&mut self, uri: &str, listener: impl Fn(&str, &Request) -> ListenerResult + 'static + Send + Sync) {
+        self.add_exact(uri, move |uri, request, _| listener(uri, request), false)
+    }
+
+    /// Like on, but the listener is also given a response sink for sending informational responses before the final response.
+    pub fn on_with_sink(&mut self, uri: &str, listener: impl Fn(&str, &Request, &mut dyn ResponseSink) -> ListenerResult + 'static + Send + Sync) {
+        self.on_with_context(uri, move |uri, request, context| listener(uri, request, context))
+    }
+
+    /// Accepts WebSocket connections on requests with URIs that equal the given URI, using the default WebSocket config.
+    /// The handler is called on a new thread for each connection with the handshake request and the connection.
+    /// ```
+    /// use my_http::server::Router;
+    /// use my_http::websocket::Message;
+    ///
+    /// let mut router = Router::new();
+    /// router.websocket("/echo", |_, mut socket| {
+    ///     while let Ok(message) = socket.read_message() {
+    ///         match message {
+    ///             Message::Text(text) => socket.send_text(&text).unwrap_or_default(),
+    ///             Message::Binary(data) => socket.send_binary(&data).unwrap_or_default(),
+    ///             _ => {}
+    ///         }
+    ///     }
+    /// });
+    /// ```
+    pub fn websocket(&mut self, uri: &str, handler: impl Fn(&Request, WebSocket<UpgradedStream>) + 'static + Send + Sync) {
+        self.websocket_with_config(uri, websocket::Config::default(), handler)
+    }
+
+    /// Like websocket, but uses the given config for subprotocol negotiation and message size limits.
+    /// Requests that are not valid WebSocket handshakes are answered with a 426 or 400 response.
+    pub fn websocket_with_config(&mut self, uri: &str, config: websocket::Config, handler: impl Fn(&Request, WebSocket<UpgradedStream>) + 'static + Send + Sync) {
+        let handler = Arc::new(handler);
+        // only the handshake is done by the listener, so it does not block
+        self.on_inline(uri, move |_, request| {
+            let (response, protocol) = match handshake::accept(request, &config.protocols) {
+                Ok(accepted) => accepted,
+                Err(response) => return SendResponse(response),
+            };
+            let handler = handler.clone();
+            let request = request.clone();
+            let max_message_size = config.max_message_size;
+            ListenerResult::Upgrade(Upgrade::with_response(response, move |stream| {
+                handler(&request, WebSocket::new(stream, Role::Server, protocol, max_message_size))
+            }))
+        });
+    }
+
+    /// Responds to requests to the given URI with a new event stream, and calls the given function with its sender.
+    /// The function should store the sender or move it to another thread rather than send all events itself, since the
+    /// response is not sent until it returns.
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    /// use my_http::server::Router;
+    /// use my_http::sse::Event;
+    ///
+    /// let subscribers = Arc::new(Mutex::new(Vec::new()));
+    /// let mut router = Router::new();
+    /// let new_subscribers = subscribers.clone();
+    /// router.event_stream("/events", move |_, sender| new_subscribers.lock().unwrap().push(sender));
+    ///
+    /// // later, from any thread
+    /// subscribers.lock().unwrap().retain(|sender| sender.send(&Event::new("update")).is_ok());
+    /// ```
+    pub fn event_stream(&mut self, uri: &str, handler: impl Fn(&Request, EventSender) + 'static + Send + Sync) {
+        self.on(uri, move |_, request| {
+            let (stream, sender) = EventStream::new(request);
+            handler(request, sender);
+            ListenerResult::EventStream(stream)
+        });
     }
 
     /// Like on_prefix, but instead passes all requests that start with the given URI to router.
@@ -60,17 +262,55 @@ impl Router {
     /// ```
     pub fn route(&mut self, uri: &str, router: Router) {
         let uri_length = uri.len();
-        let listener = move |request_uri: &str, request: &Request| {
-            router.result_internal(&request_uri[uri_length..], request)
+        let router = Arc::new(router);
+        let blocking_router = router.clone();
+        let listener = move |request_uri: &str, request: &Request, context: &mut RequestContext| {
+            router.result_internal(&request_uri[uri_length..], request, context)
         };
-        self.on_prefix(uri, listener);
+        let blocking = move |request_uri: &str, request: &Request| blocking_router.is_blocking_internal(&request_uri[uri_length..], request);
+        self.add(uri, listener, blocking);
+    }
+
+    /// Adds a listener that is called on requests with URIs that start with the given URI, along with the function
+    /// that checks if it may block on a request.
+    fn add(&mut self,
+           uri: &str,
+           listener: impl Fn(&str, &Request, &mut RequestContext) -> ListenerResult + 'static + Send + Sync,
+           blocking: impl Fn(&str, &Request) -> bool + 'static + Send + Sync) {
+        self.listeners.push((uri.into(), Box::new(listener), Box::new(blocking)))
+    }
+
+    /// Adds a listener that is only called on requests with URIs that equal the given URI.
+    fn add_exact(&mut self, uri: &str, listener: impl Fn(&str, &Request, &mut RequestContext) -> ListenerResult + 'static + Send + Sync, blocking: bool) {
+        let uri_string = uri.to_string();
+        let blocking_uri = uri_string.clone();
+        let listener = move |router_uri: &str, request: &Request, context: &mut RequestContext| {
+            if uri_string.eq(router_uri) {
+                return listener(router_uri, request, context);
+            }
+            Next
+        };
+        self.add("", listener, move |router_uri, _| blocking && blocking_uri.eq(router_uri));
+    }
+
+    /// Checks if any listener that may be called on the given request based on request_uri may block.
+    fn is_blocking_internal(&self, request_uri: &str, request: &Request) -> bool {
+        self.listeners.iter()
+            .filter(|(uri, _, _)| request_uri.starts_with(uri))
+            .any(|(_, _, blocking)| blocking(request_uri, request))
+    }
+
+    /// Checks if any listener that may be called on the given request may block, in which case the request is
+    /// handled on the thread pool of the server rather than on the event loop that read it.
+    pub(crate) fn is_blocking(&self, request: &Request) -> bool {
+        self.is_blocking_internal(&request.uri, request)
     }
 
     /// Calls listeners on the given request based on request_uri and produces a listener result.
-    fn result_internal(&self, request_uri: &str, request: &Request) -> ListenerResult {
+    fn result_internal(&self, request_uri: &str, request: &Request, context: &mut RequestContext) -> ListenerResult {
         self.listeners.iter()
-            .filter(|(uri, _)| request_uri.starts_with(uri))
-            .map(|(_, listener)| listener(request_uri, request))
+            .filter(|(uri, _, _)| request_uri.starts_with(uri))
+            .map(|(_, listener, _)| listener(request_uri, request, context))
             .find(|result| *result != Next)
             .unwrap_or(Next)
     }
@@ -78,8 +318,19 @@ impl Router {
     /// Gets the result from listeners that are called on the given request.
     /// The result from the last listener to be called on the given request is returned.
     /// If no listeners were called, then "Next" is returned.
+    /// Informational responses sent by listeners are discarded.
     pub fn result(&self, request: &Request) -> ListenerResult {
-        self.result_internal(&request.uri, request)
+        self.result_with_sink(request, &mut DiscardSink)
+    }
+
+    /// Like result, but informational responses sent by listeners are sent to the given sink.
+    pub fn result_with_sink(&self, request: &Request, sink: &mut dyn ResponseSink) -> ListenerResult {
+        self.result_with_context(request, &mut RequestContext::new(None, sink))
+    }
+
+    /// Like result, but listeners are given the given context.
+    pub fn result_with_context(&self, request: &Request, context: &mut RequestContext) -> ListenerResult {
+        self.result_internal(&request.uri, request, context)
     }
 }
 
@@ -92,8 +343,11 @@ mod tests {
     use crate::common::request::Request;
     use crate::common::response::Response;
     use crate::common::status;
-    use crate::server::router::{ListenerResult, Router};
-    use crate::server::router::ListenerResult::{Next, SendResponse, SendResponseArc};
+    use crate::server::connection_info::ConnectionInfo;
+    use crate::server::event_stream::EventStream;
+    use crate::server::router::{ListenerResult, RequestContext, ResponseSink, Router};
+    use crate::server::router::ListenerResult::{Next, SendResponse, SendResponseArc, Upgrade};
+    use crate::server::upgrade;
 
     type FunctionCalls = Arc<Mutex<Vec<&'static str>>>;
 
@@ -584,4 +838,116 @@ mod tests {
         clear_function_call(&calls);
         test_route(&router, "/long/test/uri/blah/blah/blah/yada/yada/wioefjiowef/woeifjo/oiwejfiowefd/qiowjd", &calls, Next, &call_2s);
     }
-}
\ No newline at end of file
+
+    struct VecSink(Vec<Response>);
+
+    impl ResponseSink for VecSink {
+        fn send_informational(&mut self, response: &Response) -> std::io::Result<()> {
+            self.0.push(response.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn listeners_with_sink() {
+        let mut router = Router::new();
+        let mut sub_router = Router::new();
+
+        router.on_prefix_with_sink("", |_, _, sink| {
+            sink.send_informational(&Response::empty(status::PROCESSING)).unwrap();
+            Next
+        });
+        sub_router.on_with_sink("/bar", |_, _, sink| {
+            sink.send_informational(&Response::empty(status::EARLY_HINTS)).unwrap();
+            SendResponse(test_response())
+        });
+        router.route("/foo", sub_router);
+
+        let mut sink = VecSink(vec![]);
+        let result = router.result_with_sink(&test_request("/foo/bar"), &mut sink);
+
+        assert_eq!(result, SendResponse(test_response()));
+        assert_eq!(sink.0, vec![Response::empty(status::PROCESSING), Response::empty(status::EARLY_HINTS)]);
+    }
+
+    #[test]
+    fn result_discards_informational_responses() {
+        let mut router = Router::new();
+
+        router.on_with_sink("/", |_, _, sink| {
+            sink.send_informational(&Response::empty(status::EARLY_HINTS)).unwrap();
+            SendResponse(test_response())
+        });
+
+        assert_eq!(router.result(&test_request("/")), SendResponse(test_response()));
+    }
+
+    #[test]
+    fn listeners_with_context() {
+        let mut router = Router::new();
+        let mut sub_router = Router::new();
+
+        router.on_prefix_with_context("", |_, _, context| {
+            context.send_informational(&Response::empty(status::EARLY_HINTS)).unwrap();
+            Next
+        });
+        sub_router.on_with_context("/bar", |_, _, context| {
+            let info = context.connection().unwrap();
+            SendResponse(Response::text(info.peer_addr.to_string()))
+        });
+        router.route("/foo", sub_router);
+
+        let info = ConnectionInfo { peer_addr: "127.0.0.1:5000".parse().unwrap(), local_addr: "127.0.0.1:80".parse().unwrap(), tls: None };
+        let mut sink = VecSink(vec![]);
+        let result = router.result_with_context(&test_request("/foo/bar"), &mut RequestContext::new(Some(&info), &mut sink));
+
+        assert_eq!(result, SendResponse(Response::text("127.0.0.1:5000")));
+        assert_eq!(sink.0, vec![Response::empty(status::EARLY_HINTS)]);
+    }
+
+    #[test]
+    fn result_has_no_connection() {
+        let mut router = Router::new();
+
+        router.on_with_context("/", |_, _, context| {
+            assert!(context.connection().is_none());
+            SendResponse(test_response())
+        });
+
+        assert_eq!(router.result(&test_request("/")), SendResponse(test_response()));
+    }
+
+    #[test]
+    fn listener_result_equality() {
+        assert_eq!(Next, Next);
+        assert_eq!(SendResponse(test_response()), SendResponse(test_response()));
+        assert_eq!(SendResponseArc(Arc::new(test_response())), SendResponseArc(Arc::new(test_response())));
+        assert_ne!(SendResponse(test_response()), SendResponseArc(Arc::new(test_response())));
+        assert_ne!(SendResponse(test_response()), Next);
+
+        let upgrade = || Upgrade(upgrade::Upgrade::new("test", |_| {}));
+        assert_ne!(upgrade(), upgrade());
+        let event_stream = || ListenerResult::EventStream(EventStream::new(&test_request("/")).0);
+        assert_ne!(event_stream(), event_stream());
+    }
+
+    #[test]
+    fn blocking_listeners() {
+        let mut router = Router::new();
+        router.on_inline("/cheap", |_, _| SendResponse(test_response()));
+        router.on("/slow", |_, _| SendResponse(test_response()));
+        let mut sub_router = Router::new();
+        sub_router.on_prefix_inline("/cheap", |_, _| Next);
+        sub_router.on_prefix("/slow", |_, _| Next);
+        router.route("/sub", sub_router);
+
+        assert!(!router.is_blocking(&test_request("/cheap")));
+        assert!(router.is_blocking(&test_request("/slow")));
+        assert!(!router.is_blocking(&test_request("/sub/cheap/page")));
+        assert!(router.is_blocking(&test_request("/sub/slow/page")));
+        assert!(!router.is_blocking(&test_request("/other")));
+
+        router.on_prefix("/", |_, _| Next);
+        assert!(router.is_blocking(&test_request("/cheap")));
+    }
+}
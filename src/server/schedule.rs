@@ -0,0 +1,117 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// The connection is not queued or being handled.
+const IDLE: u8 = 0;
+/// The connection is queued on the thread pool.
+const QUEUED: u8 = 1;
+/// The connection is being handled.
+const RUNNING: u8 = 2;
+/// The connection is being handled, and became ready again since it started, so it has to be handled again after.
+const RUNNING_AGAIN: u8 = 3;
+
+/// The scheduling state of a connection, which makes sure it is queued on the thread pool at most once.
+/// Readiness that arrives while the connection is queued is picked up when it runs, and readiness that arrives while
+/// it is running makes it run again afterwards, so none is missed.
+#[derive(Default)]
+pub struct Schedule {
+    state: AtomicU8,
+}
+
+impl Schedule {
+    /// Marks the connection as ready. Returns true if the connection should be queued, which is only when it is idle.
+    pub fn ready(&self) -> bool {
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            let next = match state {
+                IDLE => QUEUED,
+                RUNNING => RUNNING_AGAIN,
+                _ => return false,
+            };
+            match self.state.compare_exchange_weak(state, next, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => return next == QUEUED,
+                Err(actual) => state = actual,
+            }
+        }
+    }
+
+    /// Marks the connection as ready if it is queued or being handled, in which case it is picked up by the thread pool.
+    /// Returns false if the connection is idle, so the caller should handle it.
+    pub fn ready_if_scheduled(&self) -> bool {
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            let next = match state {
+                IDLE => return false,
+                RUNNING => RUNNING_AGAIN,
+                _ => return true,
+            };
+            match self.state.compare_exchange_weak(state, next, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => return true,
+                Err(actual) => state = actual,
+            }
+        }
+    }
+
+    /// Marks the queued connection as being handled.
+    pub fn start(&self) {
+        self.state.store(RUNNING, Ordering::Release);
+    }
+
+    /// Marks the connection as done being handled. Returns true if the connection became ready while it was handled,
+    /// in which case it is marked as queued again and should be queued.
+    pub fn finish(&self) -> bool {
+        match self.state.compare_exchange(RUNNING, IDLE, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => false,
+            Err(_) => {
+                self.state.store(QUEUED, Ordering::Release);
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server::schedule::Schedule;
+
+    #[test]
+    fn queued_once() {
+        let schedule = Schedule::default();
+        assert!(schedule.ready());
+        assert!(!schedule.ready());
+        assert!(!schedule.ready());
+
+        schedule.start();
+        assert!(!schedule.finish());
+        assert!(schedule.ready());
+    }
+
+    #[test]
+    fn ready_while_running() {
+        let schedule = Schedule::default();
+        assert!(schedule.ready());
+        schedule.start();
+        assert!(!schedule.ready());
+        assert!(!schedule.ready());
+
+        assert!(schedule.finish());
+        // still queued from finishing
+        assert!(!schedule.ready());
+        schedule.start();
+        assert!(!schedule.finish());
+    }
+
+    #[test]
+    fn ready_if_scheduled() {
+        let schedule = Schedule::default();
+        assert!(!schedule.ready_if_scheduled());
+
+        assert!(schedule.ready());
+        assert!(schedule.ready_if_scheduled());
+        schedule.start();
+        assert!(schedule.ready_if_scheduled());
+        assert!(schedule.finish());
+        schedule.start();
+        assert!(!schedule.finish());
+        assert!(!schedule.ready_if_scheduled());
+    }
+}
@@ -1,30 +1,64 @@
-use std::io::{BufReader, Read, Write};
-use std::sync::{Arc, Mutex};
+use std::io::{BufReader, Error as IoError, ErrorKind, Read, Write};
+use std::net::Shutdown;
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::time::Instant;
 
 use mio::net::TcpStream;
+use mio::Registry;
 use rustls::{ServerConfig, ServerSession};
 
-use crate::common::header::{CONNECTION, HeaderMapOps};
+use crate::common::header::{CONNECTION, CONTENT_LENGTH, DATE, Header, HeaderMapOps, SERVER, TRANSFER_ENCODING};
+use crate::common::method::Method;
 use crate::common::request::Request;
 use crate::common::response::Response;
-use crate::common::version::HTTP_VERSION_1_1;
-use crate::server::config::Config;
-use crate::server::connection::{Connection, ReadRequestError};
-use crate::server::connection::ReadRequestResult::{Closed, Error, NotReady, Ready};
+use crate::common::status::{INTERNAL_SERVER_ERROR, SWITCHING_PROTOCOLS};
+use crate::common::version::{HTTP_VERSION_1_0, HTTP_VERSION_1_1};
+use crate::server::config::{Config, ConnectionLimitAction, ContinueResult};
+use crate::server::connection::{Connection, ReadRequestError, Waiting};
+use crate::server::connection_info::{ConnectionInfo, DescribeConnection};
+#[cfg(feature = "http2")]
+use crate::server::connection::Preface;
+use crate::server::connection::ReadRequestResult::{Closed, Error, ExpectsContinue, NotReady, Ready};
+use crate::server::date_cache::with_current_date;
+use crate::server::event_stream::{EventStream, Waker};
+use crate::server::framing::{BodyFraming, Framing, response_framing};
 use crate::server::nonblocking_buf_writer::NonBlockingBufWriter;
-use crate::server::poll::listen;
-use crate::server::router::ListenerResult::{Next, SendResponse, SendResponseArc};
-use crate::server::router::Router;
+use crate::server::poll::{listen, Timer};
+use crate::server::router::ListenerResult::{EventStream as SendEventStream, Next, SendResponse, SendResponseArc, Upgrade};
+use crate::server::router::{RequestContext, ResponseSink};
+use crate::server::schedule::Schedule;
+use crate::server::server::ConnectionOutcome::{Close, KeepOpen, Offloaded, Streaming, Upgraded};
+use crate::server::upgrade::{counted_handler, ServerStream, TcpBacked, unsent_data, upgrade_connection, UpgradeHandler};
+use crate::util::chunked_writer::ChunkedWriter;
+use crate::util::panic::catch_panic;
 use crate::util::stream;
 use crate::util::stream::{BufStream, Stream};
 use crate::util::thread_pool::ThreadPool;
+#[cfg(feature = "http2")]
+use crate::tls::default_alpn_protocols;
 use crate::util::tls_stream::TlsStream;
 
 /// Raw bytes for a request parsing error response.
 const REQUEST_PARSING_ERROR_RESPONSE: &[u8; 28] = b"HTTP/1.1 400 Bad Request\r\n\r\n";
 
+/// Raw bytes for a 100 continue response.
+const CONTINUE_RESPONSE: &[u8; 25] = b"HTTP/1.1 100 Continue\r\n\r\n";
+
 /// Raw bytes for a 404 not found response.
-const NOT_FOUND_RESPONSE: &[u8; 26] = b"HTTP/1.1 404 Not Found\r\n\r\n";
+const NOT_FOUND_RESPONSE: &[u8; 45] = b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n";
+
+/// Raw bytes for a 408 request timeout response, sent when a client takes too long to send a request.
+const REQUEST_TIMEOUT_RESPONSE: &[u8; 70] = b"HTTP/1.1 408 Request Timeout\r\nconnection: close\r\ncontent-length: 0\r\n\r\n";
+
+/// Raw bytes for a 500 internal server error response, sent when a response from the router can not be written.
+const INTERNAL_SERVER_ERROR_RESPONSE: &[u8; 57] = b"HTTP/1.1 500 Internal Server Error\r\ncontent-length: 0\r\n\r\n";
+
+/// Raw bytes for a 503 service unavailable response, sent when a connection can not be upgraded since too many
+/// upgraded connections are open.
+const SERVICE_UNAVAILABLE_RESPONSE: &[u8; 55] = b"HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\n\r\n";
+
+/// Framing for the head of an event stream response, whose body is written in chunks as events are sent.
+const EVENT_STREAM_FRAMING: Framing = Framing { add_content_length: None, write_framing_headers: true, body: BodyFraming::Omitted };
 
 /// Size of connection read buffers.
 const READ_BUF_SIZE: usize = 4096;
@@ -34,39 +68,73 @@ const WRITE_BUF_SIZE: usize = 4096;
 
 /// Starts an HTTP server. This function blocks.
 pub fn listen_http(config: Config) -> std::io::Result<()> {
-    listen_abstract(config, |stream| stream)
+    listen_abstract(config, |stream| stream, reject_connection)
 }
 
 /// Starts an HTTPS server. This function blocks.
-pub fn listen_https(config: Config, tls_config: ServerConfig) -> std::io::Result<()> {
+/// The TLS config is either a rustls ServerConfig or a TlsConfig, which picks certificates by hostname.
+/// With the http2 feature, the server offers HTTP/2 and HTTP/1.1 with ALPN, unless the TLS config already sets its
+/// own ALPN protocols.
+/// With ConnectionLimitAction::Reject, connections over the limit are closed without a response, so the retry_after of
+/// the action is not sent.
+pub fn listen_https(config: Config, tls_config: impl Into<ServerConfig>) -> std::io::Result<()> {
+    #[cfg_attr(not(feature = "http2"), allow(unused_mut))]
+    let mut tls_config = tls_config.into();
+    #[cfg(feature = "http2")]
+    if tls_config.alpn_protocols.is_empty() {
+        tls_config.set_protocols(&default_alpn_protocols());
+    }
     let tls_config = Arc::new(tls_config);
-    listen_abstract(config, |stream| TlsStream::new(ServerSession::new(&tls_config), stream))
+    // a response to a rejected connection can only be sent after a TLS handshake, so the connection is just closed and
+    // retry_after is not used
+    listen_abstract(config, |stream| TlsStream::new(ServerSession::new(&tls_config), stream), |_, _| {})
 }
 
 /// Starts the server with the given config, and uses the given on_new_connection function to get streams for the incoming connections.
 /// This abstraction is necessary since HTTP and HTTPS connections use different underlying streams.
-fn listen_abstract<T: Stream + Send + 'static>(config: Config, on_new_connection: impl Fn(TcpStream) -> T) -> std::io::Result<()> {
+/// Connections over the connection limit are passed to the given reject function along with the number of seconds the
+/// client should wait before retrying, if the config says to reject them.
+fn listen_abstract<T: Stream + TcpBacked + DescribeConnection + Send + 'static>(config: Config,
+                                                                               on_new_connection: impl Fn(TcpStream) -> T + Sync,
+                                                                               reject: fn(TcpStream, u64)) -> std::io::Result<()> {
     let addr = config.addr.parse().expect("Invalid socket address");
-    let thread_pool = ThreadPool::new(config.connection_handler_threads);
+    let thread_pool = Arc::new(ThreadPool::new(config.connection_handler_threads));
 
     let config = Arc::new(config);
 
+    let on_rejected = match config.at_max_connections {
+        ConnectionLimitAction::Wait => None,
+        ConnectionLimitAction::Reject { retry_after } => Some(move |socket| reject(socket, retry_after)),
+    };
+
     listen(addr,
-           |socket, addr| {
+           config.event_loops,
+           config.max_connections,
+           &config.connection_counter,
+           |socket, addr, timer| {
                let stream = on_new_connection(socket);
                let stream = new_buffered_stream(stream);
                let connection = Connection::new(addr, stream);
-               Arc::new(Mutex::new(Some(connection)))
+               Arc::new(SharedConnection { connection: Mutex::new(Some(connection)), timer, schedule: Schedule::default() })
            },
-           |connection| {
-               let connection = connection.clone();
-               let config = config.clone();
-               thread_pool.execute(move || handle_io_ready_connection(config, connection));
-           })
+           |connection, registry| handle_on_event_loop(&config, registry, &Arc::downgrade(&thread_pool), connection),
+           on_rejected.as_ref().map(|on_rejected| on_rejected as &(dyn Fn(TcpStream) + Sync)))
+}
+
+/// Sends a 503 response to the client of the given connection, which is over the connection limit, and closes it.
+/// The response tells the client to retry after the given number of seconds.
+fn reject_connection(mut socket: TcpStream, retry_after: u64) {
+    // unread data would make the connection be reset when it is closed, which may discard the response
+    let mut discarded = [0; READ_BUF_SIZE];
+    while matches!(socket.read(&mut discarded), Ok(amount) if amount > 0) {}
+
+    let response = format!("HTTP/1.1 503 Service Unavailable\r\nretry-after: {}\r\nconnection: close\r\ncontent-length: 0\r\n\r\n", retry_after);
+    // the socket was just accepted, so its send buffer has room for the whole response
+    socket.write_all(response.as_bytes()).and_then(|_| socket.shutdown(Shutdown::Write)).unwrap_or_default();
 }
 
 /// Wraps the stream with a buffered reader and writer.
-fn new_buffered_stream(stream: impl Stream + 'static) -> impl BufStream {
+fn new_buffered_stream<T: Stream + 'static>(stream: T) -> ServerStream<T> {
     fn buf_reader<R: Read>(reader: R) -> BufReader<R> {
         BufReader::with_capacity(READ_BUF_SIZE, reader)
     }
@@ -78,50 +146,425 @@ fn new_buffered_stream(stream: impl Stream + 'static) -> impl BufStream {
     stream::with_buf_reader_and_writer(stream, buf_reader, buf_writer)
 }
 
-/// Tries reading requests and responding for the given connection. May drop the given connection if it should be closed.
-fn handle_io_ready_connection<T: BufStream>(config: Arc<Config>, connection: Arc<Mutex<Option<Connection<T>>>>) {
-    let mut lock = connection.lock().unwrap();
+/// A connection that is shared between the poll and the thread pool, along with its timer and scheduling state.
+/// The connection is None once it has been closed or upgraded.
+struct SharedConnection<T: Stream + 'static> {
+    connection: Mutex<Option<Connection<ServerStream<T>>>>,
+    timer: Timer,
+    schedule: Schedule,
+}
+
+/// What should happen to a connection after responding to its requests.
+enum ConnectionOutcome {
+    /// The connection should be kept open for more requests.
+    KeepOpen,
+    /// The connection should be dropped.
+    Close,
+    /// The connection was upgraded and should be handed to the given handler.
+    Upgraded(UpgradeHandler),
+    /// The head of the given event stream response was sent, and the connection should be kept open to send its events.
+    Streaming(EventStream),
+    /// A request for a listener that may block was read on the event loop, and the connection should be handed to the
+    /// thread pool to respond to it.
+    Offloaded,
+}
+
+/// Handles the given connection on the current thread, which is the thread of its event loop. Requests are read and
+/// the inline listeners of the router are called here, and the connection is handed to the given thread pool once it
+/// has a request for a listener that may block. While the connection is on the thread pool, its readiness is passed on
+/// to the thread pool instead, so it is only ever handled by one thread at a time.
+fn handle_on_event_loop<T>(config: &Arc<Config>, registry: &Arc<Registry>, thread_pool: &Weak<ThreadPool>, connection: &Arc<SharedConnection<T>>)
+    where T: Stream + TcpBacked + DescribeConnection + Send + 'static
+{
+    if connection.schedule.ready_if_scheduled() {
+        return;
+    }
+    // a panic would otherwise stop the event loop along with all of its connections
+    match catch_panic(|| handle_io_ready_connection(config, registry, true, connection)) {
+        Ok(true) => schedule_connection(config, registry, thread_pool, connection),
+        Ok(false) => {}
+        Err(message) => close_after_panic(connection, &message),
+    }
+}
+
+/// Queues the given connection to be handled on the given thread pool, unless it is already queued. If it is being
+/// handled, then it is queued again once it is done instead, so a connection only ever takes up one thread.
+fn schedule_connection<T>(config: &Arc<Config>,
+                          registry: &Arc<Registry>,
+                          thread_pool: &Weak<ThreadPool>,
+                          connection: &Arc<SharedConnection<T>>)
+    where T: Stream + TcpBacked + DescribeConnection + Send + 'static
+{
+    if connection.schedule.ready() {
+        queue_connection(config.clone(), registry.clone(), thread_pool.clone(), connection.clone());
+    }
+}
 
-    if let Some(mut connection) = lock.take() {
-        // first try to flush any existing unflushed data
-        if !connection.flush().is_ok() { // if we cant flush assume the connection is bad
-            return;
+/// Queues the given connection, which has been marked as queued, to be handled on the given thread pool. Queues it
+/// again after it is handled if it became ready in the meantime.
+fn queue_connection<T>(config: Arc<Config>,
+                       registry: Arc<Registry>,
+                       thread_pool: Weak<ThreadPool>,
+                       connection: Arc<SharedConnection<T>>)
+    where T: Stream + TcpBacked + DescribeConnection + Send + 'static
+{
+    if let Some(pool) = thread_pool.upgrade() {
+        pool.execute(move || {
+            connection.schedule.start();
+            let result = catch_panic(|| handle_io_ready_connection(&config, &registry, false, &connection));
+            if let Err(message) = result {
+                close_after_panic(&connection, &message);
+            }
+            if connection.schedule.finish() {
+                queue_connection(config, registry, thread_pool, connection);
+            }
+        });
+    }
+}
+
+/// Closes the given connection after a panic while it was handled with the given message, since it may have been left
+/// in an invalid state. Closing its timer right away frees its slot instead of leaving it open until it times out.
+fn close_after_panic<T: Stream>(connection: &SharedConnection<T>, message: &str) {
+    println!("Error: panicked while handling connection: {}", message);
+    connection.connection.lock().unwrap_or_else(PoisonError::into_inner).take();
+    connection.timer.close();
+}
+
+/// Tries reading requests and responding for the given connection. May drop the given connection if it should be closed,
+/// or move it out of the server's poll with the given registry if it has been upgraded.
+/// If an event stream is started, then the connection is handled again on its event loop whenever events are sent.
+/// Connections that are kept open have their timer set to handle them again once they time out, and connections that
+/// have timed out are closed.
+/// On the event loop, returns true once a request for a listener that may block has been read, in which case the
+/// connection has to be handed to the thread pool.
+fn handle_io_ready_connection<T>(config: &Arc<Config>,
+                                 registry: &Arc<Registry>,
+                                 on_event_loop: bool,
+                                 connection: &Arc<SharedConnection<T>>) -> bool
+    where T: Stream + TcpBacked + DescribeConnection + Send + 'static
+{
+    let mut lock = match connection.connection.lock() {
+        Ok(lock) => lock,
+        Err(poisoned) => {
+            // a thread panicked while handling the connection, so it may have been left in an invalid state
+            println!("Error: dropping connection after a panic while it was handled");
+            poisoned.into_inner().take();
+            connection.timer.close();
+            return false;
         }
+    };
+
+    let mut offloaded = false;
+
+    if let Some(mut conn) = lock.take() {
+        // the connection can not time out while it is being handled
+        connection.timer.set(None);
 
-        // try to read requests and write responses
-        let should_close = respond_to_requests(&mut connection, &config.router);
+        // first try to flush any existing unflushed data, which also continues the TLS handshake
+        if let Err(error) = conn.flush() { // if we cant flush assume the connection is bad
+            // invalid data means the handshake failed, such as when a client certificate is rejected
+            if error.kind() == ErrorKind::InvalidData {
+                println!("Error: TLS handshake with {} failed: {}", conn.addr, error);
+            }
+            connection.timer.close();
+            return false;
+        }
 
-        // put the connection back in the Option if we should keep it alive
-        if !should_close {
-            lock.replace(connection);
+        // try to send events or read requests and write responses
+        let new_waker = || connection_waker(&connection.timer);
+        let sending = unsent_data(conn.get_ref()).is_some();
+        match serve_connection(&mut conn, config, sending, on_event_loop, &new_waker) {
+            // put the connection back in the Option if we should keep it alive
+            KeepOpen => *lock = wait_for_client(conn, config, &connection.timer),
+            // the connection is kept until the rest of the last response has been sent
+            Close if !conn.closing && unsent_data(conn.get_ref()).is_some() => {
+                conn.closing = true;
+                *lock = wait_for_client(conn, config, &connection.timer);
+            }
+            Close => {}
+            // the Option is left empty, so the connection is ignored if it is still in the poll
+            Upgraded(handler) => upgrade_connection(conn, registry, handler),
+            Streaming(stream) => {
+                stream.attach(new_waker());
+                conn.event_stream = Some(stream);
+                *lock = wait_for_client(conn, config, &connection.timer);
+            }
+            // the timer is set by the thread pool once it has responded
+            Offloaded => {
+                *lock = Some(conn);
+                offloaded = true;
+            }
+        }
+
+        if lock.is_none() {
+            connection.timer.close();
         }
     }
+    offloaded
 }
 
-/// Responds to requests in the given connection using the given router. Returns true if the connection should be dropped.
-fn respond_to_requests<T: BufStream>(connection: &mut Connection<T>, router: &Router) -> bool {
+/// Checks how long the given connection has been waiting for the client. Returns the connection with its timer set to
+/// handle it again once it times out, or None if it has already timed out using the timeouts from the given config.
+/// Also returns None if the connection is closing and all of its data has been sent.
+fn wait_for_client<T>(mut connection: Connection<ServerStream<T>>, config: &Config, timer: &Timer) -> Option<Connection<ServerStream<T>>>
+    where T: Stream + TcpBacked + 'static
+{
+    let unsent = unsent_data(connection.get_ref());
+    if connection.closing && unsent.is_none() {
+        return None;
+    }
+    let now = Instant::now();
+    let waiting = connection.update_waiting(unsent, now);
+    match waiting.deadline(&config.timeouts) {
+        Some(deadline) if deadline <= now => {
+            time_out(&mut connection, waiting);
+            None
+        }
+        deadline => {
+            timer.set(deadline);
+            Some(connection)
+        }
+    }
+}
+
+/// Responds to the given connection timing out while it was waiting for the given thing. Clients that were in the
+/// middle of sending a request are sent a 408 response. The connection should be dropped after.
+fn time_out(writer: &mut impl Write, waiting: Waiting) {
+    if waiting.is_receiving_request() {
+        writer.write_all(REQUEST_TIMEOUT_RESPONSE).and_then(|_| writer.flush()).unwrap_or_default();
+    }
+}
+
+/// Creates a waker that handles the connection with the given timer again on its event loop.
+fn connection_waker(timer: &Timer) -> Waker {
+    // the timer does not keep the connection alive, and waking it does nothing once the connection is closed
+    let timer = timer.clone();
+    Arc::new(move || timer.wake())
+}
+
+/// Writes the events sent to the event stream of the given connection, if it has one. Once there is no event stream,
+/// responds to requests in the connection using the router from the given config.
+/// Sending is true if data written to the connection is still being sent, in which case events are left in the event
+/// stream until it has been sent, so that a client that stops reading can not make the written data grow without limit.
+/// On the event loop, requests for listeners that may block are left for the thread pool.
+/// Connections that start with the HTTP/2 preface are served as HTTP/2 connections instead, which use the given
+/// function to create the waker for their event streams.
+/// Closing connections are only kept open while the client keeps its side open.
+#[cfg_attr(not(feature = "http2"), allow(unused_variables))]
+fn serve_connection<T: BufStream + DescribeConnection>(connection: &mut Connection<T>,
+                                                       config: &Config,
+                                                       sending: bool,
+                                                       on_event_loop: bool,
+                                                       new_waker: &dyn Fn() -> Waker) -> ConnectionOutcome {
+    if connection.closing {
+        return if connection.discard_input() { KeepOpen } else { Close };
+    }
+
+    #[cfg(feature = "http2")]
+    match connection.read_preface() {
+        Preface::NotReady => return KeepOpen,
+        Preface::Closed => return Close,
+        Preface::Error(error) => {
+            write_error_response(connection, error).unwrap_or_default();
+            return Close;
+        }
+        Preface::Http2 => {
+            let (http2, stream) = connection.http2_mut().unwrap();
+            return match http2.serve(stream, config, on_event_loop, new_waker) {
+                false => Close,
+                true if http2.has_offloaded() => Offloaded,
+                true => KeepOpen,
+            };
+        }
+        Preface::Http1 => {}
+    }
+
+    if let Some(stream) = connection.event_stream.take() {
+        if !connection.discard_input() {
+            return Close;
+        }
+        if sending {
+            connection.event_stream = Some(stream);
+            return KeepOpen;
+        }
+
+        let (events, finished) = stream.take_events();
+        let mut writer = ChunkedWriter::new(&mut *connection);
+        let result = writer.write_all(&events).and_then(|_| if finished { writer.finish().map(|_| ()) } else { writer.flush() });
+        match result {
+            Err(_) => return Close,
+            Ok(()) if !finished => {
+                connection.event_stream = Some(stream);
+                return KeepOpen;
+            }
+            Ok(()) if stream.close_after => return Close,
+            Ok(()) => {}
+        }
+    }
+
+    respond_to_requests(connection, config, on_event_loop)
+}
+
+/// Responds to requests in the given connection using the router from the given config. On the event loop, a request
+/// for a listener that may block is left in the connection for the thread pool, which responds to it first.
+fn respond_to_requests<T: BufStream + DescribeConnection>(connection: &mut Connection<T>, config: &Config, on_event_loop: bool) -> ConnectionOutcome {
     loop {
-        match connection.read_request() {
+        let result = match connection.offloaded.take() {
+            Some(request) => Ready(request),
+            None => connection.read_request(),
+        };
+        match result {
+            Ready(request) if on_event_loop && config.router.is_blocking(&request) => {
+                connection.offloaded = Some(request);
+                return Offloaded;
+            }
             Ready(request) => {
-                let write_result = write_response_from_router(connection, router, &request);
-                if write_result.is_err() || should_close_after_response(&request) { return true; }
+                let info = connection.info();
+                // the connection is closed after the last request it is allowed, which the client is told in the response
+                let last = config.max_requests_per_connection.is_some_and(|max| connection.requests() >= max);
+                match write_response_from_router(connection, config, &request, &info, last) {
+                    Ok(KeepOpen) if !last && !should_close_after_response(&request) => {}
+                    Ok(Streaming(mut stream)) => {
+                        stream.close_after = last || should_close_after_response(&request);
+                        return Streaming(stream);
+                    }
+                    Ok(Upgraded(handler)) => return Upgraded(handler),
+                    _ => return Close,
+                }
+            }
+            // HTTP/1.0 clients do not know 100 continue responses, so their expectations are ignored
+            ExpectsContinue(_, HTTP_VERSION_1_0) => {}
+            ExpectsContinue(request, _) => {
+                if respond_to_expect_continue(connection, config, &request) { return Close; }
             }
-            NotReady => return false,
-            Closed => return true,
+            NotReady => return KeepOpen,
+            Closed => return Close,
             Error(error) => {
                 write_error_response(connection, error).unwrap_or_default();
-                return true;
+                return Close;
             }
         }
     }
 }
 
+/// Asks the continue listener from the given config whether the client should send the body of the given request, and
+/// writes either a 100 continue response or the rejection. Returns true if the connection should be dropped.
+/// If the listener panics, then the request is rejected with a 500 response.
+fn respond_to_expect_continue(writer: &mut impl Write, config: &Config, request: &Request) -> bool {
+    let result = match &config.continue_listener {
+        Some(listener) => catch_panic(|| listener(request)).unwrap_or_else(|message| {
+            println!("Error: continue listener panicked on {} {}: {}", request.method, request.uri, message);
+            ContinueResult::Reject(Response::empty(INTERNAL_SERVER_ERROR))
+        }),
+        None => ContinueResult::Continue
+    };
+
+    match result {
+        ContinueResult::Continue => writer.write_all(CONTINUE_RESPONSE).and_then(|_| writer.flush()).is_err(),
+        ContinueResult::Reject(response) => {
+            write_server_response(writer, config, request, &response, true).unwrap_or_default();
+            true
+        }
+    }
+}
+
 /// Gets a response from the router and writes. If the router has no response, then writes a 404 response.
-fn write_response_from_router(writer: &mut impl Write, router: &Router, request: &Request) -> std::io::Result<()> {
-    match router.result(&request) {
-        SendResponse(response) => write_response(writer, &response),
-        SendResponseArc(response) => write_response(writer, &response),
-        Next => writer.write_all(NOT_FOUND_RESPONSE).and_then(|_| writer.flush())
+/// The router is given the information about the connection along with the request.
+/// If close is true, then a "connection: close" header is added to the response, unless the connection is upgraded.
+/// If a listener panics, then a 500 response is written and the connection is closed, since the listener may have left
+/// it in the middle of a response.
+/// Returns Upgraded if the router upgraded the connection, Streaming if the router started an event stream, Close if a
+/// listener panicked, and KeepOpen otherwise.
+fn write_response_from_router(writer: &mut impl Write, config: &Config, request: &Request, info: &ConnectionInfo, close: bool) -> std::io::Result<ConnectionOutcome> {
+    let result = match catch_panic(|| config.router.result_with_context(request, &mut RequestContext::new(Some(info), &mut InformationalWriter(&mut *writer)))) {
+        Ok(result) => result,
+        Err(message) => {
+            println!("Error: listener panicked on {} {}: {}", request.method, request.uri, message);
+            return write_raw_response(writer, INTERNAL_SERVER_ERROR_RESPONSE, true).map(|_| Close);
+        }
+    };
+    match result {
+        SendResponse(response) => write_server_response(writer, config, request, &response, close).map(|_| KeepOpen),
+        SendResponseArc(response) => write_server_response(writer, config, request, &response, close).map(|_| KeepOpen),
+        Upgrade(upgrade) if upgrade.response.status != SWITCHING_PROTOCOLS => {
+            println!("Error: upgrade response has status {}", upgrade.response.status.code);
+            write_raw_response(writer, INTERNAL_SERVER_ERROR_RESPONSE, close).map(|_| KeepOpen)
+        }
+        Upgrade(upgrade) => match counted_handler(upgrade.handler, &config.upgraded_connection_counter, config.max_upgraded_connections) {
+            Some(handler) => write_server_response(writer, config, request, &upgrade.response, false).map(|_| Upgraded(handler)),
+            None => {
+                println!("Error: too many upgraded connections to upgrade {} {}", request.method, request.uri);
+                write_raw_response(writer, SERVICE_UNAVAILABLE_RESPONSE, close).map(|_| KeepOpen)
+            }
+        },
+        // a response to a HEAD request has no body, so the stream ends right away
+        SendEventStream(stream) if request.method == Method::HEAD =>
+            write_server_framed_response(writer, config, &stream.response(), EVENT_STREAM_FRAMING, close).map(|_| KeepOpen),
+        SendEventStream(stream) =>
+            write_server_framed_response(writer, config, &stream.response(), EVENT_STREAM_FRAMING, close).map(|_| Streaming(stream)),
+        Next => write_raw_response(writer, NOT_FOUND_RESPONSE, close).map(|_| KeepOpen)
+    }
+}
+
+/// Writes the given raw response bytes and flushes. If close is true, then a "connection: close" header is added
+/// after the status line.
+fn write_raw_response(writer: &mut impl Write, response: &[u8], close: bool) -> std::io::Result<()> {
+    if close {
+        let status_line_end = response.windows(2).position(|window| window == b"\r\n").map_or(0, |i| i + 2);
+        writer.write_all(&response[..status_line_end])?;
+        write!(writer, "{}: close\r\n", CONNECTION)?;
+        writer.write_all(&response[status_line_end..])?;
+    } else {
+        writer.write_all(response)?;
+    }
+    writer.flush()
+}
+
+/// Writes the response along with a date header, the configured server header, and a "connection: close" header if close is true.
+/// Headers already present in the response are not overridden. If the response has invalid framing, then a 500 response is
+/// written instead.
+fn write_server_response(writer: &mut impl Write, config: &Config, request: &Request, response: &Response, close: bool) -> std::io::Result<()> {
+    let framing = match response_framing(response, request.method == Method::HEAD) {
+        Ok(framing) => framing,
+        Err(error) => {
+            println!("Error: {}", error);
+            return write_raw_response(writer, INTERNAL_SERVER_ERROR_RESPONSE, close);
+        }
+    };
+
+    write_server_framed_response(writer, config, response, framing, close)
+}
+
+/// Like write_server_response, but writes the response using the given framing.
+fn write_server_framed_response(writer: &mut impl Write, config: &Config, response: &Response, framing: Framing, close: bool) -> std::io::Result<()> {
+    with_current_date(|date| {
+        let mut extra_headers: Vec<(&Header, &str)> = Vec::with_capacity(3);
+        if !response.headers.contains_key(&DATE) {
+            extra_headers.push((&DATE, date));
+        }
+        if let Some(server) = &config.server_header {
+            if !response.headers.contains_key(&SERVER) {
+                extra_headers.push((&SERVER, server));
+            }
+        }
+        if close && !response.headers.contains_header_value(&CONNECTION, "close") {
+            extra_headers.push((&CONNECTION, "close"));
+        }
+        write_framed_response(writer, response, framing, &extra_headers)
+    })
+}
+
+/// Response sink that writes informational responses to the wrapped writer.
+struct InformationalWriter<'a, W: Write>(&'a mut W);
+
+impl<W: Write> ResponseSink for InformationalWriter<'_, W> {
+    fn send_informational(&mut self, response: &Response) -> std::io::Result<()> {
+        // 101 switching protocols is a final response for the connection, so it can not be sent as an informational response
+        let code = response.status.code;
+        if !(100..200).contains(&code) || code == 101 {
+            return Err(IoError::new(ErrorKind::InvalidInput, format!("{} is not an informational status", code)));
+        }
+        write_response(self.0, response)
     }
 }
 
@@ -138,45 +581,108 @@ fn should_close_after_response(request: &Request) -> bool {
 }
 
 /// Writes the response as bytes to the given writer.
+/// A content-length header is added if the response does not have one, and the body is chunk encoded if the response has a
+/// chunked transfer-encoding header. The body is omitted for 1xx, 204, and 304 responses.
+/// Returns an InvalidData error without writing anything if the framing headers of the response conflict with its body.
 pub fn write_response(writer: &mut impl Write, response: &Response) -> std::io::Result<()> {
+    let framing = response_framing(response, false).map_err(|error| IoError::new(ErrorKind::InvalidData, error))?;
+    write_framed_response(writer, response, framing, &[])
+}
+
+/// Writes the response as bytes to the given writer using the given framing. The extra headers are written before the response's own headers.
+fn write_framed_response(writer: &mut impl Write, response: &Response, framing: Framing, extra_headers: &[(&Header, &str)]) -> std::io::Result<()> {
     // write! will call write multiple times and does not flush
     write!(writer, "{} {} {}\r\n", HTTP_VERSION_1_1, response.status.code, response.status.reason)?;
+    for (header, value) in extra_headers {
+        write!(writer, "{}: {}\r\n", header, value)?;
+    }
+    if let Some(content_length) = framing.add_content_length {
+        write!(writer, "{}: {}\r\n", CONTENT_LENGTH, content_length)?;
+    }
     for (header, values) in response.headers.iter() {
+        if !framing.write_framing_headers && (*header == CONTENT_LENGTH || *header == TRANSFER_ENCODING) {
+            continue;
+        }
         for value in values {
             write!(writer, "{}: {}\r\n", header, value)?;
         }
     }
-    writer.write(b"\r\n")?;
-    writer.write(&response.body)?;
+    writer.write_all(b"\r\n")?;
+    match framing.body {
+        BodyFraming::Omitted => {}
+        BodyFraming::Length => writer.write_all(&response.body)?,
+        BodyFraming::Chunked => {
+            let mut chunked_writer = ChunkedWriter::new(&mut *writer);
+            chunked_writer.write_all(&response.body)?;
+            chunked_writer.finish()?;
+        }
+    }
     writer.flush()?;
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use std::cell::RefCell;
     use std::collections::HashMap;
-    use std::io::BufReader;
+    use std::io::{BufReader, ErrorKind, Read};
+    use std::rc::Rc;
     use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
 
-    use crate::common::header::{CONNECTION, CONTENT_LENGTH, CONTENT_TYPE, Header, HeaderMap, HeaderMapOps};
+    use crate::common::date::parse_http_date;
+    use crate::common::header::{CONNECTION, CONTENT_LENGTH, CONTENT_TYPE, DATE, Header, HeaderMap, HeaderMapOps, SERVER, TRANSFER_ENCODING};
     use crate::common::method::Method;
     use crate::common::request::Request;
     use crate::common::response::Response;
     use crate::common::status;
     use crate::common::status::Status;
-    use crate::server::connection::Connection;
-    use crate::server::router::ListenerResult::SendResponse;
+    use crate::header_map;
+    use crate::server::config::{Config, ContinueResult, MinDataRate, Timeouts};
+    use crate::server::connection::{Connection, Waiting};
+    use crate::server::router::ListenerResult::{SendResponse, Upgrade};
     use crate::server::router::Router;
-    use crate::server::server::{respond_to_requests, write_response};
+    use crate::server::event_stream::{EventSender, Waker};
+    use crate::server::server::{respond_to_requests, serve_connection, time_out, write_raw_response, write_response};
+    use crate::server::server::ConnectionOutcome::{Close, KeepOpen, Offloaded, Streaming, Upgraded};
+    use crate::server::upgrade::Upgrade as ConnectionUpgrade;
+    use crate::sse::Event;
     use crate::util::mock::{MockReader, MockStream, MockWriter};
 
-    fn test_respond_to_requests(input: Vec<&str>, responses: Vec<Response>, expected_requests: Vec<Request>, expected_output: &str) {
-        let reader = MockReader::from_strs(input);
+    type MockConnection = Connection<MockStream<BufReader<MockReader>, MockWriter>>;
+
+    /// The flushed output of a mock connection.
+    type Flushed = Rc<RefCell<Vec<Vec<u8>>>>;
+
+    /// Creates a connection that reads the given input and the flushed output of the connection.
+    /// If would_block is true, then the connection blocks after the input instead of reaching EOF.
+    fn mock_connection(input: Vec<&str>, would_block: bool) -> (MockConnection, Rc<RefCell<Vec<Vec<u8>>>>) {
+        let mut reader = MockReader::from_strs(input);
+        reader.return_would_block_when_empty = would_block;
         let reader = BufReader::new(reader);
         let writer = MockWriter::new();
         let flushed = writer.flushed.clone();
         let stream = MockStream::new(reader, writer);
 
+        (Connection::new("0.0.0.0:80".parse().unwrap(), stream), flushed)
+    }
+
+    /// Runs respond_to_requests with the given input and config and returns all of the output.
+    fn respond_to_input(input: Vec<&str>, config: &Config) -> String {
+        let (mut connection, flushed) = mock_connection(input, false);
+
+        respond_to_requests(&mut connection, config, false);
+
+        let output = flushed.borrow().concat();
+        String::from_utf8(output).unwrap()
+    }
+
+    /// Removes date header lines from the given output, since their values change every second.
+    fn remove_date_headers(output: &str) -> String {
+        output.split_inclusive("\r\n").filter(|line| !line.starts_with("date: ")).collect()
+    }
+
+    fn test_respond_to_requests(input: Vec<&str>, responses: Vec<Response>, expected_requests: Vec<Request>, expected_output: &str) {
         let mut router = Router::new();
 
         let actual_requests = Arc::new(Mutex::new(vec![]));
@@ -188,12 +694,8 @@ mod tests {
             SendResponse(responses.lock().unwrap().remove(0))
         });
 
-        let mut connection = Connection::new("0.0.0.0:80".parse().unwrap(), stream);
-
-        respond_to_requests(&mut connection, &router);
-
-        let actual_output = flushed.borrow().concat();
-        let actual_output = String::from_utf8(actual_output).unwrap();
+        let actual_output = respond_to_input(input, &Config { router, ..Default::default() });
+        let actual_output = remove_date_headers(&actual_output);
 
         assert_eq!(expected_output, actual_output);
         assert_eq!(expected_requests, actual_requests.lock().unwrap().to_vec());
@@ -672,8 +1174,8 @@ mod tests {
         let response_bytes_as_string = String::from_utf8_lossy(&bytes);
 
         assert!(
-            response_bytes_as_string.eq("HTTP/1.1 200 OK\r\ncontent-type: hello\r\nconnection: bye\r\n\r\nthe body")
-                || response_bytes_as_string.eq("HTTP/1.1 200 OK\r\nconnection: bye\r\ncontent-type: hello\r\n\r\nthe body")
+            response_bytes_as_string.eq("HTTP/1.1 200 OK\r\ncontent-length: 8\r\ncontent-type: hello\r\nconnection: bye\r\n\r\nthe body")
+                || response_bytes_as_string.eq("HTTP/1.1 200 OK\r\ncontent-length: 8\r\nconnection: bye\r\ncontent-type: hello\r\n\r\nthe body")
         )
     }
 
@@ -686,7 +1188,7 @@ mod tests {
         };
         let mut buf: Vec<u8> = vec![];
         write_response(&mut buf, &response).unwrap();
-        assert_eq!(String::from_utf8_lossy(&buf), "HTTP/1.1 200 OK\r\n\r\n")
+        assert_eq!(String::from_utf8_lossy(&buf), "HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
     }
 
     #[test]
@@ -700,6 +1202,653 @@ mod tests {
         };
         let mut buf: Vec<u8> = vec![];
         write_response(&mut buf, &response).unwrap();
-        assert_eq!(String::from_utf8_lossy(&buf), "HTTP/1.1 200 OK\r\ncustom header: header value\r\n\r\n")
+        assert_eq!(String::from_utf8_lossy(&buf), "HTTP/1.1 200 OK\r\ncontent-length: 0\r\ncustom header: header value\r\n\r\n")
+    }
+
+    #[test]
+    fn date_header_added() {
+        let mut router = Router::new();
+        router.on_prefix("", |_, _| SendResponse(status::OK.into()));
+
+        let output = respond_to_input(vec!["GET / HTTP/1.1\r\n\r\n"], &Config { router, ..Default::default() });
+
+        let date = output.split("\r\n").find_map(|line| line.strip_prefix("date: ")).unwrap();
+        assert!(parse_http_date(date).is_some());
+        assert!(!output.contains("server: "));
+    }
+
+    #[test]
+    fn date_header_from_response_not_overridden() {
+        let mut router = Router::new();
+        router.on_prefix("", |_, _| SendResponse(Response {
+            status: status::OK,
+            headers: header_map![(DATE, "Sun, 06 Nov 1994 08:49:37 GMT")],
+            body: vec![],
+        }));
+
+        let output = respond_to_input(vec!["GET / HTTP/1.1\r\n\r\n"], &Config { router, ..Default::default() });
+
+        assert_eq!(output, "HTTP/1.1 200 OK\r\ncontent-length: 0\r\ndate: Sun, 06 Nov 1994 08:49:37 GMT\r\n\r\n");
+    }
+
+    #[test]
+    fn server_header_added() {
+        let mut router = Router::new();
+        router.on_prefix("", |_, _| SendResponse(Response { status: status::OK, headers: HeaderMap::new(), body: vec![] }));
+
+        let config = Config { router, server_header: Some("my_http".to_string()), ..Default::default() };
+        let output = respond_to_input(vec!["GET / HTTP/1.1\r\n\r\n"], &config);
+
+        assert_eq!(remove_date_headers(&output), "HTTP/1.1 200 OK\r\nserver: my_http\r\ncontent-length: 0\r\n\r\n");
+    }
+
+    #[test]
+    fn server_header_from_response_not_overridden() {
+        let mut router = Router::new();
+        router.on_prefix("", |_, _| SendResponse(Response { status: status::OK, headers: header_map![(SERVER, "custom")], body: vec![] }));
+
+        let config = Config { router, server_header: Some("my_http".to_string()), ..Default::default() };
+        let output = respond_to_input(vec!["GET / HTTP/1.1\r\n\r\n"], &config);
+
+        assert_eq!(remove_date_headers(&output), "HTTP/1.1 200 OK\r\ncontent-length: 0\r\nserver: custom\r\n\r\n");
+    }
+
+    #[test]
+    fn write_response_chunked() {
+        let response = Response {
+            status: status::OK,
+            headers: header_map![(TRANSFER_ENCODING, "chunked")],
+            body: b"the body".to_vec(),
+        };
+        let mut buf: Vec<u8> = vec![];
+        write_response(&mut buf, &response).unwrap();
+        assert_eq!(String::from_utf8_lossy(&buf), "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n8\r\nthe body\r\n0\r\n\r\n")
+    }
+
+    #[test]
+    fn write_response_no_content_omits_body_and_length() {
+        let response = Response {
+            status: status::NO_CONTENT,
+            headers: header_map![(CONTENT_LENGTH, "8")],
+            body: b"the body".to_vec(),
+        };
+        let mut buf: Vec<u8> = vec![];
+        write_response(&mut buf, &response).unwrap();
+        assert_eq!(String::from_utf8_lossy(&buf), "HTTP/1.1 204 NO CONTENT\r\n\r\n")
+    }
+
+    #[test]
+    fn write_response_conflicting_content_length() {
+        let response = Response {
+            status: status::OK,
+            headers: header_map![(CONTENT_LENGTH, "3")],
+            body: b"the body".to_vec(),
+        };
+        let mut buf: Vec<u8> = vec![];
+        let error = write_response(&mut buf, &response).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn head_request_omits_body() {
+        let mut router = Router::new();
+        router.on_prefix("", |_, _| SendResponse("the body".into()));
+
+        let output = respond_to_input(vec!["HEAD / HTTP/1.1\r\n\r\n"], &Config { router, ..Default::default() });
+
+        assert_eq!(remove_date_headers(&output), "HTTP/1.1 200 OK\r\ncontent-length: 8\r\n\r\n");
+    }
+
+    #[test]
+    fn conflicting_content_length_from_router() {
+        let mut router = Router::new();
+        router.on_prefix("", |_, _| SendResponse(Response {
+            status: status::OK,
+            headers: header_map![(CONTENT_LENGTH, "3")],
+            body: b"the body".to_vec(),
+        }));
+
+        let output = respond_to_input(vec!["GET / HTTP/1.1\r\n\r\n", "GET / HTTP/1.1\r\n\r\n"], &Config { router, ..Default::default() });
+
+        assert_eq!(output, "HTTP/1.1 500 Internal Server Error\r\ncontent-length: 0\r\n\r\n".repeat(2));
+    }
+
+    #[test]
+    fn conflicting_content_length_from_router_on_last_request() {
+        let mut router = Router::new();
+        router.on_prefix("", |_, _| SendResponse(Response {
+            status: status::OK,
+            headers: header_map![(CONTENT_LENGTH, "3")],
+            body: b"the body".to_vec(),
+        }));
+
+        let config = Config { router, max_requests_per_connection: Some(1), ..Default::default() };
+        let output = respond_to_input(vec!["GET / HTTP/1.1\r\n\r\n"], &config);
+
+        assert_eq!(output, "HTTP/1.1 500 Internal Server Error\r\nconnection: close\r\ncontent-length: 0\r\n\r\n");
+    }
+
+    #[test]
+    fn blocking_listeners_are_left_for_thread_pool() {
+        let mut router = Router::new();
+        router.on("/blocking", |_, _| SendResponse(Response::text("blocking")));
+        router.on_inline("/inline", |_, _| SendResponse(Response::text("inline")));
+        let config = Config { router, ..Default::default() };
+
+        let (mut connection, flushed) = mock_connection(vec!["GET /inline HTTP/1.1\r\n\r\n", "GET /blocking HTTP/1.1\r\n\r\n"], true);
+
+        assert!(matches!(respond_to_requests(&mut connection, &config, true), Offloaded));
+        assert!(connection.offloaded.is_some());
+        assert!(String::from_utf8(flushed.borrow().concat()).unwrap().ends_with("inline"));
+
+        assert!(matches!(respond_to_requests(&mut connection, &config, false), KeepOpen));
+        assert!(connection.offloaded.is_none());
+        assert!(String::from_utf8(flushed.borrow().concat()).unwrap().ends_with("blocking"));
+    }
+
+    #[test]
+    fn expect_continue_ignored_for_http_1_0() {
+        let (mut connection, flushed) = mock_connection(vec!["POST / HTTP/1.0\r\ncontent-length: 5\r\nexpect: 100-continue\r\n\r\n"], true);
+        let config = Config { continue_listener: Some(Box::new(|_| panic!("called continue listener"))), ..Default::default() };
+
+        assert!(matches!(respond_to_requests(&mut connection, &config, false), KeepOpen));
+
+        assert!(flushed.borrow().is_empty());
+    }
+
+    #[test]
+    fn expect_continue() {
+        let (mut connection, flushed) = mock_connection(vec!["POST / HTTP/1.1\r\ncontent-length: 5\r\nexpect: 100-continue\r\n\r\n"], true);
+        let config = Config::default();
+
+        assert!(matches!(respond_to_requests(&mut connection, &config, false), KeepOpen));
+        assert!(matches!(respond_to_requests(&mut connection, &config, false), KeepOpen));
+
+        assert_eq!(String::from_utf8(flushed.borrow().concat()).unwrap(), "HTTP/1.1 100 Continue\r\n\r\n");
+    }
+
+    #[test]
+    fn expect_continue_with_body_already_sent() {
+        let mut router = Router::new();
+        router.on_prefix("", |_, request| SendResponse(request.body.clone().into()));
+
+        let output = respond_to_input(
+            vec!["POST / HTTP/1.1\r\ncontent-length: 5\r\nexpect: 100-continue\r\n\r\nhello"],
+            &Config { router, ..Default::default() });
+
+        assert!(!output.contains("100 Continue"));
+        assert!(output.ends_with("\r\n\r\nhello"));
+    }
+
+    #[test]
+    fn expect_continue_listener() {
+        let config = Config {
+            continue_listener: Some(Box::new(|request| {
+                assert_eq!(request.uri, "/upload");
+                assert!(request.body.is_empty());
+                ContinueResult::Continue
+            })),
+            ..Default::default()
+        };
+        let (mut connection, flushed) = mock_connection(vec!["POST /upload HTTP/1.1\r\ncontent-length: 5\r\nexpect: 100-continue\r\n\r\n"], true);
+
+        assert!(matches!(respond_to_requests(&mut connection, &config, false), KeepOpen));
+
+        assert_eq!(String::from_utf8(flushed.borrow().concat()).unwrap(), "HTTP/1.1 100 Continue\r\n\r\n");
+    }
+
+    #[test]
+    fn expect_continue_rejected() {
+        let config = Config {
+            continue_listener: Some(Box::new(|_| ContinueResult::Reject(Response::empty(status::PAYLOAD_TOO_LARGE)))),
+            ..Default::default()
+        };
+        let (mut connection, flushed) = mock_connection(vec!["POST / HTTP/1.1\r\ncontent-length: 5000\r\nexpect: 100-continue\r\n\r\n"], true);
+
+        assert!(matches!(respond_to_requests(&mut connection, &config, false), Close));
+
+        let output = String::from_utf8(flushed.borrow().concat()).unwrap();
+        assert_eq!(remove_date_headers(&output), "HTTP/1.1 413 PAYLOAD TOO LARGE\r\nconnection: close\r\ncontent-length: 0\r\n\r\n");
+    }
+
+    #[test]
+    fn panicking_listener() {
+        let mut router = Router::new();
+        router.on_prefix("", |_, _| panic!("oh no"));
+        let config = Config { router, ..Default::default() };
+        let (mut connection, flushed) = mock_connection(vec!["GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n"], true);
+
+        assert!(matches!(respond_to_requests(&mut connection, &config, false), Close));
+
+        // the connection is closed after the first request, since the listener may have left it in an invalid state
+        let output = String::from_utf8(flushed.borrow().concat()).unwrap();
+        assert_eq!(output, "HTTP/1.1 500 Internal Server Error\r\nconnection: close\r\ncontent-length: 0\r\n\r\n");
+    }
+
+    #[test]
+    fn panicking_continue_listener() {
+        let config = Config {
+            continue_listener: Some(Box::new(|_| panic!("oh no"))),
+            ..Default::default()
+        };
+        let (mut connection, flushed) = mock_connection(vec!["POST / HTTP/1.1\r\ncontent-length: 5\r\nexpect: 100-continue\r\n\r\n"], true);
+
+        assert!(matches!(respond_to_requests(&mut connection, &config, false), Close));
+
+        let output = String::from_utf8(flushed.borrow().concat()).unwrap();
+        assert_eq!(remove_date_headers(&output), "HTTP/1.1 500 INTERNAL SERVER ERROR\r\nconnection: close\r\ncontent-length: 0\r\n\r\n");
+    }
+
+    #[test]
+    fn informational_responses() {
+        let mut router = Router::new();
+        router.on_prefix_with_sink("", |_, _, sink| {
+            sink.send_informational(&Response::empty(status::PROCESSING)).unwrap();
+            sink.send_informational(&Response {
+                status: status::EARLY_HINTS,
+                headers: header_map![("link", "</style.css>; rel=preload; as=style")],
+                body: vec![],
+            }).unwrap();
+            SendResponse(Response::empty(status::OK))
+        });
+
+        let output = respond_to_input(vec!["GET / HTTP/1.1\r\n\r\n"], &Config { router, ..Default::default() });
+
+        assert_eq!(remove_date_headers(&output), "HTTP/1.1 102 PROCESSING\r\n\r\n\
+            HTTP/1.1 103 EARLY HINTS\r\nlink: </style.css>; rel=preload; as=style\r\n\r\n\
+            HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n");
+    }
+
+    #[test]
+    fn informational_responses_must_be_1xx() {
+        let mut router = Router::new();
+        router.on_prefix_with_sink("", |_, _, sink| {
+            assert_eq!(sink.send_informational(&Response::empty(status::OK)).unwrap_err().kind(), ErrorKind::InvalidInput);
+            assert_eq!(sink.send_informational(&Response::empty(status::SWITCHING_PROTOCOLS)).unwrap_err().kind(), ErrorKind::InvalidInput);
+            SendResponse(Response::empty(status::OK))
+        });
+
+        let output = respond_to_input(vec!["GET / HTTP/1.1\r\n\r\n"], &Config { router, ..Default::default() });
+
+        assert_eq!(remove_date_headers(&output), "HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n");
+    }
+
+    #[test]
+    fn connection_info() {
+        let mut router = Router::new();
+        router.on_prefix_with_context("", |_, _, context| {
+            let info = context.connection().unwrap();
+            assert!(info.tls.is_none());
+            SendResponse(Response::text(format!("{} {}", info.peer_addr, info.local_addr)))
+        });
+
+        let output = respond_to_input(vec!["GET / HTTP/1.1\r\n\r\n"], &Config { router, ..Default::default() });
+
+        assert!(output.ends_with("\r\n\r\n0.0.0.0:80 127.0.0.1:80"));
+    }
+
+    #[test]
+    fn upgrade() {
+        let mut router = Router::new();
+        router.on("/chat", |_, _| Upgrade(ConnectionUpgrade::new("chat", |_| {})));
+
+        let (mut connection, flushed) = mock_connection(vec!["GET /chat HTTP/1.1\r\nupgrade: chat\r\nconnection: upgrade\r\n\r\nhello", "GET / HTTP/1.1\r\n\r\n"], false);
+
+        assert!(matches!(respond_to_requests(&mut connection, &Config { router, ..Default::default() }, false), Upgraded(_)));
+
+        let output = String::from_utf8(flushed.borrow().concat()).unwrap();
+        let output = remove_date_headers(&output);
+        assert!(output == "HTTP/1.1 101 SWITCHING PROTOCOLS\r\nconnection: upgrade\r\nupgrade: chat\r\n\r\n"
+            || output == "HTTP/1.1 101 SWITCHING PROTOCOLS\r\nupgrade: chat\r\nconnection: upgrade\r\n\r\n");
+
+        // data after the upgrade request is left in the stream for the handler
+        let mut leftover = String::new();
+        connection.into_stream().read_to_string(&mut leftover).unwrap();
+        assert_eq!(leftover, "helloGET / HTTP/1.1\r\n\r\n");
+    }
+
+    #[test]
+    fn upgrade_over_limit() {
+        let mut router = Router::new();
+        router.on("/chat", |_, _| Upgrade(ConnectionUpgrade::new("chat", |_| {})));
+        let config = Config { router, max_upgraded_connections: 1, ..Default::default() };
+        let upgrade_request = "GET /chat HTTP/1.1\r\nupgrade: chat\r\nconnection: upgrade\r\n\r\n";
+
+        let (mut first, _) = mock_connection(vec![upgrade_request], false);
+        let handler = match respond_to_requests(&mut first, &config, false) {
+            Upgraded(handler) => handler,
+            _ => panic!("expected an upgrade"),
+        };
+        assert_eq!(config.upgraded_connection_counter.get(), 1);
+
+        let (mut second, flushed) = mock_connection(vec![upgrade_request], false);
+        assert!(matches!(respond_to_requests(&mut second, &config, false), Close));
+        assert_eq!(String::from_utf8(flushed.borrow().concat()).unwrap(), "HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\n\r\n");
+
+        // the upgraded connection is no longer counted once its handler is done
+        drop(handler);
+        assert_eq!(config.upgraded_connection_counter.get(), 0);
+        let (mut third, _) = mock_connection(vec![upgrade_request], false);
+        assert!(matches!(respond_to_requests(&mut third, &config, false), Upgraded(_)));
+    }
+
+    #[test]
+    fn upgrade_without_switching_protocols_status() {
+        let mut router = Router::new();
+        router.on("/chat", |_, _| Upgrade(ConnectionUpgrade::with_response(Response::empty(status::OK), |_| {})));
+
+        let output = respond_to_input(vec!["GET /chat HTTP/1.1\r\n\r\n"], &Config { router, ..Default::default() });
+
+        assert_eq!(output, "HTTP/1.1 500 Internal Server Error\r\ncontent-length: 0\r\n\r\n");
+    }
+
+    /// Creates a waker that does nothing, for connections whose event streams are served manually.
+    fn no_waker() -> Waker {
+        Arc::new(|| {})
+    }
+
+    fn start_event_stream(input: Vec<&str>, would_block: bool) -> (MockConnection, Flushed, EventSender, Config) {
+        let senders = Arc::new(Mutex::new(vec![]));
+        let new_senders = senders.clone();
+        let mut router = Router::new();
+        router.event_stream("/events", move |_, sender| new_senders.lock().unwrap().push(sender));
+        let config = Config { router, ..Default::default() };
+
+        let (mut connection, flushed) = mock_connection(input, would_block);
+        match respond_to_requests(&mut connection, &config, false) {
+            Streaming(stream) => connection.event_stream = Some(stream),
+            _ => panic!("expected an event stream"),
+        }
+
+        let sender = senders.lock().unwrap().pop().unwrap();
+        (connection, flushed, sender, config)
+    }
+
+    #[test]
+    fn event_stream() {
+        let (mut connection, flushed, sender, config) = start_event_stream(vec!["GET /events HTTP/1.1\r\nlast-event-id: 3\r\n\r\n"], true);
+
+        let head = String::from_utf8(flushed.borrow().concat()).unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains("content-type: text/event-stream\r\n"));
+        assert!(head.contains("transfer-encoding: chunked\r\n"));
+        assert!(head.ends_with("\r\n\r\n"));
+        assert_eq!(sender.last_event_id(), Some("3"));
+        flushed.borrow_mut().clear();
+
+        sender.send(&Event::new("hello").with_id("4")).unwrap();
+        assert!(matches!(serve_connection(&mut connection, &config, false, false, &no_waker), KeepOpen));
+        assert_eq!(String::from_utf8(flushed.borrow().concat()).unwrap(), "13\r\nid: 4\ndata: hello\n\n\r\n");
+        flushed.borrow_mut().clear();
+
+        // the stream ends with the last chunk once the sender is dropped
+        drop(sender);
+        assert!(matches!(serve_connection(&mut connection, &config, false, false, &no_waker), KeepOpen));
+        assert_eq!(String::from_utf8(flushed.borrow().concat()).unwrap(), "0\r\n\r\n");
+        assert!(connection.event_stream.is_none());
+    }
+
+    #[test]
+    fn event_stream_waits_for_sent_data() {
+        let (mut connection, flushed, sender, config) = start_event_stream(vec!["GET /events HTTP/1.1\r\n\r\n"], true);
+        flushed.borrow_mut().clear();
+
+        // events stay in the stream while earlier data is still being sent
+        sender.send(&Event::new("hello")).unwrap();
+        assert!(matches!(serve_connection(&mut connection, &config, true, false, &no_waker), KeepOpen));
+        assert!(flushed.borrow().is_empty());
+
+        assert!(matches!(serve_connection(&mut connection, &config, false, false, &no_waker), KeepOpen));
+        assert_eq!(String::from_utf8(flushed.borrow().concat()).unwrap(), "d\r\ndata: hello\n\n\r\n");
+    }
+
+    #[test]
+    fn event_stream_then_next_request() {
+        let input = vec!["GET /events HTTP/1.1\r\n\r\n", "GET /events HTTP/1.1\r\nconnection: close\r\n\r\n"];
+        let (mut connection, _, sender, config) = start_event_stream(input, true);
+        drop(sender);
+
+        // requests sent while the stream is open are discarded
+        assert!(matches!(serve_connection(&mut connection, &config, false, false, &no_waker), KeepOpen));
+        assert!(connection.event_stream.is_none());
+    }
+
+    #[test]
+    fn event_stream_closed_by_client() {
+        let (mut connection, _, sender, config) = start_event_stream(vec!["GET /events HTTP/1.1\r\n\r\n"], false);
+
+        assert!(matches!(serve_connection(&mut connection, &config, false, false, &no_waker), Close));
+        drop(connection);
+        assert!(sender.is_closed());
+    }
+
+    #[test]
+    fn event_stream_closes_connection_after_end() {
+        let (mut connection, flushed, sender, config) = start_event_stream(vec!["GET /events HTTP/1.1\r\nconnection: close\r\n\r\n"], true);
+        flushed.borrow_mut().clear();
+        drop(sender);
+
+        assert!(matches!(serve_connection(&mut connection, &config, false, false, &no_waker), Close));
+        assert_eq!(String::from_utf8(flushed.borrow().concat()).unwrap(), "0\r\n\r\n");
+    }
+
+    #[test]
+    fn event_stream_head_request() {
+        let senders = Arc::new(Mutex::new(vec![]));
+        let new_senders = senders.clone();
+        let mut router = Router::new();
+        router.event_stream("/events", move |_, sender| new_senders.lock().unwrap().push(sender));
+
+        let output = respond_to_input(vec!["HEAD /events HTTP/1.1\r\n\r\n"], &Config { router, ..Default::default() });
+
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(output.ends_with("\r\n\r\n"));
+        assert!(senders.lock().unwrap()[0].is_closed());
+    }
+
+    #[test]
+    #[cfg(feature = "http2")]
+    fn http2_preface() {
+        let preface = std::str::from_utf8(crate::http2::PREFACE).unwrap();
+        let (mut connection, flushed) = mock_connection(vec![&preface[..10], &preface[10..], "\0\0\0\x04\0\0\0\0\0"], true);
+
+        assert!(matches!(serve_connection(&mut connection, &Config::default(), false, false, &no_waker), KeepOpen));
+
+        // the server sends its settings and acknowledges the settings of the client
+        let output = flushed.borrow().concat();
+        assert_eq!(&output[3..5], &[0x4, 0x0]);
+        assert!(output.ends_with(&[0, 0, 0, 0x4, 0x1, 0, 0, 0, 0]));
+    }
+
+    #[test]
+    #[cfg(feature = "http2")]
+    fn partial_preface_then_http1_request() {
+        let (mut connection, flushed) = mock_connection(vec!["P", "UT / HTTP/1.1\r\ncontent-length: 0\r\n\r\n"], true);
+        assert!(matches!(serve_connection(&mut connection, &Config::default(), false, false, &no_waker), KeepOpen));
+        assert!(matches!(serve_connection(&mut connection, &Config::default(), false, false, &no_waker), KeepOpen));
+
+        let output = String::from_utf8(flushed.borrow().concat()).unwrap();
+        assert_eq!(output, "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n");
+    }
+
+    #[test]
+    #[cfg(feature = "http2")]
+    fn partial_preface_then_invalid_request() {
+        let (mut connection, flushed) = mock_connection(vec!["PRI * HTTP/2.0\r\n", "\r\nXY"], true);
+
+        assert!(matches!(serve_connection(&mut connection, &Config::default(), false, false, &no_waker), Close));
+
+        let output = String::from_utf8(flushed.borrow().concat()).unwrap();
+        assert_eq!(output, "HTTP/1.1 400 Bad Request\r\n\r\n");
+    }
+
+    #[test]
+    fn waiting_for_next_request() {
+        let (mut connection, _) = mock_connection(vec![], true);
+        let opened = match connection.update_waiting(None, Instant::now()) {
+            Waiting::Request(since) => since,
+            waiting => panic!("unexpected waiting: {:?}", waiting),
+        };
+        assert_eq!(connection.update_waiting(None, opened + Duration::from_secs(5)), Waiting::Request(opened));
+    }
+
+    #[test]
+    fn waiting_for_head() {
+        let (mut connection, _) = mock_connection(vec!["GET / HTTP/1.1\r\nhost: "], true);
+        assert!(matches!(respond_to_requests(&mut connection, &Config::default(), false), KeepOpen));
+
+        let now = Instant::now();
+        assert_eq!(connection.update_waiting(None, now), Waiting::Head(now));
+        assert_eq!(connection.update_waiting(None, now + Duration::from_secs(1)), Waiting::Head(now));
+    }
+
+    #[test]
+    fn waiting_for_body() {
+        let (mut connection, _) = mock_connection(vec!["POST / HTTP/1.1\r\ncontent-length: 10\r\n\r\n0123"], true);
+        assert!(matches!(respond_to_requests(&mut connection, &Config::default(), false), KeepOpen));
+
+        let now = Instant::now();
+        assert_eq!(connection.update_waiting(None, now), Waiting::Body(now, 4));
+    }
+
+    #[test]
+    fn waiting_for_next_request_after_response() {
+        let (mut connection, _) = mock_connection(vec!["GET / HTTP/1.1\r\n\r\n"], true);
+        let opened = Instant::now();
+        connection.update_waiting(None, opened);
+        assert!(matches!(respond_to_requests(&mut connection, &Config::default(), false), KeepOpen));
+
+        let now = opened + Duration::from_secs(1);
+        assert_eq!(connection.update_waiting(None, now), Waiting::Request(now));
+    }
+
+    #[test]
+    fn waiting_to_write() {
+        let (mut connection, _) = mock_connection(vec!["GET / HTTP/1.1\r\nhost: "], true);
+        assert!(matches!(respond_to_requests(&mut connection, &Config::default(), false), KeepOpen));
+
+        let start = Instant::now();
+        assert_eq!(connection.update_waiting(Some(100), start), Waiting::Write(start, 100));
+        // the time is kept until more of the data has been sent
+        assert_eq!(connection.update_waiting(Some(100), start + Duration::from_secs(1)), Waiting::Write(start, 100));
+        let sent = start + Duration::from_secs(2);
+        assert_eq!(connection.update_waiting(Some(50), sent), Waiting::Write(sent, 50));
+        // the client is waited on again once the data is sent
+        let done = start + Duration::from_secs(3);
+        assert_eq!(connection.update_waiting(None, done), Waiting::Head(done));
+    }
+
+    #[test]
+    fn not_waiting_while_streaming_events() {
+        let (mut connection, _, _sender, _) = start_event_stream(vec!["GET /events HTTP/1.1\r\n\r\n"], true);
+        assert_eq!(connection.update_waiting(None, Instant::now()), Waiting::Nothing);
+    }
+
+    #[test]
+    fn waiting_deadlines() {
+        let timeouts = Timeouts {
+            keep_alive: Some(Duration::from_secs(60)),
+            header_read: Some(Duration::from_secs(10)),
+            min_body_rate: Some(MinDataRate { bytes_per_second: 100, grace_period: Duration::from_secs(5) }),
+            write: Some(Duration::from_secs(30)),
+        };
+        let since = Instant::now();
+
+        assert_eq!(Waiting::Nothing.deadline(&timeouts), None);
+        assert_eq!(Waiting::Request(since).deadline(&timeouts), Some(since + Duration::from_secs(60)));
+        assert_eq!(Waiting::Head(since).deadline(&timeouts), Some(since + Duration::from_secs(10)));
+        assert_eq!(Waiting::Body(since, 0).deadline(&timeouts), Some(since + Duration::from_secs(5)));
+        assert_eq!(Waiting::Body(since, 250).deadline(&timeouts), Some(since + Duration::from_millis(7500)));
+        assert_eq!(Waiting::Write(since, 10).deadline(&timeouts), Some(since + Duration::from_secs(30)));
+
+        let no_timeouts = Timeouts { keep_alive: None, header_read: None, min_body_rate: None, write: None };
+        assert_eq!(Waiting::Request(since).deadline(&no_timeouts), None);
+        assert_eq!(Waiting::Head(since).deadline(&no_timeouts), None);
+        assert_eq!(Waiting::Body(since, 0).deadline(&no_timeouts), None);
+        assert_eq!(Waiting::Write(since, 10).deadline(&no_timeouts), None);
+    }
+
+    #[test]
+    fn time_out_while_receiving_request() {
+        let mut output = vec![];
+        time_out(&mut output, Waiting::Head(Instant::now()));
+        assert_eq!(output, b"HTTP/1.1 408 Request Timeout\r\nconnection: close\r\ncontent-length: 0\r\n\r\n");
+
+        let mut output = vec![];
+        time_out(&mut output, Waiting::Body(Instant::now(), 10));
+        assert!(output.starts_with(b"HTTP/1.1 408 Request Timeout\r\n"));
+    }
+
+    #[test]
+    fn time_out_while_idle() {
+        let mut output = vec![];
+        time_out(&mut output, Waiting::Request(Instant::now()));
+        time_out(&mut output, Waiting::Write(Instant::now(), 10));
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn max_requests_per_connection() {
+        let mut router = Router::new();
+        router.on("/", |_, _| SendResponse(Response::empty(status::OK)));
+        let config = Config { router, max_requests_per_connection: Some(2), ..Default::default() };
+        let (mut connection, flushed) = mock_connection(vec!["GET / HTTP/1.1\r\n\r\n", "GET / HTTP/1.1\r\n\r\n", "GET / HTTP/1.1\r\n\r\n"], true);
+
+        assert!(matches!(respond_to_requests(&mut connection, &config, false), Close));
+
+        let output = remove_date_headers(&String::from_utf8(flushed.borrow().concat()).unwrap());
+        assert_eq!(output, "HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\nHTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: 0\r\n\r\n");
+    }
+
+    #[test]
+    fn max_requests_per_connection_not_found() {
+        let config = Config { max_requests_per_connection: Some(1), ..Default::default() };
+        let (mut connection, flushed) = mock_connection(vec!["GET / HTTP/1.1\r\n\r\n"], true);
+
+        assert!(matches!(respond_to_requests(&mut connection, &config, false), Close));
+
+        let output = String::from_utf8(flushed.borrow().concat()).unwrap();
+        assert_eq!(output, "HTTP/1.1 404 Not Found\r\nconnection: close\r\ncontent-length: 0\r\n\r\n");
+    }
+
+    #[test]
+    fn max_requests_per_connection_event_stream() {
+        let senders = Arc::new(Mutex::new(vec![]));
+        let new_senders = senders.clone();
+        let mut router = Router::new();
+        router.event_stream("/events", move |_, sender| new_senders.lock().unwrap().push(sender));
+        let config = Config { router, max_requests_per_connection: Some(1), ..Default::default() };
+        let (mut connection, flushed) = mock_connection(vec!["GET /events HTTP/1.1\r\n\r\n"], true);
+
+        match respond_to_requests(&mut connection, &config, false) {
+            Streaming(stream) => assert!(stream.close_after),
+            _ => panic!("expected an event stream"),
+        }
+        let output = String::from_utf8(flushed.borrow().concat()).unwrap();
+        assert!(output.contains("\r\nconnection: close\r\n"));
+    }
+
+    #[test]
+    fn closing_connection_reads_no_requests() {
+        let (mut connection, flushed) = mock_connection(vec!["GET / HTTP/1.1\r\n\r\n"], true);
+        connection.closing = true;
+        assert!(matches!(serve_connection(&mut connection, &Config::default(), false, false, &no_waker), KeepOpen));
+        assert!(flushed.borrow().concat().is_empty());
+
+        let (mut connection, _) = mock_connection(vec!["GET / HTTP/1.1\r\n\r\n"], false);
+        connection.closing = true;
+        assert!(matches!(serve_connection(&mut connection, &Config::default(), false, false, &no_waker), Close));
+    }
+
+    #[test]
+    fn raw_response_with_close() {
+        let mut output = vec![];
+        write_raw_response(&mut output, b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n", true).unwrap();
+        assert_eq!(output, b"HTTP/1.1 404 Not Found\r\nconnection: close\r\ncontent-length: 0\r\n\r\n");
+
+        let mut output = vec![];
+        write_raw_response(&mut output, b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n", false).unwrap();
+        assert_eq!(output, b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n");
     }
 }
@@ -0,0 +1,178 @@
+use std::time::{Duration, Instant};
+
+/// A hashed timer wheel, which stores values until their deadlines pass.
+/// Time is split into ticks, and each value is put in the slot for the first tick that starts at or after its deadline.
+/// Slots are reused every revolution of the wheel, so a slot may hold values for later revolutions, which stay in it
+/// until their deadline passes. Inserting is constant time, and expiring is proportional to the number of ticks that
+/// passed.
+pub struct TimerWheel<T> {
+    tick: Duration,
+    slots: Vec<Vec<(Instant, T)>>,
+    /// The time that the first tick started at.
+    start: Instant,
+    /// The number of the next tick to expire.
+    next_tick: u64,
+    len: usize,
+}
+
+impl<T> TimerWheel<T> {
+    /// Creates an empty wheel with the given number of slots, which are each the given tick long, starting at the given time.
+    pub fn new(tick: Duration, slots: usize, start: Instant) -> TimerWheel<T> {
+        assert!(slots > 0 && tick > Duration::from_secs(0));
+        TimerWheel { tick, slots: (0..slots).map(|_| vec![]).collect(), start, next_tick: 0, len: 0 }
+    }
+
+    /// Checks if the wheel has no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Inserts the given value, to be expired once the given deadline passes.
+    /// Values whose deadline has already passed are expired the next time the wheel is expired.
+    pub fn insert(&mut self, deadline: Instant, value: T) {
+        let ticks = self.ticks_until(deadline, true).max(self.next_tick);
+        let slot = self.slot(ticks);
+        self.slots[slot].push((deadline, value));
+        self.len += 1;
+    }
+
+    /// Removes the values whose deadline is at or before the given time, and passes them to on_expired along with their deadline.
+    pub fn expire(&mut self, now: Instant, mut on_expired: impl FnMut(Instant, T)) {
+        let last_tick = self.ticks_until(now, false);
+        // every slot is visited at most once, since a revolution covers all of them
+        let first_tick = self.next_tick.max((last_tick + 1).saturating_sub(self.slots.len() as u64));
+        for tick in first_tick..=last_tick {
+            let slot = self.slot(tick);
+            let entries = &mut self.slots[slot];
+            let mut i = 0;
+            while i < entries.len() {
+                if entries[i].0 <= now {
+                    let (deadline, value) = entries.swap_remove(i);
+                    self.len -= 1;
+                    on_expired(deadline, value);
+                } else {
+                    i += 1;
+                }
+            }
+        }
+        self.next_tick = self.next_tick.max(last_tick + 1);
+    }
+
+    /// Gets the time until the next tick that has values, which is when the wheel should be expired next, or None if
+    /// the wheel is empty. The time is zero if that tick has already started.
+    pub fn next_timeout(&self, now: Instant) -> Option<Duration> {
+        if self.is_empty() {
+            return None;
+        }
+        let tick = (self.next_tick..self.next_tick + self.slots.len() as u64)
+            .find(|tick| !self.slots[self.slot(*tick)].is_empty())?;
+        let time = self.start + Duration::from_nanos((self.tick.as_nanos() * tick as u128) as u64);
+        Some(time.saturating_duration_since(now))
+    }
+
+    /// Gets the number of ticks from the start until the given time, rounded up or down.
+    fn ticks_until(&self, time: Instant, round_up: bool) -> u64 {
+        let elapsed = time.saturating_duration_since(self.start).as_nanos();
+        let tick = self.tick.as_nanos();
+        let ticks = if round_up { elapsed.div_ceil(tick) } else { elapsed / tick };
+        ticks as u64
+    }
+
+    /// Gets the index of the slot for the given tick.
+    fn slot(&self, tick: u64) -> usize {
+        (tick % self.slots.len() as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::server::timer_wheel::TimerWheel;
+
+    const TICK: Duration = Duration::from_millis(100);
+
+    fn expired<T>(wheel: &mut TimerWheel<T>, now: Instant) -> Vec<T> {
+        let mut values = vec![];
+        wheel.expire(now, |_, value| values.push(value));
+        values
+    }
+
+    #[test]
+    fn expires_after_deadline() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(TICK, 8, start);
+        wheel.insert(start + Duration::from_millis(250), "a");
+        wheel.insert(start + Duration::from_millis(450), "b");
+
+        assert!(expired(&mut wheel, start + Duration::from_millis(200)).is_empty());
+        assert!(expired(&mut wheel, start + Duration::from_millis(299)).is_empty());
+        assert_eq!(expired(&mut wheel, start + Duration::from_millis(300)), vec!["a"]);
+        assert!(!wheel.is_empty());
+        assert_eq!(expired(&mut wheel, start + Duration::from_millis(1000)), vec!["b"]);
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn gives_deadline() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(TICK, 8, start);
+        let deadline = start + Duration::from_millis(150);
+        wheel.insert(deadline, "a");
+
+        let mut deadlines = vec![];
+        wheel.expire(start + Duration::from_millis(200), |deadline, _| deadlines.push(deadline));
+        assert_eq!(deadlines, vec![deadline]);
+    }
+
+    #[test]
+    fn deadline_in_later_revolution() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(TICK, 4, start);
+        wheel.insert(start + Duration::from_millis(1000), "a");
+
+        assert!(expired(&mut wheel, start + Duration::from_millis(200)).is_empty());
+        assert!(expired(&mut wheel, start + Duration::from_millis(600)).is_empty());
+        assert!(expired(&mut wheel, start + Duration::from_millis(900)).is_empty());
+        assert_eq!(expired(&mut wheel, start + Duration::from_millis(1000)), vec!["a"]);
+    }
+
+    #[test]
+    fn expire_after_many_revolutions() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(TICK, 4, start);
+        for i in 0..10 {
+            wheel.insert(start + TICK * i, i);
+        }
+
+        let mut values = expired(&mut wheel, start + Duration::from_secs(10));
+        values.sort_unstable();
+        assert_eq!(values, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn insert_past_deadline() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(TICK, 8, start);
+        assert!(expired(&mut wheel, start + Duration::from_millis(500)).is_empty());
+
+        wheel.insert(start + Duration::from_millis(100), "a");
+        assert_eq!(wheel.next_timeout(start + Duration::from_millis(550)), Some(Duration::from_millis(50)));
+        assert_eq!(expired(&mut wheel, start + Duration::from_millis(600)), vec!["a"]);
+    }
+
+    #[test]
+    fn next_timeout() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(TICK, 8, start);
+        assert_eq!(wheel.next_timeout(start), None);
+
+        wheel.insert(start + Duration::from_millis(550), "a");
+        wheel.insert(start + Duration::from_millis(350), "b");
+        assert_eq!(wheel.next_timeout(start + Duration::from_millis(50)), Some(Duration::from_millis(350)));
+        assert_eq!(wheel.next_timeout(start + Duration::from_millis(500)), Some(Duration::from_millis(0)));
+
+        assert_eq!(expired(&mut wheel, start + Duration::from_millis(400)), vec!["b"]);
+        assert_eq!(wheel.next_timeout(start + Duration::from_millis(400)), Some(Duration::from_millis(200)));
+    }
+}
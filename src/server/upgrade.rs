@@ -0,0 +1,318 @@
+use std::fmt::{Debug, Formatter};
+use std::io::{BufRead, BufReader, Error as IoError, ErrorKind, Read, Result, Write};
+use std::net::SocketAddr;
+use std::thread;
+use std::time::Duration;
+
+use mio::{Events, Interest, Poll, Registry, Token};
+use mio::net::TcpStream;
+use rustls::Session;
+
+use crate::common::header::{CONNECTION, UPGRADE};
+use crate::common::response::Response;
+use crate::common::status::SWITCHING_PROTOCOLS;
+use crate::header_map;
+use crate::server::config::ConnectionCounter;
+use crate::server::connection::Connection;
+use crate::server::nonblocking_buf_writer::NonBlockingBufWriter;
+use crate::util::stream::{BufStream, InnerMut, ReadableWriter, Stream, WriteableReader};
+use crate::util::tls_stream::TlsStream;
+
+/// Token used for the stream of an upgraded connection in its own poll.
+const STREAM_TOKEN: Token = Token(0);
+
+/// The number of IO events processed at a time for an upgraded connection.
+const POLL_EVENT_CAPACITY: usize = 4;
+
+/// Amount of written data that may be buffered before writes to an upgraded stream wait for it to be sent.
+const MAX_BUFFERED_WRITE: usize = 16384;
+
+/// The buffered stream used for connections to the server.
+pub(crate) type ServerStream<T> = ReadableWriter<NonBlockingBufWriter<WriteableReader<BufReader<T>>>>;
+
+/// A function that takes over the stream of an upgraded connection.
+pub type UpgradeHandler = Box<dyn FnOnce(UpgradedStream) + 'static + Send>;
+
+/// A switch of a connection to a different protocol, such as WebSocket, in response to a request with an upgrade header.
+/// Each upgraded connection has its own thread, so a server only has up to Config::max_upgraded_connections of them
+/// open at once. Upgrades past the limit are answered with a 503 service unavailable response, and the handler is
+/// dropped without being called.
+pub struct Upgrade {
+    /// The response sent before the connection is handed to the handler. Must have a 101 switching protocols status.
+    pub response: Response,
+    /// Called on a new thread with the stream of the connection once the response has been sent. The connection counts
+    /// towards the limit of upgraded connections until the handler returns.
+    pub handler: UpgradeHandler,
+}
+
+impl Upgrade {
+    /// Creates an upgrade to the given protocol with a 101 switching protocols response.
+    /// ```
+    /// use std::io::{BufRead, Write};
+    /// use my_http::server::{Router, Upgrade};
+    /// use my_http::server::ListenerResult;
+    ///
+    /// let mut router = Router::new();
+    /// router.on("/echo", |_, _| ListenerResult::Upgrade(Upgrade::new("echo", |mut stream| {
+    ///     let mut line = String::new();
+    ///     while stream.read_line(&mut line).unwrap_or(0) > 0 {
+    ///         stream.write_all(line.as_bytes()).unwrap();
+    ///         stream.flush().unwrap();
+    ///         line.clear();
+    ///     }
+    /// })));
+    /// ```
+    pub fn new(protocol: &str, handler: impl FnOnce(UpgradedStream) + 'static + Send) -> Upgrade {
+        let response = Response {
+            status: SWITCHING_PROTOCOLS,
+            headers: header_map![(CONNECTION, "upgrade"), (UPGRADE, protocol)],
+            body: vec![],
+        };
+        Upgrade::with_response(response, handler)
+    }
+
+    /// Creates an upgrade that sends the given 101 switching protocols response.
+    pub fn with_response(response: Response, handler: impl FnOnce(UpgradedStream) + 'static + Send) -> Upgrade {
+        Upgrade { response, handler: Box::new(handler) }
+    }
+}
+
+impl Debug for Upgrade {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Upgrade").field("response", &self.response).finish_non_exhaustive()
+    }
+}
+
+/// The stream of a connection that has been upgraded to another protocol.
+/// The stream is no longer polled by the server, so reads and writes block until they can make progress.
+/// Data the client sent after the upgrade request is read first.
+pub struct UpgradedStream {
+    /// The address of the client.
+    pub addr: SocketAddr,
+    inner: Box<dyn BlockingIo>,
+}
+
+impl UpgradedStream {
+    /// Sets the maximum time a read or write waits for the connection before returning a TimedOut error.
+    /// None, the default, waits forever.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.inner.set_timeout(timeout)
+    }
+}
+
+impl Read for UpgradedStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl BufRead for UpgradedStream {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+}
+
+impl Write for UpgradedStream {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A buffered stream whose reads and writes block.
+trait BlockingIo: BufStream + Send {
+    /// Sets the maximum time to wait for the stream to be ready.
+    fn set_timeout(&mut self, timeout: Option<Duration>);
+}
+
+/// A stream that reads and writes through a mio TCP stream.
+pub(crate) trait TcpBacked {
+    /// Gets the underlying TCP stream.
+    fn tcp_stream(&mut self) -> &mut TcpStream;
+
+    /// Checks if the stream holds data that has not been written to the TCP stream yet.
+    fn wants_write(&self) -> bool {
+        false
+    }
+}
+
+impl TcpBacked for TcpStream {
+    fn tcp_stream(&mut self) -> &mut TcpStream {
+        self
+    }
+}
+
+impl<S: Session> TcpBacked for TlsStream<S, TcpStream> {
+    fn tcp_stream(&mut self) -> &mut TcpStream {
+        self.get_mut()
+    }
+
+    fn wants_write(&self) -> bool {
+        TlsStream::wants_write(self)
+    }
+}
+
+/// An upgraded connection counted by a counter, which is removed from the count once this is dropped.
+struct CountedUpgrade(ConnectionCounter);
+
+impl Drop for CountedUpgrade {
+    fn drop(&mut self) {
+        self.0.decrement();
+    }
+}
+
+/// Adds an upgraded connection to the given counter, unless the given maximum number of upgraded connections are open.
+/// Returns the given handler wrapped to remove the connection from the count once it returns or is dropped, or None if
+/// the limit has been reached.
+pub(crate) fn counted_handler(handler: UpgradeHandler, counter: &ConnectionCounter, max: usize) -> Option<UpgradeHandler> {
+    if !counter.try_increment(max) {
+        return None;
+    }
+    let counted = CountedUpgrade(counter.clone());
+    Some(Box::new(move |stream| {
+        let _counted = counted;
+        handler(stream)
+    }))
+}
+
+/// Moves the stream of the given connection out of the poll of the given registry, and calls the handler with the
+/// stream on a new thread.
+pub(crate) fn upgrade_connection<T>(connection: Connection<ServerStream<T>>, registry: &Registry, handler: UpgradeHandler)
+    where T: Stream + TcpBacked + Send + 'static
+{
+    let addr = connection.addr;
+    let stream = match BlockingStream::new(connection.into_stream(), registry) {
+        Ok(stream) => stream,
+        Err(error) => {
+            println!("Error upgrading connection: {:?}", error);
+            return;
+        }
+    };
+
+    thread::spawn(move || {
+        let mut stream = UpgradedStream { addr, inner: Box::new(stream) };
+        // the 101 response may not have been completely sent yet
+        if stream.flush().is_ok() {
+            handler(stream);
+        }
+    });
+}
+
+/// A server stream registered with its own poll, which is waited on whenever the stream would block.
+struct BlockingStream<T: Stream + TcpBacked> {
+    stream: ServerStream<T>,
+    poll: Poll,
+    events: Events,
+    timeout: Option<Duration>,
+}
+
+impl<T: Stream + TcpBacked> BlockingStream<T> {
+    /// Moves the given stream from the poll of the given registry to a new poll.
+    fn new(mut stream: ServerStream<T>, registry: &Registry) -> Result<BlockingStream<T>> {
+        let tcp_stream = tcp_stream(&mut stream);
+        registry.deregister(tcp_stream)?;
+        let poll = Poll::new()?;
+        poll.registry().register(tcp_stream, STREAM_TOKEN, Interest::READABLE | Interest::WRITABLE)?;
+        Ok(BlockingStream { stream, poll, events: Events::with_capacity(POLL_EVENT_CAPACITY), timeout: None })
+    }
+
+    /// Waits until the stream becomes ready for reading or writing.
+    fn wait(&mut self) -> Result<()> {
+        match self.poll.poll(&mut self.events, self.timeout) {
+            Err(error) if error.kind() == ErrorKind::Interrupted => Ok(()),
+            Err(error) => Err(error),
+            Ok(()) if self.events.is_empty() => Err(IoError::new(ErrorKind::TimedOut, "upgraded connection timed out")),
+            Ok(()) => Ok(())
+        }
+    }
+
+    /// Gets the amount of written data that is still buffered.
+    fn buffered(&self) -> usize {
+        self.stream.get_ref().buffered()
+    }
+
+    /// Checks if any written data has not been sent to the TCP stream.
+    fn has_unsent_data(&self) -> bool {
+        unsent_data(&self.stream).is_some()
+    }
+}
+
+impl<T: Stream + TcpBacked> Read for BlockingStream<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            match self.stream.read(buf) {
+                Err(error) if error.kind() == ErrorKind::WouldBlock => self.wait()?,
+                result => return result
+            }
+        }
+    }
+}
+
+impl<T: Stream + TcpBacked + 'static> BufRead for BlockingStream<T> {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        loop {
+            match self.stream.fill_buf() {
+                Err(error) if error.kind() == ErrorKind::WouldBlock => self.wait()?,
+                Err(error) => return Err(error),
+                Ok(_) => break
+            }
+        }
+        // the data is buffered now, so this does not read from the stream again
+        self.stream.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.stream.consume(amt)
+    }
+}
+
+impl<T: Stream + TcpBacked> Write for BlockingStream<T> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let len = self.stream.write(buf)?;
+        // the buffered writer never blocks, so wait for it to drain instead of letting its buffer grow without limit
+        while self.buffered() > MAX_BUFFERED_WRITE {
+            self.wait()?;
+            self.stream.flush()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        loop {
+            self.stream.flush()?;
+            if !self.has_unsent_data() {
+                return Ok(());
+            }
+            self.wait()?;
+        }
+    }
+}
+
+impl<T: Stream + TcpBacked + Send + 'static> BlockingIo for BlockingStream<T> {
+    fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+}
+
+/// Gets the amount of data written to the given stream that has not been sent to its TCP stream, or None if all of it
+/// has been sent. Data held by the TLS session is not counted in the amount.
+pub(crate) fn unsent_data<T: Stream + TcpBacked>(stream: &ServerStream<T>) -> Option<usize> {
+    let buffered = stream.get_ref().buffered();
+    if buffered > 0 || stream.get_ref().get_ref().get_ref().get_ref().wants_write() {
+        Some(buffered)
+    } else {
+        None
+    }
+}
+
+/// Gets the TCP stream underneath the buffers of the given server stream.
+fn tcp_stream<T: TcpBacked>(stream: &mut ServerStream<T>) -> &mut TcpStream {
+    stream.get_mut().inner_mut().get_mut().get_mut().tcp_stream()
+}
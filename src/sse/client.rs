@@ -0,0 +1,267 @@
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, BufReader, BufWriter, Read, Take};
+use std::thread;
+use std::time::Duration;
+
+use crate::client::{RequestError, StreamFactory, write_request};
+use crate::common::header::{ACCEPT, CACHE_CONTROL, HeaderMapOps, HOST, LAST_EVENT_ID};
+use crate::common::method::Method;
+use crate::common::request::Request;
+use crate::common::response::Response;
+use crate::common::status;
+use crate::common::typed_header::{ContentLength, ContentType, TransferEncoding, TypedHeader};
+use crate::header_map;
+use crate::parse::error::ParsingError;
+use crate::parse::event_stream::EventParser;
+use crate::parse::parse::Parse;
+use crate::parse::parse::ParseStatus::{Done, IoErr};
+use crate::parse::response::ResponseParser;
+use crate::sse::Event;
+use crate::util::chunked_reader::ChunkedReader;
+use crate::util::stream;
+use crate::util::stream::{StdBufStream, Stream};
+
+/// The time to wait before reconnecting until the server sets a different time.
+const DEFAULT_RETRY: Duration = Duration::from_secs(3);
+
+/// Error from an event source.
+#[derive(Debug)]
+pub enum EventSourceError {
+    /// Error connecting to the server, sending the request, or reading the response head. The event source reconnects
+    /// when the next event is requested.
+    Request(RequestError),
+    /// The server responded with a status other than 200 OK, or with a content type other than text/event-stream.
+    /// The event source is closed.
+    Rejected(Response),
+    /// The event stream could not be parsed. The event source reconnects when the next event is requested.
+    Parsing(ParsingError),
+}
+
+impl Display for EventSourceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EventSourceError::Request(error) => write!(f, "error requesting event stream: {:?}", error),
+            EventSourceError::Rejected(response) => write!(f, "server rejected event stream request with status {}", response.status.code),
+            EventSourceError::Parsing(error) => write!(f, "error parsing event stream: {:?}", error),
+        }
+    }
+}
+
+impl std::error::Error for EventSourceError {}
+
+impl From<RequestError> for EventSourceError {
+    fn from(error: RequestError) -> Self {
+        EventSourceError::Request(error)
+    }
+}
+
+/// A client for a stream of server-sent events, which iterates over the events as they are received.
+/// If the connection is lost, then the event source waits for the reconnection time set by the server and reconnects,
+/// sending the ID of the last event it received in a last-event-id header.
+/// Reads use the read timeout of the stream factory, so the timeout should be longer than the time between the
+/// keep-alive comments sent by the server. A timed out connection is treated as lost.
+/// ```no_run
+/// use std::time::Duration;
+/// use my_http::client::{Config, TcpStreamFactory};
+/// use my_http::sse::EventSource;
+///
+/// let factory = TcpStreamFactory::new(&Config {
+///     addr: "localhost:8080",
+///     read_timeout: Duration::from_secs(60),
+///     num_connections: 1,
+///     server_name: None,
+/// });
+/// for event in EventSource::new(factory, "localhost:8080", "/events") {
+///     match event {
+///         Ok(event) => println!("{:?}: {}", event.event, event.data),
+///         Err(error) => println!("{}", error),
+///     }
+/// }
+/// ```
+pub struct EventSource<S: Stream + 'static, F> {
+    stream_factory: F,
+    host: String,
+    uri: String,
+    /// The body of the current response, or None if there is no connection.
+    body: Option<Body<S>>,
+    last_event_id: Option<String>,
+    retry: Duration,
+    /// Whether a connection has been attempted, so the next one has to wait for the reconnection time first.
+    reconnecting: bool,
+    closed: bool,
+}
+
+impl<S: Stream + 'static, F: StreamFactory<S>> EventSource<S, F> {
+    /// Creates an event source for the event stream at the given host and URI. Will not actually connect to the server
+    /// until the first event is requested.
+    pub fn new(stream_factory: F, host: &str, uri: &str) -> EventSource<S, F> {
+        EventSource {
+            stream_factory,
+            host: host.to_string(),
+            uri: uri.to_string(),
+            body: None,
+            last_event_id: None,
+            retry: DEFAULT_RETRY,
+            reconnecting: false,
+            closed: false,
+        }
+    }
+
+    /// Sets the ID of the last event received, to resume a stream from an earlier event source.
+    pub fn with_last_event_id(mut self, last_event_id: &str) -> EventSource<S, F> {
+        self.last_event_id = Some(last_event_id.to_string());
+        self
+    }
+
+    /// Gets the ID of the last event received, which is sent to the server when reconnecting.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    /// Gets the time waited before reconnecting.
+    pub fn retry(&self) -> Duration {
+        self.retry
+    }
+
+    /// Closes the connection to the server. No more events will be returned.
+    pub fn close(&mut self) {
+        self.body = None;
+        self.closed = true;
+    }
+
+    /// Opens a new connection and requests the event stream. Returns false if the server responded with 204 no content,
+    /// which means the client should stop reconnecting.
+    fn connect(&mut self) -> Result<bool, EventSourceError> {
+        let stream = self.stream_factory.create().map_err(RequestError::Connecting)?;
+        let mut stream = stream::with_buf_reader_and_writer(stream, BufReader::new, BufWriter::new);
+
+        let mut request = Request {
+            uri: self.uri.clone(),
+            method: Method::GET,
+            headers: header_map![
+                (HOST, self.host.as_str()),
+                (ACCEPT, "text/event-stream"),
+                (CACHE_CONTROL, "no-cache")
+            ],
+            body: vec![],
+        };
+        if let Some(last_event_id) = &self.last_event_id {
+            request.headers.add_header(LAST_EVENT_ID, last_event_id.clone());
+        }
+        write_request(&mut stream, &request).map_err(RequestError::Writing)?;
+
+        let response = loop {
+            // the body is read as events, so only the head of the response is parsed here
+            let response = match ResponseParser::for_head_request().parse(&mut stream).map_err(RequestError::ResponseParsing)? {
+                Done(response) => response,
+                IoErr(_, error) => return Err(RequestError::Reading(error).into()),
+            };
+            if !response.status.is_informational() {
+                break response;
+            }
+        };
+
+        if response.status == status::NO_CONTENT {
+            return Ok(false);
+        }
+        let is_event_stream = matches!(ContentType::decode(&response.headers), Some(Ok(ContentType(media_type))) if media_type.essence() == "text/event-stream");
+        if response.status != status::OK || !is_event_stream {
+            return Err(EventSourceError::Rejected(response));
+        }
+
+        self.body = Some(match (TransferEncoding::decode(&response.headers), ContentLength::decode(&response.headers)) {
+            (Some(Ok(transfer_encoding)), _) if transfer_encoding.is_chunked() => Body::Chunked(ChunkedReader::new(stream)),
+            (_, Some(Ok(ContentLength(length)))) => Body::Sized(stream.take(length)),
+            _ => Body::UntilEof(stream),
+        });
+        Ok(true)
+    }
+}
+
+impl<S: Stream + 'static, F: StreamFactory<S>> Iterator for EventSource<S, F> {
+    type Item = Result<Event, EventSourceError>;
+
+    /// Gets the next event, connecting or reconnecting to the server as necessary. Returns None once the event source
+    /// has been closed, or the server responded with 204 no content. Errors are returned for failed connection attempts,
+    /// but the event source keeps reconnecting unless it is closed.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.closed {
+                return None;
+            }
+
+            if self.body.is_none() {
+                if self.reconnecting {
+                    thread::sleep(self.retry);
+                }
+                self.reconnecting = true;
+
+                match self.connect() {
+                    Ok(true) => {}
+                    Ok(false) => self.close(),
+                    Err(error) => {
+                        if let EventSourceError::Rejected(_) = error {
+                            self.close();
+                        }
+                        return Some(Err(error));
+                    }
+                }
+                continue;
+            }
+
+            let parser = EventParser::new(self.last_event_id.take());
+            match parser.parse(self.body.as_mut().unwrap()) {
+                Ok(Done(event)) => {
+                    self.last_event_id = event.id.clone();
+                    self.retry = event.retry.unwrap_or(self.retry);
+                    return Some(Ok(event));
+                }
+                // the connection was lost, and the partially received event is discarded
+                Ok(IoErr(parser, _)) => {
+                    self.last_event_id = parser.last_event_id().map(String::from);
+                    self.retry = parser.retry().unwrap_or(self.retry);
+                    self.body = None;
+                }
+                Err(error) => {
+                    self.body = None;
+                    return Some(Err(EventSourceError::Parsing(error)));
+                }
+            }
+        }
+    }
+}
+
+/// The body of an event stream response.
+enum Body<S: Stream + 'static> {
+    Chunked(ChunkedReader<StdBufStream<S>>),
+    Sized(Take<StdBufStream<S>>),
+    UntilEof(StdBufStream<S>),
+}
+
+impl<S: Stream + 'static> Read for Body<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Body::Chunked(reader) => reader.read(buf),
+            Body::Sized(reader) => reader.read(buf),
+            Body::UntilEof(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl<S: Stream + 'static> BufRead for Body<S> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        match self {
+            Body::Chunked(reader) => reader.fill_buf(),
+            Body::Sized(reader) => reader.fill_buf(),
+            Body::UntilEof(reader) => reader.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match self {
+            Body::Chunked(reader) => reader.consume(amt),
+            Body::Sized(reader) => reader.consume(amt),
+            Body::UntilEof(reader) => reader.consume(amt),
+        }
+    }
+}
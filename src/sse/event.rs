@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+/// A server-sent event.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct Event {
+    /// The type of the event, or None for the default "message" type.
+    pub event: Option<String>,
+    /// The data of the event. Lines are separated by "\n".
+    pub data: String,
+    /// The ID of the event, which a reconnecting client sends back in its last-event-id header.
+    pub id: Option<String>,
+    /// The time a client should wait before reconnecting if the connection is lost.
+    pub retry: Option<Duration>,
+}
+
+impl Event {
+    /// Creates an event of the default type with the given data.
+    pub fn new(data: &str) -> Event {
+        Event { data: data.to_string(), ..Default::default() }
+    }
+
+    /// Sets the type of the event.
+    pub fn with_event(mut self, event: &str) -> Event {
+        self.event = Some(event.to_string());
+        self
+    }
+
+    /// Sets the ID of the event.
+    pub fn with_id(mut self, id: &str) -> Event {
+        self.id = Some(id.to_string());
+        self
+    }
+
+    /// Sets the reconnection time of the event.
+    pub fn with_retry(mut self, retry: Duration) -> Event {
+        self.retry = Some(retry);
+        self
+    }
+}
+
+/// Appends the given event to the given buffer in the text/event-stream format.
+/// Returns an error without writing anything if the type or ID of the event contains a line break, or the ID contains a null character.
+pub(crate) fn write_event(out: &mut Vec<u8>, event: &Event) -> Result<(), &'static str> {
+    if event.event.as_deref().is_some_and(has_line_break) {
+        return Err("event type contains a line break");
+    }
+    if event.id.as_deref().is_some_and(|id| has_line_break(id) || id.contains('\0')) {
+        return Err("event ID contains a line break or null character");
+    }
+
+    if let Some(event) = &event.event {
+        write_field(out, "event", event);
+    }
+    if let Some(id) = &event.id {
+        write_field(out, "id", id);
+    }
+    if let Some(retry) = event.retry {
+        write_field(out, "retry", &retry.as_millis().to_string());
+    }
+    for line in lines(&event.data) {
+        write_field(out, "data", line);
+    }
+    out.push(b'\n');
+    Ok(())
+}
+
+/// Appends the given text to the given buffer as a comment, which clients ignore.
+pub(crate) fn write_comment(out: &mut Vec<u8>, text: &str) {
+    for line in lines(text) {
+        out.push(b':');
+        if !line.is_empty() {
+            out.push(b' ');
+            out.extend_from_slice(line.as_bytes());
+        }
+        out.push(b'\n');
+    }
+    out.push(b'\n');
+}
+
+/// Appends a single field to the given buffer.
+fn write_field(out: &mut Vec<u8>, name: &str, value: &str) {
+    out.extend_from_slice(name.as_bytes());
+    out.extend_from_slice(b": ");
+    out.extend_from_slice(value.as_bytes());
+    out.push(b'\n');
+}
+
+/// Splits the given text on any of the line breaks allowed in an event stream, "\r\n", "\r", and "\n".
+fn lines(text: &str) -> impl Iterator<Item=&str> {
+    text.split('\n').flat_map(|line| line.strip_suffix('\r').unwrap_or(line).split('\r'))
+}
+
+/// Checks if the given text contains a line break.
+fn has_line_break(text: &str) -> bool {
+    text.contains(['\r', '\n'])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::sse::event::{Event, write_comment, write_event};
+
+    fn encoded(event: &Event) -> String {
+        let mut out = vec![];
+        write_event(&mut out, event).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn data_only() {
+        assert_eq!(encoded(&Event::new("hello")), "data: hello\n\n");
+        assert_eq!(encoded(&Event::new("")), "data: \n\n");
+    }
+
+    #[test]
+    fn all_fields() {
+        let event = Event::new("hello").with_event("greeting").with_id("5").with_retry(Duration::from_secs(3));
+        assert_eq!(encoded(&event), "event: greeting\nid: 5\nretry: 3000\ndata: hello\n\n");
+    }
+
+    #[test]
+    fn multiline_data() {
+        assert_eq!(encoded(&Event::new("a\nb\r\nc\rd\n")), "data: a\ndata: b\ndata: c\ndata: d\ndata: \n\n");
+    }
+
+    #[test]
+    fn invalid_fields() {
+        let mut out = vec![];
+        assert!(write_event(&mut out, &Event::new("").with_event("a\nb")).is_err());
+        assert!(write_event(&mut out, &Event::new("").with_id("a\rb")).is_err());
+        assert!(write_event(&mut out, &Event::new("").with_id("a\0b")).is_err());
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn comments() {
+        let mut out = vec![];
+        write_comment(&mut out, "");
+        write_comment(&mut out, "keep-alive\nsecond line");
+        assert_eq!(String::from_utf8(out).unwrap(), ":\n\n: keep-alive\n: second line\n\n");
+    }
+}
@@ -0,0 +1,7 @@
+pub use client::*;
+pub use event::*;
+
+/// Server-sent events.
+mod event;
+/// Client for streams of server-sent events.
+mod client;
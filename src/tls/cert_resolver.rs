@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use rustls::{Certificate, ClientHello, PrivateKey, ResolvesServerCert};
+use rustls::sign::{any_supported_type, CertifiedKey};
+use webpki::DNSNameRef;
+
+use crate::tls::TlsError;
+
+/// Picks the certificate for a connection based on the hostname the client requested with SNI, so a single server
+/// can serve multiple domains.
+/// ```
+/// use my_http::tls::{CertResolver, load_certs, load_private_key};
+///
+/// let mut resolver = CertResolver::new();
+/// resolver.add("localhost", load_certs("./tests/certs/localhost.crt").unwrap(), &load_private_key("./tests/certs/localhost.key").unwrap()).unwrap();
+/// resolver.add("*.wildcard.test", load_certs("./tests/certs/wildcard.crt").unwrap(), &load_private_key("./tests/certs/wildcard.key").unwrap()).unwrap();
+/// ```
+pub struct CertResolver {
+    /// The certificates by lowercase hostname. Wildcard hostnames start with "*.".
+    by_hostname: HashMap<String, CertifiedKey>,
+    /// The certificate for clients that do not use SNI or request a hostname with no certificate.
+    default: Option<CertifiedKey>,
+}
+
+impl CertResolver {
+    /// Creates a resolver with no certificates.
+    pub fn new() -> CertResolver {
+        CertResolver { by_hostname: HashMap::new(), default: None }
+    }
+
+    /// Adds the given certificate chain and private key for the given hostname, replacing any previous certificate for
+    /// it. A hostname starting with "*." is a wildcard, which matches hostnames with exactly one more label, such as
+    /// "a.example.com" for "*.example.com". Hostnames without a wildcard take precedence.
+    /// Returns an error if the hostname is invalid, the key is not supported, or the certificate is not valid for the
+    /// hostname. Wildcard certificates are only checked for syntax.
+    pub fn add(&mut self, hostname: &str, certs: Vec<Certificate>, key: &PrivateKey) -> Result<(), TlsError> {
+        let hostname = hostname.to_ascii_lowercase();
+        let (domain, wildcard) = match hostname.strip_prefix("*.") {
+            Some(domain) => (domain, true),
+            None => (hostname.as_str(), false),
+        };
+        let domain = DNSNameRef::try_from_ascii_str(domain).map_err(|_| TlsError::InvalidHostname(hostname.clone()))?;
+
+        let certified_key = certified_key(certs, key)?;
+        certified_key.cross_check_end_entity_cert(if wildcard { None } else { Some(domain) }).map_err(TlsError::InvalidCertificate)?;
+        self.by_hostname.insert(hostname, certified_key);
+        Ok(())
+    }
+
+    /// Sets the certificate chain and private key for clients that do not use SNI or request a hostname with no
+    /// certificate. Without a default, the handshakes of those clients fail.
+    pub fn set_default(&mut self, certs: Vec<Certificate>, key: &PrivateKey) -> Result<(), TlsError> {
+        let certified_key = certified_key(certs, key)?;
+        certified_key.cross_check_end_entity_cert(None).map_err(TlsError::InvalidCertificate)?;
+        self.default = Some(certified_key);
+        Ok(())
+    }
+
+    /// Finds the certificate for the given hostname, or for a client without SNI if the hostname is None.
+    pub(crate) fn find(&self, hostname: Option<&str>) -> Option<&CertifiedKey> {
+        let hostname = hostname.map(|hostname| hostname.trim_end_matches('.').to_ascii_lowercase());
+        let exact = || self.by_hostname.get(hostname.as_ref()?);
+        let wildcard = || {
+            let (_, parent) = hostname.as_ref()?.split_once('.')?;
+            self.by_hostname.get(&format!("*.{}", parent))
+        };
+        exact().or_else(wildcard).or(self.default.as_ref())
+    }
+}
+
+impl Default for CertResolver {
+    fn default() -> Self {
+        CertResolver::new()
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<CertifiedKey> {
+        self.find(client_hello.server_name().map(Into::into)).cloned()
+    }
+}
+
+/// Pairs the given certificate chain with a signing key for the given private key.
+pub(crate) fn certified_key(certs: Vec<Certificate>, key: &PrivateKey) -> Result<CertifiedKey, TlsError> {
+    let key = any_supported_type(key).map_err(|_| TlsError::UnsupportedPrivateKey)?;
+    Ok(CertifiedKey::new(certs, Arc::new(key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use rustls::{Certificate, PrivateKey};
+
+    use crate::tls::{CertResolver, load_certs, load_private_key, TlsError};
+
+    fn cert(name: &str) -> (Vec<Certificate>, PrivateKey) {
+        (load_certs(format!("./tests/certs/{}.crt", name)).unwrap(), load_private_key(format!("./tests/certs/{}.key", name)).unwrap())
+    }
+
+    fn resolver() -> CertResolver {
+        let mut resolver = CertResolver::new();
+        let (certs, key) = cert("example");
+        resolver.add("example.test", certs, &key).unwrap();
+        let (certs, key) = cert("wildcard");
+        resolver.add("*.wildcard.test", certs, &key).unwrap();
+        resolver
+    }
+
+    /// Gets the first certificate of the chain found for the given hostname.
+    fn found(resolver: &CertResolver, hostname: Option<&str>) -> Option<Certificate> {
+        resolver.find(hostname).map(|key| key.cert[0].clone())
+    }
+
+    #[test]
+    fn exact_hostname() {
+        let resolver = resolver();
+        assert_eq!(found(&resolver, Some("example.test")), Some(cert("example").0[0].clone()));
+        assert_eq!(found(&resolver, Some("EXAMPLE.test.")), Some(cert("example").0[0].clone()));
+        assert_eq!(found(&resolver, Some("other.test")), None);
+        assert_eq!(found(&resolver, Some("a.example.test")), None);
+    }
+
+    #[test]
+    fn wildcard_hostname() {
+        let resolver = resolver();
+        assert_eq!(found(&resolver, Some("a.wildcard.test")), Some(cert("wildcard").0[0].clone()));
+        assert_eq!(found(&resolver, Some("wildcard.test")), None);
+        assert_eq!(found(&resolver, Some("a.b.wildcard.test")), None);
+    }
+
+    #[test]
+    fn exact_hostname_before_wildcard() {
+        let mut resolver = resolver();
+        let (certs, key) = cert("wildcard");
+        resolver.add("*.test", certs, &key).unwrap();
+        assert_eq!(found(&resolver, Some("example.test")), Some(cert("example").0[0].clone()));
+        assert_eq!(found(&resolver, Some("other.test")), Some(cert("wildcard").0[0].clone()));
+    }
+
+    #[test]
+    fn default_cert() {
+        let mut resolver = resolver();
+        assert_eq!(found(&resolver, None), None);
+
+        let (certs, key) = cert("localhost");
+        resolver.set_default(certs, &key).unwrap();
+        assert_eq!(found(&resolver, None), Some(cert("localhost").0[0].clone()));
+        assert_eq!(found(&resolver, Some("other.test")), Some(cert("localhost").0[0].clone()));
+    }
+
+    #[test]
+    fn cert_not_valid_for_hostname() {
+        let (certs, key) = cert("example");
+        assert!(matches!(CertResolver::new().add("other.test", certs, &key), Err(TlsError::InvalidCertificate(_))));
+    }
+
+    #[test]
+    fn invalid_hostname() {
+        let (certs, key) = cert("example");
+        assert!(matches!(CertResolver::new().add("not a hostname", certs, &key), Err(TlsError::InvalidHostname(_))));
+    }
+
+    #[test]
+    fn unsupported_key() {
+        let (certs, _) = cert("example");
+        assert!(matches!(CertResolver::new().add("example.test", certs, &PrivateKey(vec![1, 2, 3])), Err(TlsError::UnsupportedPrivateKey)));
+    }
+}
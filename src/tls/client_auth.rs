@@ -0,0 +1,83 @@
+use rustls::{AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, Certificate, NoClientAuth, RootCertStore, ServerConfig, TLSError};
+
+use crate::tls::TlsError;
+
+/// Whether an HTTPS server asks clients for certificates, and the CA certificates they are verified against.
+/// The identity of a verified client is available to listeners through the connection info of the request context.
+/// ```
+/// use my_http::tls::{ClientAuth, load_certs, TlsConfig};
+///
+/// let mut tls_config = TlsConfig::default();
+/// tls_config.client_auth = ClientAuth::required(&load_certs("./tests/certs/ca.crt").unwrap()).unwrap();
+/// ```
+#[derive(Default)]
+pub enum ClientAuth {
+    /// Clients are not asked for certificates.
+    #[default]
+    None,
+    /// Clients are asked for a certificate, but clients that do not send one are still accepted. Certificates that are
+    /// sent must be issued by one of the CAs in the store.
+    Optional(RootCertStore),
+    /// Clients must send a certificate issued by one of the CAs in the store.
+    Required(RootCertStore),
+}
+
+impl ClientAuth {
+    /// Requires clients to send a certificate issued by one of the given CA certificates, such as the certificates
+    /// loaded from a CA bundle with load_certs.
+    pub fn required(ca_certs: &[Certificate]) -> Result<ClientAuth, TlsError> {
+        Ok(ClientAuth::Required(root_store(ca_certs)?))
+    }
+
+    /// Asks clients for a certificate issued by one of the given CA certificates, but also accepts clients that do not
+    /// send one.
+    pub fn optional(ca_certs: &[Certificate]) -> Result<ClientAuth, TlsError> {
+        Ok(ClientAuth::Optional(root_store(ca_certs)?))
+    }
+
+    /// Creates a rustls ServerConfig that verifies client certificates as configured.
+    pub(crate) fn into_server_config(self) -> ServerConfig {
+        ServerConfig::new(match self {
+            ClientAuth::None => NoClientAuth::new(),
+            ClientAuth::Optional(roots) => AllowAnyAnonymousOrAuthenticatedClient::new(roots),
+            ClientAuth::Required(roots) => AllowAnyAuthenticatedClient::new(roots),
+        })
+    }
+}
+
+/// Creates a store of trusted CAs out of the given CA certificates. Returns an error if there are no certificates or
+/// one of them can not be used as a trust anchor.
+pub(crate) fn root_store(ca_certs: &[Certificate]) -> Result<RootCertStore, TlsError> {
+    if ca_certs.is_empty() {
+        return Err(TlsError::NoCertificates);
+    }
+    let mut store = RootCertStore::empty();
+    for cert in ca_certs {
+        store.add(cert).map_err(|error| TlsError::InvalidCertificate(TLSError::WebPKIError(error)))?;
+    }
+    Ok(store)
+}
+
+#[cfg(test)]
+mod tests {
+    use rustls::Certificate;
+
+    use crate::tls::{ClientAuth, load_certs, TlsError};
+
+    #[test]
+    fn ca_bundle() {
+        let certs = load_certs("./tests/certs/ca.crt").unwrap();
+        assert!(matches!(ClientAuth::required(&certs), Ok(ClientAuth::Required(store)) if store.len() == 1));
+        assert!(matches!(ClientAuth::optional(&certs), Ok(ClientAuth::Optional(store)) if store.len() == 1));
+    }
+
+    #[test]
+    fn empty_ca_bundle() {
+        assert!(matches!(ClientAuth::required(&[]), Err(TlsError::NoCertificates)));
+    }
+
+    #[test]
+    fn invalid_ca_cert() {
+        assert!(matches!(ClientAuth::required(&[Certificate(vec![1, 2, 3])]), Err(TlsError::InvalidCertificate(_))));
+    }
+}
@@ -0,0 +1,226 @@
+use std::sync::Arc;
+
+use ring::digest::{digest, SHA256};
+use rustls::{Certificate, ClientConfig, PrivateKey, ResolvesClientCert, RootCertStore, ServerCertVerified, ServerCertVerifier, SignatureScheme, TLSError, WebPKIVerifier};
+use rustls::sign::CertifiedKey;
+use webpki::DNSNameRef;
+
+use crate::tls::cert_resolver::certified_key;
+use crate::tls::der::{fields_from_subject, read, read_encoded};
+use crate::tls::server_config::default_alpn_protocols;
+use crate::tls::TlsError;
+
+/// TLS settings for an HTTPS client, which can be passed to Client::new_https or TlsStreamFactory::new in place of a
+/// rustls ClientConfig.
+/// ```
+/// use my_http::tls::{ClientTlsConfig, load_certs, load_private_key, spki_sha256};
+///
+/// let mut tls_config = ClientTlsConfig::new();
+/// tls_config.add_ca_certs(&load_certs("./tests/certs/ca.crt").unwrap()).unwrap();
+/// tls_config.set_client_cert(load_certs("./tests/certs/client.crt").unwrap(), &load_private_key("./tests/certs/client.key").unwrap()).unwrap();
+/// tls_config.pinned_spki_sha256.push(spki_sha256(&load_certs("./tests/certs/localhost.crt").unwrap()[0]).unwrap());
+/// ```
+pub struct ClientTlsConfig {
+    /// The CAs that are trusted to issue server certificates. Empty by default.
+    pub root_certs: RootCertStore,
+    /// SHA-256 hashes of subject public key infos, as returned by spki_sha256. If not empty, then a server certificate
+    /// chain is only accepted if it is issued by a trusted CA and one of the certificates the server sends has one of
+    /// these public keys.
+    pub pinned_spki_sha256: Vec<[u8; 32]>,
+    /// The protocols offered with ALPN, in order of preference. Defaults to HTTP/2 and HTTP/1.1 with the http2 feature,
+    /// and only HTTP/1.1 without it.
+    pub alpn_protocols: Vec<Vec<u8>>,
+    /// Whether any server certificate is accepted, without checking who issued it, what hostname it is for, or
+    /// whether it is expired. This makes connections open to interception, so it must only be used for local
+    /// development. Defaults to false.
+    #[cfg(feature = "dangerous_accept_invalid_certs")]
+    pub accept_invalid_certs: bool,
+    /// The certificate chain and key sent to servers that ask for a client certificate.
+    client_cert: Option<CertifiedKey>,
+}
+
+impl ClientTlsConfig {
+    /// Creates a config that trusts no CAs and sends no client certificate.
+    pub fn new() -> ClientTlsConfig {
+        ClientTlsConfig {
+            root_certs: RootCertStore::empty(),
+            pinned_spki_sha256: vec![],
+            alpn_protocols: default_alpn_protocols(),
+            #[cfg(feature = "dangerous_accept_invalid_certs")]
+            accept_invalid_certs: false,
+            client_cert: None,
+        }
+    }
+
+    /// Trusts the given CA certificates, such as the certificates loaded from a CA bundle with load_certs.
+    /// Returns an error if one of the certificates can not be used as a trust anchor, in which case none of them are
+    /// added.
+    pub fn add_ca_certs(&mut self, ca_certs: &[Certificate]) -> Result<(), TlsError> {
+        let mut root_certs = self.root_certs.clone();
+        for cert in ca_certs {
+            root_certs.add(cert).map_err(|error| TlsError::InvalidCertificate(TLSError::WebPKIError(error)))?;
+        }
+        self.root_certs = root_certs;
+        Ok(())
+    }
+
+    /// Sets the certificate chain and private key sent to servers that ask for a client certificate, as with mutual TLS.
+    /// Returns an error if the key is not supported.
+    pub fn set_client_cert(&mut self, certs: Vec<Certificate>, key: &PrivateKey) -> Result<(), TlsError> {
+        self.client_cert = Some(certified_key(certs, key)?);
+        Ok(())
+    }
+}
+
+impl Default for ClientTlsConfig {
+    fn default() -> Self {
+        ClientTlsConfig::new()
+    }
+}
+
+impl From<ClientTlsConfig> for ClientConfig {
+    fn from(config: ClientTlsConfig) -> Self {
+        let mut client_config = ClientConfig::new();
+        client_config.root_store = config.root_certs;
+        client_config.set_protocols(&config.alpn_protocols);
+        if let Some(client_cert) = config.client_cert {
+            client_config.client_auth_cert_resolver = Arc::new(ClientCert(client_cert));
+        }
+
+        #[cfg(feature = "dangerous_accept_invalid_certs")]
+        if config.accept_invalid_certs {
+            client_config.dangerous().set_certificate_verifier(Arc::new(AcceptInvalidCerts));
+            return client_config;
+        }
+        if !config.pinned_spki_sha256.is_empty() {
+            let verifier = PinnedVerifier { pins: config.pinned_spki_sha256, verifier: WebPKIVerifier::new() };
+            client_config.dangerous().set_certificate_verifier(Arc::new(verifier));
+        }
+        client_config
+    }
+}
+
+/// Gets the SHA-256 hash of the subject public key info of the given certificate, which can be used to pin the
+/// certificate or any other certificate with the same public key. Returns an InvalidCertificate error if the
+/// certificate is not valid DER.
+pub fn spki_sha256(cert: &Certificate) -> Result<[u8; 32], TlsError> {
+    let invalid = || TlsError::InvalidCertificate(TLSError::WebPKIError(webpki::Error::BadDER));
+    let mut fields = fields_from_subject(&cert.0).ok_or_else(invalid)?;
+    read(&mut fields).ok_or_else(invalid)?;
+    let spki = read_encoded(&mut fields).ok_or_else(invalid)?;
+
+    let mut hash = [0; 32];
+    hash.copy_from_slice(digest(&SHA256, spki).as_ref());
+    Ok(hash)
+}
+
+/// Sends the same client certificate to every server that asks for one.
+struct ClientCert(CertifiedKey);
+
+impl ResolvesClientCert for ClientCert {
+    fn resolve(&self, _acceptable_issuers: &[&[u8]], _sigschemes: &[SignatureScheme]) -> Option<CertifiedKey> {
+        Some(self.0.clone())
+    }
+
+    fn has_certs(&self) -> bool {
+        true
+    }
+}
+
+/// Verifies server certificate chains as usual, and then checks that one of the certificates has a pinned public key.
+struct PinnedVerifier {
+    pins: Vec<[u8; 32]>,
+    verifier: WebPKIVerifier,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(&self, roots: &RootCertStore, presented_certs: &[Certificate], dns_name: DNSNameRef, ocsp_response: &[u8]) -> Result<ServerCertVerified, TLSError> {
+        let verified = self.verifier.verify_server_cert(roots, presented_certs, dns_name, ocsp_response)?;
+        let pinned = presented_certs.iter()
+            .filter_map(|cert| spki_sha256(cert).ok())
+            .any(|hash| self.pins.contains(&hash));
+        if !pinned {
+            return Err(TLSError::General("no certificate in the chain has a pinned public key".into()));
+        }
+        Ok(verified)
+    }
+}
+
+/// Accepts any server certificate.
+#[cfg(feature = "dangerous_accept_invalid_certs")]
+struct AcceptInvalidCerts;
+
+#[cfg(feature = "dangerous_accept_invalid_certs")]
+impl ServerCertVerifier for AcceptInvalidCerts {
+    fn verify_server_cert(&self, _roots: &RootCertStore, _presented_certs: &[Certificate], _dns_name: DNSNameRef, _ocsp_response: &[u8]) -> Result<ServerCertVerified, TLSError> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustls::{Certificate, PrivateKey, ServerCertVerifier, WebPKIVerifier};
+    use webpki::DNSNameRef;
+
+    use crate::tls::{ClientTlsConfig, load_certs, spki_sha256, TlsError};
+    use crate::tls::client_config::PinnedVerifier;
+
+    fn cert(name: &str) -> Certificate {
+        load_certs(format!("./tests/certs/{}.crt", name)).unwrap().remove(0)
+    }
+
+    #[test]
+    fn ca_certs() {
+        let mut config = ClientTlsConfig::new();
+        config.add_ca_certs(&[cert("ca")]).unwrap();
+        assert_eq!(config.root_certs.len(), 1);
+
+        assert!(matches!(config.add_ca_certs(&[cert("ca"), Certificate(vec![1, 2, 3])]), Err(TlsError::InvalidCertificate(_))));
+        assert_eq!(config.root_certs.len(), 1);
+    }
+
+    #[test]
+    fn unsupported_client_key() {
+        let mut config = ClientTlsConfig::new();
+        assert!(matches!(config.set_client_cert(vec![cert("client")], &PrivateKey(vec![1, 2, 3])), Err(TlsError::UnsupportedPrivateKey)));
+    }
+
+    #[test]
+    fn spki_hash() {
+        // openssl x509 -in localhost.crt -pubkey -noout | openssl pkey -pubin -outform der | sha256sum
+        let expected = "135cb936fadefbcf225d16698fd4e7d3da5afa88893cbd43c7688fbec237e019";
+        let hash: String = spki_sha256(&cert("localhost")).unwrap().iter().map(|byte| format!("{:02x}", byte)).collect();
+        assert_eq!(hash, expected);
+        assert!(spki_sha256(&Certificate(vec![0x30, 0x00])).is_err());
+    }
+
+    #[test]
+    fn pinned_certs() {
+        let mut config = ClientTlsConfig::new();
+        config.add_ca_certs(&[cert("ca")]).unwrap();
+        let hostname = DNSNameRef::try_from_ascii_str("localhost").unwrap();
+        let verify = |pin: &str| {
+            let verifier = PinnedVerifier { pins: vec![spki_sha256(&cert(pin)).unwrap()], verifier: WebPKIVerifier::new() };
+            verifier.verify_server_cert(&config.root_certs, &[cert("localhost")], hostname, &[])
+        };
+
+        assert!(verify("localhost").is_ok());
+        assert!(verify("example").is_err());
+    }
+
+    #[test]
+    fn pinned_certs_must_be_trusted() {
+        let verifier = PinnedVerifier { pins: vec![spki_sha256(&cert("localhost")).unwrap()], verifier: WebPKIVerifier::new() };
+        let hostname = DNSNameRef::try_from_ascii_str("localhost").unwrap();
+        assert!(verifier.verify_server_cert(&ClientTlsConfig::new().root_certs, &[cert("localhost")], hostname, &[]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "dangerous_accept_invalid_certs")]
+    fn accept_invalid_certs() {
+        use crate::tls::client_config::AcceptInvalidCerts;
+
+        let hostname = DNSNameRef::try_from_ascii_str("other.test").unwrap();
+        assert!(AcceptInvalidCerts.verify_server_cert(&ClientTlsConfig::new().root_certs, &[cert("localhost")], hostname, &[]).is_ok());
+    }
+}
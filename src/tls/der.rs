@@ -0,0 +1,76 @@
+/// Universal DER tags used in certificates.
+pub(crate) const BOOLEAN: u8 = 0x01;
+pub(crate) const OCTET_STRING: u8 = 0x04;
+pub(crate) const OBJECT_IDENTIFIER: u8 = 0x06;
+pub(crate) const SEQUENCE: u8 = 0x30;
+pub(crate) const SET: u8 = 0x31;
+
+/// The context-specific tag of the version in the TBSCertificate of a certificate.
+const VERSION: u8 = 0xa0;
+
+/// Gets the fields of the TBSCertificate of the given DER-encoded certificate, starting from the subject. The subject
+/// is followed by the subject public key info and any extensions. Returns None if the certificate is not valid DER.
+pub(crate) fn fields_from_subject(mut cert: &[u8]) -> Option<&[u8]> {
+    let mut certificate = read_expected(&mut cert, SEQUENCE)?;
+    let mut tbs_certificate = read_expected(&mut certificate, SEQUENCE)?;
+
+    if tbs_certificate.first() == Some(&VERSION) {
+        read(&mut tbs_certificate)?;
+    }
+    // the serial number, signature algorithm, issuer, and validity come before the subject
+    for _ in 0..4 {
+        read(&mut tbs_certificate)?;
+    }
+    Some(tbs_certificate)
+}
+
+/// Reads a DER value from the given input and returns its tag and contents. Returns None if the input does not start
+/// with a valid DER value. Only the low tag numbers used in certificates are supported.
+pub(crate) fn read<'a>(input: &mut &'a [u8]) -> Option<(u8, &'a [u8])> {
+    let (&tag, rest) = input.split_first()?;
+    if tag & 0x1f == 0x1f {
+        return None;
+    }
+    let (&first, mut rest) = rest.split_first()?;
+    let length = if first < 0x80 {
+        first as usize
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let length = rest[..count].iter().fold(0, |length, &byte| (length << 8) | byte as usize);
+        rest = &rest[count..];
+        length
+    };
+    if rest.len() < length {
+        return None;
+    }
+    let (contents, rest) = rest.split_at(length);
+    *input = rest;
+    Some((tag, contents))
+}
+
+/// Reads a DER value with the given tag from the given input and returns its contents.
+pub(crate) fn read_expected<'a>(input: &mut &'a [u8], expected_tag: u8) -> Option<&'a [u8]> {
+    match read(input)? {
+        (tag, contents) if tag == expected_tag => Some(contents),
+        _ => None,
+    }
+}
+
+/// Encodes the given length as a DER length.
+pub(crate) fn encode_length(length: usize) -> Vec<u8> {
+    if length < 0x80 {
+        return vec![length as u8];
+    }
+    let bytes: Vec<u8> = length.to_be_bytes().iter().copied().skip_while(|&byte| byte == 0).collect();
+    std::iter::once(0x80 | bytes.len() as u8).chain(bytes).collect()
+}
+
+/// Reads a DER value from the given input and returns all of it, including its tag and length.
+pub(crate) fn read_encoded<'a>(input: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let start = *input;
+    read(input)?;
+    Some(&start[..start.len() - input.len()])
+}
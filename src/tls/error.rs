@@ -0,0 +1,39 @@
+use std::fmt::{Display, Formatter};
+
+/// Error loading certificates or private keys, or adding them to a certificate resolver.
+#[derive(Debug)]
+pub enum TlsError {
+    /// Error reading a file.
+    Io(std::io::Error),
+    /// The PEM data contains no certificates, or a certificate that is not valid base64.
+    NoCertificates,
+    /// The PEM data contains no PKCS#8 or RSA private keys, or a key that is not valid base64.
+    NoPrivateKey,
+    /// The private key is not of a supported type.
+    UnsupportedPrivateKey,
+    /// The certificate chain is empty, can not be parsed, or is not valid for the hostname it was added for.
+    InvalidCertificate(rustls::TLSError),
+    /// The hostname is not a valid DNS name, or a wildcard followed by a valid DNS name. Contains the hostname.
+    InvalidHostname(String),
+}
+
+impl Display for TlsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsError::Io(error) => write!(f, "error reading file: {}", error),
+            TlsError::NoCertificates => f.write_str("no valid certificates in PEM data"),
+            TlsError::NoPrivateKey => f.write_str("no valid PKCS#8 or RSA private key in PEM data"),
+            TlsError::UnsupportedPrivateKey => f.write_str("unsupported private key type"),
+            TlsError::InvalidCertificate(error) => write!(f, "invalid certificate: {}", error),
+            TlsError::InvalidHostname(hostname) => write!(f, "invalid hostname {:?}", hostname),
+        }
+    }
+}
+
+impl std::error::Error for TlsError {}
+
+impl From<std::io::Error> for TlsError {
+    fn from(error: std::io::Error) -> Self {
+        TlsError::Io(error)
+    }
+}
@@ -0,0 +1,300 @@
+use std::convert::TryFrom;
+use std::fmt::Write;
+use std::net::IpAddr;
+
+use rustls::{Certificate, TLSError};
+
+use crate::tls::der::{BOOLEAN, encode_length, fields_from_subject, OBJECT_IDENTIFIER, OCTET_STRING, read, read_expected, SEQUENCE, SET};
+use crate::tls::TlsError;
+
+/// DER string tags.
+const UTF8_STRING: u8 = 0x0c;
+const NUMERIC_STRING: u8 = 0x12;
+const PRINTABLE_STRING: u8 = 0x13;
+const TELETEX_STRING: u8 = 0x14;
+const IA5_STRING: u8 = 0x16;
+const VISIBLE_STRING: u8 = 0x1a;
+const BMP_STRING: u8 = 0x1e;
+
+/// The context-specific tag of the extensions in the TBSCertificate of a certificate.
+const EXTENSIONS: u8 = 0xa3;
+
+/// Context-specific tags of the general names in a subject alternative name extension.
+const RFC822_NAME: u8 = 0x81;
+const DNS_NAME: u8 = 0x82;
+const URI: u8 = 0x86;
+const IP_ADDRESS: u8 = 0x87;
+
+/// The encoded OID of the subject alternative name extension, 2.5.29.17.
+const SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+
+/// The encoded OID of the common name attribute, 2.5.4.3.
+const COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+/// The names of the attribute types that are written by name in distinguished names, as listed in RFC 4514.
+const ATTRIBUTE_NAMES: [(&[u8], &str); 9] = [
+    (COMMON_NAME, "CN"),
+    (&[0x55, 0x04, 0x07], "L"),
+    (&[0x55, 0x04, 0x08], "ST"),
+    (&[0x55, 0x04, 0x0a], "O"),
+    (&[0x55, 0x04, 0x0b], "OU"),
+    (&[0x55, 0x04, 0x06], "C"),
+    (&[0x55, 0x04, 0x09], "STREET"),
+    (&[0x09, 0x92, 0x26, 0x89, 0x93, 0xf2, 0x2c, 0x64, 0x01, 0x19], "DC"),
+    (&[0x09, 0x92, 0x26, 0x89, 0x93, 0xf2, 0x2c, 0x64, 0x01, 0x01], "UID"),
+];
+
+/// The identity a certificate was issued to, such as the identity of a client authenticated with a client certificate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CertificateIdentity {
+    /// The distinguished name of the subject, formatted as in RFC 4514, such as "CN=client,O=Example Inc,C=US".
+    pub subject: String,
+    /// The value of the first common name (CN) attribute of the subject, if any.
+    pub common_name: Option<String>,
+    /// The names in the subject alternative name extension, in the order they appear in the certificate.
+    pub subject_alt_names: Vec<SubjectAltName>,
+}
+
+/// A name in the subject alternative name extension of a certificate. Kinds of names other than these are ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubjectAltName {
+    /// A DNS name, such as "client.example.com".
+    Dns(String),
+    /// An email address (an RFC 822 name).
+    Email(String),
+    /// A URI, such as a SPIFFE ID.
+    Uri(String),
+    /// An IP address.
+    Ip(IpAddr),
+}
+
+impl CertificateIdentity {
+    /// Parses the subject and subject alternative names of the given DER-encoded certificate.
+    /// Returns an InvalidCertificate error if the certificate is not valid DER.
+    pub fn from_certificate(cert: &Certificate) -> Result<CertificateIdentity, TlsError> {
+        parse_identity(&cert.0).ok_or(TlsError::InvalidCertificate(TLSError::WebPKIError(webpki::Error::BadDER)))
+    }
+
+    /// Gets the DNS names in the subject alternative names.
+    pub fn dns_names(&self) -> impl Iterator<Item=&str> {
+        self.subject_alt_names.iter().filter_map(|name| match name {
+            SubjectAltName::Dns(name) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+}
+
+/// Parses the identity in the given certificate, or returns None if it is not valid DER.
+fn parse_identity(cert: &[u8]) -> Option<CertificateIdentity> {
+    let mut tbs_certificate = fields_from_subject(cert)?;
+    let subject = read_expected(&mut tbs_certificate, SEQUENCE)?;
+
+    let mut subject_alt_names = vec![];
+    while !tbs_certificate.is_empty() {
+        let (tag, mut contents) = read(&mut tbs_certificate)?;
+        if tag == EXTENSIONS {
+            subject_alt_names = parse_subject_alt_names(read_expected(&mut contents, SEQUENCE)?)?;
+        }
+    }
+
+    let (subject, common_name) = parse_name(subject)?;
+    Some(CertificateIdentity { subject, common_name, subject_alt_names })
+}
+
+/// Formats the given distinguished name as in RFC 4514, where the last relative distinguished name comes first.
+/// Also returns the first common name in the name.
+fn parse_name(mut name: &[u8]) -> Option<(String, Option<String>)> {
+    let mut rdns = vec![];
+    let mut common_name = None;
+    while !name.is_empty() {
+        let mut rdn = read_expected(&mut name, SET)?;
+        let mut attributes = vec![];
+        while !rdn.is_empty() {
+            let mut attribute = read_expected(&mut rdn, SEQUENCE)?;
+            let oid = read_expected(&mut attribute, OBJECT_IDENTIFIER)?;
+            let (tag, contents) = read(&mut attribute)?;
+            let value = decode_string(tag, contents);
+
+            let mut formatted = match ATTRIBUTE_NAMES.iter().find(|(name_oid, _)| *name_oid == oid) {
+                Some((_, name)) => format!("{}=", name),
+                None => format!("{}=", format_oid(oid)?),
+            };
+            match &value {
+                Some(value) => escape_value(value, &mut formatted),
+                // values that are not strings are written as the hex of their DER encoding
+                None => {
+                    write!(formatted, "#{:02x}", tag).unwrap();
+                    for byte in encode_length(contents.len()).iter().chain(contents) {
+                        write!(formatted, "{:02x}", byte).unwrap();
+                    }
+                }
+            }
+            if oid == COMMON_NAME && common_name.is_none() {
+                common_name = value;
+            }
+            attributes.push(formatted);
+        }
+        rdns.push(attributes.join("+"));
+    }
+    rdns.reverse();
+    Some((rdns.join(","), common_name))
+}
+
+/// Parses the names in the subject alternative name extension from the given extensions, if there is one.
+fn parse_subject_alt_names(mut extensions: &[u8]) -> Option<Vec<SubjectAltName>> {
+    while !extensions.is_empty() {
+        let mut extension = read_expected(&mut extensions, SEQUENCE)?;
+        let oid = read_expected(&mut extension, OBJECT_IDENTIFIER)?;
+        if extension.first() == Some(&BOOLEAN) {
+            read(&mut extension)?;
+        }
+        let mut value = read_expected(&mut extension, OCTET_STRING)?;
+        if oid != SUBJECT_ALT_NAME {
+            continue;
+        }
+
+        let mut general_names = read_expected(&mut value, SEQUENCE)?;
+        let mut names = vec![];
+        while !general_names.is_empty() {
+            let (tag, contents) = read(&mut general_names)?;
+            let name = match tag {
+                RFC822_NAME => SubjectAltName::Email(String::from_utf8(contents.to_vec()).ok()?),
+                DNS_NAME => SubjectAltName::Dns(String::from_utf8(contents.to_vec()).ok()?),
+                URI => SubjectAltName::Uri(String::from_utf8(contents.to_vec()).ok()?),
+                IP_ADDRESS => match contents.len() {
+                    4 => SubjectAltName::Ip(IpAddr::from(<[u8; 4]>::try_from(contents).ok()?)),
+                    16 => SubjectAltName::Ip(IpAddr::from(<[u8; 16]>::try_from(contents).ok()?)),
+                    _ => return None,
+                },
+                _ => continue,
+            };
+            names.push(name);
+        }
+        return Some(names);
+    }
+    Some(vec![])
+}
+
+/// Decodes the given DER string, or returns None if the tag is not a string type.
+fn decode_string(tag: u8, contents: &[u8]) -> Option<String> {
+    match tag {
+        UTF8_STRING | NUMERIC_STRING | PRINTABLE_STRING | IA5_STRING | VISIBLE_STRING => String::from_utf8(contents.to_vec()).ok(),
+        // teletex strings are treated as Latin-1, as most implementations do
+        TELETEX_STRING => Some(contents.iter().map(|&byte| byte as char).collect()),
+        BMP_STRING if contents.len() % 2 == 0 => {
+            let units: Vec<u16> = contents.chunks(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+            String::from_utf16(&units).ok()
+        }
+        _ => None,
+    }
+}
+
+/// Formats the given encoded OID in dotted decimal form, such as "2.5.4.3".
+fn format_oid(oid: &[u8]) -> Option<String> {
+    // the last byte of every arc has its high bit clear
+    if oid.last()? & 0x80 != 0 {
+        return None;
+    }
+    let mut arcs = vec![];
+    let mut arc: u64 = 0;
+    for &byte in oid {
+        arc = arc.checked_mul(128)? | (byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            arcs.push(arc);
+            arc = 0;
+        }
+    }
+    let (&first, rest) = arcs.split_first()?;
+    let (a, b) = if first < 80 { (first / 40, first % 40) } else { (2, first - 80) };
+    let mut formatted = format!("{}.{}", a, b);
+    for arc in rest {
+        write!(formatted, ".{}", arc).unwrap();
+    }
+    Some(formatted)
+}
+
+/// Appends the given attribute value to the given string, escaped as in RFC 4514.
+fn escape_value(value: &str, out: &mut String) {
+    let last = value.chars().count().saturating_sub(1);
+    for (i, c) in value.chars().enumerate() {
+        match c {
+            ',' | '+' | '"' | '\\' | '<' | '>' | ';' => out.push('\\'),
+            '#' if i == 0 => out.push('\\'),
+            ' ' if i == 0 || i == last => out.push('\\'),
+            '\0' => {
+                out.push_str("\\00");
+                continue;
+            }
+            _ => {}
+        }
+        out.push(c);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use rustls::Certificate;
+
+    use crate::tls::{CertificateIdentity, load_certs, SubjectAltName, TlsError};
+    use crate::tls::identity::{escape_value, format_oid, parse_name};
+
+    fn identity(name: &str) -> CertificateIdentity {
+        CertificateIdentity::from_certificate(&load_certs(format!("./tests/certs/{}.crt", name)).unwrap()[0]).unwrap()
+    }
+
+    #[test]
+    fn client_certificate() {
+        let identity = identity("client");
+        assert_eq!(identity.subject, "CN=client,OU=Clients\\, Inc.,O=my_http tests,C=US");
+        assert_eq!(identity.common_name.as_deref(), Some("client"));
+        assert_eq!(identity.subject_alt_names, vec![
+            SubjectAltName::Dns("client.test".into()),
+            SubjectAltName::Email("client@example.test".into()),
+            SubjectAltName::Uri("spiffe://example.test/client".into()),
+            SubjectAltName::Ip(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))),
+        ]);
+        assert_eq!(identity.dns_names().collect::<Vec<&str>>(), vec!["client.test"]);
+    }
+
+    #[test]
+    fn server_certificate() {
+        let identity = identity("example");
+        assert_eq!(identity.subject, "CN=example.test");
+        assert_eq!(identity.dns_names().collect::<Vec<&str>>(), vec!["example.test"]);
+    }
+
+    #[test]
+    fn invalid_der() {
+        let cert = Certificate(vec![0x30, 0x05, 0x30, 0x01]);
+        assert!(matches!(CertificateIdentity::from_certificate(&cert), Err(TlsError::InvalidCertificate(_))));
+    }
+
+    #[test]
+    fn unknown_attributes_and_multi_valued_rdns() {
+        // SET { SEQUENCE { 2.5.4.3, UTF8String "a" }, SEQUENCE { 1.2.840.113549.1.9.1, INTEGER 5 } }, SET { SEQUENCE { 2.5.4.10, PrintableString "b" } }
+        let name = [
+            0x31, 0x1a,
+            0x30, 0x08, 0x06, 0x03, 0x55, 0x04, 0x03, 0x0c, 0x01, b'a',
+            0x30, 0x0e, 0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x01, 0x02, 0x01, 0x05,
+            0x31, 0x0a,
+            0x30, 0x08, 0x06, 0x03, 0x55, 0x04, 0x0a, 0x13, 0x01, b'b',
+        ];
+        assert_eq!(parse_name(&name), Some(("O=b,CN=a+1.2.840.113549.1.9.1=#020105".to_string(), Some("a".to_string()))));
+    }
+
+    #[test]
+    fn oids() {
+        assert_eq!(format_oid(&[0x55, 0x04, 0x03]).as_deref(), Some("2.5.4.3"));
+        assert_eq!(format_oid(&[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d]).as_deref(), Some("1.2.840.113549"));
+        assert_eq!(format_oid(&[0x55, 0x86]), None);
+    }
+
+    #[test]
+    fn escaping() {
+        let mut out = String::new();
+        escape_value("#a, b+c\\ ", &mut out);
+        assert_eq!(out, "\\#a\\, b\\+c\\\\\\ ");
+    }
+}
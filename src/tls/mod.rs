@@ -0,0 +1,27 @@
+pub use cert_resolver::*;
+pub use client_auth::*;
+pub use client_config::*;
+pub use error::*;
+pub use identity::*;
+pub use pem::*;
+pub use reload::*;
+pub use server_config::*;
+
+/// Picking server certificates by hostname.
+mod cert_resolver;
+/// Verifying client certificates on servers.
+mod client_auth;
+/// TLS settings for clients.
+mod client_config;
+/// Reading the DER encoding of certificates.
+mod der;
+/// Errors from loading certificates and keys.
+mod error;
+/// Parsing the identity in certificates.
+mod identity;
+/// Loading certificates and private keys from PEM files.
+mod pem;
+/// Certificates that can be replaced while a server is running.
+mod reload;
+/// TLS settings for servers.
+mod server_config;
@@ -0,0 +1,69 @@
+use std::path::Path;
+
+use rustls::{Certificate, PrivateKey};
+use rustls::internal::pemfile;
+
+use crate::tls::TlsError;
+
+/// Loads all certificates from the PEM file at the given path, such as a certificate chain or a CA bundle.
+pub fn load_certs(path: impl AsRef<Path>) -> Result<Vec<Certificate>, TlsError> {
+    read_certs(&std::fs::read(path)?)
+}
+
+/// Loads the first private key from the PEM file at the given path. PKCS#8 keys are preferred over RSA (PKCS#1) keys.
+pub fn load_private_key(path: impl AsRef<Path>) -> Result<PrivateKey, TlsError> {
+    read_private_key(&std::fs::read(path)?)
+}
+
+/// Reads all certificates from the given PEM data.
+pub fn read_certs(pem: &[u8]) -> Result<Vec<Certificate>, TlsError> {
+    match pemfile::certs(&mut &pem[..]) {
+        Ok(certs) if !certs.is_empty() => Ok(certs),
+        _ => Err(TlsError::NoCertificates),
+    }
+}
+
+/// Reads the first private key from the given PEM data. PKCS#8 keys are preferred over RSA (PKCS#1) keys.
+pub fn read_private_key(pem: &[u8]) -> Result<PrivateKey, TlsError> {
+    let pkcs8_keys = pemfile::pkcs8_private_keys(&mut &pem[..]).map_err(|_| TlsError::NoPrivateKey)?;
+    let rsa_keys = pemfile::rsa_private_keys(&mut &pem[..]).map_err(|_| TlsError::NoPrivateKey)?;
+    pkcs8_keys.into_iter().chain(rsa_keys).next().ok_or(TlsError::NoPrivateKey)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tls::{load_certs, load_private_key, read_certs, read_private_key, TlsError};
+
+    #[test]
+    fn certs() {
+        assert_eq!(load_certs("./tests/certs/localhost.crt").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn pkcs8_key() {
+        assert!(load_private_key("./tests/certs/localhost.key").is_ok());
+    }
+
+    #[test]
+    fn rsa_key() {
+        assert!(load_private_key("./tests/certs/wildcard.key").is_ok());
+    }
+
+    #[test]
+    fn missing_file() {
+        assert!(matches!(load_certs("./tests/certs/missing.crt"), Err(TlsError::Io(_))));
+    }
+
+    #[test]
+    fn no_certs() {
+        assert!(matches!(read_certs(b"not pem"), Err(TlsError::NoCertificates)));
+        let key = std::fs::read("./tests/certs/localhost.key").unwrap();
+        assert!(matches!(read_certs(&key), Err(TlsError::NoCertificates)));
+    }
+
+    #[test]
+    fn no_private_key() {
+        let cert = std::fs::read("./tests/certs/localhost.crt").unwrap();
+        assert!(matches!(read_private_key(&cert), Err(TlsError::NoPrivateKey)));
+    }
+}
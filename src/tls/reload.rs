@@ -0,0 +1,265 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock, Weak};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use rustls::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+
+use crate::tls::{CertResolver, load_certs, load_private_key, TlsError};
+
+/// The PEM files of a certificate chain and its private key.
+#[derive(Debug, Clone)]
+pub struct CertFiles {
+    /// The hostname the certificate is for, as passed to CertResolver::add, or None for the default certificate.
+    pub hostname: Option<String>,
+    /// The PEM file of the certificate chain.
+    pub cert_path: PathBuf,
+    /// The PEM file of the private key.
+    pub key_path: PathBuf,
+}
+
+impl CertFiles {
+    /// Creates the files of a certificate for the given hostname, which may be a wildcard.
+    pub fn new(hostname: &str, cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> CertFiles {
+        CertFiles { hostname: Some(hostname.into()), cert_path: cert_path.into(), key_path: key_path.into() }
+    }
+
+    /// Creates the files of the certificate for clients that do not use SNI or request a hostname with no certificate.
+    pub fn default_cert(cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> CertFiles {
+        CertFiles { hostname: None, cert_path: cert_path.into(), key_path: key_path.into() }
+    }
+
+    /// Loads the certificate and key and adds them to the given resolver.
+    fn add_to(&self, resolver: &mut CertResolver) -> Result<(), TlsError> {
+        let certs = load_certs(&self.cert_path)?;
+        let key = load_private_key(&self.key_path)?;
+        match &self.hostname {
+            Some(hostname) => resolver.add(hostname, certs, &key),
+            None => resolver.set_default(certs, &key),
+        }
+    }
+}
+
+/// Certificates that can be replaced while the server is running, either explicitly or by reloading them from their
+/// files. New TLS handshakes use the new certificates, while connections that have already been established keep
+/// going. Clones share the same certificates, so a clone can be kept to reload the certificates after the config has
+/// been passed to listen_https.
+/// ```
+/// use std::time::Duration;
+/// use my_http::tls::{CertFiles, ReloadableCerts, TlsConfig};
+///
+/// let certs = ReloadableCerts::from_files(vec![
+///     CertFiles::new("localhost", "./tests/certs/localhost.crt", "./tests/certs/localhost.key"),
+/// ]).unwrap();
+/// certs.watch(Duration::from_secs(60));
+///
+/// let mut tls_config = TlsConfig::default();
+/// tls_config.reloadable_certs = Some(certs.clone());
+/// // later, such as after the certificates are renewed
+/// certs.reload().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct ReloadableCerts {
+    state: Arc<State>,
+}
+
+struct State {
+    resolver: RwLock<Arc<CertResolver>>,
+    /// The files the certificates are loaded from, if any.
+    files: Vec<CertFiles>,
+}
+
+impl ReloadableCerts {
+    /// Creates reloadable certificates that start out as the certificates in the given resolver. They can only be
+    /// changed with replace.
+    pub fn new(resolver: CertResolver) -> ReloadableCerts {
+        ReloadableCerts { state: Arc::new(State { resolver: RwLock::new(Arc::new(resolver)), files: vec![] }) }
+    }
+
+    /// Loads the certificates from the given files, which are loaded again on reload.
+    pub fn from_files(files: Vec<CertFiles>) -> Result<ReloadableCerts, TlsError> {
+        let resolver = load(&files)?;
+        Ok(ReloadableCerts { state: Arc::new(State { resolver: RwLock::new(Arc::new(resolver)), files }) })
+    }
+
+    /// Replaces all of the certificates with the ones in the given resolver.
+    pub fn replace(&self, resolver: CertResolver) {
+        *self.state.resolver.write().unwrap() = Arc::new(resolver);
+    }
+
+    /// Loads the certificates from their files again, and replaces the current certificates with them. If any of the
+    /// files can not be loaded, then the error is returned and the current certificates are kept.
+    /// Does nothing if the certificates were not loaded from files.
+    pub fn reload(&self) -> Result<(), TlsError> {
+        if self.state.files.is_empty() {
+            return Ok(());
+        }
+        let resolver = load(&self.state.files)?;
+        self.replace(resolver);
+        Ok(())
+    }
+
+    /// Starts a thread that checks the modification times of the certificate files at the given interval, and reloads
+    /// the certificates when any of them change. Errors from reloading are printed, and the files are only reloaded
+    /// again once they change again. The thread stops once all clones of these certificates have been dropped.
+    pub fn watch(&self, interval: Duration) {
+        let state = Arc::downgrade(&self.state);
+        let mut modified = modification_times(&self.state.files);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            if !watch_files(&state, &mut modified) {
+                return;
+            }
+        });
+    }
+}
+
+impl ResolvesServerCert for ReloadableCerts {
+    fn resolve(&self, client_hello: ClientHello) -> Option<CertifiedKey> {
+        let resolver = self.state.resolver.read().unwrap().clone();
+        resolver.resolve(client_hello)
+    }
+}
+
+/// Reloads the certificates if the modification times of their files are different from the given times, which are
+/// updated. Returns false if the certificates have been dropped.
+fn watch_files(state: &Weak<State>, modified: &mut Vec<Option<SystemTime>>) -> bool {
+    let state = match state.upgrade() {
+        Some(state) => state,
+        None => return false,
+    };
+    let current = modification_times(&state.files);
+    if current != *modified {
+        *modified = current;
+        match load(&state.files) {
+            Ok(resolver) => *state.resolver.write().unwrap() = Arc::new(resolver),
+            Err(error) => println!("Error: reloading certificates failed, keeping the current certificates: {}", error),
+        }
+    }
+    true
+}
+
+/// Loads the given certificate files into a new resolver.
+fn load(files: &[CertFiles]) -> Result<CertResolver, TlsError> {
+    let mut resolver = CertResolver::new();
+    for files in files {
+        files.add_to(&mut resolver)?;
+    }
+    Ok(resolver)
+}
+
+/// Gets the modification times of the certificate and key files, with None for files whose time can not be read.
+fn modification_times(files: &[CertFiles]) -> Vec<Option<SystemTime>> {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    files.iter().flat_map(|files| vec![modified(&files.cert_path), modified(&files.key_path)]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use rustls::Certificate;
+
+    use crate::tls::{CertFiles, CertResolver, load_certs, load_private_key, ReloadableCerts, TlsError};
+
+    /// A directory for the certificate files of a test, which is removed when dropped.
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> TestDir {
+            let dir = std::env::temp_dir().join(format!("my_http_reload_{}_{}", name, std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            TestDir(dir)
+        }
+
+        /// Copies the certificate and key with the given name from tests/certs to cert.pem and key.pem.
+        fn copy(&self, name: &str) {
+            std::fs::copy(format!("./tests/certs/{}.crt", name), self.0.join("cert.pem")).unwrap();
+            std::fs::copy(format!("./tests/certs/{}.key", name), self.0.join("key.pem")).unwrap();
+        }
+
+        fn files(&self) -> Vec<CertFiles> {
+            vec![CertFiles::default_cert(self.0.join("cert.pem"), self.0.join("key.pem"))]
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).unwrap_or_default();
+        }
+    }
+
+    fn cert(name: &str) -> Certificate {
+        load_certs(format!("./tests/certs/{}.crt", name)).unwrap().remove(0)
+    }
+
+    /// Gets the first certificate of the chain the given certificates use for clients without SNI.
+    fn current(certs: &ReloadableCerts) -> Option<Certificate> {
+        certs.state.resolver.read().unwrap().find(None).map(|key| key.cert[0].clone())
+    }
+
+    #[test]
+    fn reload() {
+        let dir = TestDir::new("reload");
+        dir.copy("localhost");
+        let certs = ReloadableCerts::from_files(dir.files()).unwrap();
+        assert_eq!(current(&certs), Some(cert("localhost")));
+
+        dir.copy("example");
+        assert_eq!(current(&certs), Some(cert("localhost")));
+        certs.reload().unwrap();
+        assert_eq!(current(&certs), Some(cert("example")));
+    }
+
+    #[test]
+    fn reload_error_keeps_certs() {
+        let dir = TestDir::new("reload_error");
+        dir.copy("localhost");
+        let certs = ReloadableCerts::from_files(dir.files()).unwrap();
+
+        std::fs::write(dir.0.join("cert.pem"), "not a certificate").unwrap();
+        assert!(matches!(certs.reload(), Err(TlsError::NoCertificates)));
+        assert_eq!(current(&certs), Some(cert("localhost")));
+    }
+
+    #[test]
+    fn missing_files() {
+        let dir = TestDir::new("missing_files");
+        assert!(matches!(ReloadableCerts::from_files(dir.files()), Err(TlsError::Io(_))));
+    }
+
+    #[test]
+    fn replace() {
+        let mut resolver = CertResolver::new();
+        resolver.set_default(vec![cert("localhost")], &load_private_key("./tests/certs/localhost.key").unwrap()).unwrap();
+        let certs = ReloadableCerts::new(resolver);
+        let clone = certs.clone();
+
+        let mut resolver = CertResolver::new();
+        resolver.set_default(vec![cert("example")], &load_private_key("./tests/certs/example.key").unwrap()).unwrap();
+        clone.replace(resolver);
+        assert_eq!(current(&certs), Some(cert("example")));
+
+        // there are no files to reload from
+        certs.reload().unwrap();
+        assert_eq!(current(&certs), Some(cert("example")));
+    }
+
+    #[test]
+    fn watch() {
+        let dir = TestDir::new("watch");
+        dir.copy("localhost");
+        let certs = ReloadableCerts::from_files(dir.files()).unwrap();
+        certs.watch(Duration::from_millis(10));
+
+        dir.copy("example");
+        let start = Instant::now();
+        while current(&certs) != Some(cert("example")) {
+            assert!(start.elapsed() < Duration::from_secs(5), "certificates were not reloaded");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use rustls::ServerConfig;
+
+use crate::tls::{CertResolver, ClientAuth, ReloadableCerts};
+
+/// TLS settings for an HTTPS server, which can be passed to listen_https in place of a rustls ServerConfig.
+/// ```
+/// use my_http::tls::{load_certs, load_private_key, TlsConfig};
+///
+/// let mut tls_config = TlsConfig::default();
+/// tls_config.cert_resolver.add("localhost", load_certs("./tests/certs/localhost.crt").unwrap(), &load_private_key("./tests/certs/localhost.key").unwrap()).unwrap();
+/// tls_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+/// ```
+pub struct TlsConfig {
+    /// Picks the certificate for each connection based on the hostname the client requested with SNI.
+    pub cert_resolver: CertResolver,
+    /// Certificates that can be replaced while the server is running. If set, they are used instead of cert_resolver.
+    pub reloadable_certs: Option<ReloadableCerts>,
+    /// The protocols offered with ALPN, in order of preference. Defaults to HTTP/2 and HTTP/1.1 with the http2 feature,
+    /// and only HTTP/1.1 without it.
+    pub alpn_protocols: Vec<Vec<u8>>,
+    /// Whether clients are asked for certificates. Defaults to not asking.
+    pub client_auth: ClientAuth,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            cert_resolver: CertResolver::new(),
+            reloadable_certs: None,
+            alpn_protocols: default_alpn_protocols(),
+            client_auth: ClientAuth::None,
+        }
+    }
+}
+
+impl From<TlsConfig> for ServerConfig {
+    fn from(config: TlsConfig) -> Self {
+        let mut server_config = config.client_auth.into_server_config();
+        server_config.cert_resolver = match config.reloadable_certs {
+            Some(certs) => Arc::new(certs),
+            None => Arc::new(config.cert_resolver),
+        };
+        server_config.set_protocols(&config.alpn_protocols);
+        server_config
+    }
+}
+
+/// Gets the protocols offered with ALPN by default, in order of preference.
+pub(crate) fn default_alpn_protocols() -> Vec<Vec<u8>> {
+    let mut protocols = vec![];
+    if cfg!(feature = "http2") {
+        protocols.push(b"h2".to_vec());
+    }
+    protocols.push(b"http/1.1".to_vec());
+    protocols
+}
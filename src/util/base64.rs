@@ -0,0 +1,111 @@
+/// The standard base64 alphabet.
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Padding character.
+const PAD: u8 = b'=';
+
+/// Encodes the given bytes as a padded base64 string using the standard alphabet.
+pub fn encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let b0 = chunk[0] as u32;
+        let b1 = chunk.get(1).cloned().unwrap_or(0) as u32;
+        let b2 = chunk.get(2).cloned().unwrap_or(0) as u32;
+        let triple = (b0 << 16) | (b1 << 8) | b2;
+
+        out.push(ALPHABET[(triple >> 18) as usize & 0x3F] as char);
+        out.push(ALPHABET[(triple >> 12) as usize & 0x3F] as char);
+        out.push(if chunk.len() > 1 { ALPHABET[(triple >> 6) as usize & 0x3F] } else { PAD } as char);
+        out.push(if chunk.len() > 2 { ALPHABET[triple as usize & 0x3F] } else { PAD } as char);
+    }
+
+    out
+}
+
+/// Decodes the given padded base64 string using the standard alphabet. Returns None if the string is not valid base64.
+pub fn decode(data: &str) -> Option<Vec<u8>> {
+    let data = data.as_bytes();
+    if data.len() % 4 != 0 {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(data.len() / 4 * 3);
+
+    for (i, chunk) in data.chunks(4).enumerate() {
+        let is_last = i == data.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|b| **b == PAD).count();
+        if padding > 2 || (padding > 0 && !is_last) {
+            return None;
+        }
+
+        let mut triple = 0u32;
+        for (j, byte) in chunk.iter().enumerate() {
+            let value = if j >= 4 - padding { 0 } else { decode_byte(*byte)? };
+            triple |= (value as u32) << (18 - 6 * j);
+        }
+
+        out.push((triple >> 16) as u8);
+        if padding < 2 {
+            out.push((triple >> 8) as u8);
+        }
+        if padding < 1 {
+            out.push(triple as u8);
+        }
+    }
+
+    Some(out)
+}
+
+/// Gets the 6 bit value of the given base64 character.
+fn decode_byte(byte: u8) -> Option<u8> {
+    match byte {
+        b'A'..=b'Z' => Some(byte - b'A'),
+        b'a'..=b'z' => Some(byte - b'a' + 26),
+        b'0'..=b'9' => Some(byte - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::base64::{decode, encode};
+
+    #[test]
+    fn encode_rfc_4648_vectors() {
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"f"), "Zg==");
+        assert_eq!(encode(b"fo"), "Zm8=");
+        assert_eq!(encode(b"foo"), "Zm9v");
+        assert_eq!(encode(b"foob"), "Zm9vYg==");
+        assert_eq!(encode(b"fooba"), "Zm9vYmE=");
+        assert_eq!(encode(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn decode_rfc_4648_vectors() {
+        assert_eq!(decode("").unwrap(), b"");
+        assert_eq!(decode("Zg==").unwrap(), b"f");
+        assert_eq!(decode("Zm8=").unwrap(), b"fo");
+        assert_eq!(decode("Zm9v").unwrap(), b"foo");
+        assert_eq!(decode("Zm9vYg==").unwrap(), b"foob");
+        assert_eq!(decode("Zm9vYmE=").unwrap(), b"fooba");
+        assert_eq!(decode("Zm9vYmFy").unwrap(), b"foobar");
+    }
+
+    #[test]
+    fn round_trip_binary() {
+        let data: Vec<u8> = (0..=255).collect();
+        assert_eq!(decode(&encode(&data)).unwrap(), data);
+    }
+
+    #[test]
+    fn decode_invalid() {
+        assert!(decode("Zm9").is_none());
+        assert!(decode("Zm9*").is_none());
+        assert!(decode("Z===").is_none());
+        assert!(decode("Zg==Zm9v").is_none());
+    }
+}
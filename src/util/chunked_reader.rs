@@ -0,0 +1,175 @@
+use std::io::{BufRead, Error, ErrorKind, Read, Result};
+
+use crate::util::chunked_reader::State::{Data, DataEnd, Finished, Size, Trailers};
+
+/// The maximum length of a chunk size or trailer line.
+const MAX_LINE_SIZE: u64 = 512;
+
+/// A reader that decodes a body encoded using chunked transfer coding as it is read, rather than reading the whole body
+/// first. Reaches EOF after the last chunk. Partially read chunk size lines are kept, so reads can be retried after
+/// WouldBlock or TimedOut errors.
+pub struct ChunkedReader<R: BufRead> {
+    inner: R,
+    state: State,
+    /// The chunk size or trailer line being read.
+    line: String,
+}
+
+/// The part of the body being read.
+enum State {
+    /// The line with the size of the next chunk.
+    Size,
+    /// The data of a chunk, with the given amount left.
+    Data(usize),
+    /// The CRLF after the data of a chunk.
+    DataEnd,
+    /// The trailer section after the last chunk.
+    Trailers,
+    /// The end of the body.
+    Finished,
+}
+
+impl<R: BufRead> ChunkedReader<R> {
+    /// Creates a new chunked reader that reads a body from the given reader.
+    pub fn new(inner: R) -> ChunkedReader<R> {
+        ChunkedReader { inner, state: Size, line: String::new() }
+    }
+
+    /// Reads a whole CRLF terminated line and returns it without the line break.
+    fn read_line(&mut self) -> Result<String> {
+        let limit = MAX_LINE_SIZE - self.line.len() as u64;
+        (&mut self.inner).take(limit).read_line(&mut self.line)?;
+        if !self.line.ends_with('\n') {
+            let kind = if self.line.len() as u64 >= MAX_LINE_SIZE { ErrorKind::InvalidData } else { ErrorKind::UnexpectedEof };
+            return Err(Error::new(kind, "incomplete chunk line"));
+        }
+
+        let mut line = std::mem::take(&mut self.line);
+        line.pop();
+        if line.pop() != Some('\r') {
+            return Err(Error::new(ErrorKind::InvalidData, "chunk line not terminated by CRLF"));
+        }
+        Ok(line)
+    }
+}
+
+impl<R: BufRead> BufRead for ChunkedReader<R> {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        loop {
+            self.state = match self.state {
+                Size => {
+                    let line = self.read_line()?;
+                    // chunk extensions are ignored
+                    let size = line.split(';').next().unwrap_or("").trim();
+                    match usize::from_str_radix(size, 16) {
+                        Ok(0) => Trailers,
+                        Ok(size) => Data(size),
+                        Err(_) => return Err(Error::new(ErrorKind::InvalidData, "invalid chunk size")),
+                    }
+                }
+                Data(0) => DataEnd,
+                Data(_) => break,
+                DataEnd => {
+                    if !self.read_line()?.is_empty() {
+                        return Err(Error::new(ErrorKind::InvalidData, "chunk data longer than chunk size"));
+                    }
+                    Size
+                }
+                // trailer fields are ignored
+                Trailers => if self.read_line()?.is_empty() { Finished } else { Trailers },
+                Finished => return Ok(&[]),
+            }
+        }
+
+        let remaining = if let Data(remaining) = self.state { remaining } else { 0 };
+        let buf = self.inner.fill_buf()?;
+        if buf.is_empty() {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        Ok(&buf[..buf.len().min(remaining)])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Data(remaining) = &mut self.state {
+            let amt = amt.min(*remaining);
+            *remaining -= amt;
+            self.inner.consume(amt);
+        }
+    }
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let amount = {
+            let data = self.fill_buf()?;
+            let amount = data.len().min(buf.len());
+            buf[..amount].copy_from_slice(&data[..amount]);
+            amount
+        };
+        self.consume(amount);
+        Ok(amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, ErrorKind, Read};
+
+    use crate::util::chunked_reader::ChunkedReader;
+    use crate::util::mock::MockReader;
+
+    fn read_all(data: Vec<&str>) -> std::io::Result<String> {
+        let mut reader = ChunkedReader::new(BufReader::new(MockReader::from_strs(data)));
+        let mut out = String::new();
+        reader.read_to_string(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn multiple_chunks() {
+        assert_eq!(read_all(vec!["5\r\nhello\r\n1;ext=1\r\n \r\n6\r\nworld!\r\n0\r\n\r\n"]).unwrap(), "hello world!");
+    }
+
+    #[test]
+    fn fragmented() {
+        assert_eq!(read_all(vec!["5", "\r", "\nhe", "llo\r", "\n0\r\n", "\r\n"]).unwrap(), "hello");
+    }
+
+    #[test]
+    fn trailers() {
+        assert_eq!(read_all(vec!["3\r\nabc\r\n0\r\nexpires: never\r\n\r\n"]).unwrap(), "abc");
+    }
+
+    #[test]
+    fn stops_after_last_chunk() {
+        let mut reader = ChunkedReader::new(BufReader::new(MockReader::from_strs(vec!["1\r\na\r\n0\r\n\r\nmore"])));
+        let mut out = String::new();
+        reader.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "a");
+        assert_eq!(reader.read(&mut [0; 4]).unwrap(), 0);
+    }
+
+    #[test]
+    fn invalid_chunks() {
+        assert_eq!(read_all(vec!["x\r\n"]).unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(read_all(vec!["1\r\nab\r\n0\r\n\r\n"]).unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(read_all(vec!["5\r\nab"]).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn partial_reads_can_be_retried() {
+        let mut reader = MockReader::from_strs(vec!["1", "0\r", "\nhel"]);
+        reader.return_would_block_when_empty = true;
+        let mut reader = ChunkedReader::new(BufReader::with_capacity(1, reader));
+
+        let mut buf = [0; 16];
+        let mut data = vec![];
+        while data.len() < 3 {
+            let amount = reader.read(&mut buf).unwrap();
+            data.extend_from_slice(&buf[..amount]);
+        }
+        assert_eq!(data, b"hel");
+        assert_eq!(reader.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+        assert_eq!(reader.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+    }
+}
@@ -0,0 +1,65 @@
+use std::io::{Result, Write};
+
+/// A writer that encodes everything written to it using chunked transfer coding.
+/// Each call to write produces one chunk. The last chunk is written with finish.
+pub struct ChunkedWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    /// Creates a new chunked writer that writes chunks to the given writer.
+    pub fn new(inner: W) -> ChunkedWriter<W> {
+        ChunkedWriter { inner }
+    }
+
+    /// Writes the last chunk, which marks the end of the body, and flushes the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.inner.write_all(b"0\r\n\r\n")?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        // an empty chunk would be interpreted as the last chunk
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        write!(self.inner, "{:x}\r\n", buf.len())?;
+        self.inner.write_all(buf)?;
+        self.inner.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::util::chunked_writer::ChunkedWriter;
+
+    #[test]
+    fn no_chunks() {
+        let writer = ChunkedWriter::new(vec![]);
+        assert_eq!(writer.finish().unwrap(), b"0\r\n\r\n");
+    }
+
+    #[test]
+    fn multiple_chunks() {
+        let mut writer = ChunkedWriter::new(vec![]);
+        writer.write_all(b"hello").unwrap();
+        writer.write_all(b"").unwrap();
+        writer.write_all(&[b'a'; 26]).unwrap();
+
+        let mut expected = b"5\r\nhello\r\n1a\r\n".to_vec();
+        expected.extend_from_slice(&[b'a'; 26]);
+        expected.extend_from_slice(b"\r\n0\r\n\r\n");
+
+        assert_eq!(writer.finish().unwrap(), expected);
+    }
+}
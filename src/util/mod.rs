@@ -1,6 +1,21 @@
+/// Base64 encoding and decoding.
+pub mod base64;
+
+/// SHA-1 digests.
+pub mod sha1;
+
+/// Writer for chunked transfer coding.
+pub mod chunked_writer;
+
+/// Reader for chunked transfer coding.
+pub mod chunked_reader;
+
 /// Basic thread pool utility.
 pub mod thread_pool;
 
+/// Catching panics in code that should not bring down its thread.
+pub mod panic;
+
 /// Utility for creating mock trait implementations.
 #[cfg(test)]
 pub mod mock;
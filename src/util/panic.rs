@@ -0,0 +1,48 @@
+use std::any::Any;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+
+/// Calls the given function, catching it if it panics. Returns the message of the panic if it panicked.
+/// The function is assumed to be unwind safe, so anything it uses must be left in a valid state or be discarded by the
+/// caller if it panics.
+pub fn catch_panic<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    catch_unwind(AssertUnwindSafe(f)).map_err(|payload| panic_message(&*payload))
+}
+
+/// Gets the message from the given panic payload, which is a string for panics with a message.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::panic_any;
+
+    use crate::util::panic::catch_panic;
+
+    #[test]
+    fn no_panic() {
+        assert_eq!(catch_panic(|| 5), Ok(5));
+    }
+
+    #[test]
+    fn static_message() {
+        assert_eq!(catch_panic(|| -> () { panic!("oh no") }), Err("oh no".to_string()));
+    }
+
+    #[test]
+    fn formatted_message() {
+        let value = 5;
+        assert_eq!(catch_panic(|| -> () { panic!("oh no {}", value) }), Err("oh no 5".to_string()));
+    }
+
+    #[test]
+    fn other_payload() {
+        assert_eq!(catch_panic(|| -> () { panic_any(5) }), Err("unknown panic".to_string()));
+    }
+}
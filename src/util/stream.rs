@@ -41,6 +41,30 @@ pub struct ReadableWriter<T>(T);
 /// A reader that contains a writable inner.
 pub struct WriteableReader<T>(T);
 
+impl<T> ReadableWriter<T> {
+    /// Gets a reference to the inner writer.
+    pub fn get_ref(&self) -> &T {
+        &self.0
+    }
+
+    /// Gets a mutable reference to the inner writer.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> WriteableReader<T> {
+    /// Gets a reference to the inner reader.
+    pub fn get_ref(&self) -> &T {
+        &self.0
+    }
+
+    /// Gets a mutable reference to the inner reader.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
 impl<R: Read, T: InnerMut<Inner=R>> Read for ReadableWriter<T> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         self.0.inner_mut().read(buf)
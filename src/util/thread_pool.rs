@@ -3,6 +3,8 @@ use std::sync::mpsc;
 use std::sync::Mutex;
 use std::thread;
 
+use crate::util::panic::catch_panic;
+
 /// A basic thread pool with a constant number of threads.
 pub struct ThreadPool {
     workers: Vec<Worker>,
@@ -75,12 +77,17 @@ type Worker = Option<thread::JoinHandle<()>>;
 
 /// Creates a new worker with the given receiver end of an mpsc channel.
 /// The worker will run until a Terminate message is sent to it through the channel.
+/// Jobs that panic are logged, and the worker keeps running the next jobs.
 fn new_worker(receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Worker {
     let thread = thread::spawn(move || loop {
         let message = receiver.lock().unwrap().recv().unwrap();
 
         match message {
-            Message::NewJob(job) => job(),
+            Message::NewJob(job) => {
+                if let Err(message) = catch_panic(job) {
+                    println!("Error: thread pool job panicked: {}", message);
+                }
+            }
             Message::Terminate => break
         }
     });
@@ -88,3 +95,35 @@ fn new_worker(receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Worker {
     Some(thread)
 }
 
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use crate::util::thread_pool::ThreadPool;
+
+    #[test]
+    fn runs_jobs() {
+        let pool = ThreadPool::new(2);
+        let (sender, receiver) = mpsc::channel();
+        for i in 0..10 {
+            let sender = sender.clone();
+            pool.execute(move || sender.send(i).unwrap());
+        }
+
+        let mut results: Vec<i32> = receiver.iter().take(10).collect();
+        results.sort_unstable();
+        assert_eq!(results, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn survives_panicking_jobs() {
+        let pool = ThreadPool::new(1);
+        let (sender, receiver) = mpsc::channel();
+        pool.execute(|| panic!("oh no"));
+        pool.execute(move || sender.send(()).unwrap());
+
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(()));
+    }
+}
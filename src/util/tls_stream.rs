@@ -1,4 +1,6 @@
-use std::io::{Read, Result, Write};
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
 
 use rustls::{Session, StreamOwned};
 
@@ -15,6 +17,105 @@ impl<S: Session, T: Stream> TlsStream<S, T> {
     pub fn new(session: S, inner: T) -> TlsStream<S, T> {
         TlsStream(StreamOwned::new(session, inner))
     }
+
+    /// Gets a reference to the inner stream.
+    pub fn get_ref(&self) -> &T {
+        &self.0.sock
+    }
+
+    /// Gets a mutable reference to the inner stream.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.0.sock
+    }
+
+    /// Gets a reference to the TLS session.
+    pub fn session(&self) -> &S {
+        &self.0.sess
+    }
+
+    /// Blocks until the TLS handshake is complete.
+    pub fn complete_handshake(&mut self) -> Result<()> {
+        while self.0.sess.is_handshaking() {
+            self.0.sess.complete_io(&mut self.0.sock)?;
+        }
+        Ok(())
+    }
+
+    /// Gets the protocol negotiated with ALPN, if any. The protocol is only known once the handshake is complete.
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.0.sess.get_alpn_protocol()
+    }
+
+    /// Checks if the session has TLS data that has not been written to the inner stream yet.
+    pub fn wants_write(&self) -> bool {
+        self.0.sess.wants_write()
+    }
+}
+
+impl<S: Session> TlsStream<S, TcpStream> {
+    /// Splits the stream into a reader and a writer that share the TLS session, so one thread can write to the stream
+    /// while another thread is blocked reading from it.
+    pub fn split(self) -> Result<(TlsReader<S>, TlsWriter<S>)> {
+        let socket = self.get_ref().try_clone()?;
+        let stream = Arc::new(Mutex::new(self));
+        Ok((TlsReader { stream: stream.clone(), socket, buf: vec![0; 16384] }, TlsWriter(stream)))
+    }
+
+    /// Processes TLS data that has been read from the inner stream. Any TLS data the session has to send in return,
+    /// such as alerts, is written right away.
+    fn receive(&mut self, mut data: &[u8]) -> Result<()> {
+        while !data.is_empty() {
+            self.0.sess.read_tls(&mut data)?;
+            if let Err(error) = self.0.sess.process_new_packets() {
+                let _ = self.0.sess.write_tls(&mut self.0.sock);
+                return Err(Error::new(ErrorKind::InvalidData, error));
+            }
+            while self.0.sess.wants_write() {
+                self.0.sess.write_tls(&mut self.0.sock)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The reading half of a split TLS stream. The inner stream is read without locking the session, which is only locked
+/// to decrypt the data that has been read.
+pub struct TlsReader<S: Session> {
+    stream: Arc<Mutex<TlsStream<S, TcpStream>>>,
+    socket: TcpStream,
+    buf: Vec<u8>,
+}
+
+impl<S: Session> Read for TlsReader<S> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let read = self.stream.lock().unwrap().0.sess.read(buf)?;
+            if read > 0 {
+                return Ok(read);
+            }
+            let received = self.socket.read(&mut self.buf)?;
+            if received == 0 {
+                return Ok(0);
+            }
+            self.stream.lock().unwrap().receive(&self.buf[..received])?;
+        }
+    }
+}
+
+/// The writing half of a split TLS stream.
+pub struct TlsWriter<S: Session>(Arc<Mutex<TlsStream<S, TcpStream>>>);
+
+impl<S: Session> Write for TlsWriter<S> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.0.lock().unwrap().flush()
+    }
 }
 
 impl<S: Session, T: Stream> Read for TlsStream<S, T> {
@@ -0,0 +1,97 @@
+use std::fmt::{Display, Formatter};
+use std::io::{BufReader, BufWriter};
+
+use crate::client::{RequestError, StreamFactory, write_request};
+use crate::common::response::Response;
+use crate::common::status;
+use crate::parse::parse::Parse;
+use crate::parse::parse::ParseStatus::{Done, IoErr};
+use crate::parse::response::ResponseParser;
+use crate::util::stream;
+use crate::util::stream::{StdBufStream, Stream};
+use crate::websocket::config::Config;
+use crate::websocket::handshake;
+use crate::websocket::socket::{Role, WebSocket};
+
+/// Error when opening a WebSocket connection to a server.
+#[derive(Debug)]
+pub enum ConnectError {
+    /// Error sending the opening handshake or reading the response to it.
+    Request(RequestError),
+    /// The server responded to the opening handshake without switching protocols.
+    Rejected(Response),
+    /// The server switched protocols with a response that does not complete the opening handshake.
+    Handshake(&'static str),
+}
+
+impl Display for ConnectError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectError::Request(error) => write!(f, "error sending opening handshake: {:?}", error),
+            ConnectError::Rejected(response) => write!(f, "server rejected opening handshake with status {}", response.status.code),
+            ConnectError::Handshake(reason) => write!(f, "invalid opening handshake response: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ConnectError {}
+
+impl From<RequestError> for ConnectError {
+    fn from(error: RequestError) -> Self {
+        ConnectError::Request(error)
+    }
+}
+
+/// Opens a new stream with the given stream factory and performs the opening handshake for the given host and URI.
+/// The subprotocols in the config are offered to the server, and the one it selects is available from the returned socket.
+/// Reads from the socket use the read timeout of the stream factory, and a timed out read can be retried.
+/// ```no_run
+/// use std::time::Duration;
+/// use my_http::client::{Config, TcpStreamFactory};
+/// use my_http::websocket;
+/// use my_http::websocket::Message;
+///
+/// let factory = TcpStreamFactory::new(&Config {
+///     addr: "localhost:8080",
+///     read_timeout: Duration::from_secs(60),
+///     num_connections: 1,
+///     server_name: None,
+/// });
+/// let mut socket = websocket::connect(&factory, "localhost:8080", "/chat", &Default::default()).unwrap();
+/// socket.send_text("hello").unwrap();
+/// if let Message::Text(text) = socket.read_message().unwrap() {
+///     println!("{}", text);
+/// }
+/// socket.close(websocket::close_code::NORMAL, "").unwrap();
+/// ```
+pub fn connect<S, F>(stream_factory: &F, host: &str, uri: &str, config: &Config) -> Result<WebSocket<StdBufStream<S>>, ConnectError>
+    where S: Stream + 'static,
+          F: StreamFactory<S>
+{
+    let stream = stream_factory.create().map_err(RequestError::Connecting)?;
+    let mut stream = stream::with_buf_reader_and_writer(stream, BufReader::new, BufWriter::new);
+
+    let key = handshake::new_key();
+    let request = handshake::request(host, uri, &key, &config.protocols);
+    write_request(&mut stream, &request).map_err(RequestError::Writing)?;
+
+    let response = loop {
+        let response = match ResponseParser::new().parse(&mut stream).map_err(RequestError::ResponseParsing)? {
+            Done(response) => response,
+            IoErr(_, error) => return Err(RequestError::Reading(error).into()),
+        };
+        // skip other informational responses, such as 103 early hints
+        if !response.status.is_informational() || response.status == status::SWITCHING_PROTOCOLS {
+            break response;
+        }
+    };
+
+    if response.status != status::SWITCHING_PROTOCOLS {
+        return Err(ConnectError::Rejected(response));
+    }
+
+    let protocol = handshake::check_response(&response, &key, &config.protocols).map_err(ConnectError::Handshake)?;
+
+    // data the server sent right after the response is still buffered, so it is read as the first frames
+    Ok(WebSocket::new(stream, Role::Client, protocol, config.max_message_size))
+}
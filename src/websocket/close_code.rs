@@ -0,0 +1,24 @@
+/// The purpose of the connection has been fulfilled.
+pub const NORMAL: u16 = 1000;
+/// The endpoint is going away, such as a server shutting down or a browser leaving a page.
+pub const GOING_AWAY: u16 = 1001;
+/// The endpoint received a frame that breaks the protocol.
+pub const PROTOCOL_ERROR: u16 = 1002;
+/// The endpoint received a type of data it can not accept, such as binary data when it only understands text.
+pub const UNSUPPORTED_DATA: u16 = 1003;
+/// The endpoint received a text message that is not valid UTF-8.
+pub const INVALID_PAYLOAD: u16 = 1007;
+/// The endpoint received a message that violates its policy.
+pub const POLICY_VIOLATION: u16 = 1008;
+/// The endpoint received a message that is too big to process.
+pub const MESSAGE_TOO_BIG: u16 = 1009;
+/// The client expected the server to negotiate an extension that it did not.
+pub const MANDATORY_EXTENSION: u16 = 1010;
+/// The server encountered an unexpected condition.
+pub const INTERNAL_ERROR: u16 = 1011;
+
+/// Checks if the given close code may be sent in a close frame.
+/// Codes 1004, 1005, 1006, and 1015 are reserved for reporting conditions locally and must never be sent.
+pub fn is_valid(code: u16) -> bool {
+    matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999)
+}
@@ -0,0 +1,23 @@
+/// The default max message size, 16MB.
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// The config for WebSocket connections.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The subprotocols that can be used, in order of preference. For servers, the first of these that the client
+    /// offers is selected. For clients, these are offered to the server.
+    pub protocols: Vec<String>,
+    /// The max size in bytes of a received message, including all of its fragments.
+    /// The connection is closed with a message too big close code if a larger message is received.
+    pub max_message_size: usize,
+}
+
+impl Default for Config {
+    /// Creates a config with no subprotocols and a max message size of 16MB.
+    fn default() -> Self {
+        Config {
+            protocols: vec![],
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::io::Write;
+
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::websocket::socket::WebSocketError;
+
+/// The max payload length of a control frame.
+pub const MAX_CONTROL_PAYLOAD: usize = 125;
+
+/// The type of a frame.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Opcode {
+    /// A fragment of a message after its first fragment.
+    Continuation,
+    /// The first fragment of a text message.
+    Text,
+    /// The first fragment of a binary message.
+    Binary,
+    /// A close frame.
+    Close,
+    /// A ping frame.
+    Ping,
+    /// A pong frame.
+    Pong,
+}
+
+impl Opcode {
+    /// Gets the opcode from its 4 bit value.
+    fn from_bits(bits: u8) -> Option<Opcode> {
+        match bits {
+            0x0 => Some(Opcode::Continuation),
+            0x1 => Some(Opcode::Text),
+            0x2 => Some(Opcode::Binary),
+            0x8 => Some(Opcode::Close),
+            0x9 => Some(Opcode::Ping),
+            0xA => Some(Opcode::Pong),
+            _ => None
+        }
+    }
+
+    /// Gets the 4 bit value of the opcode.
+    fn bits(self) -> u8 {
+        match self {
+            Opcode::Continuation => 0x0,
+            Opcode::Text => 0x1,
+            Opcode::Binary => 0x2,
+            Opcode::Close => 0x8,
+            Opcode::Ping => 0x9,
+            Opcode::Pong => 0xA,
+        }
+    }
+
+    /// Checks if this is the opcode of a control frame.
+    pub fn is_control(self) -> bool {
+        matches!(self, Opcode::Close | Opcode::Ping | Opcode::Pong)
+    }
+}
+
+/// A frame with an unmasked payload.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Frame {
+    /// Whether this is the final fragment of a message.
+    pub fin: bool,
+    /// The type of the frame.
+    pub opcode: Opcode,
+    /// Whether the payload was masked.
+    pub masked: bool,
+    /// The payload.
+    pub payload: Vec<u8>,
+}
+
+/// Parses a frame from the start of the given data, and returns the frame along with the number of bytes it took up.
+/// Returns None if the data does not contain a full frame yet.
+/// Payloads longer than max_payload are rejected as soon as the length is known, so they are never buffered.
+pub fn parse_frame(data: &[u8], max_payload: usize) -> Result<Option<(Frame, usize)>, WebSocketError> {
+    if data.len() < 2 {
+        return Ok(None);
+    }
+
+    let fin = data[0] & 0x80 != 0;
+    if data[0] & 0x70 != 0 {
+        // no extensions are negotiated, so the reserved bits must not be set
+        return Err(WebSocketError::Protocol("reserved bits set"));
+    }
+    let opcode = Opcode::from_bits(data[0] & 0x0F).ok_or(WebSocketError::Protocol("unknown opcode"))?;
+    let masked = data[1] & 0x80 != 0;
+
+    let (payload_len, mut pos) = match data[1] & 0x7F {
+        126 if data.len() < 4 => return Ok(None),
+        126 => (u16::from_be_bytes([data[2], data[3]]) as u64, 4),
+        127 if data.len() < 10 => return Ok(None),
+        127 => {
+            let mut len = [0; 8];
+            len.copy_from_slice(&data[2..10]);
+            (u64::from_be_bytes(len), 10)
+        }
+        len => (len as u64, 2),
+    };
+
+    if opcode.is_control() && (!fin || payload_len > MAX_CONTROL_PAYLOAD as u64) {
+        return Err(WebSocketError::Protocol("invalid control frame"));
+    }
+    if payload_len > max_payload as u64 {
+        return Err(WebSocketError::MessageTooBig);
+    }
+    let payload_len = payload_len as usize;
+
+    let mask = if masked {
+        if data.len() < pos + 4 {
+            return Ok(None);
+        }
+        pos += 4;
+        Some([data[pos - 4], data[pos - 3], data[pos - 2], data[pos - 1]])
+    } else {
+        None
+    };
+
+    if data.len() < pos + payload_len {
+        return Ok(None);
+    }
+
+    let mut payload = data[pos..pos + payload_len].to_vec();
+    if let Some(mask) = mask {
+        apply_mask(&mut payload, mask);
+    }
+
+    Ok(Some((Frame { fin, opcode, masked, payload }, pos + payload_len)))
+}
+
+/// Writes a frame with the given payload. The payload is masked with the given mask, if any.
+pub fn write_frame(writer: &mut impl Write, fin: bool, opcode: Opcode, payload: &[u8], mask: Option<[u8; 4]>) -> std::io::Result<()> {
+    let mut head = Vec::with_capacity(14);
+    head.push(if fin { 0x80 } else { 0 } | opcode.bits());
+
+    let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+    match payload.len() {
+        len if len < 126 => head.push(mask_bit | len as u8),
+        len if len <= u16::MAX as usize => {
+            head.push(mask_bit | 126);
+            head.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            head.push(mask_bit | 127);
+            head.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+
+    match mask {
+        Some(mask) => {
+            head.extend_from_slice(&mask);
+            let mut payload = payload.to_vec();
+            apply_mask(&mut payload, mask);
+            writer.write_all(&head)?;
+            writer.write_all(&payload)
+        }
+        None => {
+            writer.write_all(&head)?;
+            writer.write_all(payload)
+        }
+    }
+}
+
+/// Masks or unmasks the given data with the given mask.
+fn apply_mask(data: &mut [u8], mask: [u8; 4]) {
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+/// Creates a new unpredictable mask for a frame sent by a client, using the system's secure random number generator
+/// as RFC 6455 requires.
+pub fn new_mask() -> [u8; 4] {
+    let mut mask = [0; 4];
+    SystemRandom::new().fill(&mut mask).expect("system random number generator failed");
+    mask
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::websocket::frame::{Frame, new_mask, Opcode, parse_frame, write_frame};
+    use crate::websocket::socket::WebSocketError;
+
+    fn written(fin: bool, opcode: Opcode, payload: &[u8], mask: Option<[u8; 4]>) -> Vec<u8> {
+        let mut buf = vec![];
+        write_frame(&mut buf, fin, opcode, payload, mask).unwrap();
+        buf
+    }
+
+    #[test]
+    fn rfc_6455_examples() {
+        assert_eq!(written(true, Opcode::Text, b"Hello", None), vec![0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f]);
+        assert_eq!(written(true, Opcode::Text, b"Hello", Some([0x37, 0xfa, 0x21, 0x3d])),
+                   vec![0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58]);
+        assert_eq!(written(false, Opcode::Text, b"Hel", None), vec![0x01, 0x03, 0x48, 0x65, 0x6c]);
+        assert_eq!(written(true, Opcode::Continuation, b"lo", None), vec![0x80, 0x02, 0x6c, 0x6f]);
+        assert_eq!(written(true, Opcode::Ping, b"Hello", None), vec![0x89, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f]);
+    }
+
+    #[test]
+    fn parse_masked() {
+        let data = [0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58, 0x01];
+        assert_eq!(parse_frame(&data, 100).unwrap(),
+                   Some((Frame { fin: true, opcode: Opcode::Text, masked: true, payload: b"Hello".to_vec() }, 11)));
+    }
+
+    #[test]
+    fn parse_incomplete() {
+        let data = written(true, Opcode::Binary, &[7; 300], Some(new_mask()));
+        for len in 0..data.len() {
+            assert_eq!(parse_frame(&data[..len], 1000).unwrap(), None);
+        }
+        assert_eq!(parse_frame(&data, 1000).unwrap(),
+                   Some((Frame { fin: true, opcode: Opcode::Binary, masked: true, payload: vec![7; 300] }, data.len())));
+    }
+
+    #[test]
+    fn extended_lengths() {
+        for len in [125, 126, 65535, 65536] {
+            let data = written(true, Opcode::Binary, &vec![1; len], None);
+            let (frame, used) = parse_frame(&data, 100000).unwrap().unwrap();
+            assert_eq!(frame.payload.len(), len);
+            assert_eq!(used, data.len());
+        }
+    }
+
+    #[test]
+    fn too_big() {
+        let data = written(true, Opcode::Binary, &[0; 200], None);
+        assert!(matches!(parse_frame(&data[..4], 100), Err(WebSocketError::MessageTooBig)));
+    }
+
+    #[test]
+    fn invalid_frames() {
+        assert!(matches!(parse_frame(&[0xC1, 0x00], 100), Err(WebSocketError::Protocol(_))));
+        assert!(matches!(parse_frame(&[0x83, 0x00], 100), Err(WebSocketError::Protocol(_))));
+        assert!(matches!(parse_frame(&[0x09, 0x00], 100), Err(WebSocketError::Protocol(_))));
+        assert!(matches!(parse_frame(&[0x89, 0x7E, 0x00, 0x7E], 1000), Err(WebSocketError::Protocol(_))));
+    }
+}
@@ -0,0 +1,247 @@
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::common::header::{CONNECTION, HeaderMapOps, HOST, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_VERSION, UPGRADE};
+use crate::common::method::Method;
+use crate::common::request::Request;
+use crate::common::response::Response;
+use crate::common::status;
+use crate::common::typed_header::{Connection, SecWebSocketProtocol, TypedHeader, Upgrade};
+use crate::header_map;
+use crate::util::{base64, sha1};
+
+/// The GUID that is appended to the key of a handshake to get the accept value.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The only WebSocket version, from RFC 6455.
+pub const WEBSOCKET_VERSION: &str = "13";
+
+/// Gets the value of the sec-websocket-accept header for the given sec-websocket-key header value.
+pub fn accept_key(key: &str) -> String {
+    let digest = sha1::digest(format!("{}{}", key, WEBSOCKET_GUID).as_bytes());
+    base64::encode(&digest)
+}
+
+/// Checks that the given request is a WebSocket opening handshake, and creates the 101 switching protocols response that
+/// completes it. The first of the given subprotocols that the client offers is selected and returned with the response.
+/// If the request is not a valid handshake, then the error response to send instead is returned.
+pub fn accept(request: &Request, protocols: &[String]) -> Result<(Response, Option<String>), Response> {
+    let is_upgrade = matches!(Upgrade::decode(&request.headers), Some(Ok(upgrade)) if upgrade.contains("websocket"))
+        && matches!(Connection::decode(&request.headers), Some(Ok(connection)) if connection.contains("upgrade"));
+    if !is_upgrade {
+        return Err(Response {
+            status: status::UPGRADE_REQUIRED,
+            headers: header_map![(UPGRADE, "websocket"), (CONNECTION, "upgrade")],
+            body: vec![],
+        });
+    }
+
+    if request.method != Method::GET {
+        return Err(Response::empty(status::BAD_REQUEST));
+    }
+
+    if !request.headers.contains_header_value(&SEC_WEBSOCKET_VERSION, WEBSOCKET_VERSION) {
+        return Err(Response {
+            status: status::UPGRADE_REQUIRED,
+            headers: header_map![(SEC_WEBSOCKET_VERSION, WEBSOCKET_VERSION)],
+            body: vec![],
+        });
+    }
+
+    let key = match request.headers.get_first_header_value(&SEC_WEBSOCKET_KEY) {
+        Some(key) if base64::decode(key).map(|key| key.len()) == Some(16) => key,
+        _ => return Err(Response::empty(status::BAD_REQUEST)),
+    };
+
+    let offered = match SecWebSocketProtocol::decode(&request.headers) {
+        None => vec![],
+        Some(Ok(offered)) => offered.0,
+        Some(Err(_)) => return Err(Response::empty(status::BAD_REQUEST)),
+    };
+    let protocol = protocols.iter().find(|protocol| offered.contains(protocol)).cloned();
+
+    let mut response = Response {
+        status: status::SWITCHING_PROTOCOLS,
+        headers: header_map![
+            (UPGRADE, "websocket"),
+            (CONNECTION, "upgrade"),
+            (SEC_WEBSOCKET_ACCEPT, accept_key(key))
+        ],
+        body: vec![],
+    };
+    if let Some(protocol) = &protocol {
+        SecWebSocketProtocol(vec![protocol.clone()]).encode(&mut response.headers);
+    }
+
+    Ok((response, protocol))
+}
+
+/// Creates a new random value for the sec-websocket-key header of an opening handshake.
+pub fn new_key() -> String {
+    let mut nonce = [0; 16];
+    SystemRandom::new().fill(&mut nonce).expect("system random number generator failed");
+    base64::encode(&nonce)
+}
+
+/// Creates the request that starts an opening handshake with the given key, offering the given subprotocols.
+pub fn request(host: &str, uri: &str, key: &str, protocols: &[String]) -> Request {
+    let mut request = Request {
+        uri: uri.to_string(),
+        method: Method::GET,
+        headers: header_map![
+            (HOST, host),
+            (UPGRADE, "websocket"),
+            (CONNECTION, "upgrade"),
+            (SEC_WEBSOCKET_KEY, key),
+            (SEC_WEBSOCKET_VERSION, WEBSOCKET_VERSION)
+        ],
+        body: vec![],
+    };
+    if !protocols.is_empty() {
+        SecWebSocketProtocol(protocols.to_vec()).encode(&mut request.headers);
+    }
+    request
+}
+
+/// Checks that the given 101 switching protocols response completes the opening handshake started with the given key
+/// and subprotocols. Returns the subprotocol selected by the server, or a description of why the response is invalid.
+pub fn check_response(response: &Response, key: &str, protocols: &[String]) -> Result<Option<String>, &'static str> {
+    if !matches!(Upgrade::decode(&response.headers), Some(Ok(upgrade)) if upgrade.contains("websocket")) {
+        return Err("missing websocket upgrade header");
+    }
+
+    if !matches!(Connection::decode(&response.headers), Some(Ok(connection)) if connection.contains("upgrade")) {
+        return Err("missing upgrade connection header");
+    }
+
+    if !response.headers.contains_header_value(&SEC_WEBSOCKET_ACCEPT, &accept_key(key)) {
+        return Err("invalid sec-websocket-accept header");
+    }
+
+    match SecWebSocketProtocol::decode(&response.headers) {
+        None => Ok(None),
+        Some(Ok(selected)) if selected.0.len() == 1 && protocols.contains(&selected.0[0]) => Ok(selected.0.into_iter().next()),
+        _ => Err("server selected a subprotocol that was not offered"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::header::{HeaderMap, HeaderMapOps, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_PROTOCOL, SEC_WEBSOCKET_VERSION};
+    use crate::common::method::Method;
+    use crate::common::request::Request;
+    use crate::common::status;
+    use crate::header_map;
+    use crate::util::base64;
+    use crate::websocket::handshake::{accept, accept_key, check_response, new_key, request};
+
+    fn handshake_request(headers: HeaderMap) -> Request {
+        let mut request = Request::builder()
+            .uri("/chat")
+            .header("upgrade", "websocket")
+            .header("connection", "Upgrade")
+            .header("sec-websocket-version", "13")
+            .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+            .build()
+            .unwrap();
+        request.headers.extend(headers);
+        request
+    }
+
+    #[test]
+    fn rfc_6455_accept_key() {
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn valid_handshake() {
+        let (response, protocol) = accept(&handshake_request(header_map![]), &[]).unwrap();
+
+        assert_eq!(response.status, status::SWITCHING_PROTOCOLS);
+        assert!(response.headers.contains_header_value(&SEC_WEBSOCKET_ACCEPT, "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+        assert!(!response.headers.contains_key(&SEC_WEBSOCKET_PROTOCOL));
+        assert_eq!(protocol, None);
+    }
+
+    #[test]
+    fn subprotocol_negotiation() {
+        let request = handshake_request(header_map![(SEC_WEBSOCKET_PROTOCOL, "chat, superchat")]);
+        let protocols = vec!["superchat".to_string(), "chat".to_string()];
+
+        let (response, protocol) = accept(&request, &protocols).unwrap();
+
+        assert_eq!(protocol, Some("superchat".to_string()));
+        assert!(response.headers.contains_header_value(&SEC_WEBSOCKET_PROTOCOL, "superchat"));
+
+        let (response, protocol) = accept(&request, &["other".to_string()]).unwrap();
+        assert_eq!(protocol, None);
+        assert!(!response.headers.contains_key(&SEC_WEBSOCKET_PROTOCOL));
+    }
+
+    #[test]
+    fn not_an_upgrade() {
+        let request = Request::builder().uri("/chat").build().unwrap();
+        assert_eq!(accept(&request, &[]).unwrap_err().status, status::UPGRADE_REQUIRED);
+    }
+
+    #[test]
+    fn wrong_version() {
+        let mut request = handshake_request(header_map![]);
+        request.headers.insert(SEC_WEBSOCKET_VERSION, vec!["8".to_string()]);
+
+        let response = accept(&request, &[]).unwrap_err();
+
+        assert_eq!(response.status, status::UPGRADE_REQUIRED);
+        assert!(response.headers.contains_header_value(&SEC_WEBSOCKET_VERSION, "13"));
+    }
+
+    #[test]
+    fn invalid_requests() {
+        let mut request = handshake_request(header_map![]);
+        request.method = Method::POST;
+        assert_eq!(accept(&request, &[]).unwrap_err().status, status::BAD_REQUEST);
+
+        let mut request = handshake_request(header_map![]);
+        request.headers.insert("sec-websocket-key".into(), vec!["c2hvcnQ=".to_string()]);
+        assert_eq!(accept(&request, &[]).unwrap_err().status, status::BAD_REQUEST);
+    }
+
+    #[test]
+    fn new_keys() {
+        let key = new_key();
+        assert_eq!(base64::decode(&key).unwrap().len(), 16);
+        assert_ne!(key, new_key());
+    }
+
+    #[test]
+    fn client_handshake() {
+        let protocols = vec!["chat".to_string(), "superchat".to_string()];
+        let key = new_key();
+        let request = request("localhost:8080", "/chat", &key, &protocols);
+
+        assert_eq!(request.uri, "/chat");
+        assert!(request.headers.contains_header_value(&SEC_WEBSOCKET_KEY, &key));
+        assert!(request.headers.contains_header_value(&SEC_WEBSOCKET_PROTOCOL, "chat, superchat"));
+
+        let (response, selected) = accept(&request, &["superchat".to_string()]).unwrap();
+
+        assert_eq!(selected, Some("superchat".to_string()));
+        assert_eq!(check_response(&response, &key, &protocols), Ok(Some("superchat".to_string())));
+        assert_eq!(check_response(&response, &new_key(), &protocols), Err("invalid sec-websocket-accept header"));
+        assert_eq!(check_response(&response, &key, &[]), Err("server selected a subprotocol that was not offered"));
+    }
+
+    #[test]
+    fn invalid_responses() {
+        let key = "dGhlIHNhbXBsZSBub25jZQ==";
+        let (response, _) = accept(&handshake_request(header_map![]), &[]).unwrap();
+        assert_eq!(check_response(&response, key, &[]), Ok(None));
+
+        let mut no_upgrade = response.clone();
+        no_upgrade.headers.insert("upgrade".into(), vec!["h2c".to_string()]);
+        assert_eq!(check_response(&no_upgrade, key, &[]), Err("missing websocket upgrade header"));
+
+        let mut no_connection = response;
+        no_connection.headers.insert("connection".into(), vec!["keep-alive".to_string()]);
+        assert_eq!(check_response(&no_connection, key, &[]), Err("missing upgrade connection header"));
+    }
+}
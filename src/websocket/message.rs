@@ -0,0 +1,30 @@
+/// A WebSocket message.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Message {
+    /// A UTF-8 text message.
+    Text(String),
+    /// A binary message.
+    Binary(Vec<u8>),
+    /// A ping, which is automatically answered with a pong containing the same data.
+    Ping(Vec<u8>),
+    /// A pong, sent in response to a ping or as a heartbeat.
+    Pong(Vec<u8>),
+    /// A request to close the connection, with the reason the connection is being closed if one was given.
+    Close(Option<CloseFrame>),
+}
+
+/// The reason a connection is being closed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CloseFrame {
+    /// The close code. See the close_code module for standard codes.
+    pub code: u16,
+    /// The human readable reason for closing the connection.
+    pub reason: String,
+}
+
+impl CloseFrame {
+    /// Creates a close frame with the given code and reason.
+    pub fn new(code: u16, reason: &str) -> CloseFrame {
+        CloseFrame { code, reason: reason.to_string() }
+    }
+}
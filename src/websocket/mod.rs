@@ -0,0 +1,19 @@
+pub use client::*;
+pub use config::*;
+pub use message::*;
+pub use socket::*;
+
+/// Opening WebSocket connections to servers.
+mod client;
+/// Config for WebSocket connections.
+mod config;
+/// WebSocket messages.
+mod message;
+/// Message oriented WebSocket connections.
+mod socket;
+/// Reading and writing WebSocket frames.
+mod frame;
+/// The WebSocket opening handshake.
+pub(crate) mod handshake;
+/// Status codes for closing WebSocket connections.
+pub mod close_code;
@@ -0,0 +1,436 @@
+use std::fmt::{Display, Formatter};
+use std::io::{ErrorKind, Read, Write};
+
+use crate::websocket::close_code;
+use crate::websocket::frame::{Frame, MAX_CONTROL_PAYLOAD, new_mask, Opcode, parse_frame, write_frame};
+use crate::websocket::message::{CloseFrame, Message};
+
+/// Size of the chunks read from the stream.
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// Which end of a connection a WebSocket is.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Role {
+    /// Servers send unmasked frames and require received frames to be masked.
+    Server,
+    /// Clients send masked frames and require received frames to be unmasked.
+    Client,
+}
+
+/// An error from reading or sending WebSocket messages.
+#[derive(Debug)]
+pub enum WebSocketError {
+    /// An IO error from the underlying stream.
+    Io(std::io::Error),
+    /// The peer broke the WebSocket protocol. A close frame with a protocol error code has been sent.
+    Protocol(&'static str),
+    /// The peer sent a message larger than the max message size. A close frame with a message too big code has been sent.
+    MessageTooBig,
+    /// The peer sent text that is not valid UTF-8. A close frame with an invalid payload code has been sent.
+    InvalidUtf8,
+    /// The connection has been closed, so no more messages can be read or sent.
+    Closed,
+}
+
+impl Display for WebSocketError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebSocketError::Io(error) => write!(f, "io error: {}", error),
+            WebSocketError::Protocol(reason) => write!(f, "protocol error: {}", reason),
+            WebSocketError::MessageTooBig => write!(f, "message too big"),
+            WebSocketError::InvalidUtf8 => write!(f, "text is not valid utf-8"),
+            WebSocketError::Closed => write!(f, "connection closed"),
+        }
+    }
+}
+
+impl std::error::Error for WebSocketError {}
+
+impl From<std::io::Error> for WebSocketError {
+    fn from(error: std::io::Error) -> Self {
+        WebSocketError::Io(error)
+    }
+}
+
+/// A message oriented WebSocket connection over a stream.
+/// Pings are answered automatically, and fragmented messages are reassembled before they are returned.
+pub struct WebSocket<S: Read + Write> {
+    stream: S,
+    role: Role,
+    protocol: Option<String>,
+    max_message_size: usize,
+    /// Data read from the stream that has not been parsed into a frame yet.
+    read_buf: Vec<u8>,
+    /// The opcode and data so far of a fragmented message that is being received.
+    fragmented: Option<(Opcode, Vec<u8>)>,
+    close_sent: bool,
+    close_received: bool,
+}
+
+impl<S: Read + Write> WebSocket<S> {
+    /// Creates a WebSocket over a stream that has completed the opening handshake with the given subprotocol.
+    pub(crate) fn new(stream: S, role: Role, protocol: Option<String>, max_message_size: usize) -> WebSocket<S> {
+        WebSocket {
+            stream,
+            role,
+            protocol,
+            max_message_size,
+            read_buf: vec![],
+            fragmented: None,
+            close_sent: false,
+            close_received: false,
+        }
+    }
+
+    /// Gets the subprotocol selected in the opening handshake, if any.
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    /// Gets a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Gets a mutable reference to the underlying stream. Reading from or writing to it directly will corrupt the connection.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Checks if a close frame has been both sent and received, meaning the connection can be dropped.
+    pub fn is_closed(&self) -> bool {
+        self.close_sent && self.close_received
+    }
+
+    /// Reads the next message. Pings are answered with pongs before they are returned.
+    /// When a close frame is received, it is answered with a close frame and returned, and any later reads return a Closed error.
+    /// If the stream returns an error, such as a timeout, then any partially read message is kept and reading can be retried.
+    pub fn read_message(&mut self) -> Result<Message, WebSocketError> {
+        if self.close_received {
+            return Err(WebSocketError::Closed);
+        }
+
+        loop {
+            let frame = self.read_frame()?;
+            match frame.opcode {
+                Opcode::Ping => {
+                    if !self.close_sent {
+                        self.send_frame(Opcode::Pong, &frame.payload)?;
+                    }
+                    return Ok(Message::Ping(frame.payload));
+                }
+                Opcode::Pong => return Ok(Message::Pong(frame.payload)),
+                Opcode::Close => return self.receive_close(frame.payload),
+                Opcode::Text | Opcode::Binary if self.fragmented.is_some() =>
+                    return self.fail(WebSocketError::Protocol("expected continuation frame")),
+                Opcode::Text | Opcode::Binary if frame.fin => return self.complete_message(frame.opcode, frame.payload),
+                Opcode::Text | Opcode::Binary => self.fragmented = Some((frame.opcode, frame.payload)),
+                Opcode::Continuation => {
+                    let (opcode, mut data) = match self.fragmented.take() {
+                        Some(fragmented) => fragmented,
+                        None => return self.fail(WebSocketError::Protocol("unexpected continuation frame")),
+                    };
+                    if data.len() + frame.payload.len() > self.max_message_size {
+                        return self.fail(WebSocketError::MessageTooBig);
+                    }
+                    data.extend_from_slice(&frame.payload);
+                    if frame.fin {
+                        return self.complete_message(opcode, data);
+                    }
+                    self.fragmented = Some((opcode, data));
+                }
+            }
+        }
+    }
+
+    /// Sends the given message. Sending a close message starts the close handshake, after which reading continues
+    /// until the peer's close frame is received. Ping, pong, and close payloads must be at most 125 bytes.
+    pub fn send(&mut self, message: Message) -> Result<(), WebSocketError> {
+        match message {
+            Message::Text(text) => self.send_text(&text),
+            Message::Binary(data) => self.send_binary(&data),
+            Message::Ping(data) => self.send_control(Opcode::Ping, &data),
+            Message::Pong(data) => self.send_control(Opcode::Pong, &data),
+            Message::Close(Some(frame)) => self.close(frame.code, &frame.reason),
+            Message::Close(None) => self.send_close(&[]),
+        }
+    }
+
+    /// Sends a text message.
+    pub fn send_text(&mut self, text: &str) -> Result<(), WebSocketError> {
+        self.send_data(Opcode::Text, text.as_bytes())
+    }
+
+    /// Sends a binary message.
+    pub fn send_binary(&mut self, data: &[u8]) -> Result<(), WebSocketError> {
+        self.send_data(Opcode::Binary, data)
+    }
+
+    /// Starts the close handshake by sending a close frame with the given code and reason.
+    /// Does nothing if a close frame has already been sent.
+    pub fn close(&mut self, code: u16, reason: &str) -> Result<(), WebSocketError> {
+        if !close_code::is_valid(code) {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput, format!("{} is not a valid close code", code)).into());
+        }
+        let mut payload = code.to_be_bytes().to_vec();
+        payload.extend_from_slice(reason.as_bytes());
+        self.send_close(&payload)
+    }
+
+    /// Sends a data frame, unless a close frame has been sent.
+    fn send_data(&mut self, opcode: Opcode, data: &[u8]) -> Result<(), WebSocketError> {
+        if self.close_sent {
+            return Err(WebSocketError::Closed);
+        }
+        Ok(self.send_frame(opcode, data)?)
+    }
+
+    /// Sends a ping or pong frame, unless a close frame has been sent.
+    fn send_control(&mut self, opcode: Opcode, data: &[u8]) -> Result<(), WebSocketError> {
+        if data.len() > MAX_CONTROL_PAYLOAD {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput, "control frame payload is longer than 125 bytes").into());
+        }
+        self.send_data(opcode, data)
+    }
+
+    /// Sends a close frame with the given payload if one has not been sent yet.
+    fn send_close(&mut self, payload: &[u8]) -> Result<(), WebSocketError> {
+        if payload.len() > MAX_CONTROL_PAYLOAD {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput, "close reason is longer than 123 bytes").into());
+        }
+        if self.close_sent {
+            return Ok(());
+        }
+        self.close_sent = true;
+        Ok(self.send_frame(Opcode::Close, payload)?)
+    }
+
+    /// Writes and flushes a single frame, masking it if this is a client.
+    fn send_frame(&mut self, opcode: Opcode, payload: &[u8]) -> std::io::Result<()> {
+        let mask = match self.role {
+            Role::Client => Some(new_mask()),
+            Role::Server => None,
+        };
+        write_frame(&mut self.stream, true, opcode, payload, mask)?;
+        self.stream.flush()
+    }
+
+    /// Reads the next frame from the stream, and checks that it is masked correctly for the role.
+    fn read_frame(&mut self) -> Result<Frame, WebSocketError> {
+        let max_payload = self.max_message_size - self.fragmented.as_ref().map(|(_, data)| data.len()).unwrap_or(0);
+
+        loop {
+            match parse_frame(&self.read_buf, max_payload) {
+                Ok(Some((frame, len))) => {
+                    self.read_buf.drain(..len);
+                    if frame.masked != (self.role == Role::Server) {
+                        return self.fail(WebSocketError::Protocol("frame masked incorrectly"));
+                    }
+                    return Ok(frame);
+                }
+                Ok(None) => {}
+                Err(error) => return self.fail(error),
+            }
+
+            let mut chunk = [0; READ_CHUNK_SIZE];
+            let amount = self.stream.read(&mut chunk)?;
+            if amount == 0 {
+                return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into());
+            }
+            self.read_buf.extend_from_slice(&chunk[..amount]);
+        }
+    }
+
+    /// Handles a received close frame by answering it if needed, and returns it as a message.
+    fn receive_close(&mut self, payload: Vec<u8>) -> Result<Message, WebSocketError> {
+        let frame = match payload.len() {
+            0 => None,
+            1 => return self.fail(WebSocketError::Protocol("close frame payload too short")),
+            _ => {
+                let code = u16::from_be_bytes([payload[0], payload[1]]);
+                if !close_code::is_valid(code) {
+                    return self.fail(WebSocketError::Protocol("invalid close code"));
+                }
+                match String::from_utf8(payload[2..].to_vec()) {
+                    Ok(reason) => Some(CloseFrame { code, reason }),
+                    Err(_) => return self.fail(WebSocketError::InvalidUtf8),
+                }
+            }
+        };
+
+        self.close_received = true;
+        // echo the close code back, as recommended by the RFC
+        let echo = frame.as_ref().map(|frame| frame.code.to_be_bytes().to_vec()).unwrap_or_default();
+        self.send_close(&echo)?;
+
+        Ok(Message::Close(frame))
+    }
+
+    /// Converts the data of a complete message into a message.
+    fn complete_message(&mut self, opcode: Opcode, data: Vec<u8>) -> Result<Message, WebSocketError> {
+        match opcode {
+            Opcode::Text => match String::from_utf8(data) {
+                Ok(text) => Ok(Message::Text(text)),
+                Err(_) => self.fail(WebSocketError::InvalidUtf8),
+            },
+            _ => Ok(Message::Binary(data)),
+        }
+    }
+
+    /// Sends a close frame with the close code for the given error, and returns the error.
+    /// No more messages can be read after failing.
+    fn fail<T>(&mut self, error: WebSocketError) -> Result<T, WebSocketError> {
+        let code = match error {
+            WebSocketError::MessageTooBig => close_code::MESSAGE_TOO_BIG,
+            WebSocketError::InvalidUtf8 => close_code::INVALID_PAYLOAD,
+            _ => close_code::PROTOCOL_ERROR,
+        };
+        self.close_received = true;
+        self.close(code, "").unwrap_or_default();
+        Err(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::mock::{MockReader, MockStream};
+    use crate::websocket::close_code;
+    use crate::websocket::frame::{new_mask, Opcode, parse_frame, write_frame};
+    use crate::websocket::message::{CloseFrame, Message};
+    use crate::websocket::socket::{Role, WebSocket, WebSocketError};
+
+    type MockSocket = WebSocket<MockStream<MockReader, Vec<u8>>>;
+
+    /// Creates a server socket that reads the given frames, masked as if they were sent by a client.
+    fn server_socket(frames: Vec<(bool, Opcode, &[u8])>, max_message_size: usize) -> MockSocket {
+        let mut data = vec![];
+        for (fin, opcode, payload) in frames {
+            write_frame(&mut data, fin, opcode, payload, Some(new_mask())).unwrap();
+        }
+        let stream = MockStream::new(MockReader::from_bytes(vec![&data]), vec![]);
+        WebSocket::new(stream, Role::Server, None, max_message_size)
+    }
+
+    /// Parses all of the frames written by the given socket.
+    fn written_frames(socket: &MockSocket) -> Vec<(Opcode, Vec<u8>)> {
+        let mut data = &socket.get_ref().writer[..];
+        let mut frames = vec![];
+        while let Some((frame, len)) = parse_frame(data, usize::MAX).unwrap() {
+            frames.push((frame.opcode, frame.payload));
+            data = &data[len..];
+        }
+        frames
+    }
+
+    #[test]
+    fn text_and_binary() {
+        let mut socket = server_socket(vec![(true, Opcode::Text, b"hello"), (true, Opcode::Binary, &[1, 2, 3])], 100);
+
+        assert_eq!(socket.read_message().unwrap(), Message::Text("hello".to_string()));
+        assert_eq!(socket.read_message().unwrap(), Message::Binary(vec![1, 2, 3]));
+        assert!(matches!(socket.read_message(), Err(WebSocketError::Io(_))));
+    }
+
+    #[test]
+    fn fragmented_with_ping_in_between() {
+        let mut socket = server_socket(vec![
+            (false, Opcode::Text, b"hel"),
+            (true, Opcode::Ping, b"are you there"),
+            (false, Opcode::Continuation, b"lo "),
+            (true, Opcode::Continuation, b"world"),
+        ], 100);
+
+        assert_eq!(socket.read_message().unwrap(), Message::Ping(b"are you there".to_vec()));
+        assert_eq!(socket.read_message().unwrap(), Message::Text("hello world".to_string()));
+        assert_eq!(written_frames(&socket), vec![(Opcode::Pong, b"are you there".to_vec())]);
+    }
+
+    #[test]
+    fn close_handshake_started_by_peer() {
+        let mut payload = close_code::GOING_AWAY.to_be_bytes().to_vec();
+        payload.extend_from_slice(b"bye");
+        let mut socket = server_socket(vec![(true, Opcode::Close, &payload)], 100);
+
+        assert_eq!(socket.read_message().unwrap(), Message::Close(Some(CloseFrame::new(close_code::GOING_AWAY, "bye"))));
+        assert!(socket.is_closed());
+        assert!(matches!(socket.read_message(), Err(WebSocketError::Closed)));
+        assert!(matches!(socket.send_text("too late"), Err(WebSocketError::Closed)));
+        assert_eq!(written_frames(&socket), vec![(Opcode::Close, close_code::GOING_AWAY.to_be_bytes().to_vec())]);
+    }
+
+    #[test]
+    fn close_handshake_started_by_us() {
+        let mut socket = server_socket(vec![(true, Opcode::Text, b"in flight"), (true, Opcode::Close, &[])], 100);
+
+        socket.close(close_code::NORMAL, "done").unwrap();
+        assert!(!socket.is_closed());
+        assert_eq!(socket.read_message().unwrap(), Message::Text("in flight".to_string()));
+        assert_eq!(socket.read_message().unwrap(), Message::Close(None));
+        assert!(socket.is_closed());
+
+        let mut expected = close_code::NORMAL.to_be_bytes().to_vec();
+        expected.extend_from_slice(b"done");
+        assert_eq!(written_frames(&socket), vec![(Opcode::Close, expected)]);
+    }
+
+    #[test]
+    fn unmasked_frame_from_client() {
+        let mut data = vec![];
+        write_frame(&mut data, true, Opcode::Text, b"hello", None).unwrap();
+        let mut socket = WebSocket::new(MockStream::new(MockReader::from_bytes(vec![&data]), vec![]), Role::Server, None, 100);
+
+        assert!(matches!(socket.read_message(), Err(WebSocketError::Protocol(_))));
+        assert_eq!(written_frames(&socket), vec![(Opcode::Close, close_code::PROTOCOL_ERROR.to_be_bytes().to_vec())]);
+    }
+
+    #[test]
+    fn message_too_big() {
+        let mut socket = server_socket(vec![(false, Opcode::Binary, &[0; 6]), (true, Opcode::Continuation, &[0; 6])], 10);
+
+        assert!(matches!(socket.read_message(), Err(WebSocketError::MessageTooBig)));
+        assert_eq!(written_frames(&socket), vec![(Opcode::Close, close_code::MESSAGE_TOO_BIG.to_be_bytes().to_vec())]);
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let mut socket = server_socket(vec![(true, Opcode::Text, &[0xC3, 0x28])], 10);
+
+        assert!(matches!(socket.read_message(), Err(WebSocketError::InvalidUtf8)));
+        assert_eq!(written_frames(&socket), vec![(Opcode::Close, close_code::INVALID_PAYLOAD.to_be_bytes().to_vec())]);
+    }
+
+    #[test]
+    fn unexpected_continuation() {
+        let mut socket = server_socket(vec![(true, Opcode::Continuation, b"hello")], 100);
+
+        assert!(matches!(socket.read_message(), Err(WebSocketError::Protocol(_))));
+    }
+
+    #[test]
+    fn invalid_close_code() {
+        let mut socket = server_socket(vec![(true, Opcode::Close, &1005u16.to_be_bytes())], 100);
+
+        assert!(matches!(socket.read_message(), Err(WebSocketError::Protocol(_))));
+    }
+
+    #[test]
+    fn client_masks_frames() {
+        let mut socket = WebSocket::new(MockStream::new(MockReader::from_bytes(vec![]), vec![]), Role::Client, None, 100);
+
+        socket.send_text("hello").unwrap();
+
+        let data = &socket.get_ref().writer;
+        let (frame, _) = parse_frame(data, 100).unwrap().unwrap();
+        assert!(frame.masked);
+        assert_eq!(frame.payload, b"hello");
+    }
+
+    #[test]
+    fn control_payload_too_long() {
+        let mut socket = server_socket(vec![], 100);
+
+        assert!(matches!(socket.send(Message::Ping(vec![0; 126])), Err(WebSocketError::Io(_))));
+        assert!(matches!(socket.close(1005, ""), Err(WebSocketError::Io(_))));
+        assert!(written_frames(&socket).is_empty());
+    }
+}
@@ -16,6 +16,7 @@ fn single_connection_google() {
         addr: "google.com:80",
         read_timeout: Duration::from_secs(1),
         num_connections: 1,
+        server_name: None,
     });
 
     let response = client.send(&Request {
@@ -35,6 +36,7 @@ fn reuse_connection_google() {
         addr: "google.com:80",
         read_timeout: Duration::from_secs(1),
         num_connections: 1,
+        server_name: None,
     });
 
     let response = client.send(&Request {
@@ -74,6 +76,7 @@ fn single_connection_northeastern() {
         addr: "northeastern.edu:80",
         read_timeout: Duration::from_secs(1),
         num_connections: 1,
+        server_name: None,
     });
 
     let response = client.send(&Request {
@@ -93,6 +96,7 @@ fn single_connection_reddit() {
         addr: "reddit.com:80",
         read_timeout: Duration::from_secs(1),
         num_connections: 1,
+        server_name: None,
     });
 
     let response = client.send(&Request {
@@ -138,6 +142,7 @@ fn test_empty_requests(addr: &'static str, num_connections: usize, requests: usi
         addr,
         read_timeout: Duration::from_millis(2000),
         num_connections,
+        server_name: None,
     });
 
     util::test_client::test_empty_requests(client, requests, expected_status, should_have_body);
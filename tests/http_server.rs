@@ -1,24 +1,29 @@
 extern crate my_http;
 
 use std::fs;
-use std::io::{Read, Write};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
 use std::net::TcpStream;
-use std::sync::Arc;
+use std::sync::{Arc, mpsc, Mutex};
 use std::thread::{sleep, spawn};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use my_http::{header_map, server};
-use my_http::common::header::{ACCEPT, ACCEPT_CHARSET, ACCEPT_ENCODING, ACCEPT_LANGUAGE, ACCEPT_RANGES, CONTENT_LENGTH, Header, HeaderMap, HeaderMapOps};
+use my_http::client::{Client, Config as ClientConfig, TcpStreamFactory};
+use my_http::common::header::{ACCEPT, ACCEPT_CHARSET, ACCEPT_ENCODING, ACCEPT_LANGUAGE, ACCEPT_RANGES, AUTHORIZATION, CONTENT_LENGTH, LAST_EVENT_ID, LINK, Header, HeaderMap, HeaderMapOps};
 use my_http::common::method::Method;
 use my_http::common::request::Request;
 use my_http::common::response::Response;
 use my_http::common::status;
 use my_http::common::status::Status;
-use my_http::server::{Config, Router};
+use my_http::server::{Config, ConnectionCounter, ConnectionLimitAction, ContinueResult, EventStream, ListenerResult, MinDataRate, Router, Timeouts, Upgrade};
 use my_http::server::ListenerResult::{SendResponse, SendResponseArc};
+use my_http::sse::{Event, EventSource, EventSourceError};
+use my_http::websocket;
+use my_http::websocket::Message;
 
 use crate::util::curl;
-use crate::util::test_server::{test_server, test_server_with_curl};
+use crate::util::test_server::{read_response_without_date, test_server, test_server_with_curl};
+use crate::util::websocket as raw_websocket;
 
 mod util;
 
@@ -29,6 +34,7 @@ fn many_requests_with_short_headers_and_short_bodies() {
             addr: "0.0.0.0:7000",
             connection_handler_threads: 5,
             router: Router::new(),
+            ..Default::default()
         },
         13, 11, true,
         vec![
@@ -78,6 +84,7 @@ fn many_connections_and_many_large_messages() {
             addr: "0.0.0.0:7001",
             connection_handler_threads: 5,
             router: Router::new(),
+            ..Default::default()
         },
         15, 15, true,
         vec![
@@ -151,6 +158,7 @@ fn many_connections_and_many_large_messages_no_delays() {
             addr: "0.0.0.0:7015",
             connection_handler_threads: 5,
             router: Router::new(),
+            ..Default::default()
         },
         200, 50, false,
         vec![
@@ -232,6 +240,7 @@ fn curl_request() {
         addr: "0.0.0.0:7011",
         connection_handler_threads: 5,
         router,
+        ..Default::default()
     }).unwrap());
 
     sleep(Duration::from_millis(1000));
@@ -254,6 +263,7 @@ fn curl_many_connections_and_many_large_messages() {
             addr: "0.0.0.0:7010",
             connection_handler_threads: 5,
             router: Router::new(),
+            ..Default::default()
         },
         None,
         100,
@@ -323,6 +333,7 @@ fn many_connections_with_one_simple_request_no_delays() {
             addr: "0.0.0.0:7002",
             connection_handler_threads: 5,
             router: Router::new(),
+            ..Default::default()
         },
         200, 1, false,
         vec![
@@ -349,6 +360,7 @@ fn many_connections_with_many_simple_requests() {
             addr: "0.0.0.0:7003",
             connection_handler_threads: 5,
             router: Router::new(),
+            ..Default::default()
         },
         10, 10, true,
         vec![
@@ -375,6 +387,7 @@ fn many_concurrent_connections_with_many_simple_requests_no_delay() {
             addr: "0.0.0.0:7004",
             connection_handler_threads: 5,
             router: Router::new(),
+            ..Default::default()
         },
         10, 10, false,
         vec![
@@ -400,6 +413,7 @@ fn infinite_connection() {
         addr: "0.0.0.0:7005",
         connection_handler_threads: 5,
         router: Router::new(),
+        ..Default::default()
     }).unwrap());
 
     sleep(Duration::from_millis(500));
@@ -424,6 +438,7 @@ fn infinite_connection_with_sleeps() {
         addr: "0.0.0.0:7012",
         connection_handler_threads: 5,
         router: Router::new(),
+        ..Default::default()
     }).unwrap());
 
     sleep(Duration::from_millis(500));
@@ -449,6 +464,7 @@ fn infinite_headers() {
         addr: "0.0.0.0:7006",
         connection_handler_threads: 5,
         router: Router::new(),
+        ..Default::default()
     }).unwrap());
 
     sleep(Duration::from_millis(500));
@@ -475,6 +491,7 @@ fn infinite_header_value() {
         addr: "0.0.0.0:7007",
         connection_handler_threads: 5,
         router: Router::new(),
+        ..Default::default()
     }).unwrap());
 
     sleep(Duration::from_millis(500));
@@ -501,6 +518,7 @@ fn infinite_chunked_body() {
         addr: "0.0.0.0:7008",
         connection_handler_threads: 5,
         router: Router::new(),
+        ..Default::default()
     }).unwrap());
 
     sleep(Duration::from_millis(500));
@@ -527,6 +545,7 @@ fn insanely_huge_body() {
         addr: "0.0.0.0:7009",
         connection_handler_threads: 5,
         router: Router::new(),
+        ..Default::default()
     }).unwrap());
 
     sleep(Duration::from_millis(500));
@@ -551,7 +570,7 @@ fn insanely_huge_body() {
 fn big_response() {
     let file_data = fs::read("./tests/files/big_image.jpg").unwrap();
 
-    let mut expected_response = b"HTTP/1.1 200 OK\r\n\r\n".to_vec();
+    let mut expected_response = format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n", file_data.len()).into_bytes();
     expected_response.extend_from_slice(&file_data);
 
     let response = Response {
@@ -569,6 +588,7 @@ fn big_response() {
         addr: "0.0.0.0:7013",
         connection_handler_threads: 5,
         router,
+        ..Default::default()
     }).unwrap());
 
     sleep(Duration::from_millis(50));
@@ -577,8 +597,7 @@ fn big_response() {
 
     client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
 
-    let mut actual_response = vec![0; expected_response.len()];
-    client.read_exact(&mut actual_response).unwrap();
+    let actual_response = read_response_without_date(&mut client, expected_response.len()).unwrap();
 
     assert_eq!(actual_response, expected_response);
 }
@@ -592,6 +611,7 @@ fn many_big_responses_through_concurrent_connections() {
             addr: "0.0.0.0:7014",
             connection_handler_threads: 5,
             router: Router::new(),
+            ..Default::default()
         },
         10, 10, false,
         vec![
@@ -609,4 +629,875 @@ fn many_big_responses_through_concurrent_connections() {
                 }
             )
         ])
-}
\ No newline at end of file
+}
+#[test]
+fn expect_continue() {
+    let mut router = Router::new();
+    router.on_prefix("", |_, request| SendResponse(request.body.clone().into()));
+
+    spawn(move || server::listen_http(Config {
+        addr: "0.0.0.0:7016",
+        router,
+        ..Default::default()
+    }).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    let mut client = TcpStream::connect("0.0.0.0:7016").unwrap();
+    client.write_all(b"POST / HTTP/1.1\r\ncontent-length: 5\r\nexpect: 100-continue\r\n\r\n").unwrap();
+
+    let mut continue_response = [0u8; 25];
+    client.read_exact(&mut continue_response).unwrap();
+    assert_eq!(&continue_response, b"HTTP/1.1 100 Continue\r\n\r\n");
+
+    client.write_all(b"hello").unwrap();
+
+    let expected_response = b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello";
+    let actual_response = read_response_without_date(&mut client, expected_response.len()).unwrap();
+    assert_eq!(actual_response, expected_response);
+}
+
+#[test]
+fn expect_continue_rejected() {
+    spawn(move || server::listen_http(Config {
+        addr: "0.0.0.0:7017",
+        continue_listener: Some(Box::new(|request| {
+            if request.headers.contains_key(&AUTHORIZATION) {
+                ContinueResult::Continue
+            } else {
+                ContinueResult::Reject(Response::empty(status::EXPECTATION_FAILED))
+            }
+        })),
+        ..Default::default()
+    }).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    let mut client = TcpStream::connect("0.0.0.0:7017").unwrap();
+    client.write_all(b"POST / HTTP/1.1\r\ncontent-length: 500000\r\nexpect: 100-continue\r\n\r\n").unwrap();
+
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 417 EXPECTATION FAILED\r\n"));
+    assert!(response.contains("\r\nconnection: close\r\n"));
+}
+
+#[test]
+fn early_hints() {
+    let mut router = Router::new();
+    router.on_with_sink("/", |_, _, sink| {
+        let hints = Response::builder()
+            .status(status::EARLY_HINTS)
+            .header(LINK, "</style.css>; rel=preload; as=style")
+            .build()
+            .unwrap();
+        sink.send_informational(&hints).unwrap();
+        SendResponse(Response::html("<link rel=stylesheet href=/style.css>"))
+    });
+
+    spawn(move || server::listen_http(Config {
+        addr: "0.0.0.0:7018",
+        router,
+        ..Default::default()
+    }).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    let client = Client::new_http(ClientConfig {
+        addr: "localhost:7018",
+        read_timeout: Duration::from_secs(5),
+        num_connections: 1,
+        server_name: None,
+    });
+
+    let mut informational = vec![];
+    let response = client.send_with_informational(&Request::builder().build().unwrap(), |response| informational.push(response)).unwrap();
+
+    assert_eq!(response.status, status::OK);
+    assert_eq!(response.body, b"<link rel=stylesheet href=/style.css>");
+    assert_eq!(informational.len(), 1);
+    assert_eq!(informational[0].status, status::EARLY_HINTS);
+    assert!(informational[0].headers.contains_header_value(&LINK, "</style.css>; rel=preload; as=style"));
+}
+
+#[test]
+fn upgrade() {
+    let mut router = Router::new();
+    router.on("/echo", |_, _| ListenerResult::Upgrade(Upgrade::new("echo", |mut stream| {
+        let mut line = String::new();
+        while stream.read_line(&mut line).unwrap_or(0) > 0 {
+            stream.write_all(line.to_uppercase().as_bytes()).unwrap();
+            stream.flush().unwrap();
+            line.clear();
+        }
+    })));
+
+    spawn(move || server::listen_http(Config {
+        addr: "0.0.0.0:7019",
+        router,
+        ..Default::default()
+    }).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    let mut client = TcpStream::connect("localhost:7019").unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+    // the first line is sent along with the request, so it is buffered by the server before the upgrade
+    client.write_all(b"GET /echo HTTP/1.1\r\nconnection: upgrade\r\nupgrade: echo\r\n\r\nhello\n").unwrap();
+
+    let mut client = BufReader::new(client);
+    let mut head = String::new();
+    while !head.ends_with("\r\n\r\n") {
+        client.read_line(&mut head).unwrap();
+    }
+    assert!(head.starts_with("HTTP/1.1 101 SWITCHING PROTOCOLS\r\n"));
+    assert!(head.contains("upgrade: echo\r\n"));
+
+    let mut line = String::new();
+    client.read_line(&mut line).unwrap();
+    assert_eq!(line, "HELLO\n");
+
+    client.get_mut().write_all(b"goodbye\n").unwrap();
+    line.clear();
+    client.read_line(&mut line).unwrap();
+    assert_eq!(line, "GOODBYE\n");
+}
+
+#[test]
+fn websocket_echo() {
+    let mut router = Router::new();
+    let config = websocket::Config { protocols: vec!["chat".to_string()], ..Default::default() };
+    router.websocket_with_config("/ws", config, |_, mut socket| {
+        let welcome = format!("welcome {}", socket.protocol().unwrap_or("none"));
+        socket.send_text(&welcome).unwrap();
+        while let Ok(message) = socket.read_message() {
+            if let Message::Text(text) = message {
+                socket.send_text(&text.to_uppercase()).unwrap();
+            }
+        }
+    });
+
+    spawn(move || server::listen_http(Config {
+        addr: "0.0.0.0:7020",
+        router,
+        ..Default::default()
+    }).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    let mut stream = BufReader::new(TcpStream::connect("localhost:7020").unwrap());
+    stream.get_ref().set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+    let head = raw_websocket::handshake(&mut stream, "/ws", "other, chat");
+    assert!(head.starts_with("HTTP/1.1 101 SWITCHING PROTOCOLS\r\n"));
+    assert!(head.contains("sec-websocket-accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+    assert!(head.contains("sec-websocket-protocol: chat\r\n"));
+
+    assert_eq!(raw_websocket::read_frame(&mut stream), (0x1, b"welcome chat".to_vec()));
+
+    raw_websocket::write_frame(stream.get_mut(), 0x1, b"hello");
+    assert_eq!(raw_websocket::read_frame(&mut stream), (0x1, b"HELLO".to_vec()));
+
+    raw_websocket::write_frame(stream.get_mut(), 0x9, b"ping");
+    assert_eq!(raw_websocket::read_frame(&mut stream), (0xA, b"ping".to_vec()));
+
+    raw_websocket::write_frame(stream.get_mut(), 0x8, &1000u16.to_be_bytes());
+    assert_eq!(raw_websocket::read_frame(&mut stream), (0x8, 1000u16.to_be_bytes().to_vec()));
+
+    // the handler returns after the close handshake, which closes the connection
+    let mut rest = vec![];
+    stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+
+    let mut client = TcpStream::connect("localhost:7020").unwrap();
+    client.write_all(b"GET /ws HTTP/1.1\r\n\r\n").unwrap();
+    let response = String::from_utf8(read_response_without_date(&mut client, 93).unwrap()).unwrap();
+    assert!(response.starts_with("HTTP/1.1 426 UPGRADE REQUIRED\r\n"));
+    assert!(response.contains("upgrade: websocket\r\n"));
+}
+
+#[test]
+fn websocket_client() {
+    let mut router = Router::new();
+    let config = websocket::Config { protocols: vec!["chat".to_string()], ..Default::default() };
+    router.websocket_with_config("/ws", config, |_, mut socket| {
+        while let Ok(message) = socket.read_message() {
+            match message {
+                Message::Text(text) => socket.send_text(&text.to_uppercase()).unwrap(),
+                Message::Binary(data) => socket.send_binary(&data.iter().rev().cloned().collect::<Vec<u8>>()).unwrap(),
+                _ => {}
+            }
+        }
+    });
+
+    spawn(move || server::listen_http(Config {
+        addr: "0.0.0.0:7021",
+        router,
+        ..Default::default()
+    }).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    let factory = TcpStreamFactory::new(&ClientConfig {
+        addr: "localhost:7021",
+        read_timeout: Duration::from_secs(5),
+        num_connections: 1,
+        server_name: None,
+    });
+    let config = websocket::Config { protocols: vec!["superchat".to_string(), "chat".to_string()], ..Default::default() };
+    let mut socket = websocket::connect(&factory, "localhost:7021", "/ws", &config).unwrap();
+
+    assert_eq!(socket.protocol(), Some("chat"));
+
+    socket.send_text("hello").unwrap();
+    assert_eq!(socket.read_message().unwrap(), Message::Text("HELLO".to_string()));
+
+    socket.send_binary(&[1, 2, 3]).unwrap();
+    assert_eq!(socket.read_message().unwrap(), Message::Binary(vec![3, 2, 1]));
+
+    socket.send(Message::Ping(b"ping".to_vec())).unwrap();
+    assert_eq!(socket.read_message().unwrap(), Message::Pong(b"ping".to_vec()));
+
+    socket.close(websocket::close_code::NORMAL, "bye").unwrap();
+    assert_eq!(socket.read_message().unwrap(), Message::Close(Some(websocket::CloseFrame::new(websocket::close_code::NORMAL, ""))));
+    assert!(socket.is_closed());
+
+    match websocket::connect(&factory, "localhost:7021", "/other", &config) {
+        Err(websocket::ConnectError::Rejected(response)) => assert_eq!(response.status, status::NOT_FOUND),
+        _ => panic!("expected a rejected handshake"),
+    }
+}
+
+#[test]
+fn event_stream() {
+    let (sender_tx, sender_rx) = mpsc::channel();
+    let sender_tx = Mutex::new(sender_tx);
+    let mut router = Router::new();
+    router.on("/events", move |_, request| {
+        let (stream, sender) = EventStream::new(request);
+        sender_tx.lock().unwrap().send(sender).unwrap();
+        ListenerResult::EventStream(stream.with_keep_alive(Some(Duration::from_millis(200))))
+    });
+
+    spawn(move || server::listen_http(Config {
+        addr: "0.0.0.0:7022",
+        connection_handler_threads: 1,
+        router,
+        ..Default::default()
+    }).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    let mut client = BufReader::new(TcpStream::connect("localhost:7022").unwrap());
+    client.get_ref().set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    client.get_mut().write_all(b"GET /events HTTP/1.1\r\nlast-event-id: 7\r\n\r\n").unwrap();
+
+    let mut head = String::new();
+    while !head.ends_with("\r\n\r\n") {
+        client.read_line(&mut head).unwrap();
+    }
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(head.contains("content-type: text/event-stream\r\n"));
+    assert!(head.contains("transfer-encoding: chunked\r\n"));
+
+    let sender = sender_rx.recv().unwrap();
+    assert_eq!(sender.last_event_id(), Some("7"));
+
+    // events are sent from another thread while the only handler thread is free to serve other connections
+    let sending = spawn(move || {
+        for i in 8..10 {
+            sender.send(&Event::new(&format!("event {}", i)).with_id(&i.to_string())).unwrap();
+        }
+        sender
+    });
+    let sender = sending.join().unwrap();
+
+    let mut other_client = TcpStream::connect("localhost:7022").unwrap();
+    other_client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    other_client.write_all(b"GET /other HTTP/1.1\r\n\r\n").unwrap();
+    let mut response = [0; 45];
+    other_client.read_exact(&mut response).unwrap();
+    assert_eq!(&response[..], b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n");
+
+    let mut events = String::new();
+    while !events.ends_with("data: event 9\n\n\r\n") {
+        client.read_line(&mut events).unwrap();
+    }
+    assert!(events.contains("id: 8\ndata: event 8\n\n"));
+
+    // nothing is sent for a while, so a keep-alive comment is sent
+    let mut keep_alive = String::new();
+    while !keep_alive.ends_with(": keep-alive\n\n\r\n") {
+        client.read_line(&mut keep_alive).unwrap();
+    }
+
+    drop(client);
+    let start = Instant::now();
+    while !sender.is_closed() {
+        assert!(start.elapsed() < Duration::from_secs(5), "sender was not closed");
+        let _ = sender.send_comment("anyone there?");
+        sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn event_source() {
+    let mut router = Router::new();
+    router.on("/events", |_, request| {
+        match request.headers.get_first_header_value(&LAST_EVENT_ID).map(String::as_str) {
+            // the last event has been received, so tell the client to stop reconnecting
+            Some("3") => SendResponse(Response::empty(status::NO_CONTENT)),
+            last_event_id => {
+                let (stream, sender) = EventStream::new(request);
+                if last_event_id.is_none() {
+                    sender.send(&Event::new("first").with_id("1").with_retry(Duration::from_millis(100))).unwrap();
+                }
+                sender.send_comment("ignored").unwrap();
+                sender.send(&Event::new("second\nline").with_event("update").with_id("2")).unwrap();
+                sender.send(&Event::new("third").with_id("3")).unwrap();
+                // the stream ends once the sender is dropped, so the client reconnects
+                ListenerResult::EventStream(stream)
+            }
+        }
+    });
+
+    spawn(move || server::listen_http(Config {
+        addr: "0.0.0.0:7023",
+        connection_handler_threads: 1,
+        router,
+        ..Default::default()
+    }).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    let factory = TcpStreamFactory::new(&ClientConfig {
+        addr: "localhost:7023",
+        read_timeout: Duration::from_secs(5),
+        num_connections: 1,
+        server_name: None,
+    });
+
+    let mut events = EventSource::new(factory, "localhost:7023", "/events");
+    let first = events.next().unwrap().unwrap();
+    assert_eq!(first, Event::new("first").with_id("1").with_retry(Duration::from_millis(100)));
+    assert_eq!(events.retry(), Duration::from_millis(100));
+    let second = events.next().unwrap().unwrap();
+    assert_eq!(second, Event::new("second\nline").with_event("update").with_id("2"));
+    assert_eq!(events.next().unwrap().unwrap().data, "third");
+    assert_eq!(events.last_event_id(), Some("3"));
+
+    let start = Instant::now();
+    assert!(events.next().is_none());
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    let factory = TcpStreamFactory::new(&ClientConfig {
+        addr: "localhost:7023",
+        read_timeout: Duration::from_secs(5),
+        num_connections: 1,
+        server_name: None,
+    });
+    let mut events = EventSource::new(factory, "localhost:7023", "/events").with_last_event_id("1");
+    assert_eq!(events.next().unwrap().unwrap().data, "second\nline");
+    assert_eq!(events.next().unwrap().unwrap().data, "third");
+
+    let factory = TcpStreamFactory::new(&ClientConfig {
+        addr: "localhost:7023",
+        read_timeout: Duration::from_secs(5),
+        num_connections: 1,
+        server_name: None,
+    });
+    let mut events = EventSource::new(factory, "localhost:7023", "/other");
+    match events.next() {
+        Some(Err(EventSourceError::Rejected(response))) => assert_eq!(response.status, status::NOT_FOUND),
+        other => panic!("unexpected result: {:?}", other.map(|result| result.map(|event| event.data))),
+    }
+    assert!(events.next().is_none());
+}
+
+#[test]
+#[cfg(feature = "http2")]
+fn http2_prior_knowledge() {
+    let mut router = Router::new();
+    router.on("/hello", |_, _| SendResponse(Response {
+        status: status::OK,
+        headers: header_map![],
+        body: b"hello".to_vec(),
+    }));
+    router.on("/events", |_, request| {
+        let (stream, sender) = EventStream::new(request);
+        sender.send(&Event::new("first")).unwrap();
+        sender.send(&Event::new("second")).unwrap();
+        ListenerResult::EventStream(stream)
+    });
+
+    spawn(move || server::listen_http(Config {
+        addr: "0.0.0.0:7024",
+        connection_handler_threads: 2,
+        router,
+        server_header: Some("my_http".to_string()),
+        ..Default::default()
+    }).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    // curl fails to reuse connections that use prior knowledge, so each request has its own connection
+    let responses: Vec<String> = ["/hello", "/missing", "/events", "/hello"].iter()
+        .map(|uri| curl::http2_requests("localhost:7024", &[uri], false))
+        .collect();
+
+    assert!(responses.iter().all(|response| response.starts_with("HTTP/2 ")), "{:?}", responses);
+    let responses: Vec<&str> = responses.iter().map(|response| &response["HTTP/2 ".len()..]).collect();
+    assert!(responses[0].starts_with("200"));
+    assert!(responses[0].contains("server: my_http\r\n"));
+    assert!(responses[0].contains("content-length: 5\r\n"));
+    assert!(responses[0].ends_with("\r\n\r\nhello"));
+    assert!(responses[1].starts_with("404"));
+    assert!(responses[2].contains("content-type: text/event-stream\r\n"));
+    assert!(responses[2].ends_with("\r\n\r\ndata: first\n\ndata: second\n\n"));
+    assert!(responses[3].ends_with("\r\n\r\nhello"));
+}
+
+/// Starts a server on the given address with short timeouts, which responds to every request with an empty response.
+fn start_server_with_short_timeouts(addr: &'static str) {
+    let mut router = Router::new();
+    router.on_prefix("", |_, _| SendResponse(Response::empty(status::OK)));
+
+    spawn(move || server::listen_http(Config {
+        addr,
+        router,
+        timeouts: Timeouts {
+            keep_alive: Some(Duration::from_millis(500)),
+            header_read: Some(Duration::from_millis(300)),
+            min_body_rate: Some(MinDataRate { bytes_per_second: 10, grace_period: Duration::from_millis(300) }),
+            write: Some(Duration::from_secs(1)),
+        },
+        ..Default::default()
+    }).unwrap());
+
+    sleep(Duration::from_millis(100));
+}
+
+/// Connects to the given address, sends the given data, and reads until the server closes the connection.
+/// Returns the data that was read and how long it took for the connection to be closed.
+fn send_and_read_until_closed(addr: &str, data: &[u8]) -> (String, Duration) {
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let start = Instant::now();
+    client.write_all(data).unwrap();
+
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    (response, start.elapsed())
+}
+
+#[test]
+fn request_timeouts() {
+    start_server_with_short_timeouts("0.0.0.0:7025");
+
+    let (response, elapsed) = send_and_read_until_closed("localhost:7025", b"GET / HTTP/1.1\r\nhost: local");
+    assert_eq!(response, "HTTP/1.1 408 Request Timeout\r\nconnection: close\r\ncontent-length: 0\r\n\r\n");
+    assert!(elapsed >= Duration::from_millis(300), "{:?}", elapsed);
+
+    // 5 bytes are allowed after half a second at 10 bytes per second after a grace period of 300 milliseconds
+    let (response, elapsed) = send_and_read_until_closed("localhost:7025", b"POST / HTTP/1.1\r\ncontent-length: 100\r\n\r\nhello");
+    assert_eq!(response, "HTTP/1.1 408 Request Timeout\r\nconnection: close\r\ncontent-length: 0\r\n\r\n");
+    assert!(elapsed >= Duration::from_millis(800), "{:?}", elapsed);
+}
+
+#[test]
+fn keep_alive_timeout() {
+    start_server_with_short_timeouts("0.0.0.0:7026");
+
+    // idle connections are closed without a response
+    let (response, elapsed) = send_and_read_until_closed("localhost:7026", b"");
+    assert_eq!(response, "");
+    assert!(elapsed >= Duration::from_millis(500), "{:?}", elapsed);
+
+    // a connection that keeps sending requests stays open for longer than the timeout
+    let mut client = TcpStream::connect("localhost:7026").unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let expected_response = b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n";
+    for _ in 0..5 {
+        client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(read_response_without_date(&mut client, expected_response.len()).unwrap(), expected_response);
+        sleep(Duration::from_millis(250));
+    }
+
+    let start = Instant::now();
+    let mut rest = String::new();
+    client.read_to_string(&mut rest).unwrap();
+    assert_eq!(rest, "");
+    assert!(start.elapsed() >= Duration::from_millis(200), "{:?}", start.elapsed());
+}
+
+/// Starts a server on the given address that allows the given number of connections, and returns its connection counter.
+fn start_server_with_connection_limit(addr: &'static str, max_connections: usize, at_max_connections: ConnectionLimitAction) -> ConnectionCounter {
+    let mut router = Router::new();
+    router.on_prefix("", |_, _| SendResponse(Response::empty(status::OK)));
+    let counter = ConnectionCounter::new();
+
+    let config = Config {
+        addr,
+        router,
+        max_connections: Some(max_connections),
+        at_max_connections,
+        connection_counter: counter.clone(),
+        ..Default::default()
+    };
+    spawn(move || server::listen_http(config).unwrap());
+
+    sleep(Duration::from_millis(100));
+    counter
+}
+
+/// Sends a request with the given client and checks that it gets an empty 200 response.
+fn assert_request_succeeds(client: &mut TcpStream) {
+    client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let expected_response = b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n";
+    assert_eq!(read_response_without_date(client, expected_response.len()).unwrap(), expected_response);
+}
+
+#[test]
+fn connection_limit_reject() {
+    let counter = start_server_with_connection_limit("0.0.0.0:7027", 2, ConnectionLimitAction::Reject { retry_after: 5 });
+
+    let mut first = TcpStream::connect("localhost:7027").unwrap();
+    let mut second = TcpStream::connect("localhost:7027").unwrap();
+    assert_request_succeeds(&mut first);
+    assert_request_succeeds(&mut second);
+    assert_eq!(counter.get(), 2);
+
+    let (response, _) = send_and_read_until_closed("localhost:7027", b"GET / HTTP/1.1\r\n\r\n");
+    assert_eq!(response, "HTTP/1.1 503 Service Unavailable\r\nretry-after: 5\r\nconnection: close\r\ncontent-length: 0\r\n\r\n");
+
+    drop(first);
+    sleep(Duration::from_millis(200));
+    assert_eq!(counter.get(), 1);
+    assert_request_succeeds(&mut TcpStream::connect("localhost:7027").unwrap());
+}
+
+#[test]
+fn connection_limit_wait() {
+    let counter = start_server_with_connection_limit("0.0.0.0:7028", 1, ConnectionLimitAction::Wait);
+
+    let mut first = TcpStream::connect("localhost:7028").unwrap();
+    assert_request_succeeds(&mut first);
+
+    // the second connection waits in the backlog until the first one closes
+    let mut second = TcpStream::connect("localhost:7028").unwrap();
+    second.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    second.set_read_timeout(Some(Duration::from_millis(300))).unwrap();
+    assert!(second.read(&mut [0; 16]).is_err());
+    assert_eq!(counter.get(), 1);
+
+    drop(first);
+    second.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let expected_response = b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n";
+    assert_eq!(read_response_without_date(&mut second, expected_response.len()).unwrap(), expected_response);
+    assert_eq!(counter.get(), 1);
+}
+
+#[test]
+fn max_requests_per_connection() {
+    let mut router = Router::new();
+    router.on("/", |_, _| SendResponse(Response::empty(status::OK)));
+    router.on("/big", |_, _| SendResponse(vec![b'a'; 4_000_000].into()));
+
+    spawn(move || server::listen_http(Config {
+        addr: "0.0.0.0:7029",
+        router,
+        max_requests_per_connection: Some(3),
+        ..Default::default()
+    }).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    let mut client = TcpStream::connect("localhost:7029").unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    assert_request_succeeds(&mut client);
+    assert_request_succeeds(&mut client);
+
+    // the last response is sent completely even though the client does not read it right away
+    client.write_all(b"GET /big HTTP/1.1\r\n\r\n").unwrap();
+    sleep(Duration::from_millis(300));
+    let mut response = vec![];
+    client.read_to_end(&mut response).unwrap();
+    let response = String::from_utf8(response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("\r\nconnection: close\r\n"));
+    assert!(response.ends_with(&format!("\r\ncontent-length: 4000000\r\n\r\n{}", "a".repeat(4_000_000))));
+}
+
+#[test]
+fn panicking_listener() {
+    let mut router = Router::new();
+    router.on("/", |_, _| SendResponse(Response::empty(status::OK)));
+    router.on("/panic", |_, _| panic!("oh no"));
+
+    spawn(move || server::listen_http(Config {
+        addr: "0.0.0.0:7030",
+        connection_handler_threads: 1,
+        router,
+        ..Default::default()
+    }).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    // every request panics on the only thread, which would kill it if the panics were not caught
+    for _ in 0..3 {
+        let mut client = TcpStream::connect("localhost:7030").unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        client.write_all(b"GET /panic HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = vec![];
+        client.read_to_end(&mut response).unwrap();
+        assert_eq!(response, b"HTTP/1.1 500 Internal Server Error\r\nconnection: close\r\ncontent-length: 0\r\n\r\n");
+    }
+
+    let mut client = TcpStream::connect("localhost:7030").unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    assert_request_succeeds(&mut client);
+}
+
+#[test]
+fn many_concurrent_connections_with_multiple_event_loops() {
+    test_server(
+        Config {
+            addr: "0.0.0.0:7031",
+            event_loops: 4,
+            router: Router::new(),
+            ..Default::default()
+        },
+        20, 20, false,
+        vec![
+            (
+                Request {
+                    uri: "/".to_string(),
+                    method: Method::GET,
+                    headers: Default::default(),
+                    body: vec![],
+                },
+                Response {
+                    status: status::OK,
+                    headers: Default::default(),
+                    body: b"hello".to_vec(),
+                }
+            )
+        ])
+}
+
+#[test]
+fn event_stream_with_multiple_event_loops() {
+    let (sender_tx, sender_rx) = mpsc::channel();
+    let sender_tx = Mutex::new(sender_tx);
+    let mut router = Router::new();
+    router.event_stream("/events", move |_, sender| sender_tx.lock().unwrap().send(sender).unwrap());
+
+    spawn(move || server::listen_http(Config {
+        addr: "0.0.0.0:7032",
+        event_loops: 2,
+        router,
+        ..Default::default()
+    }).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    let mut client = BufReader::new(TcpStream::connect("localhost:7032").unwrap());
+    client.get_ref().set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    client.get_mut().write_all(b"GET /events HTTP/1.1\r\n\r\n").unwrap();
+
+    let mut head = String::new();
+    while !head.ends_with("\r\n\r\n") {
+        client.read_line(&mut head).unwrap();
+    }
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+
+    // the event loop of the connection is woken to write the events sent from another thread
+    let sender = sender_rx.recv().unwrap();
+    spawn(move || sender.send(&Event::new("hello")).unwrap()).join().unwrap();
+
+    let mut events = String::new();
+    while !events.ends_with("data: hello\n\n\r\n") {
+        client.read_line(&mut events).unwrap();
+    }
+}
+
+#[test]
+fn connection_limit_with_multiple_event_loops() {
+    let mut router = Router::new();
+    router.on_prefix("", |_, _| SendResponse(Response::empty(status::OK)));
+    let counter = ConnectionCounter::new();
+
+    let config = Config {
+        addr: "0.0.0.0:7033",
+        event_loops: 4,
+        router,
+        max_connections: Some(2),
+        at_max_connections: ConnectionLimitAction::Reject { retry_after: 5 },
+        connection_counter: counter.clone(),
+        ..Default::default()
+    };
+    spawn(move || server::listen_http(config).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    // the limit is shared by the event loops, whichever of them accept the connections
+    let mut first = TcpStream::connect("localhost:7033").unwrap();
+    let mut second = TcpStream::connect("localhost:7033").unwrap();
+    assert_request_succeeds(&mut first);
+    assert_request_succeeds(&mut second);
+    assert_eq!(counter.get(), 2);
+
+    let mut third = TcpStream::connect("localhost:7033").unwrap();
+    third.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut response = String::new();
+    third.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+    assert_eq!(counter.get(), 2);
+
+    drop(first);
+    let start = Instant::now();
+    while counter.get() > 1 {
+        assert!(start.elapsed() < Duration::from_secs(5), "connection was not removed");
+        sleep(Duration::from_millis(10));
+    }
+    let mut fourth = TcpStream::connect("localhost:7033").unwrap();
+    fourth.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    assert_request_succeeds(&mut fourth);
+}
+
+#[test]
+fn event_stream_to_client_that_stops_reading() {
+    let (sender_tx, sender_rx) = mpsc::channel();
+    let sender_tx = Mutex::new(sender_tx);
+    let mut router = Router::new();
+    router.on("/events", move |_, request| {
+        let (stream, sender) = EventStream::new(request);
+        sender_tx.lock().unwrap().send(sender).unwrap();
+        ListenerResult::EventStream(stream.with_max_pending(64 * 1024))
+    });
+
+    spawn(move || server::listen_http(Config {
+        addr: "0.0.0.0:7034",
+        router,
+        ..Default::default()
+    }).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    let mut client = TcpStream::connect("localhost:7034").unwrap();
+    client.write_all(b"GET /events HTTP/1.1\r\n\r\n").unwrap();
+    let sender = sender_rx.recv().unwrap();
+
+    // the client never reads, so once the socket buffers are full the events pile up in the stream until it is full
+    let event = Event::new(&"a".repeat(1000));
+    let mut sent = 0;
+    let mut blocked = 0;
+    while blocked < 20 && sent < 64 * 1024 * 1024 {
+        match sender.send(&event) {
+            Ok(()) => {
+                sent += 1000;
+                blocked = 0;
+            }
+            Err(error) => {
+                assert_eq!(error.kind(), ErrorKind::WouldBlock);
+                blocked += 1;
+                sleep(Duration::from_millis(50));
+            }
+        }
+    }
+
+    assert_eq!(blocked, 20, "sent {} bytes without blocking", sent);
+    assert!(!sender.is_closed());
+}
+
+#[test]
+fn inline_listeners_are_not_held_up_by_blocking_listeners() {
+    let (release_tx, release_rx) = mpsc::channel::<()>();
+    let release_rx = Mutex::new(release_rx);
+
+    let mut router = Router::new();
+    router.on("/blocking", move |_, _| {
+        release_rx.lock().unwrap().recv().unwrap();
+        SendResponse(Response::text("blocking"))
+    });
+    router.on_inline("/inline", |_, _| SendResponse(Response::text("inline")));
+
+    spawn(|| server::listen_http(Config {
+        addr: "0.0.0.0:7035",
+        event_loops: 1,
+        connection_handler_threads: 1,
+        router,
+        ..Default::default()
+    }).unwrap());
+    sleep(Duration::from_millis(100));
+
+    let mut blocked = TcpStream::connect("localhost:7035").unwrap();
+    blocked.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    blocked.write_all(b"GET /blocking HTTP/1.1\r\nconnection: close\r\n\r\n").unwrap();
+    sleep(Duration::from_millis(100));
+
+    // the only connection handler thread is busy, but inline listeners are still called on the event loop
+    let mut client = TcpStream::connect("localhost:7035").unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    client.write_all(b"GET /inline HTTP/1.1\r\nconnection: close\r\n\r\n").unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    assert!(response.ends_with("inline"), "{}", response);
+
+    release_tx.send(()).unwrap();
+    let mut response = String::new();
+    blocked.read_to_string(&mut response).unwrap();
+    assert!(response.ends_with("blocking"), "{}", response);
+}
+
+/// Panics when dropped, so it panics outside of the listener that returned it.
+struct PanicOnDrop;
+
+impl Drop for PanicOnDrop {
+    fn drop(&mut self) {
+        panic!("oh no");
+    }
+}
+
+#[test]
+fn panic_outside_of_listener_frees_connection() {
+    // the upgrade is rejected for not having a 101 status, so its handler is dropped after the listener returned
+    let upgrade_dropped_by_server = || {
+        let value = PanicOnDrop;
+        ListenerResult::Upgrade(Upgrade::with_response(Response::empty(status::OK), move |_| drop(value)))
+    };
+    let mut router = Router::new();
+    router.on("/", |_, _| SendResponse(Response::empty(status::OK)));
+    router.on("/panic", move |_, _| upgrade_dropped_by_server());
+    router.on_inline("/panic_inline", move |_, _| upgrade_dropped_by_server());
+    let counter = ConnectionCounter::new();
+
+    let config = Config {
+        addr: "0.0.0.0:7036",
+        router,
+        max_connections: Some(1),
+        connection_counter: counter.clone(),
+        ..Default::default()
+    };
+    spawn(move || server::listen_http(config).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    for uri in ["/panic", "/panic_inline"] {
+        let mut client = TcpStream::connect("localhost:7036").unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        write!(client, "GET {} HTTP/1.1\r\n\r\n", uri).unwrap();
+        let mut response = vec![];
+        client.read_to_end(&mut response).unwrap();
+        assert!(response.starts_with(b"HTTP/1.1 500 Internal Server Error\r\n"));
+    }
+
+    // the panicked connections no longer count towards the limit, so an idle client is accepted right away
+    let mut idle = TcpStream::connect("localhost:7036").unwrap();
+    idle.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    sleep(Duration::from_millis(100));
+    assert_eq!(counter.get(), 1);
+    assert_request_succeeds(&mut idle);
+}
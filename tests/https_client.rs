@@ -18,6 +18,7 @@ fn single_google_request() {
             addr: "google.com:443",
             read_timeout: Duration::from_millis(2000),
             num_connections: 5,
+            server_name: None,
         },
         get_tls_config(),
     );
@@ -40,6 +41,7 @@ fn test_single_reddit_request() {
             addr: "www.reddit.com:443",
             read_timeout: Duration::from_millis(5000),
             num_connections: 5,
+            server_name: None,
         },
         get_tls_config(),
     );
@@ -64,6 +66,7 @@ fn test_single_northeastern_request() {
             addr: "www.northeastern.edu:443",
             read_timeout: Duration::from_millis(2000),
             num_connections: 5,
+            server_name: None,
         },
         get_tls_config(),
     );
@@ -106,6 +109,7 @@ fn test_empty_requests(addr: &'static str, num_connections: usize, requests: usi
             addr,
             read_timeout: Duration::from_secs(5),
             num_connections,
+            server_name: None,
         },
         get_tls_config(),
     );
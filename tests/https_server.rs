@@ -1,23 +1,29 @@
 use std::fs;
-use std::io::{BufReader, Read, Write};
+use std::io::{BufReader, ErrorKind, Read, Write};
 use std::net::TcpStream;
 use std::sync::Arc;
 use std::thread::{sleep, spawn};
 use std::time::Duration;
 
-use rustls::{Certificate, NoClientAuth, PrivateKey, ServerConfig};
+use rustls::{ClientConfig as TlsClientConfig, ClientSession, NoClientAuth, ServerConfig, Session, StreamOwned};
+use webpki::DNSNameRef;
 
 use my_http::{header_map, server};
-use my_http::common::header::CONTENT_LENGTH;
+use my_http::client::{Client, Config as ClientConfig, RequestError, TlsStreamFactory};
+use my_http::common::header::{CONTENT_LENGTH, HeaderMapOps, HOST};
 use my_http::common::method::Method;
 use my_http::common::request::Request;
 use my_http::common::response::Response;
 use my_http::common::status;
 use my_http::server::{Config, Router};
 use my_http::server::ListenerResult::SendResponse;
+use my_http::tls::{CertFiles, ClientAuth, ClientTlsConfig, load_certs, load_private_key, ReloadableCerts, spki_sha256, TlsConfig};
+use my_http::websocket;
+use my_http::websocket::Message;
 
 use crate::util::curl;
 use crate::util::test_server::test_server_with_curl;
+use crate::util::websocket as raw_websocket;
 
 mod util;
 
@@ -37,6 +43,7 @@ fn curl_request() {
         addr: "0.0.0.0:8000",
         connection_handler_threads: 5,
         router,
+        ..Default::default()
     }, get_tsl_config()).unwrap());
 
     sleep(Duration::from_millis(1000));
@@ -67,6 +74,7 @@ fn curl_multiple_requests_same_connection() {
         addr: "0.0.0.0:8001",
         connection_handler_threads: 5,
         router,
+        ..Default::default()
     }, get_tsl_config()).unwrap());
 
     sleep(Duration::from_millis(1000));
@@ -90,6 +98,7 @@ fn curl_multiple_concurrent_connections_with_many_requests() {
             addr: "0.0.0.0:8002",
             connection_handler_threads: 5,
             router: Router::new(),
+            ..Default::default()
         },
         Some(get_tsl_config()),
         50,
@@ -119,6 +128,7 @@ fn curl_multiple_concurrent_connections_with_single_requests() {
             addr: "0.0.0.0:8005",
             connection_handler_threads: 5,
             router: Router::new(),
+            ..Default::default()
         },
         Some(get_tsl_config()),
         200,
@@ -143,6 +153,7 @@ fn infinite_connection() {
         addr: "0.0.0.0:8003",
         connection_handler_threads: 5,
         router: Router::new(),
+        ..Default::default()
     }, get_tsl_config()).unwrap());
 
     sleep(Duration::from_millis(1000));
@@ -162,6 +173,7 @@ fn normal_http_message() {
         addr: "0.0.0.0:8004",
         connection_handler_threads: 5,
         router: Router::new(),
+        ..Default::default()
     }, get_tsl_config()).unwrap());
 
     sleep(Duration::from_millis(1000));
@@ -184,6 +196,7 @@ fn curl_many_big_responses_through_concurrent_connections() {
             addr: "0.0.0.0:8006",
             connection_handler_threads: 5,
             router: Router::new(),
+            ..Default::default()
         },
         Some(get_tsl_config()),
         10,
@@ -205,42 +218,472 @@ fn curl_many_big_responses_through_concurrent_connections() {
 fn get_tsl_config() -> ServerConfig {
     let mut config = ServerConfig::new(NoClientAuth::new());
 
-    let certs = read_certs("./tests/certs/server.crt");
-    let privkey = read_private_key("./tests/certs/server.key");
+    let certs = load_certs("./tests/certs/server.crt").unwrap();
+    let privkey = load_private_key("./tests/certs/server.key").unwrap();
 
     config.set_single_cert(certs, privkey).unwrap();
 
     config
 }
 
-fn read_certs(filename: &str) -> Vec<Certificate> {
-    let certfile = fs::File::open(filename).expect("cannot open certificate file");
-    let mut reader = BufReader::new(certfile);
-    rustls::internal::pemfile::certs(&mut reader).unwrap()
+#[test]
+fn websocket_echo() {
+    let mut router = Router::new();
+    router.websocket("/ws", |_, mut socket| {
+        while let Ok(message) = socket.read_message() {
+            if let Message::Text(text) = message {
+                socket.send_text(&text.to_uppercase()).unwrap();
+            }
+        }
+    });
+
+    let mut tls_config = ServerConfig::new(NoClientAuth::new());
+    tls_config.set_single_cert(load_certs("./tests/certs/localhost.crt").unwrap(), load_private_key("./tests/certs/localhost.key").unwrap()).unwrap();
+
+    spawn(|| server::listen_https(Config {
+        addr: "0.0.0.0:8007",
+        router,
+        ..Default::default()
+    }, tls_config).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    let mut client_config = TlsClientConfig::new();
+    client_config.root_store.add(&load_certs("./tests/certs/ca.crt").unwrap()[0]).unwrap();
+    let session = ClientSession::new(&Arc::new(client_config), DNSNameRef::try_from_ascii_str("localhost").unwrap());
+    let socket = TcpStream::connect("localhost:8007").unwrap();
+    socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut stream = BufReader::new(StreamOwned::new(session, socket));
+
+    let head = raw_websocket::handshake(&mut stream, "/ws", "chat");
+    assert!(head.starts_with("HTTP/1.1 101 SWITCHING PROTOCOLS\r\n"));
+    assert!(head.contains("sec-websocket-accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+    assert!(!head.contains("sec-websocket-protocol"));
+
+    raw_websocket::write_frame(stream.get_mut(), 0x1, b"hello over tls");
+    assert_eq!(raw_websocket::read_frame(&mut stream), (0x1, b"HELLO OVER TLS".to_vec()));
+
+    raw_websocket::write_frame(stream.get_mut(), 0x8, &[]);
+    assert_eq!(raw_websocket::read_frame(&mut stream), (0x8, vec![]));
+}
+
+#[test]
+fn websocket_client() {
+    let mut router = Router::new();
+    router.websocket("/ws", |_, mut socket| {
+        while let Ok(message) = socket.read_message() {
+            if let Message::Text(text) = message {
+                socket.send_text(&text.to_uppercase()).unwrap();
+            }
+        }
+    });
+
+    let mut tls_config = ServerConfig::new(NoClientAuth::new());
+    tls_config.set_single_cert(load_certs("./tests/certs/localhost.crt").unwrap(), load_private_key("./tests/certs/localhost.key").unwrap()).unwrap();
+
+    spawn(|| server::listen_https(Config {
+        addr: "0.0.0.0:8008",
+        router,
+        ..Default::default()
+    }, tls_config).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    let mut client_config = TlsClientConfig::new();
+    client_config.root_store.add(&load_certs("./tests/certs/ca.crt").unwrap()[0]).unwrap();
+    let factory = TlsStreamFactory::new(&ClientConfig {
+        addr: "localhost:8008",
+        read_timeout: Duration::from_secs(5),
+        num_connections: 1,
+        server_name: None,
+    }, client_config);
+
+    let mut socket = websocket::connect(&factory, "localhost:8008", "/ws", &Default::default()).unwrap();
+    assert_eq!(socket.protocol(), None);
+
+    // large enough to be split across multiple TLS records
+    let text = "hello over tls ".repeat(5000);
+    socket.send_text(&text).unwrap();
+    assert_eq!(socket.read_message().unwrap(), Message::Text(text.to_uppercase()));
+
+    socket.close(websocket::close_code::GOING_AWAY, "").unwrap();
+    assert_eq!(socket.read_message().unwrap(), Message::Close(Some(websocket::CloseFrame::new(websocket::close_code::GOING_AWAY, ""))));
+}
+
+#[test]
+#[cfg(feature = "http2")]
+fn curl_http2_with_alpn() {
+    let mut router = Router::new();
+
+    router.on_prefix("/", |_, _| {
+        SendResponse(Response {
+            status: status::OK,
+            headers: header_map![(CONTENT_LENGTH, "6")],
+            body: "i work".as_bytes().to_vec(),
+        })
+    });
+
+    spawn(|| server::listen_https(Config {
+        addr: "0.0.0.0:8009",
+        connection_handler_threads: 5,
+        router,
+        ..Default::default()
+    }, get_tsl_config()).unwrap());
+
+    sleep(Duration::from_millis(1000));
+
+    let output = curl::http2_requests("localhost:8009", &["/", "/other"], true);
+
+    assert_eq!(output.matches("HTTP/2 200").count(), 2, "{}", output);
+    assert!(output.ends_with("\r\n\r\ni work"));
+}
+
+/// Starts a server on server_addr that responds with the URI and host header of each request, and sends requests to it
+/// at addr from multiple threads with a client that has a single connection. Returns the response bodies.
+fn client_requests(server_addr: &'static str, addr: &'static str, alpn_protocols: &[Vec<u8>]) -> Vec<String> {
+    let mut router = Router::new();
+    router.on_prefix("/", |_, request| {
+        let host = request.headers.get_first_header_value(&HOST).map(String::as_str).unwrap_or("none");
+        SendResponse(Response {
+            status: status::OK,
+            headers: header_map![],
+            body: format!("{} {}", request.uri, host).into_bytes(),
+        })
+    });
+
+    let mut tls_config = ServerConfig::new(NoClientAuth::new());
+    tls_config.set_single_cert(load_certs("./tests/certs/localhost.crt").unwrap(), load_private_key("./tests/certs/localhost.key").unwrap()).unwrap();
+    tls_config.set_protocols(alpn_protocols);
+
+    spawn(move || server::listen_https(Config {
+        addr: server_addr,
+        connection_handler_threads: 5,
+        router,
+        ..Default::default()
+    }, tls_config).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    let mut client_config = TlsClientConfig::new();
+    client_config.root_store.add(&load_certs("./tests/certs/ca.crt").unwrap()[0]).unwrap();
+    let client = Arc::new(Client::new_https(ClientConfig {
+        addr,
+        read_timeout: Duration::from_secs(5),
+        num_connections: 1,
+        server_name: None,
+    }, client_config));
+
+    let handlers: Vec<_> = (0..10).map(|i| {
+        let client = client.clone();
+        spawn(move || {
+            let request = Request::builder().uri(format!("/{}", i)).build().unwrap();
+            String::from_utf8(client.send(&request).unwrap().body).unwrap()
+        })
+    }).collect();
+
+    handlers.into_iter().map(|handler| handler.join().unwrap()).collect()
+}
+
+#[test]
+#[cfg(feature = "http2")]
+fn client_http2_with_alpn() {
+    let bodies = client_requests("0.0.0.0:8010", "localhost:8010", &[b"h2".to_vec(), b"http/1.1".to_vec()]);
+
+    // the :authority pseudo-header field is only sent over HTTP/2, and becomes the host header of the request
+    let expected: Vec<String> = (0..10).map(|i| format!("/{} localhost:8010", i)).collect();
+    assert_eq!(bodies, expected);
+}
+
+#[test]
+fn client_http1_fallback() {
+    let bodies = client_requests("0.0.0.0:8011", "localhost:8011", &[b"http/1.1".to_vec()]);
+
+    let expected: Vec<String> = (0..10).map(|i| format!("/{} none", i)).collect();
+    assert_eq!(bodies, expected);
+}
+
+/// Connects to the given address with the given SNI hostname and ALPN protocols, and sends a request. Returns the
+/// response, and the protocol the server picked with ALPN.
+fn request_with_sni(addr: &str, hostname: &str, alpn_protocols: &[Vec<u8>]) -> std::io::Result<(String, Option<Vec<u8>>)> {
+    let mut client_config = TlsClientConfig::new();
+    client_config.root_store.add(&load_certs("./tests/certs/ca.crt").unwrap()[0]).unwrap();
+    client_config.set_protocols(alpn_protocols);
+    tls_request(addr, hostname, client_config)
+}
+
+/// Sends a request for example.test with the client certificate with the given name from tests/certs, if any, and
+/// returns the response.
+fn request_with_client_cert(addr: &str, cert_name: Option<&str>) -> std::io::Result<String> {
+    let mut client_config = TlsClientConfig::new();
+    client_config.root_store.add(&load_certs("./tests/certs/ca.crt").unwrap()[0]).unwrap();
+    if let Some(name) = cert_name {
+        let certs = load_certs(format!("./tests/certs/{}.crt", name)).unwrap();
+        let key = load_private_key(format!("./tests/certs/{}.key", name)).unwrap();
+        client_config.set_single_client_cert(certs, key).unwrap();
+    }
+    tls_request(addr, "example.test", client_config).map(|(response, _)| response)
+}
+
+/// Connects to the given address with the given SNI hostname and client config, and sends a request. Returns the
+/// response, and the protocol the server picked with ALPN.
+fn tls_request(addr: &str, hostname: &str, client_config: TlsClientConfig) -> std::io::Result<(String, Option<Vec<u8>>)> {
+    let session = ClientSession::new(&Arc::new(client_config), DNSNameRef::try_from_ascii_str(hostname).unwrap());
+    let socket = TcpStream::connect(addr).unwrap();
+    socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut stream = StreamOwned::new(session, socket);
+
+    stream.write_all(b"GET / HTTP/1.1\r\nconnection: close\r\n\r\n")?;
+    let mut response = vec![];
+    let mut buf = [0; 1024];
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => response.extend_from_slice(&buf[..n]),
+            // the server ends the connection with a close_notify alert
+            Err(error) if error.kind() == ErrorKind::ConnectionAborted => break,
+            Err(error) => return Err(error),
+        }
+    }
+    Ok((String::from_utf8(response).unwrap(), stream.sess.get_alpn_protocol().map(|protocol| protocol.to_vec())))
+}
+
+#[test]
+fn sni_certificates_and_alpn() {
+    let mut router = Router::new();
+    router.on_prefix("/", |_, _| SendResponse(Response {
+        status: status::OK,
+        headers: header_map![(CONTENT_LENGTH, "6")],
+        body: b"i work".to_vec(),
+    }));
+
+    let mut tls_config = TlsConfig::default();
+    tls_config.cert_resolver.add("example.test", load_certs("./tests/certs/example.crt").unwrap(), &load_private_key("./tests/certs/example.key").unwrap()).unwrap();
+    tls_config.cert_resolver.add("*.wildcard.test", load_certs("./tests/certs/wildcard.crt").unwrap(), &load_private_key("./tests/certs/wildcard.key").unwrap()).unwrap();
+    tls_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    spawn(|| server::listen_https(Config {
+        addr: "0.0.0.0:8012",
+        router,
+        ..Default::default()
+    }, tls_config).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    let alpn_protocols = [b"h2".to_vec(), b"http/1.1".to_vec()];
+    for hostname in &["example.test", "a.wildcard.test"] {
+        let (response, protocol) = request_with_sni("localhost:8012", hostname, &alpn_protocols).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.ends_with("i work"));
+        assert_eq!(protocol, Some(b"http/1.1".to_vec()));
+    }
+
+    // no certificate for the hostname and no default certificate, so the handshake fails
+    assert!(request_with_sni("localhost:8012", "other.test", &alpn_protocols).is_err());
+}
+
+#[test]
+fn connection_info() {
+    let mut router = Router::new();
+    router.on_prefix_with_context("/", |_, _, context| {
+        let info = context.connection().unwrap();
+        let tls = info.tls.as_ref().unwrap();
+        let body = format!("{} {} {} {} {} {}",
+                           info.peer_addr.ip(),
+                           info.local_addr.port(),
+                           tls.sni_hostname.as_deref().unwrap_or("none"),
+                           String::from_utf8_lossy(tls.alpn_protocol.as_deref().unwrap_or(b"none")),
+                           tls.protocol_version.is_some() && tls.cipher_suite.is_some(),
+                           tls.client_certs.is_none());
+        SendResponse(Response::text(body))
+    });
+
+    let mut tls_config = TlsConfig::default();
+    tls_config.cert_resolver.add("example.test", load_certs("./tests/certs/example.crt").unwrap(), &load_private_key("./tests/certs/example.key").unwrap()).unwrap();
+    tls_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    spawn(|| server::listen_https(Config {
+        addr: "0.0.0.0:8013",
+        router,
+        ..Default::default()
+    }, tls_config).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    let (response, _) = request_with_sni("127.0.0.1:8013", "example.test", &[b"http/1.1".to_vec()]).unwrap();
+    assert!(response.ends_with("\r\n\r\n127.0.0.1 8013 example.test http/1.1 true true"), "{}", response);
+}
+
+/// Starts a server on the given port that verifies client certificates with the given client auth, and responds with
+/// the identity of the client.
+fn start_client_auth_server(addr: &'static str, client_auth: ClientAuth) {
+    let mut router = Router::new();
+    router.on_prefix_with_context("/", |_, _, context| {
+        let tls = context.connection().unwrap().tls.as_ref().unwrap();
+        let body = match &tls.client_identity {
+            Some(identity) => format!("{} {}", identity.subject, identity.dns_names().collect::<Vec<&str>>().join(",")),
+            None => "anonymous".to_string(),
+        };
+        SendResponse(Response::text(body))
+    });
+
+    let mut tls_config = TlsConfig::default();
+    tls_config.cert_resolver.add("example.test", load_certs("./tests/certs/example.crt").unwrap(), &load_private_key("./tests/certs/example.key").unwrap()).unwrap();
+    tls_config.client_auth = client_auth;
+
+    spawn(move || server::listen_https(Config {
+        addr,
+        router,
+        ..Default::default()
+    }, tls_config).unwrap());
+
+    sleep(Duration::from_millis(100));
 }
 
-fn read_private_key(filename: &str) -> PrivateKey {
-    let rsa_keys = {
-        let keyfile = fs::File::open(filename)
-            .expect("cannot open private key file");
-        let mut reader = BufReader::new(keyfile);
-        rustls::internal::pemfile::rsa_private_keys(&mut reader)
-            .expect("file contains invalid rsa private key")
+/// Checks that the given result is a response with the identity of the client certificate in tests/certs/client.crt.
+fn assert_client_identity(result: std::io::Result<String>) {
+    let response = result.unwrap();
+    assert!(response.ends_with("\r\n\r\nCN=client,OU=Clients\\, Inc.,O=my_http tests,C=US client.test"), "{}", response);
+}
+
+/// Checks that the given result is not a successful response.
+fn assert_rejected(result: std::io::Result<String>) {
+    if let Ok(response) = result {
+        assert!(!response.starts_with("HTTP/1.1 200"), "{}", response);
+    }
+}
+
+#[test]
+fn client_auth_required() {
+    start_client_auth_server("0.0.0.0:8014", ClientAuth::required(&load_certs("./tests/certs/ca.crt").unwrap()).unwrap());
+
+    assert_client_identity(request_with_client_cert("localhost:8014", Some("client")));
+    assert_rejected(request_with_client_cert("localhost:8014", None));
+    assert_rejected(request_with_client_cert("localhost:8014", Some("untrusted-client")));
+}
+
+#[test]
+fn client_auth_optional() {
+    start_client_auth_server("0.0.0.0:8015", ClientAuth::optional(&load_certs("./tests/certs/ca.crt").unwrap()).unwrap());
+
+    assert_client_identity(request_with_client_cert("localhost:8015", Some("client")));
+    assert!(request_with_client_cert("localhost:8015", None).unwrap().ends_with("\r\n\r\nanonymous"));
+    assert_rejected(request_with_client_cert("localhost:8015", Some("untrusted-client")));
+}
+
+#[test]
+fn client_tls_config() {
+    let mut router = Router::new();
+    router.on_prefix_with_context("/", |_, _, context| {
+        let identity = context.connection().unwrap().tls.as_ref().unwrap().client_identity.as_ref().unwrap();
+        SendResponse(Response::text(identity.common_name.clone().unwrap()))
+    });
+
+    let mut tls_config = TlsConfig::default();
+    tls_config.cert_resolver.add("localhost", load_certs("./tests/certs/localhost.crt").unwrap(), &load_private_key("./tests/certs/localhost.key").unwrap()).unwrap();
+    tls_config.client_auth = ClientAuth::required(&load_certs("./tests/certs/ca.crt").unwrap()).unwrap();
+
+    spawn(|| server::listen_https(Config {
+        addr: "0.0.0.0:8016",
+        router,
+        ..Default::default()
+    }, tls_config).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    let send = |pinned_cert: &str, client_cert: bool| {
+        let mut tls_config = ClientTlsConfig::new();
+        tls_config.add_ca_certs(&load_certs("./tests/certs/ca.crt").unwrap()).unwrap();
+        tls_config.pinned_spki_sha256.push(spki_sha256(&load_certs(pinned_cert).unwrap()[0]).unwrap());
+        if client_cert {
+            tls_config.set_client_cert(load_certs("./tests/certs/client.crt").unwrap(), &load_private_key("./tests/certs/client.key").unwrap()).unwrap();
+        }
+        let client = Client::new_https(ClientConfig {
+            addr: "localhost:8016",
+            read_timeout: Duration::from_secs(5),
+            num_connections: 1,
+            server_name: None,
+        }, tls_config);
+        client.send(&Request::builder().uri("/").build().unwrap())
     };
 
-    let pkcs8_keys = {
-        let keyfile = fs::File::open(filename)
-            .expect("cannot open private key file");
-        let mut reader = BufReader::new(keyfile);
-        rustls::internal::pemfile::pkcs8_private_keys(&mut reader)
-            .expect("file contains invalid pkcs8 private key (encrypted keys not supported)")
+    assert_eq!(send("./tests/certs/localhost.crt", true).unwrap().body, b"client");
+    assert!(send("./tests/certs/example.crt", true).is_err());
+    assert!(send("./tests/certs/localhost.crt", false).is_err());
+}
+
+#[test]
+fn reload_certs() {
+    let dir = std::env::temp_dir().join(format!("my_http_reload_certs_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let copy_cert = |name: &str| {
+        fs::copy(format!("./tests/certs/{}.crt", name), dir.join("cert.pem")).unwrap();
+        fs::copy(format!("./tests/certs/{}.key", name), dir.join("key.pem")).unwrap();
     };
+    copy_cert("localhost");
 
-    // prefer to load pkcs8 keys
-    if !pkcs8_keys.is_empty() {
-        pkcs8_keys[0].clone()
-    } else {
-        assert!(!rsa_keys.is_empty());
-        rsa_keys[0].clone()
-    }
-}
\ No newline at end of file
+    let mut router = Router::new();
+    router.on_prefix("/", |_, _| SendResponse(Response::text("i work")));
+
+    let certs = ReloadableCerts::from_files(vec![CertFiles::default_cert(dir.join("cert.pem"), dir.join("key.pem"))]).unwrap();
+    let tls_config = TlsConfig {
+        reloadable_certs: Some(certs.clone()),
+        alpn_protocols: vec![b"http/1.1".to_vec()],
+        ..Default::default()
+    };
+
+    spawn(|| server::listen_https(Config {
+        addr: "0.0.0.0:8017",
+        router,
+        ..Default::default()
+    }, tls_config).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    // the certificate is not valid for example.test until it is reloaded
+    assert!(request_with_sni("localhost:8017", "example.test", &[]).is_err());
+
+    copy_cert("example");
+    certs.reload().unwrap();
+    let (response, _) = request_with_sni("localhost:8017", "example.test", &[]).unwrap();
+    assert!(response.ends_with("i work"));
+
+    // a file that can not be parsed is reported, and the current certificate is kept
+    fs::write(dir.join("key.pem"), "not a key").unwrap();
+    assert!(certs.reload().is_err());
+    let (response, _) = request_with_sni("localhost:8017", "example.test", &[]).unwrap();
+    assert!(response.ends_with("i work"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn client_ip_address_with_server_name() {
+    let mut router = Router::new();
+    router.on_prefix("/", |_, _| SendResponse(Response::text("i work")));
+
+    let mut tls_config = TlsConfig::default();
+    tls_config.cert_resolver.add("localhost", load_certs("./tests/certs/localhost.crt").unwrap(), &load_private_key("./tests/certs/localhost.key").unwrap()).unwrap();
+
+    spawn(|| server::listen_https(Config {
+        addr: "0.0.0.0:8018",
+        router,
+        ..Default::default()
+    }, tls_config).unwrap());
+
+    sleep(Duration::from_millis(100));
+
+    let send = |server_name: Option<&'static str>| {
+        let mut tls_config = ClientTlsConfig::new();
+        tls_config.add_ca_certs(&load_certs("./tests/certs/ca.crt").unwrap()).unwrap();
+        let client = Client::new_https(ClientConfig {
+            addr: "127.0.0.1:8018",
+            read_timeout: Duration::from_secs(5),
+            num_connections: 1,
+            server_name,
+        }, tls_config);
+        client.send(&Request::builder().uri("/").build().unwrap())
+    };
+
+    assert_eq!(send(Some("localhost")).unwrap().body, b"i work");
+    assert!(matches!(send(None), Err(RequestError::Connecting(error)) if error.kind() == ErrorKind::InvalidInput));
+}
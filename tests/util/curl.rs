@@ -45,4 +45,21 @@ pub fn requests(addr: &str, requests: &[&Request], https: bool) -> String {
     }
 
     String::from_utf8_lossy(&cmd.output().unwrap().stdout).to_string()
-}
\ No newline at end of file
+}
+/// Sends GET requests for the given URIs over HTTP/2 and returns the output of curl, which includes the head of each response.
+/// HTTP/2 is negotiated with ALPN for HTTPS, and is used with prior knowledge otherwise.
+pub fn http2_requests(addr: &str, uris: &[&str], https: bool) -> String {
+    let mut cmd = Command::new("curl");
+
+    for uri in uris {
+        cmd.arg("--next").arg("--silent").arg("--include");
+
+        if https {
+            cmd.arg("-k").arg("--http2").arg(format!("https://{}{}", addr, uri));
+        } else {
+            cmd.arg("--http2-prior-knowledge").arg(format!("http://{}{}", addr, uri));
+        }
+    }
+
+    String::from_utf8_lossy(&cmd.output().unwrap().stdout).to_string()
+}
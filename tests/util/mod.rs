@@ -1,3 +1,4 @@
 pub mod curl;
 pub mod test_server;
-pub mod test_client;
\ No newline at end of file
+pub mod test_client;
+pub mod websocket;
@@ -1,3 +1,4 @@
+use std::io;
 use std::io::Read;
 use std::net::TcpStream;
 use std::sync::Arc;
@@ -35,19 +36,15 @@ pub fn test_server(config: Config, num_connections: usize, num_loops_per_connect
 
             for _ in 0..num_loops_per_connection {
                 for (request, expected_response) in messages.iter() {
-                    let mut actual_response = vec![0u8; expected_response.len()];
-
-                    loop {
+                    let actual_response = loop {
                         let result = write_request(&mut client, request)
-                            .and_then(|_| client.read_exact(&mut actual_response));
+                            .and_then(|_| read_response_without_date(&mut client, expected_response.len()));
 
-                        if result.is_err() {
-                            client = TcpStream::connect(addr).unwrap();
-                            continue;
+                        match result {
+                            Ok(response) => break response,
+                            Err(_) => client = TcpStream::connect(addr).unwrap(),
                         }
-
-                        break;
-                    }
+                    };
 
                     assert_eq!(expected_response, &actual_response);
 
@@ -65,6 +62,23 @@ pub fn test_server(config: Config, num_connections: usize, num_loops_per_connect
     }
 }
 
+/// Length of the date header line the server adds to every response, "date: " followed by an IMF-fixdate and CRLF.
+const DATE_HEADER_LINE_LEN: usize = 37;
+
+/// Reads a response with the given length (excluding the date header) from the reader and removes the date header line.
+/// The date header is expected to be the first header, which is where the server writes it.
+pub fn read_response_without_date(reader: &mut impl Read, len: usize) -> io::Result<Vec<u8>> {
+    let mut response = vec![0u8; len + DATE_HEADER_LINE_LEN];
+    reader.read_exact(&mut response)?;
+
+    let status_line_end = response.windows(2).position(|w| w == b"\r\n").map(|i| i + 2).unwrap_or(0);
+    let date_line = &response[status_line_end..status_line_end + DATE_HEADER_LINE_LEN];
+    assert!(date_line.starts_with(b"date: ") && date_line.ends_with(b"\r\n"), "missing date header");
+
+    response.drain(status_line_end..status_line_end + DATE_HEADER_LINE_LEN);
+    Ok(response)
+}
+
 pub fn test_server_with_curl(config: Config, tls_config: Option<ServerConfig>, num_connections: usize, messages: Vec<(Request, Response)>) {
     let addr = config.addr;
 
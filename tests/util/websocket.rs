@@ -0,0 +1,34 @@
+use std::io::{BufRead, BufReader, Read, Write};
+
+/// Sends a WebSocket opening handshake request with the sample key from RFC 6455, and returns the head of the response.
+pub fn handshake<S: Read + Write>(stream: &mut BufReader<S>, uri: &str, protocols: &str) -> String {
+    write!(stream.get_mut(), "GET {} HTTP/1.1\r\nhost: localhost\r\nupgrade: websocket\r\nconnection: Upgrade\r\n\
+        sec-websocket-key: dGhlIHNhbXBsZSBub25jZQ==\r\nsec-websocket-version: 13\r\nsec-websocket-protocol: {}\r\n\r\n", uri, protocols).unwrap();
+    stream.get_mut().flush().unwrap();
+
+    let mut head = String::new();
+    while !head.ends_with("\r\n\r\n") {
+        stream.read_line(&mut head).unwrap();
+    }
+    head
+}
+
+/// Writes a masked frame with the given opcode and a payload shorter than 126 bytes.
+pub fn write_frame(stream: &mut impl Write, opcode: u8, payload: &[u8]) {
+    let mask = [0x12, 0x34, 0x56, 0x78];
+    let mut frame = vec![0x80 | opcode, 0x80 | payload.len() as u8];
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    stream.write_all(&frame).unwrap();
+    stream.flush().unwrap();
+}
+
+/// Reads an unmasked frame with a payload shorter than 126 bytes, and returns its opcode and payload.
+pub fn read_frame(stream: &mut impl Read) -> (u8, Vec<u8>) {
+    let mut head = [0; 2];
+    stream.read_exact(&mut head).unwrap();
+    assert_eq!(head[1] & 0x80, 0, "frames from a server must not be masked");
+    let mut payload = vec![0; (head[1] & 0x7F) as usize];
+    stream.read_exact(&mut payload).unwrap();
+    (head[0] & 0x0F, payload)
+}